@@ -0,0 +1,212 @@
+//! Golden-image snapshots for the renderer tests. Rendered RGBA8 pixels are
+//! compared against `tests/golden/<name>.png` with a perceptual colour
+//! distance, so driver-level rounding does not fail a test while a wrong
+//! colour or a missing shape does. On failure the actual image and a diff
+//! (mismatches in red over a faded copy of the expected image) are written to
+//! `target/golden/`. Set `UPDATE_GOLDEN=1` to accept the current output.
+//!
+//! Tests of the wgpu pipeline need an adapter, a software one will do. They
+//! fail without one unless `SKIP_GPU_TESTS=1` is set, and then say that they
+//! skipped; they never fall back to the CPU renderer and pass on its output.
+
+use std::fs;
+use std::io::Cursor;
+use std::path::PathBuf;
+
+use crate::export;
+
+/// Set to skip the tests that need an adapter on machines without one
+const SKIP_GPU_TESTS: &str = "SKIP_GPU_TESTS";
+
+/// Called by a test that found no adapter: fails it, or reports the skip
+/// when [`SKIP_GPU_TESTS`] is set so the caller can return
+pub(crate) fn require_adapter(test: &str) {
+    assert!(
+        std::env::var_os(SKIP_GPU_TESTS).is_some(),
+        "{test}: no GPU adapter; set {SKIP_GPU_TESTS}=1 to skip the GPU tests"
+    );
+    eprintln!("{test}: skipped, no GPU adapter and {SKIP_GPU_TESTS} is set");
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Tolerance {
+    /// Per-pixel YIQ distance, `0.0..=1.0`, under which pixels count as equal
+    pub(crate) threshold: f64,
+    /// Share of pixels allowed to exceed `threshold`
+    pub(crate) max_mismatch: f64,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self {
+            threshold: 0.1,
+            max_mismatch: 0.001,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Comparison {
+    pub(crate) mismatched: usize,
+    pub(crate) diff: Vec<u8>,
+}
+
+pub(crate) fn assert_golden(name: &str, width: u32, height: u32, pixels: &[u8]) {
+    assert_golden_with(name, width, height, pixels, Tolerance::default());
+}
+
+pub(crate) fn assert_golden_with(
+    name: &str,
+    width: u32,
+    height: u32,
+    pixels: &[u8],
+    tolerance: Tolerance,
+) {
+    assert_eq!(pixels.len(), (width * height * 4) as usize);
+    let golden = golden_dir().join(format!("{name}.png"));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::create_dir_all(golden_dir()).unwrap();
+        fs::write(&golden, export::png::encode(width, height, pixels).unwrap()).unwrap();
+        return;
+    }
+
+    let Ok(bytes) = fs::read(&golden) else {
+        let actual = write_output(name, "actual", width, height, pixels);
+        panic!(
+            "missing golden image {}; run with UPDATE_GOLDEN=1 to accept {}",
+            golden.display(),
+            actual.display()
+        );
+    };
+    let (w, h, expected) = decode_png(&bytes);
+    if (w, h) != (width, height) {
+        let actual = write_output(name, "actual", width, height, pixels);
+        panic!(
+            "{name}: golden is {w}x{h} but rendered {width}x{height}, see {}",
+            actual.display()
+        );
+    }
+
+    let comparison = compare(&expected, pixels, width, height, tolerance);
+    let allowed = (tolerance.max_mismatch * (width * height) as f64).floor() as usize;
+    if comparison.mismatched > allowed {
+        write_output(name, "actual", width, height, pixels);
+        let diff = write_output(name, "diff", width, height, &comparison.diff);
+        panic!(
+            "{name}: {} pixels differ from the golden image (allowed {allowed}), see {}",
+            comparison.mismatched,
+            diff.display()
+        );
+    }
+}
+
+/// Counts pixels whose perceptual distance exceeds the threshold and draws
+/// them into a diff image
+pub(crate) fn compare(
+    expected: &[u8],
+    actual: &[u8],
+    width: u32,
+    height: u32,
+    tolerance: Tolerance,
+) -> Comparison {
+    let max_delta = MAX_YIQ_DELTA * tolerance.threshold * tolerance.threshold;
+    let mut diff = Vec::with_capacity((width * height * 4) as usize);
+    let mut mismatched = 0;
+    for (e, a) in expected.chunks_exact(4).zip(actual.chunks_exact(4)) {
+        let (e, a) = ([e[0], e[1], e[2], e[3]], [a[0], a[1], a[2], a[3]]);
+        if color_delta(e, a) > max_delta {
+            mismatched += 1;
+            diff.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            let gray = (255.0 - 0.1 * (255.0 - luma(blend_white(e)))) as u8;
+            diff.extend_from_slice(&[gray, gray, gray, 255]);
+        }
+    }
+    Comparison { mismatched, diff }
+}
+
+/// Largest possible value of [`color_delta`], between black and white
+const MAX_YIQ_DELTA: f64 = 35215.0;
+
+/// Squared YIQ distance as used by pixelmatch, after compositing on white
+fn color_delta(a: [u8; 4], b: [u8; 4]) -> f64 {
+    if a == b {
+        return 0.0;
+    }
+    let (a, b) = (blend_white(a), blend_white(b));
+    let y = luma(a) - luma(b);
+    let i = in_phase(a) - in_phase(b);
+    let q = quadrature(a) - quadrature(b);
+    0.5053 * y * y + 0.299 * i * i + 0.1957 * q * q
+}
+
+fn blend_white(c: [u8; 4]) -> [f64; 3] {
+    let a = c[3] as f64 / 255.0;
+    [0, 1, 2].map(|k| 255.0 + (c[k] as f64 - 255.0) * a)
+}
+
+fn luma(c: [f64; 3]) -> f64 {
+    c[0] * 0.29889531 + c[1] * 0.58662247 + c[2] * 0.11448223
+}
+
+fn in_phase(c: [f64; 3]) -> f64 {
+    c[0] * 0.59597799 - c[1] * 0.2741761 - c[2] * 0.32180189
+}
+
+fn quadrature(c: [f64; 3]) -> f64 {
+    c[0] * 0.21147017 - c[1] * 0.52261711 + c[2] * 0.31114694
+}
+
+fn golden_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+fn write_output(name: &str, kind: &str, width: u32, height: u32, pixels: &[u8]) -> PathBuf {
+    let dir = std::env::var_os("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target"))
+        .join("golden");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(format!("{name}.{kind}.png"));
+    fs::write(&path, export::png::encode(width, height, pixels).unwrap()).unwrap();
+    path
+}
+
+fn decode_png(bytes: &[u8]) -> (u32, u32, Vec<u8>) {
+    let mut decoder = png::Decoder::new(Cursor::new(bytes));
+    decoder.set_transformations(png::Transformations::ALPHA | png::Transformations::EXPAND);
+    let mut reader = decoder.read_info().unwrap();
+    let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+    let info = reader.next_frame(&mut buf).unwrap();
+    assert_eq!(info.color_type, png::ColorType::Rgba, "golden must be RGBA");
+    buf.truncate(info.buffer_size());
+    (info.width, info.height, buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_shifts_pass_and_wrong_colours_fail() {
+        let expected = [200, 40, 40, 255].repeat(4);
+        let mut actual = expected.clone();
+        actual[0] = 203;
+        let cmp = compare(&expected, &actual, 2, 2, Tolerance::default());
+        assert_eq!(cmp.mismatched, 0);
+
+        actual[4..8].copy_from_slice(&[40, 40, 200, 255]);
+        let cmp = compare(&expected, &actual, 2, 2, Tolerance::default());
+        assert_eq!(cmp.mismatched, 1);
+        assert_eq!(&cmp.diff[4..8], &[255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn png_round_trip() {
+        let pixels: Vec<u8> = (0..3 * 2 * 4).map(|i| (i * 10) as u8).collect();
+        assert_eq!(
+            decode_png(&export::png::encode(3, 2, &pixels).unwrap()),
+            (3, 2, pixels)
+        );
+    }
+}
@@ -1,3 +1,6 @@
 pub mod renderer;
 
 pub mod renderer3d;
+
+#[cfg(test)]
+mod golden;
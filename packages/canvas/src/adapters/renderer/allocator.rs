@@ -0,0 +1,144 @@
+use std::ops::Range;
+
+/// First-fit free-list over `0..capacity` element slots of a GPU buffer.
+/// Freed ranges are coalesced with their neighbours.
+#[derive(Debug, Clone)]
+pub(crate) struct RangeAllocator {
+    capacity: u32,
+    /// Sorted, non-overlapping, non-adjacent
+    free: Vec<Range<u32>>,
+}
+
+impl RangeAllocator {
+    pub(crate) fn new(capacity: u32) -> Self {
+        let mut alloc = Self {
+            capacity: 0,
+            free: Vec::new(),
+        };
+        alloc.grow(capacity);
+        alloc
+    }
+
+    pub(crate) fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    pub(crate) fn allocate(&mut self, len: u32) -> Option<Range<u32>> {
+        if len == 0 {
+            return Some(0..0);
+        }
+        let idx = self.free.iter().position(|r| r.end - r.start >= len)?;
+        let start = self.free[idx].start;
+        if self.free[idx].end - start == len {
+            self.free.remove(idx);
+        } else {
+            self.free[idx].start += len;
+        }
+        Some(start..start + len)
+    }
+
+    pub(crate) fn free(&mut self, range: Range<u32>) {
+        if range.is_empty() {
+            return;
+        }
+        debug_assert!(range.end <= self.capacity);
+        let idx = self.free.partition_point(|r| r.start < range.start);
+        debug_assert!(idx == 0 || self.free[idx - 1].end <= range.start);
+        debug_assert!(idx == self.free.len() || range.end <= self.free[idx].start);
+
+        let merge_prev = idx > 0 && self.free[idx - 1].end == range.start;
+        let merge_next = idx < self.free.len() && self.free[idx].start == range.end;
+        match (merge_prev, merge_next) {
+            (true, true) => {
+                self.free[idx - 1].end = self.free[idx].end;
+                self.free.remove(idx);
+            }
+            (true, false) => self.free[idx - 1].end = range.end,
+            (false, true) => self.free[idx].start = range.start,
+            (false, false) => self.free.insert(idx, range),
+        }
+    }
+
+    /// Extends the managed space; existing allocations are untouched
+    pub(crate) fn grow(&mut self, new_capacity: u32) {
+        if new_capacity <= self.capacity {
+            return;
+        }
+        let old = self.capacity;
+        self.capacity = new_capacity;
+        self.free(old..new_capacity);
+    }
+
+    #[cfg(test)]
+    fn free_ranges(&self) -> &[Range<u32>] {
+        &self.free
+    }
+}
+
+/// Collapses draw ranges that follow each other in the same buffer into one
+/// call; `K` identifies the buffer.
+pub(crate) fn merge_draw_ranges<K: PartialEq>(
+    ranges: impl IntoIterator<Item = (K, Range<u32>)>,
+) -> Vec<(K, Range<u32>)> {
+    let mut out: Vec<(K, Range<u32>)> = Vec::new();
+    for (key, r) in ranges.into_iter().filter(|(_, r)| !r.is_empty()) {
+        match out.last_mut() {
+            Some((last_key, last)) if *last_key == key && last.end == r.start => last.end = r.end,
+            _ => out.push((key, r)),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocates_first_fit_and_coalesces() {
+        let mut alloc = RangeAllocator::new(100);
+        let a = alloc.allocate(10).unwrap();
+        let b = alloc.allocate(20).unwrap();
+        let c = alloc.allocate(30).unwrap();
+        assert_eq!((a.clone(), b.clone(), c.clone()), (0..10, 10..30, 30..60));
+        assert!(alloc.allocate(41).is_none());
+
+        alloc.free(a);
+        alloc.free(c);
+        assert_eq!(alloc.free_ranges(), &[0..10, 30..100]);
+        assert_eq!(alloc.allocate(5).unwrap(), 0..5);
+
+        alloc.free(0..5);
+        alloc.free(b);
+        assert_eq!(alloc.free_ranges().first(), Some(&(0..100)));
+        assert_eq!(alloc.free_ranges().len(), 1);
+    }
+
+    #[test]
+    fn grow_extends_trailing_free_range() {
+        let mut alloc = RangeAllocator::new(16);
+        assert_eq!(alloc.allocate(12).unwrap(), 0..12);
+        assert!(alloc.allocate(8).is_none());
+        alloc.grow(32);
+        assert_eq!(alloc.free_ranges().first(), Some(&(12..32)));
+        assert_eq!(alloc.allocate(8).unwrap(), 12..20);
+        assert_eq!(alloc.capacity(), 32);
+    }
+
+    #[test]
+    fn merges_adjacent_draws() {
+        let merged = merge_draw_ranges([
+            (0, 0..6),
+            (0, 6..12),
+            (0, 30..36),
+            (0, 12..18),
+            (0, 18..18),
+            (0, 18..24),
+            (1, 24..30),
+        ]);
+        assert_eq!(
+            merged,
+            vec![(0, 0..12), (0, 30..36), (0, 12..24), (1, 24..30)]
+        );
+    }
+}
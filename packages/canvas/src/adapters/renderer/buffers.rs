@@ -1,13 +1,419 @@
-use wgpu::util::DeviceExt;
+use std::collections::HashMap;
+use std::ops::Range;
 
-use crate::adapters::renderer::vertex::VERTICES;
+use crate::adapters::renderer::allocator::{merge_draw_ranges, RangeAllocator};
+use crate::adapters::renderer::tessellate::{self, Mesh};
+use crate::adapters::renderer::text_pipeline::GlyphTexture;
+use crate::adapters::renderer::vertex::{StrokeVertex, TextVertex, Vertex};
+use crate::model::{Shape, ShapeId, ShapeKind};
+use crate::text::Typesetter;
 
-pub(crate) fn create_vertex_buffer(device: &wgpu::Device) -> (wgpu::Buffer, u32) {
-    let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some("Vertex Buffer"),
-        contents: bytemuck::cast_slice(VERTICES),
-        usage: wgpu::BufferUsages::VERTEX,
-    });
+const MIN_VERTEX_CAPACITY: u32 = 4096;
+const MIN_INDEX_CAPACITY: u32 = 16384;
 
-    (vertex_buffer, VERTICES.len() as u32)
+/// GPU buffer of fixed-size elements that keeps its contents when it grows
+#[derive(Debug)]
+pub(crate) struct GrowableBuffer {
+    pub(crate) buffer: wgpu::Buffer,
+    label: &'static str,
+    usage: wgpu::BufferUsages,
+    stride: u64,
+}
+
+impl GrowableBuffer {
+    fn new(
+        device: &wgpu::Device,
+        label: &'static str,
+        usage: wgpu::BufferUsages,
+        stride: u64,
+        capacity: u32,
+    ) -> Self {
+        let usage = usage | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC;
+        Self {
+            buffer: create_buffer(device, label, usage, stride * capacity as u64),
+            label,
+            usage,
+            stride,
+        }
+    }
+
+    /// Reallocates to `capacity` elements and copies the old contents over
+    fn grow(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, capacity: u32) {
+        let next = create_buffer(
+            device,
+            self.label,
+            self.usage,
+            self.stride * capacity as u64,
+        );
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Buffer Grow Encoder"),
+        });
+        encoder.copy_buffer_to_buffer(&self.buffer, 0, &next, 0, self.buffer.size());
+        queue.submit(std::iter::once(encoder.finish()));
+        self.buffer = next;
+    }
+
+    fn write(&self, queue: &wgpu::Queue, first: u32, data: &[u8]) {
+        if !data.is_empty() {
+            queue.write_buffer(&self.buffer, first as u64 * self.stride, data);
+        }
+    }
+}
+
+fn create_buffer(
+    device: &wgpu::Device,
+    label: &'static str,
+    usage: wgpu::BufferUsages,
+    size: u64,
+) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size,
+        usage,
+        mapped_at_creation: false,
+    })
+}
+
+#[derive(Debug, Clone, Default)]
+struct Slot {
+    vertices: Range<u32>,
+    indices: Range<u32>,
+}
+
+/// A vertex/index buffer pair with its two suballocators
+#[derive(Debug)]
+pub(crate) struct GeometryBuffers {
+    pub(crate) vertices: GrowableBuffer,
+    pub(crate) indices: GrowableBuffer,
+    vertex_alloc: RangeAllocator,
+    index_alloc: RangeAllocator,
+}
+
+impl GeometryBuffers {
+    fn new(device: &wgpu::Device, label: &'static str, vertex_stride: u64) -> Self {
+        Self {
+            vertices: GrowableBuffer::new(
+                device,
+                label,
+                wgpu::BufferUsages::VERTEX,
+                vertex_stride,
+                MIN_VERTEX_CAPACITY,
+            ),
+            indices: GrowableBuffer::new(
+                device,
+                label,
+                wgpu::BufferUsages::INDEX,
+                std::mem::size_of::<u32>() as u64,
+                MIN_INDEX_CAPACITY,
+            ),
+            vertex_alloc: RangeAllocator::new(MIN_VERTEX_CAPACITY),
+            index_alloc: RangeAllocator::new(MIN_INDEX_CAPACITY),
+        }
+    }
+
+    /// Copies `mesh` into freshly allocated ranges, rebasing its indices
+    fn insert<V: bytemuck::Pod>(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        mesh: &mut Mesh<V>,
+    ) -> Slot {
+        let vertices = allocate(
+            &mut self.vertex_alloc,
+            &mut self.vertices,
+            device,
+            queue,
+            mesh.vertices.len() as u32,
+        );
+        let indices = allocate(
+            &mut self.index_alloc,
+            &mut self.indices,
+            device,
+            queue,
+            mesh.indices.len() as u32,
+        );
+        for i in &mut mesh.indices {
+            *i += vertices.start;
+        }
+        self.vertices
+            .write(queue, vertices.start, bytemuck::cast_slice(&mesh.vertices));
+        self.indices
+            .write(queue, indices.start, bytemuck::cast_slice(&mesh.indices));
+        Slot { vertices, indices }
+    }
+
+    fn free(&mut self, slot: Slot) {
+        self.vertex_alloc.free(slot.vertices);
+        self.index_alloc.free(slot.indices);
+    }
+}
+
+fn allocate(
+    alloc: &mut RangeAllocator,
+    buffer: &mut GrowableBuffer,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    len: u32,
+) -> Range<u32> {
+    if let Some(range) = alloc.allocate(len) {
+        return range;
+    }
+    let capacity = grown_capacity(alloc.capacity(), len);
+    buffer.grow(device, queue, capacity);
+    alloc.grow(capacity);
+    alloc.allocate(len).expect("buffer grown to fit")
+}
+
+fn grown_capacity(current: u32, needed: u32) -> u32 {
+    (current.saturating_mul(2))
+        .max(current + needed)
+        .next_power_of_two()
+}
+
+/// Which geometry buffer and pipeline a draw call uses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Layer {
+    Fill,
+    Stroke,
+    Text,
+}
+
+#[derive(Debug, Clone, Default)]
+struct ShapeSlot {
+    fill: Slot,
+    stroke: Slot,
+    text: Slot,
+    /// A text shape and the generation of the glyph atlas its quads point
+    /// into, to set it again once the atlas starts over
+    typeset: Option<(Shape, u64)>,
+}
+
+/// Per-shape vertex and index ranges suballocated from shared buffers, so a
+/// single shape can be patched without re-uploading the scene. Fills and
+/// anti-aliased strokes and glyph quads live in separate buffers with their
+/// own pipelines.
+#[derive(Debug)]
+pub(crate) struct SceneBuffers {
+    pub(crate) fill: GeometryBuffers,
+    pub(crate) stroke: GeometryBuffers,
+    pub(crate) text: GeometryBuffers,
+    /// The glyph atlas the text quads sample
+    pub(crate) glyphs: GlyphTexture,
+    slots: HashMap<ShapeId, ShapeSlot>,
+    /// Collaborator cursors and selections, drawn above every shape
+    overlay: Option<ShapeSlot>,
+    /// Generation of the glyph atlas the overlay's labels point into
+    overlay_generation: u64,
+    draws: Vec<(Layer, Range<u32>)>,
+    draws_dirty: bool,
+}
+
+impl SceneBuffers {
+    pub(crate) fn new(device: &wgpu::Device) -> Self {
+        Self {
+            fill: GeometryBuffers::new(device, "Fill Buffer", std::mem::size_of::<Vertex>() as u64),
+            stroke: GeometryBuffers::new(
+                device,
+                "Stroke Buffer",
+                std::mem::size_of::<StrokeVertex>() as u64,
+            ),
+            text: GeometryBuffers::new(
+                device,
+                "Text Buffer",
+                std::mem::size_of::<TextVertex>() as u64,
+            ),
+            glyphs: GlyphTexture::new(device),
+            slots: HashMap::new(),
+            overlay: None,
+            overlay_generation: 0,
+            draws: Vec::new(),
+            draws_dirty: false,
+        }
+    }
+
+    /// Tessellates `shape` into its own ranges, replacing any previous
+    /// geometry. Text is set with the fonts of `text`, and glyphs new to its
+    /// atlas are uploaded.
+    pub(crate) fn upsert(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        shape: &Shape,
+        text: &mut Typesetter,
+    ) {
+        let generation = text.atlas().generation();
+        self.insert_shape(device, queue, shape, text);
+        if text.atlas().generation() != generation {
+            self.retypeset(device, queue, text);
+        }
+        self.glyphs.sync(device, queue, text.atlas_mut());
+    }
+
+    fn insert_shape(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        shape: &Shape,
+        text: &mut Typesetter,
+    ) {
+        self.remove(&shape.id);
+
+        let mut fill = Mesh::default();
+        let mut stroke = Mesh::default();
+        let mut glyphs = Mesh::default();
+        tessellate::tessellate_shape(shape, &mut fill, &mut stroke);
+        tessellate::tessellate_text(shape, text, &mut glyphs);
+
+        let slot = ShapeSlot {
+            fill: self.fill.insert(device, queue, &mut fill),
+            stroke: self.stroke.insert(device, queue, &mut stroke),
+            text: self.text.insert(device, queue, &mut glyphs),
+            typeset: matches!(shape.kind, ShapeKind::Text { .. })
+                .then(|| (shape.clone(), text.atlas().generation())),
+        };
+        self.slots.insert(shape.id.clone(), slot);
+        self.draws_dirty = true;
+    }
+
+    /// Sets again the text whose glyphs the atlas dropped when it started
+    /// over. Text that still points at dropped glyphs afterwards, because the
+    /// atlas cannot hold all of it at once, is left out until it changes.
+    fn retypeset(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, text: &mut Typesetter) {
+        let stale = |slot: &ShapeSlot, generation: u64| {
+            slot.typeset.as_ref().is_some_and(|(_, g)| *g != generation)
+        };
+        let generation = text.atlas().generation();
+        let shapes: Vec<Shape> = self
+            .slots
+            .values()
+            .filter(|slot| stale(slot, generation))
+            .filter_map(|slot| slot.typeset.as_ref().map(|(shape, _)| shape.clone()))
+            .collect();
+        for shape in &shapes {
+            self.insert_shape(device, queue, shape, text);
+        }
+        let generation = text.atlas().generation();
+        let mut dropped = 0;
+        for slot in self.slots.values_mut() {
+            if stale(slot, generation) && !slot.text.indices.is_empty() {
+                self.text.free(std::mem::take(&mut slot.text));
+                dropped += 1;
+            }
+        }
+        if dropped > 0 {
+            tracing::warn!("the glyph atlas cannot hold all the text, {dropped} shapes left out");
+            self.draws_dirty = true;
+        }
+    }
+
+    pub(crate) fn remove(&mut self, id: &str) {
+        if let Some(slot) = self.slots.remove(id) {
+            self.fill.free(slot.fill);
+            self.stroke.free(slot.stroke);
+            self.text.free(slot.text);
+            self.draws_dirty = true;
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        let ids: Vec<ShapeId> = self.slots.keys().cloned().collect();
+        for id in ids {
+            self.remove(&id);
+        }
+    }
+
+    /// Replaces the overlay geometry, see `overlay::tessellate_presence`.
+    /// `generation` is that of the glyph atlas before the labels were set,
+    /// so text that lost its glyphs to an atlas restart is set again.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn set_overlay(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        fill: &mut Mesh,
+        stroke: &mut Mesh<StrokeVertex>,
+        glyphs: &mut Mesh<TextVertex>,
+        text: &mut Typesetter,
+        generation: u64,
+    ) {
+        self.clear_overlay();
+        self.overlay = Some(ShapeSlot {
+            fill: self.fill.insert(device, queue, fill),
+            stroke: self.stroke.insert(device, queue, stroke),
+            text: self.text.insert(device, queue, glyphs),
+            typeset: None,
+        });
+        self.overlay_generation = text.atlas().generation();
+        if self.overlay_generation != generation {
+            self.retypeset(device, queue, text);
+        }
+        self.glyphs.sync(device, queue, text.atlas_mut());
+        self.draws_dirty = true;
+    }
+
+    /// Whether the glyph atlas started over since the overlay was set,
+    /// leaving its labels pointing at glyphs it dropped
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn overlay_stale(&self, text: &Typesetter) -> bool {
+        self.overlay
+            .as_ref()
+            .is_some_and(|slot| !slot.text.indices.is_empty())
+            && self.overlay_generation != text.atlas().generation()
+    }
+
+    pub(crate) fn clear_overlay(&mut self) {
+        if let Some(slot) = self.overlay.take() {
+            self.fill.free(slot.fill);
+            self.stroke.free(slot.stroke);
+            self.text.free(slot.text);
+            self.draws_dirty = true;
+        }
+    }
+
+    /// Call when the z-order changed without any geometry change
+    pub(crate) fn invalidate_order(&mut self) {
+        self.draws_dirty = true;
+    }
+
+    /// Rebuilds the draw list if shapes or their order changed since last
+    /// frame. Exports leave the `overlay` out.
+    pub(crate) fn prepare_draws(&mut self, order: &[ShapeId], overlay: bool) {
+        if self.draws_dirty {
+            let overlay = self.overlay.as_ref().filter(|_| overlay);
+            // strokes are keyed by shape so that each is drawn, and blended,
+            // on its own; see `stroke_pipeline.rs`
+            self.draws = merge_draw_ranges(
+                order
+                    .iter()
+                    .filter_map(|id| self.slots.get(id))
+                    .chain(overlay)
+                    .enumerate()
+                    .flat_map(|(i, slot)| {
+                        [
+                            ((Layer::Fill, 0), slot.fill.indices.clone()),
+                            ((Layer::Stroke, i), slot.stroke.indices.clone()),
+                            ((Layer::Text, 0), slot.text.indices.clone()),
+                        ]
+                    }),
+            )
+            .into_iter()
+            .map(|((layer, _), range)| (layer, range))
+            .collect();
+            self.draws_dirty = false;
+        }
+    }
+
+    /// Index ranges to draw, bottom first, with neighbouring fills and text
+    /// merged
+    pub(crate) fn draws(&self) -> &[(Layer, Range<u32>)] {
+        &self.draws
+    }
+
+    pub(crate) fn geometry(&self, layer: Layer) -> &GeometryBuffers {
+        match layer {
+            Layer::Fill => &self.fill,
+            Layer::Stroke => &self.stroke,
+            Layer::Text => &self.text,
+        }
+    }
 }
@@ -0,0 +1,85 @@
+use crate::model::{Camera, Rect};
+use crate::types::Size;
+
+/// Canvas pixels kept around the viewport when culling, enough for the
+/// anti-aliased fringe of strokes along the edges
+const CULL_MARGIN_PIXELS: f64 = 2.0;
+
+/// World-to-clip transform as `clip = world * scale + translate`
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct CameraUniform {
+    pub(crate) scale: [f32; 2],
+    pub(crate) translate: [f32; 2],
+    /// Size of one canvas pixel in world units, for stroke anti-aliasing
+    pub(crate) world_per_pixel: f32,
+    pub(crate) _pad: [f32; 3],
+}
+
+impl CameraUniform {
+    /// `camera` is in CSS pixels like `worldToScreen` in geometry.ts;
+    /// `pixel_ratio` maps those onto the `size` of the canvas backing store.
+    pub(crate) fn new(camera: &Camera, size: Size, pixel_ratio: f64) -> Self {
+        let w = size.width.max(1) as f64;
+        let h = size.height.max(1) as f64;
+        let sx = 2.0 * pixel_ratio / w;
+        let sy = 2.0 * pixel_ratio / h;
+        Self {
+            scale: [(camera.zoom * sx) as f32, (-camera.zoom * sy) as f32],
+            translate: [(camera.x * sx - 1.0) as f32, (1.0 - camera.y * sy) as f32],
+            world_per_pixel: (1.0 / (camera.zoom * pixel_ratio)) as f32,
+            _pad: [0.0; 3],
+        }
+    }
+}
+
+/// World-space area a canvas of `size` shows, plus the culling margin
+pub(crate) fn visible_world_rect(camera: &Camera, size: Size, pixel_ratio: f64) -> Rect {
+    camera
+        .visible_rect(
+            size.width as f64 / pixel_ratio,
+            size.height as f64 / pixel_ratio,
+        )
+        .expand(CULL_MARGIN_PIXELS / (camera.zoom * pixel_ratio))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Point;
+
+    fn apply(uniform: &CameraUniform, p: Point) -> [f32; 2] {
+        [
+            p.x as f32 * uniform.scale[0] + uniform.translate[0],
+            p.y as f32 * uniform.scale[1] + uniform.translate[1],
+        ]
+    }
+
+    #[test]
+    fn matches_world_to_screen() {
+        let camera = Camera {
+            x: 120.0,
+            y: -40.0,
+            zoom: 2.5,
+        };
+        let size = Size {
+            width: 1600,
+            height: 1200,
+        };
+        let uniform = CameraUniform::new(&camera, size, 2.0);
+
+        let world = Point::new(33.0, 71.0);
+        let screen = camera.world_to_screen(world);
+        let clip = apply(&uniform, world);
+        let expected = [
+            (screen.x * 2.0 / 800.0 - 1.0) as f32,
+            (1.0 - screen.y * 2.0 / 600.0) as f32,
+        ];
+        assert!((clip[0] - expected[0]).abs() < 1e-5);
+        assert!((clip[1] - expected[1]).abs() < 1e-5);
+
+        let top_left = apply(&uniform, camera.screen_to_world(Point::new(0.0, 0.0)));
+        assert!((top_left[0] + 1.0).abs() < 1e-5 && (top_left[1] - 1.0).abs() < 1e-5);
+        assert!((uniform.world_per_pixel - 0.2).abs() < 1e-6);
+    }
+}
@@ -1,8 +1,28 @@
+use std::collections::BTreeMap;
+
 use wasm_bindgen::prelude::*;
 
-use crate::adapters::renderer::{buffers, pipeline, wgpu_setup};
+use crate::adapters::renderer::camera::{self, CameraUniform};
+use crate::adapters::renderer::editor::{Editor, Outcome};
+use crate::adapters::renderer::overlay;
+use crate::adapters::renderer::scene::SceneRenderer;
+use crate::adapters::renderer::{readback, wgpu_setup};
+use crate::config::app_config::{AppConfig, ConfigOverrides};
 use crate::constants::colors::CLEAR_COLOR;
+use crate::crdt::PeerId;
+use crate::error::CanvasError;
+use crate::export::pdf;
+use crate::export::png::{self, ExportRegion};
+use crate::history::{ChangeSet, Edit, History};
+use crate::model::color::Rgba;
+use crate::model::{Board, Camera, Point, Rect, Shape, ShapeId, ShapeKind, WhiteboardDoc};
+use crate::presence::{self, Presence};
+use crate::selection::{self, SelectionMode};
+use crate::spatial::{self, SpatialIndex};
 use crate::telemetry::{init_subscriber, set_panic_hook};
+use crate::text::font::Font;
+use crate::text::Typesetter;
+use crate::tools::{Modifiers, Pointer, ToolAction, ToolSettings, ToolState};
 use crate::types::Size;
 
 /// WebGPU canvas client for high-performance rendering
@@ -14,23 +34,42 @@ pub struct Client {
     queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
     size: Size,
-    render_pipeline: wgpu::RenderPipeline,
-    vertex_buffer: wgpu::Buffer,
-    num_vertices: u32,
+    renderer: SceneRenderer,
+    camera: Camera,
+    pixel_ratio: f64,
+    /// The doc, its index, the history and the tools
+    editor: Editor,
+    /// Collaborators by user id
+    presence: BTreeMap<PeerId, Presence>,
+    /// Whether the overlay needs rebuilding before the next frame
+    overlay_dirty: bool,
+    /// Shapes drawn last frame, bottom first
+    visible: Vec<ShapeId>,
+    clear_color: wgpu::Color,
+    /// Fonts and glyph atlas for text shapes; text is left out until a font
+    /// is loaded
+    text: Typesetter,
 }
 
 #[wasm_bindgen(js_name = "createClient")]
-pub async fn create_client(canvas: web_sys::HtmlCanvasElement) -> Result<Client, JsValue> {
-    Client::create_impl(canvas).await
+pub async fn create_client(
+    canvas: web_sys::HtmlCanvasElement,
+    config: Option<JsValue>,
+) -> Result<Client, JsValue> {
+    Client::create_impl(canvas, config).await
 }
 
 #[wasm_bindgen]
 impl Client {
     #[allow(deprecated)]
-    async fn create_impl(canvas: web_sys::HtmlCanvasElement) -> Result<Client, JsValue> {
+    async fn create_impl(
+        canvas: web_sys::HtmlCanvasElement,
+        config: Option<JsValue>,
+    ) -> Result<Client, JsValue> {
         init_subscriber();
         set_panic_hook();
 
+        let app_config = AppConfig::resolve(ConfigOverrides::from_js(config)?)?;
         let wgpu_setup::WgpuContext {
             surface,
             device,
@@ -38,10 +77,16 @@ impl Client {
             config,
             surface_format,
             size,
-        } = wgpu_setup::init(canvas).await?;
+        } = wgpu_setup::init(canvas, &app_config.webgpu).await?;
 
-        let render_pipeline = pipeline::create_render_pipeline(&device, surface_format);
-        let (vertex_buffer, num_vertices) = buffers::create_vertex_buffer(&device);
+        let camera = Camera::default();
+        let renderer = SceneRenderer::new(
+            &device,
+            surface_format,
+            size,
+            app_config.webgpu.sample_count,
+            &CameraUniform::new(&camera, size, 1.0),
+        );
 
         Ok(Client {
             surface,
@@ -49,12 +94,390 @@ impl Client {
             queue,
             config,
             size,
-            render_pipeline,
-            vertex_buffer,
-            num_vertices,
+            renderer,
+            camera,
+            pixel_ratio: 1.0,
+            editor: Editor::new(
+                History::new(&app_config.history),
+                ToolState::new(id_prefix()),
+            ),
+            presence: BTreeMap::new(),
+            overlay_dirty: false,
+            visible: Vec::new(),
+            clear_color: CLEAR_COLOR,
+            text: Typesetter::new(),
         })
     }
 
+    /// Replaces the whole scene with a `WhiteboardDoc` serialised as JSON
+    #[wasm_bindgen(js_name = "setDoc")]
+    pub fn set_doc(&mut self, doc_json: &str) -> Result<(), JsValue> {
+        let doc = WhiteboardDoc::from_json(doc_json)?;
+        self.editor.set_doc(doc, &self.text);
+        self.overlay_dirty = true;
+        self.renderer.buffers.clear();
+        for shape in self.editor.doc.ordered_shapes() {
+            self.renderer
+                .buffers
+                .upsert(&self.device, &self.queue, shape, &mut self.text);
+        }
+        Ok(())
+    }
+
+    /// Adds a shape on top, or replaces the shape with the same id in place
+    #[wasm_bindgen(js_name = "upsertShape")]
+    pub fn upsert_shape(&mut self, shape_json: &str) -> Result<(), JsValue> {
+        let shape: Shape = serde_json::from_str(shape_json)
+            .map_err(|e| CanvasError::InvalidDocument(e.to_string()))?;
+        self.renderer
+            .buffers
+            .upsert(&self.device, &self.queue, &shape, &mut self.text);
+        self.editor.index.insert(&shape, &self.text);
+        self.editor.doc.add_shape(shape);
+        self.overlay_dirty = true;
+        Ok(())
+    }
+
+    /// Adds a TrueType or OpenType font, such as the bytes of a fetched
+    /// `.ttf`, behind those already loaded so it draws only the characters
+    /// they lack. Load a Thai-capable font for Thai text.
+    #[wasm_bindgen(js_name = "loadFont")]
+    pub fn load_font(&mut self, bytes: Vec<u8>) -> Result<(), JsValue> {
+        self.text.add_font(Font::from_bytes(bytes)?);
+        // text is measured with the new font from now on
+        self.editor.index = SpatialIndex::from_doc(&self.editor.doc, &self.text);
+        self.overlay_dirty = true;
+        let text = self
+            .editor
+            .doc
+            .ordered_shapes()
+            .filter(|s| matches!(s.kind, ShapeKind::Text { .. }));
+        for shape in text {
+            self.renderer
+                .buffers
+                .upsert(&self.device, &self.queue, shape, &mut self.text);
+        }
+        Ok(())
+    }
+
+    #[wasm_bindgen(js_name = "removeShape")]
+    pub fn remove_shape(&mut self, id: &str) {
+        self.editor.doc.remove_shape(id);
+        self.editor.index.remove(id);
+        self.renderer.buffers.remove(id);
+        self.overlay_dirty = true;
+    }
+
+    /// Sets the z-order, bottom first; unknown ids are ignored
+    pub fn reorder(&mut self, ids: Vec<ShapeId>) {
+        self.editor.doc.reorder(&ids);
+        self.editor.index.set_order(&self.editor.doc.order);
+        self.renderer.buffers.invalidate_order();
+    }
+
+    /// Applies an undoable edit given as JSON, e.g.
+    /// `{"op":"patch","id":"a","key":"points","value":[...]}`, and returns the
+    /// resulting change set as JSON. `upsertShape`, `removeShape` and
+    /// `reorder` stay outside the history, for changes made elsewhere.
+    pub fn edit(&mut self, edit_json: &str) -> Result<String, JsValue> {
+        let edit: Edit = serde_json::from_str(edit_json)
+            .map_err(|e| CanvasError::InvalidDocument(e.to_string()))?;
+        let changes = self.editor.history.apply(&mut self.editor.doc, edit)?;
+        self.apply_changes(&changes)
+    }
+
+    /// Groups the following edits into one undo step until
+    /// `commitTransaction`; consecutive transactions with the same
+    /// `mergeKey` also undo together
+    #[wasm_bindgen(js_name = "beginTransaction")]
+    pub fn begin_transaction(&mut self, merge_key: Option<String>) {
+        self.editor.history.begin(merge_key);
+    }
+
+    #[wasm_bindgen(js_name = "commitTransaction")]
+    pub fn commit_transaction(&mut self) {
+        self.editor.history.commit();
+    }
+
+    /// Reverts the last undo step and returns the change set as JSON, or
+    /// `undefined` when there is nothing to undo. A gesture in progress is
+    /// finished first, so its step is the one reverted.
+    pub fn undo(&mut self) -> Result<Option<String>, JsValue> {
+        self.end_gesture()?;
+        match self.editor.history.undo(&mut self.editor.doc)? {
+            Some(changes) => Ok(Some(self.apply_changes(&changes)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn redo(&mut self) -> Result<Option<String>, JsValue> {
+        self.end_gesture()?;
+        match self.editor.history.redo(&mut self.editor.doc)? {
+            Some(changes) => Ok(Some(self.apply_changes(&changes)?)),
+            None => Ok(None),
+        }
+    }
+
+    #[wasm_bindgen(js_name = "canUndo")]
+    pub fn can_undo(&self) -> bool {
+        self.editor.history.can_undo()
+    }
+
+    #[wasm_bindgen(js_name = "canRedo")]
+    pub fn can_redo(&self) -> bool {
+        self.editor.history.can_redo()
+    }
+
+    /// Finishes the gesture in progress, if any, as `pointerUp` would
+    fn end_gesture(&mut self) -> Result<(), JsValue> {
+        let out = self
+            .editor
+            .end_gesture(&self.text, self.camera, Pointer::default())?;
+        self.apply_outcome(&out).map(|_| ())
+    }
+
+    /// Brings the spatial index and the GPU buffers in line with a change
+    /// the history already made to the doc, returning it as JSON
+    fn apply_changes(&mut self, changes: &ChangeSet) -> Result<String, JsValue> {
+        self.editor.follow(changes, &self.text);
+        self.upload(changes);
+        serde_json::to_string(changes)
+            .map_err(|e| CanvasError::InvalidDocument(e.to_string()).into())
+    }
+
+    /// Brings the GPU buffers in line with a change the index already follows
+    fn upload(&mut self, changes: &ChangeSet) {
+        for id in &changes.removed {
+            self.renderer.buffers.remove(id);
+        }
+        for shape in &changes.upserted {
+            self.renderer
+                .buffers
+                .upsert(&self.device, &self.queue, shape, &mut self.text);
+        }
+        if changes.order.is_some() {
+            self.renderer.buffers.invalidate_order();
+        }
+        self.overlay_dirty = true;
+    }
+
+    /// Uploads what a pointer event changed, moves the camera as the tools
+    /// asked and returns the tools' actions as JSON
+    fn apply_outcome(&mut self, out: &Outcome) -> Result<String, JsValue> {
+        for changes in &out.changes {
+            self.upload(changes);
+        }
+        for action in &out.actions {
+            match action {
+                ToolAction::Pan { dx, dy } => self.camera.pan_by(*dx, *dy),
+                ToolAction::Zoom { x, y, factor } => {
+                    self.camera.zoom_at(Point::new(*x, *y), *factor)
+                }
+                ToolAction::Edit { .. } | ToolAction::Select { .. } => continue,
+            }
+            self.overlay_dirty = true;
+            self.write_camera();
+        }
+        tool_actions_json(&out.actions)
+    }
+
+    /// Picks the tool by its `ToolId`, such as `"pencil"`, first finishing
+    /// the gesture in progress as `pointerUp` would and returning its
+    /// actions as JSON
+    #[wasm_bindgen(js_name = "setTool")]
+    pub fn set_tool(&mut self, tool: &str) -> Result<String, JsValue> {
+        let tool = tool.parse()?;
+        let out = self.editor.set_tool(&self.text, self.camera, tool)?;
+        self.apply_outcome(&out)
+    }
+
+    /// Sets the style of new shapes from the store's `UiSettings` as JSON
+    #[wasm_bindgen(js_name = "setToolSettings")]
+    pub fn set_tool_settings(&mut self, settings_json: &str) -> Result<(), JsValue> {
+        let settings: ToolSettings =
+            serde_json::from_str(settings_json).map_err(|e| CanvasError::Config(e.to_string()))?;
+        self.editor.tools.set_settings(settings);
+        Ok(())
+    }
+
+    /// Ids the tools have selected, bottom first
+    pub fn selection(&self) -> Vec<ShapeId> {
+        self.editor.tools.selection().to_vec()
+    }
+
+    /// Replaces the selection, first finishing the gesture in progress as
+    /// `pointerUp` would and returning its actions as JSON
+    #[wasm_bindgen(js_name = "setSelection")]
+    pub fn set_selection(&mut self, ids: Vec<ShapeId>) -> Result<String, JsValue> {
+        let out = self.editor.set_selection(&self.text, self.camera, ids)?;
+        self.apply_outcome(&out)
+    }
+
+    /// Feeds a pointer down at screen pixel (`x`, `y`) to the current tool.
+    /// `buttons` is `PointerEvent.buttons` and `modifiers` sums shift 1,
+    /// ctrl 2, alt 4 and meta 8. `pressure` is `PointerEvent.pressure` for
+    /// pens only, leaving mouse and touch strokes an even width, and `time`
+    /// is `PointerEvent.timeStamp`. Document edits and camera moves are
+    /// applied here; all actions come back as JSON so the store can follow
+    /// along. Everything up to the next `pointerUp` undoes as one step.
+    #[wasm_bindgen(js_name = "pointerDown")]
+    pub fn pointer_down(
+        &mut self,
+        x: f64,
+        y: f64,
+        buttons: u16,
+        modifiers: u8,
+        pressure: Option<f64>,
+        time: Option<f64>,
+    ) -> Result<String, JsValue> {
+        let pointer = pointer(x, y, buttons, modifiers, pressure, time);
+        let out = self.editor.pointer_down(&self.text, self.camera, pointer)?;
+        self.apply_outcome(&out)
+    }
+
+    #[wasm_bindgen(js_name = "pointerMove")]
+    pub fn pointer_move(
+        &mut self,
+        x: f64,
+        y: f64,
+        buttons: u16,
+        modifiers: u8,
+        pressure: Option<f64>,
+        time: Option<f64>,
+    ) -> Result<String, JsValue> {
+        let pointer = pointer(x, y, buttons, modifiers, pressure, time);
+        let out = self.editor.pointer_move(&self.text, self.camera, pointer)?;
+        if let Some(shape) = &out.preview {
+            self.renderer
+                .buffers
+                .upsert(&self.device, &self.queue, shape, &mut self.text);
+        }
+        self.apply_outcome(&out)
+    }
+
+    #[wasm_bindgen(js_name = "pointerUp")]
+    pub fn pointer_up(
+        &mut self,
+        x: f64,
+        y: f64,
+        buttons: u16,
+        modifiers: u8,
+    ) -> Result<String, JsValue> {
+        let pointer = pointer(x, y, buttons, modifiers, None, None);
+        let out = self.editor.end_gesture(&self.text, self.camera, pointer)?;
+        self.apply_outcome(&out)
+    }
+
+    /// Shows or updates a collaborator from an `encodePresence` message
+    #[wasm_bindgen(js_name = "setPresence")]
+    pub fn set_presence(&mut self, bytes: &[u8]) -> Result<(), JsValue> {
+        let presence = presence::decode(bytes)?;
+        self.presence.insert(presence.user_id, presence);
+        self.overlay_dirty = true;
+        Ok(())
+    }
+
+    /// Hides a collaborator who left. `user_id` is the full peer id, a
+    /// `BigInt` in JavaScript, so ids past 2³² match what `setPresence` got.
+    #[wasm_bindgen(js_name = "removePresence")]
+    pub fn remove_presence(&mut self, user_id: PeerId) {
+        self.presence.remove(&user_id);
+        self.overlay_dirty = true;
+    }
+
+    #[wasm_bindgen(js_name = "clearPresence")]
+    pub fn clear_presence(&mut self) {
+        self.presence.clear();
+        self.overlay_dirty = true;
+    }
+
+    /// Topmost shape within `tolerance` world units (6 by default) of the
+    /// world point (`x`, `y`), like `hitTestTop` in geometry.ts
+    #[wasm_bindgen(js_name = "hitTest")]
+    pub fn hit_test(&self, x: f64, y: f64, tolerance: Option<f64>) -> Option<ShapeId> {
+        let tolerance = tolerance.unwrap_or(spatial::DEFAULT_TOLERANCE);
+        self.editor
+            .index
+            .hit_test(&self.editor.doc, Point::new(x, y), tolerance, &self.text)
+    }
+
+    /// Shapes whose bounds touch the world rect, bottom first
+    #[wasm_bindgen(js_name = "queryRect")]
+    pub fn query_rect(&self, x: f64, y: f64, w: f64, h: f64) -> Vec<ShapeId> {
+        self.editor.index.query_rect(Rect::new(x, y, w, h))
+    }
+
+    /// Shape whose bounds are closest to the world point (`x`, `y`)
+    pub fn nearest(&self, x: f64, y: f64) -> Option<ShapeId> {
+        self.editor.index.nearest(Point::new(x, y))
+    }
+
+    /// Shapes picked by a marquee over the world rect, bottom first: those
+    /// painted entirely inside it when `contained`, else any it touches
+    #[wasm_bindgen(js_name = "selectRect")]
+    pub fn select_rect(&self, x: f64, y: f64, w: f64, h: f64, contained: bool) -> Vec<ShapeId> {
+        let rect = Rect::new(x, y, w, h);
+        selection::marquee(
+            &self.editor.index,
+            &self.editor.doc,
+            rect,
+            mode(contained),
+            &self.text,
+        )
+    }
+
+    /// Shapes picked by a lasso through the world points `[x0, y0, x1, y1,
+    /// ..]`, bottom first, with the same `contained` rule as `selectRect`
+    #[wasm_bindgen(js_name = "selectLasso")]
+    pub fn select_lasso(&self, points: &[f64], contained: bool) -> Vec<ShapeId> {
+        let points: Vec<Point> = points
+            .chunks_exact(2)
+            .map(|p| Point::new(p[0], p[1]))
+            .collect();
+        selection::lasso(
+            &self.editor.index,
+            &self.editor.doc,
+            &points,
+            mode(contained),
+            &self.text,
+        )
+    }
+
+    /// Sets the pan offset (screen pixels) and zoom, as in the store's `camera`
+    #[wasm_bindgen(js_name = "setCamera")]
+    pub fn set_camera(&mut self, x: f64, y: f64, zoom: f64) {
+        if zoom > 0.0 {
+            self.camera = Camera { x, y, zoom };
+            self.overlay_dirty = true;
+            self.write_camera();
+        }
+    }
+
+    /// Sets `devicePixelRatio`, the number of canvas pixels per screen pixel
+    #[wasm_bindgen(js_name = "setPixelRatio")]
+    pub fn set_pixel_ratio(&mut self, ratio: f64) {
+        if ratio > 0.0 {
+            self.pixel_ratio = ratio;
+            self.overlay_dirty = true;
+            self.write_camera();
+        }
+    }
+
+    /// Sets the clear colour from a CSS colour string such as `#ffffff`
+    #[wasm_bindgen(js_name = "setBackground")]
+    pub fn set_background(&mut self, css: &str) -> Result<(), JsValue> {
+        let c = Rgba::parse(css)
+            .ok_or_else(|| CanvasError::InvalidDocument(format!("unsupported colour {css}")))?
+            .to_linear();
+        self.clear_color = wgpu::Color {
+            r: c.r as f64,
+            g: c.g as f64,
+            b: c.b as f64,
+            a: c.a as f64,
+        };
+        Ok(())
+    }
+
     /// Resizes the canvas
     #[wasm_bindgen]
     pub fn resize(&mut self, width: u32, height: u32) {
@@ -63,9 +486,67 @@ impl Client {
             self.config.width = width;
             self.config.height = height;
             self.surface.configure(&self.device, &self.config);
+            self.renderer.resize(&self.device, self.size);
+            self.write_camera();
         }
     }
 
+    fn write_camera(&self) {
+        let uniform = CameraUniform::new(&self.camera, self.size, self.pixel_ratio);
+        self.renderer.write_camera(&self.queue, &uniform);
+    }
+
+    /// Renders `bounds` (JSON `{ x, y, w, h }` in world units, by default the
+    /// content plus a margin) at `scale` image pixels per world unit and
+    /// resolves to PNG bytes. `background` is a CSS colour; without one the
+    /// image is transparent.
+    #[wasm_bindgen(js_name = "exportPng")]
+    pub async fn export_png(
+        &mut self,
+        bounds: Option<String>,
+        scale: f64,
+        background: Option<String>,
+    ) -> Result<Vec<u8>, JsValue> {
+        let bounds = bounds
+            .map(|json| serde_json::from_str::<Rect>(&json))
+            .transpose()
+            .map_err(|e| CanvasError::Export(format!("invalid bounds: {e}")))?;
+        let clear = png::background(background.as_deref())?;
+        let max_dimension = self.device.limits().max_texture_dimension_2d;
+        let region =
+            ExportRegion::for_doc(&self.editor.doc, &self.text, bounds, scale, max_dimension)?;
+
+        let buffer = self.renderer.render_offscreen(
+            &self.device,
+            &self.queue,
+            region.size,
+            &CameraUniform::new(&region.camera, region.size, 1.0),
+            wgpu::Color {
+                r: clear.r as f64,
+                g: clear.g as f64,
+                b: clear.b as f64,
+                a: clear.a as f64,
+            },
+            &self.editor.doc.order,
+        );
+        self.write_camera();
+        let mut pixels =
+            readback::read(&self.device, &buffer, region.size.width, region.size.height).await?;
+        readback::to_rgba(self.renderer.format(), &mut pixels);
+        Ok(png::encode_rendered(region.size, pixels)?)
+    }
+
+    /// Serialises a `Board` given as JSON to PDF bytes, with text set in
+    /// subsets of the loaded fonts. Call the store's `saveActivePageSnapshot`
+    /// first so the active page is up to date.
+    #[wasm_bindgen(js_name = "exportPdf")]
+    pub fn export_pdf(&self, board_json: &str) -> Result<Vec<u8>, JsValue> {
+        Ok(pdf::to_pdf(
+            &Board::from_json(board_json)?,
+            self.text.fonts(),
+        ))
+    }
+
     /// Renders a frame
     pub fn draw(&mut self) {
         let output = match self.surface.get_current_texture() {
@@ -86,30 +567,72 @@ impl Client {
                 label: Some("Render Encoder"),
             });
 
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    depth_slice: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(CLEAR_COLOR),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: None,
-                timestamp_writes: None,
-                occlusion_query_set: None,
-                multiview_mask: None,
-            });
-
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.draw(0..self.num_vertices, 0..1);
+        // only shapes touching the viewport are drawn
+        let visible = self.editor.index.query_rect(camera::visible_world_rect(
+            &self.camera,
+            self.size,
+            self.pixel_ratio,
+        ));
+        if visible != self.visible {
+            self.visible = visible;
+            self.renderer.buffers.invalidate_order();
         }
+        if self.overlay_dirty || self.renderer.buffers.overlay_stale(&self.text) {
+            let uniform = CameraUniform::new(&self.camera, self.size, self.pixel_ratio);
+            overlay::upload(
+                &mut self.renderer.buffers,
+                &self.device,
+                &self.queue,
+                self.presence.values(),
+                &self.editor.doc,
+                &mut self.text,
+                uniform.world_per_pixel,
+            );
+            self.overlay_dirty = false;
+        }
+        self.renderer
+            .encode(&mut encoder, &view, self.clear_color, &self.visible);
 
         self.queue.submit(std::iter::once(encoder.finish()));
         output.present();
     }
 }
+
+fn mode(contained: bool) -> SelectionMode {
+    if contained {
+        SelectionMode::Contained
+    } else {
+        SelectionMode::Intersecting
+    }
+}
+
+fn pointer(
+    x: f64,
+    y: f64,
+    buttons: u16,
+    modifiers: u8,
+    pressure: Option<f64>,
+    time: Option<f64>,
+) -> Pointer {
+    Pointer {
+        x,
+        y,
+        buttons,
+        modifiers: Modifiers::from_bits(modifiers),
+        pressure,
+        time,
+    }
+}
+
+fn tool_actions_json(actions: &[ToolAction]) -> Result<String, JsValue> {
+    serde_json::to_string(actions).map_err(|e| CanvasError::InvalidDocument(e.to_string()).into())
+}
+
+/// Random prefix for the ids of shapes drawn here, so they cannot collide
+/// with shapes drawn by collaborators
+fn id_prefix() -> String {
+    format!(
+        "{:012x}",
+        (js_sys::Math::random() * (1u64 << 48) as f64) as u64
+    )
+}
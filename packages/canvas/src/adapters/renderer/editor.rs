@@ -0,0 +1,316 @@
+//! The document side of `Client`: the doc, its spatial index, the undo
+//! history and the tools, kept in step with each other. It knows nothing of
+//! the GPU, so the client's pointer handling runs on the host too; the
+//! client only uploads what the returned [`Outcome`]s name.
+
+use crate::error::CanvasError;
+use crate::history::{ChangeSet, History};
+use crate::model::{Camera, Shape, ShapeId, WhiteboardDoc};
+use crate::spatial::SpatialIndex;
+use crate::text::Typesetter;
+use crate::tools::{Pointer, Tool, ToolAction, ToolContext, ToolState};
+
+#[derive(Debug)]
+pub(crate) struct Editor {
+    pub(crate) doc: WhiteboardDoc,
+    pub(crate) index: SpatialIndex,
+    pub(crate) history: History,
+    pub(crate) tools: ToolState,
+    /// Whether a gesture holds an undo transaction open, which its end
+    /// commits
+    in_gesture: bool,
+}
+
+/// What a pointer event did
+#[derive(Debug, Default)]
+pub(crate) struct Outcome {
+    /// Everything the tools asked for, for the store to follow along; camera
+    /// moves are left to the caller
+    pub(crate) actions: Vec<ToolAction>,
+    /// What the edits among `actions` changed, in order. The index already
+    /// follows them.
+    pub(crate) changes: Vec<ChangeSet>,
+    /// The pencil stroke being drawn, to show in place of the doc's until
+    /// its points reach the doc on pointer up. The index already follows it.
+    pub(crate) preview: Option<Shape>,
+}
+
+impl Editor {
+    pub(crate) fn new(history: History, tools: ToolState) -> Self {
+        Self {
+            doc: WhiteboardDoc::default(),
+            index: SpatialIndex::new(),
+            history,
+            tools,
+            in_gesture: false,
+        }
+    }
+
+    /// Ends any gesture and starts the one the press begins. Its edits undo
+    /// as one step once it ends; a press that starts none, such as a text
+    /// click, is a step of its own.
+    pub(crate) fn pointer_down(
+        &mut self,
+        text: &Typesetter,
+        camera: Camera,
+        pointer: Pointer,
+    ) -> Result<Outcome, CanvasError> {
+        let mut out = self.end_gesture(text, camera, pointer)?;
+        self.history.begin(None);
+        let ctx = ToolContext {
+            doc: &self.doc,
+            index: &self.index,
+            text,
+            camera,
+        };
+        let actions = self.tools.pointer_down(&ctx, pointer);
+        let applied = self.apply(&actions, text, &mut out);
+        out.actions.extend(actions);
+        if self.tools.is_active() {
+            self.in_gesture = true;
+        } else {
+            self.history.commit();
+        }
+        applied.map(|_| out)
+    }
+
+    pub(crate) fn pointer_move(
+        &mut self,
+        text: &Typesetter,
+        camera: Camera,
+        pointer: Pointer,
+    ) -> Result<Outcome, CanvasError> {
+        let ctx = ToolContext {
+            doc: &self.doc,
+            index: &self.index,
+            text,
+            camera,
+        };
+        let actions = self.tools.pointer_move(&ctx, pointer);
+        let mut out = Outcome::default();
+        self.apply(&actions, text, &mut out)?;
+        out.actions = actions;
+        out.preview = self.tools.ink_preview(&self.doc);
+        if let Some(shape) = &out.preview {
+            self.index.insert(shape, text);
+        }
+        Ok(out)
+    }
+
+    /// Finishes the gesture in progress, if any, and closes its undo step
+    pub(crate) fn end_gesture(
+        &mut self,
+        text: &Typesetter,
+        camera: Camera,
+        pointer: Pointer,
+    ) -> Result<Outcome, CanvasError> {
+        let mut out = Outcome::default();
+        if !self.in_gesture {
+            return Ok(out);
+        }
+        let ctx = ToolContext {
+            doc: &self.doc,
+            index: &self.index,
+            text,
+            camera,
+        };
+        let actions = self.tools.pointer_up(&ctx, pointer);
+        let applied = self.apply(&actions, text, &mut out);
+        out.actions = actions;
+        self.in_gesture = false;
+        self.history.commit();
+        applied.map(|_| out)
+    }
+
+    /// Finishes the gesture in progress, as a pointer up would, before
+    /// switching tools
+    pub(crate) fn set_tool(
+        &mut self,
+        text: &Typesetter,
+        camera: Camera,
+        tool: Tool,
+    ) -> Result<Outcome, CanvasError> {
+        let out = self.end_gesture(text, camera, Pointer::default());
+        self.tools.set_tool(tool);
+        out
+    }
+
+    /// Finishes the gesture in progress before replacing the selection
+    pub(crate) fn set_selection(
+        &mut self,
+        text: &Typesetter,
+        camera: Camera,
+        ids: Vec<ShapeId>,
+    ) -> Result<Outcome, CanvasError> {
+        let out = self.end_gesture(text, camera, Pointer::default());
+        self.tools.set_selection(ids);
+        out
+    }
+
+    /// Replaces the doc wholesale, dropping the gesture in progress along
+    /// with the history
+    pub(crate) fn set_doc(&mut self, doc: WhiteboardDoc, text: &Typesetter) {
+        self.tools.cancel();
+        self.in_gesture = false;
+        self.history.clear();
+        self.index = SpatialIndex::from_doc(&doc, text);
+        self.doc = doc;
+    }
+
+    /// Brings the index in line with a change the history made to the doc
+    pub(crate) fn follow(&mut self, changes: &ChangeSet, text: &Typesetter) {
+        for id in &changes.removed {
+            self.index.remove(id);
+        }
+        for shape in &changes.upserted {
+            self.index.insert(shape, text);
+        }
+        if changes.order.is_some() {
+            self.index.set_order(&self.doc.order);
+        }
+    }
+
+    fn apply(
+        &mut self,
+        actions: &[ToolAction],
+        text: &Typesetter,
+        out: &mut Outcome,
+    ) -> Result<(), CanvasError> {
+        for action in actions {
+            if let ToolAction::Edit { edit } = action {
+                let changes = self.history.apply(&mut self.doc, edit.clone())?;
+                self.follow(&changes, text);
+                out.changes.push(changes);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::PRIMARY_BUTTON;
+
+    fn editor(tool: Tool) -> Editor {
+        let mut tools = ToolState::new("t");
+        tools.set_tool(tool);
+        Editor::new(History::default(), tools)
+    }
+
+    fn press(x: f64, y: f64) -> Pointer {
+        Pointer {
+            x,
+            y,
+            buttons: PRIMARY_BUTTON,
+            ..Pointer::default()
+        }
+    }
+
+    fn drag(editor: &mut Editor, from: (f64, f64), to: (f64, f64)) {
+        let (text, camera) = (Typesetter::new(), Camera::default());
+        editor
+            .pointer_down(&text, camera, press(from.0, from.1))
+            .unwrap();
+        editor
+            .pointer_move(&text, camera, press(to.0, to.1))
+            .unwrap();
+        editor
+            .end_gesture(&text, camera, Pointer::default())
+            .unwrap();
+    }
+
+    #[test]
+    fn presses_without_a_gesture_do_not_hold_the_undo_step_open() {
+        let (text, camera) = (Typesetter::new(), Camera::default());
+        let mut editor = editor(Tool::Text);
+        editor
+            .pointer_down(&text, camera, press(40.0, 40.0))
+            .unwrap();
+        assert!(!editor.tools.is_active());
+        editor
+            .end_gesture(&text, camera, Pointer::default())
+            .unwrap();
+
+        editor.tools.set_tool(Tool::Rectangle);
+        drag(&mut editor, (0.0, 0.0), (20.0, 20.0));
+        drag(&mut editor, (100.0, 0.0), (120.0, 20.0));
+        assert_eq!(editor.history.len(), 3);
+
+        let changes = editor.history.undo(&mut editor.doc).unwrap().unwrap();
+        editor.follow(&changes, &text);
+        assert_eq!(changes.removed, ["t-3"]);
+        assert_eq!(editor.doc.order, ["t-1", "t-2"]);
+        assert_eq!(editor.index.len(), 2);
+    }
+
+    #[test]
+    fn switching_tools_finishes_the_gesture() {
+        let (text, camera) = (Typesetter::new(), Camera::default());
+        let mut editor = editor(Tool::Rectangle);
+        editor.pointer_down(&text, camera, press(0.0, 0.0)).unwrap();
+        editor
+            .pointer_move(&text, camera, press(20.0, 20.0))
+            .unwrap();
+        editor.set_tool(&text, camera, Tool::Ellipse).unwrap();
+        assert!(!editor.tools.is_active());
+        assert_eq!(editor.history.len(), 1);
+
+        drag(&mut editor, (100.0, 0.0), (120.0, 20.0));
+        assert_eq!(editor.history.len(), 2);
+        let changes = editor.history.undo(&mut editor.doc).unwrap().unwrap();
+        assert_eq!(changes.removed, ["t-2"]);
+        assert_eq!(editor.doc.order, ["t-1"]);
+    }
+
+    /// Draws most of a pencil stroke, leaving the pointer down
+    fn ink(editor: &mut Editor) -> Outcome {
+        let (text, camera) = (Typesetter::new(), Camera::default());
+        editor.pointer_down(&text, camera, press(0.0, 0.0)).unwrap();
+        let mut last = Outcome::default();
+        for i in 1..=20 {
+            let x = i as f64 * 5.0;
+            last = editor
+                .pointer_move(&text, camera, press(x, x / 2.0))
+                .unwrap();
+        }
+        last
+    }
+
+    fn indexed_like_the_doc(editor: &Editor, id: &str) -> bool {
+        let fresh = SpatialIndex::from_doc(&editor.doc, &Typesetter::new());
+        editor.index.bounds(id) == fresh.bounds(id)
+    }
+
+    #[test]
+    fn ink_previews_do_not_outlive_their_stroke() {
+        let (text, camera) = (Typesetter::new(), Camera::default());
+        let mut editor = editor(Tool::Pencil);
+        let out = ink(&mut editor);
+        let preview = out.preview.expect("the stroke is previewed");
+        let mut previewed = editor.doc.clone();
+        previewed.add_shape(preview);
+        let expected = SpatialIndex::from_doc(&previewed, &text).bounds("t-1");
+        assert_eq!(editor.index.bounds("t-1"), expected);
+        assert!(!indexed_like_the_doc(&editor, "t-1"));
+
+        // the selection changing under the stroke finishes it
+        let out = editor.set_selection(&text, camera, Vec::new()).unwrap();
+        assert_eq!(out.changes.len(), 1);
+        assert_eq!(out.changes[0].upserted[0], *editor.doc.get("t-1").unwrap());
+        assert!(indexed_like_the_doc(&editor, "t-1"));
+        assert!(!editor.tools.is_active());
+        assert_eq!(editor.history.len(), 1);
+
+        // a new doc drops it, and with it the preview in the index
+        ink(&mut editor);
+        editor.set_doc(WhiteboardDoc::default(), &text);
+        assert!(editor.index.is_empty());
+        let out = editor
+            .pointer_move(&text, camera, press(200.0, 0.0))
+            .unwrap();
+        assert!(out.preview.is_none() && out.actions.is_empty());
+        drag(&mut editor, (0.0, 0.0), (10.0, 10.0));
+        assert_eq!(editor.history.len(), 1);
+    }
+}
@@ -0,0 +1,765 @@
+use std::collections::BTreeMap;
+
+use crate::adapters::renderer::camera::{self, CameraUniform};
+use crate::adapters::renderer::overlay;
+use crate::adapters::renderer::raster::Raster;
+use crate::adapters::renderer::scene::SceneRenderer;
+use crate::adapters::renderer::tessellate::{self, Mesh};
+use crate::adapters::renderer::vertex::TextVertex;
+use crate::adapters::renderer::{readback, wgpu_setup};
+use crate::config::app_config::{AppConfig, WebGpuConfig};
+use crate::constants::colors::CLEAR_COLOR;
+use crate::crdt::PeerId;
+use crate::error::CanvasError;
+use crate::export::png::{self, ExportRegion};
+use crate::model::color::Rgba;
+use crate::model::{Camera, Rect, Shape, ShapeId, ShapeKind, WhiteboardDoc};
+use crate::presence::Presence;
+use crate::spatial::SpatialIndex;
+use crate::text::font::Font;
+use crate::text::Typesetter;
+use crate::types::Size;
+
+/// Same bytes as the sRGB surface the browser client renders to
+const TARGET_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// Largest side of a CPU-rendered export, matching the default GPU limit
+const MAX_CPU_EXPORT_DIMENSION: u32 = 8192;
+
+/// Renders the same scene as `Client` into an offscreen target and hands back
+/// RGBA8 pixels, for tests and server-side thumbnails. Any adapter will do,
+/// including software ones such as llvmpipe or lavapipe; without one the
+/// meshes are rasterised on the CPU instead.
+#[derive(Debug)]
+pub struct HeadlessClient {
+    backend: Backend,
+    size: Size,
+    sample_count: u32,
+    camera: Camera,
+    pixel_ratio: f64,
+    doc: WhiteboardDoc,
+    index: SpatialIndex,
+    /// Collaborators by user id
+    presence: BTreeMap<PeerId, Presence>,
+    /// Shapes drawn by the last GPU frame, bottom first
+    visible: Vec<ShapeId>,
+    clear_color: wgpu::Color,
+    /// Fonts and glyph atlas for text shapes; text is left out until a font
+    /// is loaded
+    text: Typesetter,
+}
+
+#[derive(Debug)]
+enum Backend {
+    Gpu(Box<GpuTarget>),
+    Cpu,
+}
+
+#[derive(Debug)]
+struct GpuTarget {
+    adapter_name: String,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    renderer: SceneRenderer,
+}
+
+impl GpuTarget {
+    fn new(size: Size, webgpu: &WebGpuConfig) -> Result<Self, CanvasError> {
+        let instance = wgpu_setup::create_instance();
+        let adapter = pollster::block_on(wgpu_setup::request_adapter(&instance, None, webgpu))?;
+        wgpu_setup::check_sample_count(&adapter, TARGET_FORMAT, webgpu.sample_count)?;
+        let (device, queue) = pollster::block_on(wgpu_setup::request_device(&adapter, webgpu))?;
+
+        let (texture, view) = create_target(&device, size);
+        let renderer = SceneRenderer::new(
+            &device,
+            TARGET_FORMAT,
+            size,
+            webgpu.sample_count,
+            &CameraUniform::new(&Camera::default(), size, 1.0),
+        );
+        Ok(Self {
+            adapter_name: adapter.get_info().name,
+            device,
+            queue,
+            texture,
+            view,
+            renderer,
+        })
+    }
+}
+
+fn create_target(device: &wgpu::Device, size: Size) -> (wgpu::Texture, wgpu::TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Headless Target"),
+        size: wgpu::Extent3d {
+            width: size.width,
+            height: size.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: TARGET_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (texture, view)
+}
+
+impl HeadlessClient {
+    /// Uses the configuration [`AppConfig::load`] finds
+    pub fn new(width: u32, height: u32) -> Result<Self, CanvasError> {
+        Self::with_config(width, height, &AppConfig::load()?)
+    }
+
+    /// Renders on the adapter `config` asks for, falling back to the CPU
+    /// only when no adapter or device can be had
+    pub fn with_config(width: u32, height: u32, config: &AppConfig) -> Result<Self, CanvasError> {
+        config.webgpu.validate()?;
+        let size = clamp_size(width, height);
+        let backend = match GpuTarget::new(size, &config.webgpu) {
+            Ok(gpu) => Backend::Gpu(Box::new(gpu)),
+            Err(e @ (CanvasError::AdapterRequest(_) | CanvasError::DeviceRequest(_))) => {
+                tracing::warn!("{e}, rendering on the CPU");
+                Backend::Cpu
+            }
+            Err(e) => return Err(e),
+        };
+        Ok(Self::with_backend(
+            backend,
+            size,
+            config.webgpu.sample_count,
+        ))
+    }
+
+    /// Always rasterises on the CPU; `sample_count` is 1 or 4 as in the config
+    pub fn cpu(width: u32, height: u32, sample_count: u32) -> Result<Self, CanvasError> {
+        let mut config = AppConfig::default();
+        config.webgpu.sample_count = sample_count;
+        config.webgpu.validate()?;
+        Ok(Self::with_backend(
+            Backend::Cpu,
+            clamp_size(width, height),
+            sample_count,
+        ))
+    }
+
+    fn with_backend(backend: Backend, size: Size, sample_count: u32) -> Self {
+        Self {
+            backend,
+            size,
+            sample_count,
+            camera: Camera::default(),
+            pixel_ratio: 1.0,
+            doc: WhiteboardDoc::default(),
+            index: SpatialIndex::new(),
+            presence: BTreeMap::new(),
+            visible: Vec::new(),
+            clear_color: CLEAR_COLOR,
+            text: Typesetter::new(),
+        }
+    }
+
+    /// Name of the adapter in use, or `None` when rendering on the CPU
+    pub fn adapter_name(&self) -> Option<&str> {
+        match &self.backend {
+            Backend::Gpu(gpu) => Some(&gpu.adapter_name),
+            Backend::Cpu => None,
+        }
+    }
+
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    pub fn doc(&self) -> &WhiteboardDoc {
+        &self.doc
+    }
+
+    /// The index over the document's shapes, for hit testing
+    pub fn index(&self) -> &SpatialIndex {
+        &self.index
+    }
+
+    /// The loaded fonts, which measure text for the index
+    pub fn text(&self) -> &Typesetter {
+        &self.text
+    }
+
+    pub fn set_doc(&mut self, doc: WhiteboardDoc) {
+        self.doc = doc;
+        self.index = SpatialIndex::from_doc(&self.doc, &self.text);
+        if let Backend::Gpu(gpu) = &mut self.backend {
+            gpu.renderer.buffers.clear();
+            for shape in self.doc.ordered_shapes() {
+                gpu.renderer
+                    .buffers
+                    .upsert(&gpu.device, &gpu.queue, shape, &mut self.text);
+            }
+        }
+    }
+
+    pub fn upsert_shape(&mut self, shape: Shape) {
+        if let Backend::Gpu(gpu) = &mut self.backend {
+            gpu.renderer
+                .buffers
+                .upsert(&gpu.device, &gpu.queue, &shape, &mut self.text);
+        }
+        self.index.insert(&shape, &self.text);
+        self.doc.add_shape(shape);
+    }
+
+    /// Adds a TrueType or OpenType font behind those already loaded, used
+    /// for the characters they lack, and redraws the text shapes with it
+    pub fn load_font(&mut self, bytes: Vec<u8>) -> Result<(), CanvasError> {
+        self.text.add_font(Font::from_bytes(bytes)?);
+        // text is measured with the new font from now on
+        self.index = SpatialIndex::from_doc(&self.doc, &self.text);
+        if let Backend::Gpu(gpu) = &mut self.backend {
+            let text = self
+                .doc
+                .ordered_shapes()
+                .filter(|s| matches!(s.kind, ShapeKind::Text { .. }));
+            for shape in text {
+                gpu.renderer
+                    .buffers
+                    .upsert(&gpu.device, &gpu.queue, shape, &mut self.text);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn remove_shape(&mut self, id: &str) {
+        self.doc.remove_shape(id);
+        self.index.remove(id);
+        if let Backend::Gpu(gpu) = &mut self.backend {
+            gpu.renderer.buffers.remove(id);
+        }
+    }
+
+    pub fn reorder(&mut self, ids: &[ShapeId]) {
+        self.doc.reorder(ids);
+        self.index.set_order(&self.doc.order);
+        if let Backend::Gpu(gpu) = &mut self.backend {
+            gpu.renderer.buffers.invalidate_order();
+        }
+    }
+
+    /// Shows or updates a collaborator's cursor and selection
+    pub fn set_presence(&mut self, presence: Presence) {
+        self.presence.insert(presence.user_id, presence);
+    }
+
+    pub fn remove_presence(&mut self, user_id: PeerId) {
+        self.presence.remove(&user_id);
+    }
+
+    pub fn set_camera(&mut self, camera: Camera) {
+        if camera.zoom > 0.0 {
+            self.camera = camera;
+        }
+    }
+
+    pub fn set_pixel_ratio(&mut self, ratio: f64) {
+        if ratio > 0.0 {
+            self.pixel_ratio = ratio;
+        }
+    }
+
+    /// Sets the clear colour from a CSS colour string such as `#ffffff`
+    pub fn set_background(&mut self, css: &str) -> Result<(), CanvasError> {
+        let c = Rgba::parse(css)
+            .ok_or_else(|| CanvasError::InvalidDocument(format!("unsupported colour {css}")))?
+            .to_linear();
+        self.clear_color = wgpu::Color {
+            r: c.r as f64,
+            g: c.g as f64,
+            b: c.b as f64,
+            a: c.a as f64,
+        };
+        Ok(())
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.size = clamp_size(width, height);
+        if let Backend::Gpu(gpu) = &mut self.backend {
+            let (texture, view) = create_target(&gpu.device, self.size);
+            gpu.texture = texture;
+            gpu.view = view;
+            gpu.renderer.resize(&gpu.device, self.size);
+        }
+    }
+
+    /// Renders a frame and returns it as tightly packed, sRGB-encoded RGBA8
+    /// rows, top row first
+    pub fn render(&mut self) -> Result<Vec<u8>, CanvasError> {
+        let camera = CameraUniform::new(&self.camera, self.size, self.pixel_ratio);
+        // only shapes touching the viewport are drawn, as in `Client::draw`
+        let visible = self.index.query_rect(camera::visible_world_rect(
+            &self.camera,
+            self.size,
+            self.pixel_ratio,
+        ));
+        match &mut self.backend {
+            Backend::Gpu(gpu) => {
+                if visible != self.visible {
+                    self.visible = visible;
+                    gpu.renderer.buffers.invalidate_order();
+                }
+                gpu.renderer.write_camera(&gpu.queue, &camera);
+                overlay::upload(
+                    &mut gpu.renderer.buffers,
+                    &gpu.device,
+                    &gpu.queue,
+                    self.presence.values(),
+                    &self.doc,
+                    &mut self.text,
+                    camera.world_per_pixel,
+                );
+                let mut encoder =
+                    gpu.device
+                        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                            label: Some("Headless Encoder"),
+                        });
+                gpu.renderer
+                    .encode(&mut encoder, &gpu.view, self.clear_color, &self.visible);
+                let buffer =
+                    readback::copy_texture_to_buffer(&gpu.device, &mut encoder, &gpu.texture);
+                gpu.queue.submit(std::iter::once(encoder.finish()));
+                pollster::block_on(readback::read(
+                    &gpu.device,
+                    &buffer,
+                    self.size.width,
+                    self.size.height,
+                ))
+            }
+            Backend::Cpu => {
+                let clear = Rgba::new(
+                    self.clear_color.r as f32,
+                    self.clear_color.g as f32,
+                    self.clear_color.b as f32,
+                    self.clear_color.a as f32,
+                );
+                let raster =
+                    Raster::new(self.size.width, self.size.height, self.sample_count, clear);
+                Ok(rasterize(
+                    raster,
+                    &self.doc,
+                    &visible,
+                    Some(&self.presence),
+                    &mut self.text,
+                    &camera,
+                ))
+            }
+        }
+    }
+
+    /// Renders `bounds` (the content plus [`CONTENT_PADDING`](crate::export::CONTENT_PADDING) when
+    /// `None`) at `scale` image pixels per world unit and encodes it as PNG.
+    /// `background` is a CSS colour; without one the image is transparent.
+    pub fn export_png(
+        &mut self,
+        bounds: Option<Rect>,
+        scale: f64,
+        background: Option<&str>,
+    ) -> Result<Vec<u8>, CanvasError> {
+        let clear = png::background(background)?;
+        let max_dimension = match &self.backend {
+            Backend::Gpu(gpu) => gpu.device.limits().max_texture_dimension_2d,
+            Backend::Cpu => MAX_CPU_EXPORT_DIMENSION,
+        };
+        let region = ExportRegion::for_doc(&self.doc, &self.text, bounds, scale, max_dimension)?;
+        let camera = CameraUniform::new(&region.camera, region.size, 1.0);
+        let pixels = match &mut self.backend {
+            Backend::Gpu(gpu) => {
+                let buffer = gpu.renderer.render_offscreen(
+                    &gpu.device,
+                    &gpu.queue,
+                    region.size,
+                    &camera,
+                    wgpu::Color {
+                        r: clear.r as f64,
+                        g: clear.g as f64,
+                        b: clear.b as f64,
+                        a: clear.a as f64,
+                    },
+                    &self.doc.order,
+                );
+                let mut pixels = pollster::block_on(readback::read(
+                    &gpu.device,
+                    &buffer,
+                    region.size.width,
+                    region.size.height,
+                ))?;
+                readback::to_rgba(gpu.renderer.format(), &mut pixels);
+                pixels
+            }
+            Backend::Cpu => rasterize(
+                Raster::new(
+                    region.size.width,
+                    region.size.height,
+                    self.sample_count,
+                    clear,
+                ),
+                &self.doc,
+                &self.doc.order,
+                None,
+                &mut self.text,
+                &camera,
+            ),
+        };
+        png::encode_rendered(region.size, pixels)
+    }
+}
+
+/// Draws `order` and the overlay of `presence` into the freshly cleared
+/// `raster`
+fn rasterize(
+    mut raster: Raster,
+    doc: &WhiteboardDoc,
+    order: &[ShapeId],
+    presence: Option<&BTreeMap<PeerId, Presence>>,
+    text: &mut Typesetter,
+    camera: &CameraUniform,
+) -> Vec<u8> {
+    for shape in order.iter().filter_map(|id| doc.get(id)) {
+        let mut fill = Mesh::default();
+        let mut stroke = Mesh::default();
+        let mut glyphs: Mesh<TextVertex> = Mesh::default();
+        tessellate::tessellate_shape(shape, &mut fill, &mut stroke);
+        tessellate::tessellate_text(shape, text, &mut glyphs);
+        raster.fill(&fill, camera);
+        raster.stroke(&stroke, camera);
+        raster.text(&glyphs, text.atlas(), camera);
+    }
+    // the overlay goes last, so no shape's text moves its labels' glyphs
+    if let Some(presence) = presence {
+        let mut fill = Mesh::default();
+        let mut stroke = Mesh::default();
+        let mut glyphs = Mesh::default();
+        overlay::tessellate_presence(
+            presence.values(),
+            doc,
+            text,
+            camera.world_per_pixel,
+            &mut fill,
+            &mut stroke,
+            &mut glyphs,
+        );
+        raster.fill(&fill, camera);
+        raster.stroke(&stroke, camera);
+        raster.text(&glyphs, text.atlas(), camera);
+    }
+    raster.to_rgba8()
+}
+
+fn clamp_size(width: u32, height: u32) -> Size {
+    Size {
+        width: width.max(1),
+        height: height.max(1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::golden::{assert_golden, require_adapter};
+    use crate::geometry;
+    use crate::model::Point;
+
+    const DOC_JSON: &str = r##"{
+        "shapes": {
+            "r": { "id": "r", "type": "rectangle", "x": 8, "y": 8, "w": 24, "h": 16,
+                   "stroke": "#111827", "fill": "#ff0000", "strokeWidth": 2 },
+            "l": { "id": "l", "type": "line", "a": { "x": 4, "y": 40 }, "b": { "x": 60, "y": 44 },
+                   "stroke": "#2563eb", "fill": null, "strokeWidth": 3 }
+        },
+        "order": ["r", "l"]
+    }"##;
+
+    fn pixel(pixels: &[u8], width: u32, x: u32, y: u32) -> [u8; 4] {
+        let i = ((y * width + x) * 4) as usize;
+        [pixels[i], pixels[i + 1], pixels[i + 2], pixels[i + 3]]
+    }
+
+    fn render(mut client: HeadlessClient) -> Vec<u8> {
+        client.set_background("#ffffff").unwrap();
+        client.set_doc(WhiteboardDoc::from_json(DOC_JSON).unwrap());
+        client.render().unwrap()
+    }
+
+    #[test]
+    fn cpu_renders_fill_stroke_and_background() {
+        let pixels = render(HeadlessClient::cpu(64, 48, 4).unwrap());
+        assert_eq!(pixels.len(), 64 * 48 * 4);
+        assert_eq!(pixel(&pixels, 64, 2, 2), [255, 255, 255, 255]);
+        assert_eq!(pixel(&pixels, 64, 20, 16), [255, 0, 0, 255]);
+        // outline centred on the rectangle's top edge
+        let outline = pixel(&pixels, 64, 20, 8);
+        assert!(outline[0] < 64 && outline[1] < 64);
+        let line = pixel(&pixels, 64, 32, 42);
+        assert!(line[2] > 200 && line[0] < 100);
+    }
+
+    #[test]
+    fn cpu_draws_remote_cursors_above_the_shapes() {
+        let mut client = HeadlessClient::cpu(64, 48, 4).unwrap();
+        client.set_background("#ffffff").unwrap();
+        client.set_doc(WhiteboardDoc::from_json(DOC_JSON).unwrap());
+        client.set_presence(Presence {
+            user_id: 2,
+            name: "Mo".to_string(),
+            color: "#1971c2".to_string(),
+            cursor: Some(crate::model::Point::new(10.0, 10.0)),
+            selection: vec!["r".to_string()],
+            viewport: None,
+        });
+        let pixels = client.render().unwrap();
+        let inside = pixel(&pixels, 64, 11, 17);
+        assert!(inside[2] > 150 && inside[0] < 60, "{inside:?}");
+        // the selection tint leaves the fill nearly red
+        let tinted = pixel(&pixels, 64, 28, 20);
+        assert!(tinted[0] > 200 && tinted[2] > 0, "{tinted:?}");
+
+        client.remove_presence(2);
+        assert_eq!(
+            client.render().unwrap(),
+            render(HeadlessClient::cpu(64, 48, 4).unwrap())
+        );
+    }
+
+    #[test]
+    fn gpu_matches_cpu() {
+        let Some(client) = gpu_client("gpu_matches_cpu", 64, 48, 4) else {
+            return;
+        };
+        let gpu = render(client);
+        let cpu = render(HeadlessClient::cpu(64, 48, 4).unwrap());
+        let max_diff = gpu
+            .iter()
+            .zip(&cpu)
+            .map(|(a, b)| a.abs_diff(*b))
+            .max()
+            .unwrap();
+        assert!(max_diff <= 2, "GPU and CPU differ by {max_diff}");
+    }
+
+    const TEXT_JSON: &str = r##"{
+        "shapes": {
+            "t": { "id": "t", "type": "text", "x": 10.5, "y": 30, "text": "!ก่ำ", "fontSize": 20,
+                   "stroke": "#000000", "fill": null, "strokeWidth": 1 }
+        },
+        "order": ["t"]
+    }"##;
+
+    fn render_text(mut client: HeadlessClient) -> Vec<u8> {
+        client.set_background("#ffffff").unwrap();
+        client.set_doc(WhiteboardDoc::from_json(TEXT_JSON).unwrap());
+        let before = client.render().unwrap();
+        assert!(before.chunks_exact(4).all(|p| p == [255; 4]));
+        client
+            .load_font(crate::text::test_font::test_font(true, true))
+            .unwrap();
+        client.render().unwrap()
+    }
+
+    #[test]
+    fn text_is_drawn_once_a_font_is_loaded() {
+        let cpu = render_text(HeadlessClient::cpu(64, 48, 4).unwrap());
+        // `!` is a box 1..9 px right of the anchor and 14 px tall, its left
+        // edge through the centre of column 11
+        assert_eq!(pixel(&cpu, 64, 14, 23), [0, 0, 0, 255]);
+        assert_eq!(pixel(&cpu, 64, 14, 34), [255; 4]);
+        let edge = pixel(&cpu, 64, 11, 23);
+        assert!(edge[0] > 150 && edge[0] < 225, "{edge:?}");
+        // the Thai cluster follows, a consonant box 10 px high
+        assert_eq!(pixel(&cpu, 64, 26, 25), [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn gpu_draws_text_like_the_cpu() {
+        let Some(client) = gpu_client("gpu_draws_text_like_the_cpu", 64, 48, 4) else {
+            return;
+        };
+        let gpu = render_text(client);
+        let cpu = render_text(HeadlessClient::cpu(64, 48, 4).unwrap());
+        let max_diff = gpu
+            .iter()
+            .zip(&cpu)
+            .map(|(a, b)| a.abs_diff(*b))
+            .max()
+            .unwrap();
+        // samplers filter with a few bits of precision, half a percent of
+        // coverage on nearly covered pixels, which sRGB stretches near black
+        assert!(max_diff <= 12, "GPU and CPU text differ by {max_diff}");
+    }
+
+    #[test]
+    fn loading_a_font_measures_text_with_it() {
+        let mut client = HeadlessClient::cpu(64, 48, 1).unwrap();
+        client.set_doc(WhiteboardDoc::from_json(TEXT_JSON).unwrap());
+        let sans = client.index().bounds("t").unwrap();
+        client
+            .load_font(crate::text::test_font::test_font(true, true))
+            .unwrap();
+        let shape = client.doc().get("t").unwrap();
+        let loaded = geometry::painted_bounds(shape, client.text());
+        assert_ne!(loaded, sans);
+        assert_eq!(client.index().bounds("t"), Some(loaded));
+        // past the Sans box but on the test font's wider glyphs
+        let p = Point::new(loaded.right() - 1.0, 25.0);
+        assert!(p.x > sans.right());
+        let hit = client.index().hit_test(client.doc(), p, 0.0, client.text());
+        assert_eq!(hit.as_deref(), Some("t"));
+    }
+
+    /// A client rendering through the wgpu pipeline, never the CPU fallback;
+    /// see [`require_adapter`] for machines without an adapter
+    fn gpu_client(
+        test: &str,
+        width: u32,
+        height: u32,
+        sample_count: u32,
+    ) -> Option<HeadlessClient> {
+        let mut config = AppConfig::default();
+        config.webgpu.force_fallback = true;
+        config.webgpu.sample_count = sample_count;
+        let client = HeadlessClient::with_config(width, height, &config).unwrap();
+        if client.adapter_name().is_none() {
+            require_adapter(test);
+            return None;
+        }
+        Some(client)
+    }
+
+    const ALL_SHAPES_JSON: &str = r##"{
+        "shapes": {
+            "rect": { "id": "rect", "type": "rectangle", "x": 10, "y": 10, "w": 60, "h": 40,
+                      "stroke": "#111827", "fill": "#fde68a", "strokeWidth": 3 },
+            "ellipse": { "id": "ellipse", "type": "ellipse", "x": 50, "y": 30, "w": 70, "h": 50,
+                         "stroke": "#7c3aed", "fill": "rgba(124,58,237,0.35)", "strokeWidth": 2 },
+            "line": { "id": "line", "type": "line", "a": { "x": 8, "y": 90 }, "b": { "x": 150, "y": 70 },
+                      "stroke": "#059669", "fill": null, "strokeWidth": 1 },
+            "arrow": { "id": "arrow", "type": "arrow", "a": { "x": 20, "y": 110 }, "b": { "x": 140, "y": 100 },
+                       "stroke": "#dc2626", "fill": null, "strokeWidth": 4 },
+            "pencil": { "id": "pencil", "type": "pencil",
+                        "points": [{ "x": 110, "y": 12 }, { "x": 125, "y": 30 }, { "x": 135, "y": 15 },
+                                   { "x": 150, "y": 40 }],
+                        "stroke": "#2563eb", "fill": null, "strokeWidth": 6 }
+        },
+        "order": ["rect", "ellipse", "line", "arrow", "pencil"]
+    }"##;
+
+    fn all_shapes(mut client: HeadlessClient) -> Vec<u8> {
+        client.set_background("#ffffff").unwrap();
+        client.set_doc(WhiteboardDoc::from_json(ALL_SHAPES_JSON).unwrap());
+        client.render().unwrap()
+    }
+
+    fn all_shapes_zoomed(mut client: HeadlessClient) -> Vec<u8> {
+        client.set_doc(WhiteboardDoc::from_json(ALL_SHAPES_JSON).unwrap());
+        client.set_camera(Camera {
+            x: -30.0,
+            y: -10.0,
+            zoom: 1.5,
+        });
+        client.set_pixel_ratio(1.25);
+        client.render().unwrap()
+    }
+
+    #[test]
+    fn golden_all_shapes() {
+        let Some(client) = gpu_client("golden_all_shapes", 160, 120, 4) else {
+            return;
+        };
+        let pixels = all_shapes(client);
+        assert_golden("shapes_msaa4", 160, 120, &pixels);
+    }
+
+    #[test]
+    fn golden_all_shapes_without_msaa() {
+        let Some(client) = gpu_client("golden_all_shapes_without_msaa", 160, 120, 1) else {
+            return;
+        };
+        let pixels = all_shapes(client);
+        assert_golden("shapes_msaa1", 160, 120, &pixels);
+    }
+
+    #[test]
+    fn golden_camera_and_pixel_ratio() {
+        let Some(client) = gpu_client("golden_camera_and_pixel_ratio", 160, 120, 4) else {
+            return;
+        };
+        let pixels = all_shapes_zoomed(client);
+        assert_golden("shapes_zoomed", 160, 120, &pixels);
+    }
+
+    /// The CPU fallback draws the same images as the pipeline, so it is held
+    /// to the same goldens
+    #[test]
+    fn cpu_matches_the_goldens() {
+        let cpu = |sample_count| HeadlessClient::cpu(160, 120, sample_count).unwrap();
+        assert_golden("shapes_msaa4", 160, 120, &all_shapes(cpu(4)));
+        assert_golden("shapes_msaa1", 160, 120, &all_shapes(cpu(1)));
+        assert_golden("shapes_zoomed", 160, 120, &all_shapes_zoomed(cpu(4)));
+    }
+
+    fn decode(bytes: &[u8]) -> (u32, u32, Vec<u8>) {
+        let mut reader = ::png::Decoder::new(std::io::Cursor::new(bytes))
+            .read_info()
+            .unwrap();
+        let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+        let info = reader.next_frame(&mut buf).unwrap();
+        assert_eq!(info.color_type, ::png::ColorType::Rgba);
+        (info.width, info.height, buf)
+    }
+
+    fn check_export(mut client: HeadlessClient) -> Vec<u8> {
+        client.set_doc(WhiteboardDoc::from_json(DOC_JSON).unwrap());
+
+        let (w, h, _) = decode(&client.export_png(None, 1.0, Some("#fff")).unwrap());
+        // content 2.5..61.5 x 7..45.5 with the outline and line width
+        assert_eq!((w, h), (91, 71));
+
+        let bytes = client
+            .export_png(Some(Rect::new(0.0, 0.0, 64.0, 48.0)), 2.0, None)
+            .unwrap();
+        let (w, h, pixels) = decode(&bytes);
+        assert_eq!((w, h), (128, 96));
+        assert_eq!(pixel(&pixels, w, 4, 4)[3], 0);
+        assert_eq!(pixel(&pixels, w, 40, 32), [255, 0, 0, 255]);
+        // anti-aliased line edges keep their colour over transparency
+        let line_edges: Vec<[u8; 4]> = (70..96)
+            .map(|y| pixel(&pixels, w, 64, y))
+            .filter(|p| p[3] > 16 && p[3] < 240)
+            .collect();
+        assert!(!line_edges.is_empty());
+        assert!(line_edges.iter().all(|p| p[2] > 200 && p[0] < 100));
+        pixels
+    }
+
+    #[test]
+    fn exports_png_on_the_cpu() {
+        check_export(HeadlessClient::cpu(32, 32, 4).unwrap());
+    }
+
+    #[test]
+    fn exports_png_on_the_gpu_like_the_cpu() {
+        let Some(client) = gpu_client("exports_png_on_the_gpu_like_the_cpu", 32, 32, 4) else {
+            return;
+        };
+        let gpu = check_export(client);
+        let cpu = check_export(HeadlessClient::cpu(32, 32, 4).unwrap());
+        for (a, b) in gpu.chunks_exact(4).zip(cpu.chunks_exact(4)) {
+            assert!(a[3].abs_diff(b[3]) <= 2, "{a:?} vs {b:?}");
+            // straight colour is only as precise as the alpha it was divided by
+            if a[3] > 64 {
+                assert!((0..3).all(|c| a[c].abs_diff(b[c]) <= 8), "{a:?} vs {b:?}");
+            }
+        }
+    }
+}
@@ -1,16 +1,43 @@
 #[cfg(target_arch = "wasm32")]
 pub mod client;
 
-#[cfg(target_arch = "wasm32")]
 pub(crate) mod wgpu_setup;
 
-#[cfg(target_arch = "wasm32")]
 pub(crate) mod pipeline;
 
-#[cfg(target_arch = "wasm32")]
+pub(crate) mod stroke_pipeline;
+
+pub(crate) mod text_pipeline;
+
 pub(crate) mod buffers;
 
+#[cfg(any(target_arch = "wasm32", test))]
+pub(crate) mod editor;
+
+pub(crate) mod scene;
+
+pub(crate) mod targets;
+
 #[cfg(not(target_arch = "wasm32"))]
 #[path = "client_stub.rs"]
 pub mod client;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod headless;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) mod raster;
+
+pub(crate) mod readback;
+
+pub(crate) mod allocator;
+
+pub(crate) mod camera;
+
+pub(crate) mod overlay;
+
+pub(crate) mod stroke;
+
+pub(crate) mod tessellate;
+
 pub mod vertex;
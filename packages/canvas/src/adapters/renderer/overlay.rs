@@ -0,0 +1,323 @@
+//! Collaborator overlay: remote cursors with name labels and outlines around
+//! the shapes each collaborator has selected. Everything is sized in screen
+//! pixels, so the overlay is rebuilt whenever the zoom changes, and it is
+//! drawn above the document without touching its geometry.
+
+use crate::adapters::renderer::buffers::SceneBuffers;
+use crate::adapters::renderer::stroke::{stroke_polyline, LineJoin, StrokeStyle};
+use crate::adapters::renderer::tessellate::{self, Mesh};
+use crate::adapters::renderer::vertex::{StrokeVertex, TextVertex};
+use crate::geometry;
+use crate::geometry::text::{is_combining, TextMetrics};
+use crate::model::color::Rgba;
+use crate::model::WhiteboardDoc;
+use crate::presence::Presence;
+use crate::text::layout::TextStyle;
+use crate::text::Typesetter;
+
+/// Screen pixels between a selected shape and its outline
+const SELECTION_GAP: f32 = 4.0;
+const SELECTION_WIDTH: f32 = 1.5;
+/// Opacity of the tint laid over selected shapes
+const SELECTION_TINT: f32 = 0.08;
+
+/// The pointer, tip at the origin, in screen pixels
+const CURSOR: [[f32; 2]; 4] = [[0.0, 0.0], [0.0, 16.0], [4.5, 12.0], [11.5, 11.5]];
+const CURSOR_OUTLINE: f32 = 1.5;
+
+/// Label offset from the cursor tip and padding around the name
+const LABEL_OFFSET: [f32; 2] = [10.0, 18.0];
+const LABEL_PADDING: [f32; 2] = [5.0, 4.0];
+/// Size of the name in screen pixels
+const LABEL_FONT_SIZE: f32 = 12.0;
+/// Longest name shown before it is cut short, in characters without their
+/// combining marks
+const MAX_LABEL_CHARS: usize = 20;
+
+/// Tessellates the overlay of every collaborator in `peers`, outlines first
+/// so cursors stay on top. Names are set with the fonts of `text` like text
+/// shapes are. `world_per_pixel` is the current size of a screen pixel in
+/// world units.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn tessellate_presence<'a>(
+    peers: impl IntoIterator<Item = &'a Presence> + Clone,
+    doc: &WhiteboardDoc,
+    text: &mut Typesetter,
+    world_per_pixel: f32,
+    fill: &mut Mesh,
+    stroke: &mut Mesh<StrokeVertex>,
+    glyphs: &mut Mesh<TextVertex>,
+) {
+    let px = world_per_pixel;
+    for peer in peers.clone() {
+        let color = peer.rgba();
+        let tint = Rgba {
+            a: SELECTION_TINT,
+            ..color
+        }
+        .to_linear()
+        .to_array();
+        let style = StrokeStyle {
+            join: LineJoin::Miter,
+            ..StrokeStyle::canvas_default(SELECTION_WIDTH * px, color.to_linear().to_array())
+        };
+        for shape in peer.selection.iter().filter_map(|id| doc.get(id)) {
+            let r = geometry::painted_bounds(shape, &*text).expand((SELECTION_GAP * px) as f64);
+            let (x0, y0) = (r.x as f32, r.y as f32);
+            let (x1, y1) = (r.right() as f32, r.bottom() as f32);
+            let corners = [[x0, y0], [x1, y0], [x1, y1], [x0, y1]];
+            fill.push_fan(&corners, tint);
+            stroke_polyline(&corners, true, &style, stroke);
+        }
+    }
+    for peer in peers {
+        if let Some(tip) = peer.cursor {
+            let tip = [tip.x as f32, tip.y as f32];
+            cursor(peer, tip, px, text, fill, stroke, glyphs);
+        }
+    }
+}
+
+/// Rebuilds the overlay in `buffers`, or drops it when nobody else is here
+pub(crate) fn upload<'a>(
+    buffers: &mut SceneBuffers,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    peers: impl ExactSizeIterator<Item = &'a Presence> + Clone,
+    doc: &WhiteboardDoc,
+    text: &mut Typesetter,
+    world_per_pixel: f32,
+) {
+    if peers.len() == 0 {
+        buffers.clear_overlay();
+        return;
+    }
+    let mut fill = Mesh::default();
+    let mut stroke = Mesh::default();
+    let mut glyphs = Mesh::default();
+    let generation = text.atlas().generation();
+    tessellate_presence(
+        peers,
+        doc,
+        text,
+        world_per_pixel,
+        &mut fill,
+        &mut stroke,
+        &mut glyphs,
+    );
+    buffers.set_overlay(
+        device,
+        queue,
+        &mut fill,
+        &mut stroke,
+        &mut glyphs,
+        text,
+        generation,
+    );
+}
+
+fn cursor(
+    peer: &Presence,
+    tip: [f32; 2],
+    px: f32,
+    text: &mut Typesetter,
+    fill: &mut Mesh,
+    stroke: &mut Mesh<StrokeVertex>,
+    glyphs: &mut Mesh<TextVertex>,
+) {
+    let at = |p: [f32; 2]| [tip[0] + p[0] * px, tip[1] + p[1] * px];
+    let color = peer.rgba();
+    let linear = color.to_linear().to_array();
+    let outline = CURSOR.map(at);
+    // the pointer is concave at its notch, so it goes in as two triangles
+    fill.push_triangle(outline[0], outline[1], outline[2], linear);
+    fill.push_triangle(outline[0], outline[2], outline[3], linear);
+    let white = StrokeStyle {
+        join: LineJoin::Round,
+        ..StrokeStyle::canvas_default(CURSOR_OUTLINE * px, Rgba::WHITE.to_array())
+    };
+    stroke_polyline(&outline, true, &white, stroke);
+
+    let name = label(&peer.name);
+    if name.is_empty() {
+        return;
+    }
+    let size = (LABEL_FONT_SIZE * px) as f64;
+    let origin = at(LABEL_OFFSET);
+    let pen = [
+        (origin[0] + LABEL_PADDING[0] * px) as f64,
+        (origin[1] + LABEL_PADDING[1] * px) as f64 + text.ascent() * size,
+    ];
+    let line = text.line_box(pen[0], pen[1], &name, size);
+    let end = [
+        line.right() as f32 + LABEL_PADDING[0] * px,
+        line.bottom() as f32 + LABEL_PADDING[1] * px,
+    ];
+    fill.push_fan(
+        &[origin, [end[0], origin[1]], end, [origin[0], end[1]]],
+        linear,
+    );
+    let ink = if luminance(color) > 0.6 {
+        Rgba::BLACK
+    } else {
+        Rgba::WHITE
+    };
+    let layout = text.layout(&name, &TextStyle::new(size));
+    let ink = ink.to_linear().to_array();
+    if !tessellate::set_text(pen[0], pen[1], size, ink, &layout, text, glyphs) {
+        tracing::warn!(
+            "the name of peer {} does not fit in the glyph atlas",
+            peer.user_id
+        );
+    }
+}
+
+/// `name` on one line, cut short past [`MAX_LABEL_CHARS`] without splitting
+/// a character from its marks
+fn label(name: &str) -> String {
+    let bases = name.chars().filter(|c| !is_combining(*c as u32)).count();
+    let mut out = String::new();
+    let mut count = 0;
+    for c in name.chars() {
+        if !is_combining(c as u32) {
+            count += 1;
+            if bases > MAX_LABEL_CHARS && count == MAX_LABEL_CHARS {
+                out.push('.');
+                break;
+            }
+        }
+        out.push(if c.is_control() { ' ' } else { c });
+    }
+    out
+}
+
+/// Relative luminance of an sRGB colour
+fn luminance(c: Rgba) -> f32 {
+    let l = c.to_linear();
+    0.2126 * l.r + 0.7152 * l.g + 0.0722 * l.b
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::text::SansMetrics;
+    use crate::model::Point;
+    use crate::text::font::Font;
+    use crate::text::test_font::test_font;
+
+    #[test]
+    fn scales_with_the_zoom_and_outlines_selections() {
+        let doc = WhiteboardDoc::from_json(
+            r##"{ "shapes": { "a": { "id": "a", "type": "rectangle", "x": 0, "y": 0,
+                  "w": 100, "h": 50, "stroke": "#000", "fill": null, "strokeWidth": 2 } },
+                  "order": ["a"] }"##,
+        )
+        .unwrap();
+        let peer = Presence {
+            user_id: 7,
+            name: "Al".to_string(),
+            color: "#1971c2".to_string(),
+            cursor: Some(Point::new(200.0, 100.0)),
+            selection: vec!["a".to_string(), "gone".to_string()],
+            viewport: None,
+        };
+        let extent = |px: f32| {
+            let mut fill = Mesh::default();
+            let mut stroke = Mesh::default();
+            let mut glyphs = Mesh::default();
+            let mut text = Typesetter::new();
+            tessellate_presence(
+                [&peer],
+                &doc,
+                &mut text,
+                px,
+                &mut fill,
+                &mut stroke,
+                &mut glyphs,
+            );
+            assert!(!stroke.vertices.is_empty());
+            // without a font the name is measured but not drawn
+            assert!(glyphs.vertices.is_empty());
+            // the label's right edge, past everything else
+            fill.vertices
+                .iter()
+                .map(|v| v.position[0])
+                .fold(f32::MIN, f32::max)
+        };
+        let label =
+            2.0 * LABEL_PADDING[0] + SansMetrics.advance("Al", LABEL_FONT_SIZE as f64) as f32;
+        assert!((extent(1.0) - (200.0 + LABEL_OFFSET[0] + label)).abs() < 1e-3);
+        assert!((extent(0.5) - (200.0 + (LABEL_OFFSET[0] + label) * 0.5)).abs() < 1e-3);
+
+        // the tint covers the shape plus the gap
+        let mut fill = Mesh::default();
+        let mut stroke = Mesh::default();
+        let idle = Presence {
+            cursor: None,
+            ..peer
+        };
+        tessellate_presence(
+            [&idle],
+            &doc,
+            &mut Typesetter::new(),
+            1.0,
+            &mut fill,
+            &mut stroke,
+            &mut Mesh::default(),
+        );
+        let min_x = fill
+            .vertices
+            .iter()
+            .map(|v| v.position[0])
+            .fold(f32::MAX, f32::min);
+        assert_eq!(min_x, -1.0 - SELECTION_GAP);
+        assert_eq!(fill.indices.len(), 6);
+    }
+
+    #[test]
+    fn names_are_set_with_the_loaded_fonts() {
+        let mut text = Typesetter::new();
+        text.add_font(Font::from_bytes(test_font(true, true)).unwrap());
+        let peer = Presence {
+            user_id: 7,
+            name: "สมชาย".to_string(),
+            color: "#1971c2".to_string(),
+            cursor: Some(Point::new(0.0, 0.0)),
+            selection: Vec::new(),
+            viewport: None,
+        };
+        let mut fill = Mesh::default();
+        let mut glyphs = Mesh::default();
+        tessellate_presence(
+            [&peer],
+            &WhiteboardDoc::default(),
+            &mut text,
+            1.0,
+            &mut fill,
+            &mut Mesh::default(),
+            &mut glyphs,
+        );
+        // one quad per letter, in white on the dark label
+        assert_eq!(glyphs.vertices.len(), 5 * 4);
+        assert!(glyphs.vertices.iter().all(|v| v.color == [1.0; 4]));
+        // the label spans the name as laid out
+        let width = text.advance(&peer.name, LABEL_FONT_SIZE as f64) as f32;
+        let right = fill
+            .vertices
+            .iter()
+            .map(|v| v.position[0])
+            .fold(f32::MIN, f32::max);
+        assert!((right - (LABEL_OFFSET[0] + 2.0 * LABEL_PADDING[0] + width)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn long_names_are_cut_between_characters() {
+        assert_eq!(label("Mo"), "Mo");
+        assert_eq!(label("a\nb"), "a b");
+        let long = "ก่".repeat(MAX_LABEL_CHARS + 1);
+        let cut = label(&long);
+        assert_eq!(cut, format!("{}.", "ก่".repeat(MAX_LABEL_CHARS - 1)));
+        let exact = "ก่".repeat(MAX_LABEL_CHARS);
+        assert_eq!(label(&exact), exact);
+    }
+}
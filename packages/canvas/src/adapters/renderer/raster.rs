@@ -0,0 +1,366 @@
+//! Pure-CPU rasteriser for the 2D meshes, used by `HeadlessClient` when no
+//! adapter is available at all. It mirrors `shader.wgsl`, `stroke.wgsl` and
+//! `text.wgsl`:
+//! the same transforms, shading once per pixel at its centre, the standard
+//! 4x MSAA sample pattern and alpha blending in linear space, so its output
+//! stays within a few levels of the GPU path.
+
+use crate::adapters::renderer::camera::CameraUniform;
+use crate::adapters::renderer::tessellate::Mesh;
+use crate::adapters::renderer::vertex::{StrokeVertex, TextVertex, Vertex};
+use crate::model::color::Rgba;
+use crate::text::atlas::GlyphAtlas;
+use crate::text::sdf::SDF_RANGE;
+
+/// Positions are snapped to 1/256 px as GPU rasterisers do, which keeps the
+/// edge tests exact so an edge shared by two triangles is covered once
+const SUBPIXEL: f32 = 256.0;
+
+const SAMPLES_1X: &[[i64; 2]] = &[[128, 128]];
+/// The standard D3D/Vulkan/WebGPU 4x pattern, in subpixel units
+const SAMPLES_4X: &[[i64; 2]] = &[[96, 32], [224, 96], [32, 160], [160, 224]];
+
+#[derive(Debug, Clone)]
+pub(crate) struct Raster {
+    width: u32,
+    height: u32,
+    samples: &'static [[i64; 2]],
+    /// Linear colour per sample, `samples.len()` entries per pixel
+    color: Vec<[f32; 4]>,
+}
+
+impl Raster {
+    /// `clear` is linear, like a render pass clear colour
+    pub(crate) fn new(width: u32, height: u32, sample_count: u32, clear: Rgba) -> Self {
+        let samples = if sample_count > 1 {
+            SAMPLES_4X
+        } else {
+            SAMPLES_1X
+        };
+        Self {
+            width,
+            height,
+            samples,
+            color: vec![clear.to_array(); (width * height) as usize * samples.len()],
+        }
+    }
+
+    /// Draws a mesh of the fill pipeline
+    pub(crate) fn fill(&mut self, mesh: &Mesh<Vertex>, camera: &CameraUniform) {
+        for tri in mesh.indices.chunks_exact(3) {
+            let v = [0, 1, 2].map(|k| &mesh.vertices[tri[k] as usize]);
+            let p = v.map(|v| self.to_pixel(camera, v.position));
+            self.triangle(p, |b| lerp4(b, v.map(|v| v.color)), blend_into);
+        }
+    }
+
+    /// Draws a mesh of the stroke pipeline, including its AA fringe. Like the
+    /// two stroke passes, every sample blends once, with the first of the
+    /// fragments that cover it most.
+    pub(crate) fn stroke(&mut self, mesh: &Mesh<StrokeVertex>, camera: &CameraUniform) {
+        let px = camera.world_per_pixel;
+        let mut fragments = Vec::new();
+        for tri in mesh.indices.chunks_exact(3) {
+            let v = [0, 1, 2].map(|k| &mesh.vertices[tri[k] as usize]);
+            let p = v.map(|v| {
+                let world = [
+                    v.position[0] + v.extrude[0] * px,
+                    v.position[1] + v.extrude[1] * px,
+                ];
+                self.to_pixel(camera, world)
+            });
+            let dist = v.map(|v| [v.dist[0] + v.grow[0] * px, v.dist[1] + v.grow[1] * px]);
+            let aa = [
+                fwidth(p, dist.map(|d| d[0])).max(1e-6),
+                fwidth(p, dist.map(|d| d[1])).max(1e-6),
+            ];
+            self.triangle(
+                p,
+                |b| {
+                    let d = [lerp(b, dist.map(|d| d[0])), lerp(b, dist.map(|d| d[1]))];
+                    let half_width = lerp(b, v.map(|v| v.half_width));
+                    let across = ((half_width - d[0].abs()) / aa[0] + 0.5).clamp(0.0, 1.0);
+                    let along = (-d[1] / aa[1] + 0.5).clamp(0.0, 1.0);
+                    let coverage = across * along;
+                    let mut color = lerp4(b, v.map(|v| v.color));
+                    color[3] *= coverage;
+                    // the 64 levels `stroke.wgsl` keeps in the depth buffer
+                    ((coverage * 63.0).round() as u32, color)
+                },
+                |_, sample, fragment| fragments.push((sample, fragment)),
+            );
+        }
+
+        fragments.sort_by_key(|&(sample, _)| sample);
+        for run in fragments.chunk_by(|a, b| a.0 == b.0) {
+            let (sample, (_, src)) = run
+                .iter()
+                .copied()
+                .reduce(|best, f| if f.1 .0 > best.1 .0 { f } else { best })
+                .expect("runs are never empty");
+            blend(&mut self.color[sample], src);
+        }
+    }
+
+    /// Draws a mesh of the text pipeline, sampling `atlas` like the linear
+    /// sampler does
+    pub(crate) fn text(
+        &mut self,
+        mesh: &Mesh<TextVertex>,
+        atlas: &GlyphAtlas,
+        camera: &CameraUniform,
+    ) {
+        for tri in mesh.indices.chunks_exact(3) {
+            let v = [0, 1, 2].map(|k| &mesh.vertices[tri[k] as usize]);
+            let p = v.map(|v| self.to_pixel(camera, v.position));
+            let uv = [0, 1].map(|c| v.map(|v| v.uv[c]));
+            let texels = (0.5 * (fwidth(p, uv[0]) + fwidth(p, uv[1]))).max(1e-6);
+            self.triangle(
+                p,
+                |b| {
+                    let field = atlas.sample(lerp(b, uv[0]), lerp(b, uv[1]));
+                    let coverage =
+                        ((field - 0.5) * 2.0 * SDF_RANGE as f32 / texels + 0.5).clamp(0.0, 1.0);
+                    let mut color = lerp4(b, v.map(|v| v.color));
+                    color[3] *= coverage;
+                    color
+                },
+                blend_into,
+            );
+        }
+    }
+
+    /// Resolves the samples and encodes them like an `Rgba8UnormSrgb` target
+    pub(crate) fn to_rgba8(&self) -> Vec<u8> {
+        let n = self.samples.len();
+        let mut out = Vec::with_capacity(self.color.len() / n * 4);
+        for pixel in self.color.chunks_exact(n) {
+            let mut sum = [0.0f32; 4];
+            for sample in pixel {
+                for c in 0..4 {
+                    sum[c] += sample[c];
+                }
+            }
+            let avg = sum.map(|c| c / n as f32);
+            let srgb = Rgba::new(avg[0], avg[1], avg[2], avg[3]).to_srgb();
+            out.extend(
+                srgb.to_array()
+                    .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8),
+            );
+        }
+        out
+    }
+
+    fn to_pixel(&self, camera: &CameraUniform, world: [f32; 2]) -> [f32; 2] {
+        let clip = [
+            world[0] * camera.scale[0] + camera.translate[0],
+            world[1] * camera.scale[1] + camera.translate[1],
+        ];
+        [
+            (clip[0] + 1.0) * 0.5 * self.width as f32,
+            (1.0 - clip[1]) * 0.5 * self.height as f32,
+        ]
+    }
+
+    /// Covers the samples inside `p` and hands `shade`, evaluated at the
+    /// pixel centre with barycentric weights, to `plot` for each of them
+    fn triangle<T: Copy>(
+        &mut self,
+        p: [[f32; 2]; 3],
+        shade: impl Fn([f32; 3]) -> T,
+        mut plot: impl FnMut(&mut [[f32; 4]], usize, T),
+    ) {
+        let area = edge_f(p[0], p[1], p[2]);
+        let mut q = p.map(|v| v.map(|c| (c * SUBPIXEL).round() as i64));
+        match edge(q[0], q[1], q[2]) {
+            0 => return,
+            a if a < 0 => q.swap(1, 2),
+            _ => {}
+        }
+
+        let sub = SUBPIXEL as i64;
+        let lo = |axis: usize, limit: u32| {
+            let min = q.iter().map(|v| v[axis]).min().unwrap_or(0);
+            min.div_euclid(sub).clamp(0, limit as i64)
+        };
+        let hi = |axis: usize, limit: u32| {
+            let max = q.iter().map(|v| v[axis]).max().unwrap_or(0);
+            (max.div_euclid(sub) + 1).clamp(0, limit as i64)
+        };
+        let (x0, x1) = (lo(0, self.width), hi(0, self.width));
+        let (y0, y1) = (lo(1, self.height), hi(1, self.height));
+
+        let n = self.samples.len();
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let origin = [x * sub, y * sub];
+                let mut mask = 0u32;
+                for (s, offset) in self.samples.iter().enumerate() {
+                    if covers(&q, [origin[0] + offset[0], origin[1] + offset[1]]) {
+                        mask |= 1 << s;
+                    }
+                }
+                if mask == 0 {
+                    continue;
+                }
+
+                let centre = [x as f32 + 0.5, y as f32 + 0.5];
+                let b0 = edge_f(p[1], p[2], centre) / area;
+                let b1 = edge_f(p[2], p[0], centre) / area;
+                let src = shade([b0, b1, 1.0 - b0 - b1]);
+
+                let base = (y as usize * self.width as usize + x as usize) * n;
+                for s in (0..n).filter(|s| mask & (1 << s) != 0) {
+                    plot(&mut self.color, base + s, src);
+                }
+            }
+        }
+    }
+}
+
+fn edge(a: [i64; 2], b: [i64; 2], p: [i64; 2]) -> i128 {
+    (b[0] - a[0]) as i128 * (p[1] - a[1]) as i128 - (b[1] - a[1]) as i128 * (p[0] - a[0]) as i128
+}
+
+fn edge_f(a: [f32; 2], b: [f32; 2], p: [f32; 2]) -> f32 {
+    (b[0] - a[0]) * (p[1] - a[1]) - (b[1] - a[1]) * (p[0] - a[0])
+}
+
+/// `q` must have positive area. Samples exactly on an edge belong to only
+/// one of the two triangles sharing it, decided by the edge direction.
+fn covers(q: &[[i64; 2]; 3], p: [i64; 2]) -> bool {
+    (0..3).all(|i| {
+        let (a, b) = (q[i], q[(i + 1) % 3]);
+        match edge(a, b, p) {
+            0 => {
+                let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+                dy > 0 || (dy == 0 && dx < 0)
+            }
+            w => w > 0,
+        }
+    })
+}
+
+/// `|d/dx| + |d/dy|` of an attribute interpolated across a triangle, which is
+/// what `fwidth` returns for it on the GPU
+fn fwidth(p: [[f32; 2]; 3], a: [f32; 3]) -> f32 {
+    let (e1, e2) = (
+        [p[1][0] - p[0][0], p[1][1] - p[0][1]],
+        [p[2][0] - p[0][0], p[2][1] - p[0][1]],
+    );
+    let det = e1[0] * e2[1] - e2[0] * e1[1];
+    if det == 0.0 {
+        return 0.0;
+    }
+    let (da1, da2) = (a[1] - a[0], a[2] - a[0]);
+    let ddx = (da1 * e2[1] - da2 * e1[1]) / det;
+    let ddy = (da2 * e1[0] - da1 * e2[0]) / det;
+    ddx.abs() + ddy.abs()
+}
+
+fn lerp(b: [f32; 3], a: [f32; 3]) -> f32 {
+    b[0] * a[0] + b[1] * a[1] + b[2] * a[2]
+}
+
+fn lerp4(b: [f32; 3], a: [[f32; 4]; 3]) -> [f32; 4] {
+    [0, 1, 2, 3].map(|c| lerp(b, a.map(|v| v[c])))
+}
+
+fn blend_into(color: &mut [[f32; 4]], sample: usize, src: [f32; 4]) {
+    blend(&mut color[sample], src);
+}
+
+/// `wgpu::BlendState::ALPHA_BLENDING`, with `src` clamped like a fragment
+/// output to a unorm target
+fn blend(dst: &mut [f32; 4], src: [f32; 4]) {
+    let src = src.map(|c| c.clamp(0.0, 1.0));
+    let a = src[3];
+    for c in 0..3 {
+        dst[c] = src[c] * a + dst[c] * (1.0 - a);
+    }
+    dst[3] = a + dst[3] * (1.0 - a);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::renderer::stroke::{stroke_polyline, LineCap, LineJoin, StrokeStyle};
+    use crate::model::Camera;
+    use crate::types::Size;
+
+    fn camera(width: u32, height: u32) -> CameraUniform {
+        CameraUniform::new(&Camera::default(), Size { width, height }, 1.0)
+    }
+
+    fn quad(color: [f32; 4], x0: f32, y0: f32, x1: f32, y1: f32) -> Mesh {
+        let corners = [[x0, y0], [x1, y0], [x1, y1], [x0, y1]];
+        Mesh {
+            vertices: corners.map(|position| Vertex { position, color }).to_vec(),
+            indices: vec![0, 1, 2, 0, 2, 3],
+        }
+    }
+
+    #[test]
+    fn shared_edges_are_blended_once() {
+        let mut raster = Raster::new(8, 8, 4, Rgba::BLACK);
+        raster.fill(
+            &quad([1.0, 1.0, 1.0, 0.5], 0.0, 0.0, 8.0, 8.0),
+            &camera(8, 8),
+        );
+        let pixels = raster.to_rgba8();
+        let expected = Rgba::new(0.5, 0.5, 0.5, 1.0).to_srgb().r;
+        let expected = (expected * 255.0).round() as u8;
+        assert!(pixels.chunks_exact(4).all(|p| p[0] == expected));
+    }
+
+    #[test]
+    fn msaa_gives_partial_edge_coverage() {
+        let mut raster = Raster::new(4, 1, 4, Rgba::BLACK);
+        // covers all of pixel 0 and the left half of pixel 1
+        raster.fill(
+            &quad([1.0, 1.0, 1.0, 1.0], 0.0, 0.0, 1.5, 1.0),
+            &camera(4, 1),
+        );
+        let linear = |byte: u8| Rgba::new(byte as f32 / 255.0, 0.0, 0.0, 1.0).to_linear().r;
+        let pixels = raster.to_rgba8();
+        assert_eq!(pixels[0], 255);
+        assert!((linear(pixels[4]) - 0.5).abs() < 0.01);
+        assert_eq!(pixels[8], 0);
+    }
+
+    #[test]
+    fn translucent_strokes_blend_once_at_joins_and_caps() {
+        let style = StrokeStyle {
+            join: LineJoin::Round,
+            cap: LineCap::Round,
+            ..StrokeStyle::canvas_default(6.0, [1.0, 1.0, 1.0, 0.5])
+        };
+        let mut mesh = Mesh::default();
+        stroke_polyline(
+            &[[6.0, 6.0], [26.0, 6.0], [26.0, 26.0]],
+            false,
+            &style,
+            &mut mesh,
+        );
+        let mut raster = Raster::new(32, 32, 4, Rgba::BLACK);
+        raster.stroke(&mesh, &camera(32, 32));
+
+        let pixels = raster.to_rgba8();
+        let at = |x: usize, y: usize| pixels[(y * 32 + x) * 4];
+        let body = at(16, 6);
+        assert_eq!(
+            body,
+            (Rgba::new(0.5, 0.5, 0.5, 1.0).to_srgb().r * 255.0).round() as u8
+        );
+        assert_eq!(at(26, 6), body, "the join");
+        assert_eq!(at(5, 6), body, "the cap");
+        assert!(pixels.chunks_exact(4).all(|p| p[0] <= body));
+    }
+
+    #[test]
+    fn fwidth_matches_screen_space_gradient() {
+        let p = [[0.0, 0.0], [4.0, 0.0], [0.0, 2.0]];
+        // a = x + 3y
+        assert!((fwidth(p, [0.0, 4.0, 6.0]) - 4.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,163 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use crate::error::CanvasError;
+
+const BYTES_PER_PIXEL: u32 = 4;
+
+/// Row stride of a texture-to-buffer copy, which wgpu requires to be a
+/// multiple of `COPY_BYTES_PER_ROW_ALIGNMENT`
+pub(crate) fn padded_bytes_per_row(width: u32) -> u32 {
+    let unpadded = width * BYTES_PER_PIXEL;
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    unpadded.div_ceil(align) * align
+}
+
+/// Drops the per-row padding of a mapped readback buffer
+pub(crate) fn unpad_rows(padded: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let row = (width * BYTES_PER_PIXEL) as usize;
+    let stride = padded_bytes_per_row(width) as usize;
+    let mut pixels = Vec::with_capacity(row * height as usize);
+    for y in 0..height as usize {
+        pixels.extend_from_slice(&padded[y * stride..y * stride + row]);
+    }
+    pixels
+}
+
+/// Buffer that a 4-byte-per-pixel `texture` has been copied into by `encoder`
+pub(crate) fn copy_texture_to_buffer(
+    device: &wgpu::Device,
+    encoder: &mut wgpu::CommandEncoder,
+    texture: &wgpu::Texture,
+) -> wgpu::Buffer {
+    let size = texture.size();
+    let bytes_per_row = padded_bytes_per_row(size.width);
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Readback Buffer"),
+        size: bytes_per_row as u64 * size.height as u64,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        wgpu::TexelCopyBufferInfo {
+            buffer: &buffer,
+            layout: wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(bytes_per_row),
+                rows_per_image: Some(size.height),
+            },
+        },
+        wgpu::Extent3d {
+            depth_or_array_layers: 1,
+            ..size
+        },
+    );
+    buffer
+}
+
+/// Maps `buffer` filled by [`copy_texture_to_buffer`] and returns tightly
+/// packed rows. In the browser the mapping completes from the event loop;
+/// natively the device is polled until it does.
+pub(crate) async fn read(
+    device: &wgpu::Device,
+    buffer: &wgpu::Buffer,
+    width: u32,
+    height: u32,
+) -> Result<Vec<u8>, CanvasError> {
+    let slice = buffer.slice(..);
+    let mapped = MapFuture::default();
+    let state = mapped.state.clone();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        let mut state = state.lock().unwrap();
+        state.result = Some(result);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    });
+    #[cfg(not(target_arch = "wasm32"))]
+    device
+        .poll(wgpu::PollType::wait_indefinitely())
+        .map_err(|e| CanvasError::Readback(e.to_string()))?;
+    #[cfg(target_arch = "wasm32")]
+    let _ = device;
+
+    mapped
+        .await
+        .map_err(|e| CanvasError::Readback(e.to_string()))?;
+    let pixels = unpad_rows(&slice.get_mapped_range(), width, height);
+    buffer.unmap();
+    Ok(pixels)
+}
+
+/// Swaps red and blue in place when `format` stores pixels as BGRA, as
+/// browser surfaces often do
+pub(crate) fn to_rgba(format: wgpu::TextureFormat, pixels: &mut [u8]) {
+    if matches!(
+        format,
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+    ) {
+        for px in pixels.chunks_exact_mut(4) {
+            px.swap(0, 2);
+        }
+    }
+}
+
+#[derive(Default)]
+struct MapState {
+    result: Option<Result<(), wgpu::BufferAsyncError>>,
+    waker: Option<Waker>,
+}
+
+/// Resolves once the `map_async` callback has run
+#[derive(Default)]
+struct MapFuture {
+    state: Arc<Mutex<MapState>>,
+}
+
+impl Future for MapFuture {
+    type Output = Result<(), wgpu::BufferAsyncError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.state.lock().unwrap();
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_row_padding() {
+        assert_eq!(padded_bytes_per_row(64), 256);
+        assert_eq!(padded_bytes_per_row(65), 512);
+
+        let (width, height) = (3, 2);
+        let stride = padded_bytes_per_row(width) as usize;
+        let mut padded = vec![0xee; stride * 2];
+        padded[..12].copy_from_slice(&[1; 12]);
+        padded[stride..stride + 12].copy_from_slice(&[2; 12]);
+        let pixels = unpad_rows(&padded, width, height);
+        assert_eq!(pixels.len(), 24);
+        assert!(pixels[..12].iter().all(|&b| b == 1));
+        assert!(pixels[12..].iter().all(|&b| b == 2));
+    }
+
+    #[test]
+    fn bgra_is_swizzled() {
+        let mut pixels = [1, 2, 3, 4];
+        to_rgba(wgpu::TextureFormat::Rgba8UnormSrgb, &mut pixels);
+        assert_eq!(pixels, [1, 2, 3, 4]);
+        to_rgba(wgpu::TextureFormat::Bgra8UnormSrgb, &mut pixels);
+        assert_eq!(pixels, [3, 2, 1, 4]);
+    }
+}
@@ -0,0 +1,252 @@
+use std::ops::Range;
+
+use crate::adapters::renderer::buffers::{Layer, SceneBuffers};
+use crate::adapters::renderer::camera::CameraUniform;
+use crate::adapters::renderer::stroke_pipeline::{StrokePass, MAX_STROKES_PER_PASS};
+use crate::adapters::renderer::targets::{self, DepthStencilTarget, MsaaTarget};
+use crate::adapters::renderer::{pipeline, readback, stroke_pipeline, text_pipeline};
+use crate::model::ShapeId;
+use crate::types::Size;
+
+/// Everything needed to draw a whiteboard scene into a colour target of one
+/// format, independent of where the target comes from: the browser
+/// `Client` renders into its surface, `HeadlessClient` into a texture.
+#[derive(Debug)]
+pub(crate) struct SceneRenderer {
+    render_pipeline: wgpu::RenderPipeline,
+    stroke_coverage_pipeline: wgpu::RenderPipeline,
+    stroke_pipeline: wgpu::RenderPipeline,
+    text_pipeline: wgpu::RenderPipeline,
+    pub(crate) buffers: SceneBuffers,
+    camera_buffer: wgpu::Buffer,
+    camera_bind_group: wgpu::BindGroup,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+    msaa: Option<MsaaTarget>,
+    depth_stencil: DepthStencilTarget,
+}
+
+impl SceneRenderer {
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        size: Size,
+        sample_count: u32,
+        camera: &CameraUniform,
+    ) -> Self {
+        let camera_layout = pipeline::create_camera_bind_group_layout(device);
+        let render_pipeline =
+            pipeline::create_render_pipeline(device, format, &camera_layout, sample_count);
+        let stroke_coverage_pipeline = stroke_pipeline::create_stroke_pipeline(
+            device,
+            format,
+            &camera_layout,
+            sample_count,
+            StrokePass::Coverage,
+        );
+        let stroke_pipeline = stroke_pipeline::create_stroke_pipeline(
+            device,
+            format,
+            &camera_layout,
+            sample_count,
+            StrokePass::Blend,
+        );
+        let buffers = SceneBuffers::new(device);
+        let text_pipeline = text_pipeline::create_text_pipeline(
+            device,
+            format,
+            &camera_layout,
+            &buffers.glyphs.layout,
+            sample_count,
+        );
+        let camera_buffer = pipeline::create_camera_buffer(device, camera);
+        let camera_bind_group =
+            pipeline::create_camera_bind_group(device, &camera_layout, &camera_buffer);
+
+        Self {
+            render_pipeline,
+            stroke_coverage_pipeline,
+            stroke_pipeline,
+            text_pipeline,
+            buffers,
+            camera_buffer,
+            camera_bind_group,
+            format,
+            sample_count,
+            msaa: MsaaTarget::new(device, format, size.width, size.height, sample_count),
+            depth_stencil: DepthStencilTarget::new(device, size.width, size.height, sample_count),
+        }
+    }
+
+    /// Recreates the multisampled and depth-stencil targets to match a new
+    /// target size
+    pub(crate) fn resize(&mut self, device: &wgpu::Device, size: Size) {
+        self.msaa = MsaaTarget::new(
+            device,
+            self.format,
+            size.width,
+            size.height,
+            self.sample_count,
+        );
+        self.depth_stencil =
+            DepthStencilTarget::new(device, size.width, size.height, self.sample_count);
+    }
+
+    pub(crate) fn write_camera(&self, queue: &wgpu::Queue, camera: &CameraUniform) {
+        queue.write_buffer(&self.camera_buffer, 0, bytemuck::bytes_of(camera));
+    }
+
+    /// Records one pass that clears `target` and draws the shapes in `order`
+    /// with the collaborator overlay on top
+    pub(crate) fn encode(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        clear: wgpu::Color,
+        order: &[ShapeId],
+    ) {
+        self.buffers.prepare_draws(order, true);
+        self.record(
+            encoder,
+            self.msaa.as_ref(),
+            &self.depth_stencil,
+            target,
+            clear,
+        );
+    }
+
+    /// Draws `order` through `camera` into a new texture of `size` and queues
+    /// a copy of it into the returned buffer, ready for `readback::read`. The
+    /// camera uniform is left at `camera`, so callers rendering to screen
+    /// afterwards must write theirs again.
+    pub(crate) fn render_offscreen(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        size: Size,
+        camera: &CameraUniform,
+        clear: wgpu::Color,
+        order: &[ShapeId],
+    ) -> wgpu::Buffer {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Offscreen Target"),
+            size: wgpu::Extent3d {
+                width: size.width,
+                height: size.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let msaa = MsaaTarget::new(
+            device,
+            self.format,
+            size.width,
+            size.height,
+            self.sample_count,
+        );
+        let depth_stencil =
+            DepthStencilTarget::new(device, size.width, size.height, self.sample_count);
+
+        self.write_camera(queue, camera);
+        // frames may have drawn a culled list and the overlay; rebuild for
+        // `order` alone and again for the next frame
+        self.buffers.invalidate_order();
+        self.buffers.prepare_draws(order, false);
+        self.buffers.invalidate_order();
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Offscreen Encoder"),
+        });
+        self.record(&mut encoder, msaa.as_ref(), &depth_stencil, &view, clear);
+        let buffer = readback::copy_texture_to_buffer(device, &mut encoder, &texture);
+        queue.submit(std::iter::once(encoder.finish()));
+        buffer
+    }
+
+    pub(crate) fn format(&self) -> wgpu::TextureFormat {
+        self.format
+    }
+
+    /// Draws the prepared list in as few passes as the stroke ordinals allow:
+    /// each pass tells at most [`MAX_STROKES_PER_PASS`] strokes apart
+    fn record(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        msaa: Option<&MsaaTarget>,
+        depth_stencil: &DepthStencilTarget,
+        target: &wgpu::TextureView,
+        clear: wgpu::Color,
+    ) {
+        let draws = self.buffers.draws();
+        let mut passes = Vec::new();
+        let (mut start, mut strokes) = (0, 0);
+        for (i, (layer, _)) in draws.iter().enumerate() {
+            if *layer == Layer::Stroke {
+                if strokes == MAX_STROKES_PER_PASS {
+                    passes.push(start..i);
+                    (start, strokes) = (i, 0);
+                }
+                strokes += 1;
+            }
+        }
+        passes.push(start..draws.len());
+
+        let last = passes.len() - 1;
+        for (n, pass) in passes.into_iter().enumerate() {
+            let mut color = targets::color_attachment(msaa, target, clear);
+            if n > 0 {
+                color.ops.load = wgpu::LoadOp::Load;
+            }
+            if n < last {
+                color.ops.store = wgpu::StoreOp::Store;
+            }
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Render Pass"),
+                color_attachments: &[Some(color)],
+                depth_stencil_attachment: Some(depth_stencil.attachment()),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+                multiview_mask: None,
+            });
+            self.draw(&mut render_pass, &draws[pass]);
+        }
+    }
+
+    fn draw(&self, render_pass: &mut wgpu::RenderPass<'_>, draws: &[(Layer, Range<u32>)]) {
+        render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+        let mut bound: Option<Layer> = None;
+        // ordinals start at 1 so that every stroke is above the cleared depth
+        let mut ordinal = 0;
+        for (layer, range) in draws {
+            if bound != Some(*layer) {
+                match layer {
+                    Layer::Fill => render_pass.set_pipeline(&self.render_pipeline),
+                    Layer::Stroke => {}
+                    Layer::Text => {
+                        render_pass.set_pipeline(&self.text_pipeline);
+                        render_pass.set_bind_group(1, &self.buffers.glyphs.bind_group, &[]);
+                    }
+                }
+                let geometry = self.buffers.geometry(*layer);
+                render_pass.set_vertex_buffer(0, geometry.vertices.buffer.slice(..));
+                render_pass
+                    .set_index_buffer(geometry.indices.buffer.slice(..), wgpu::IndexFormat::Uint32);
+                bound = Some(*layer);
+            }
+            if *layer == Layer::Stroke {
+                ordinal += 1;
+                render_pass.set_pipeline(&self.stroke_coverage_pipeline);
+                render_pass.draw_indexed(range.clone(), 0, ordinal..ordinal + 1);
+                render_pass.set_pipeline(&self.stroke_pipeline);
+                render_pass.draw_indexed(range.clone(), 0, ordinal..ordinal + 1);
+            } else {
+                render_pass.draw_indexed(range.clone(), 0, 0..1);
+            }
+        }
+    }
+}
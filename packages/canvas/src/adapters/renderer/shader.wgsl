@@ -1,22 +1,31 @@
 struct VertexInput {
-  @location(0) position: vec3<f32>,
-  @location(1) color: vec3<f32>,
+  @location(0) position: vec2<f32>,
+  @location(1) color: vec4<f32>,
 }
 
 struct VertexOutput {
   @builtin(position) position: vec4<f32>,
-  @location(0) color: vec3<f32>,
+  @location(0) color: vec4<f32>,
 }
 
+struct Camera {
+  scale: vec2<f32>,
+  translate: vec2<f32>,
+  world_per_pixel: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
 @vertex
 fn vs_main(in: VertexInput) -> VertexOutput {
   var out: VertexOutput;
-  out.position = vec4<f32>(in.position, 1.0);
+  out.position = vec4<f32>(in.position * camera.scale + camera.translate, 0.0, 1.0);
   out.color = in.color;
   return out;
 }
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-  return vec4<f32>(in.color, 1.0);
+  return in.color;
 }
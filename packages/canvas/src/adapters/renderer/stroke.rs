@@ -0,0 +1,537 @@
+//! Polyline stroker for the anti-aliased stroke pipeline.
+//!
+//! Geometry is emitted at the exact stroke outline. Every vertex also carries
+//! an extrusion direction and the signed edge distances at that point. The
+//! vertex shader pushes vertices out by one device pixel along the extrusion.
+//! The fragment shader then turns the interpolated distances into coverage.
+
+use std::f32::consts::PI;
+
+use crate::adapters::renderer::tessellate::Mesh;
+use crate::adapters::renderer::vertex::StrokeVertex;
+
+/// Edge distance for points that are nowhere near a cap edge
+const INSIDE: f32 = -1.0e4;
+
+/// Maximum deviation of round joins/caps from the true arc, in world units
+const ARC_TOLERANCE: f32 = 0.05;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum LineJoin {
+    Miter,
+    Round,
+    Bevel,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum LineCap {
+    Butt,
+    Round,
+    Square,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct StrokeStyle {
+    pub(crate) width: f32,
+    pub(crate) join: LineJoin,
+    pub(crate) cap: LineCap,
+    /// Same meaning as canvas `miterLimit`: max ratio of miter length to width
+    pub(crate) miter_limit: f32,
+    pub(crate) color: [f32; 4],
+}
+
+impl StrokeStyle {
+    /// Canvas 2D defaults: miter joins, butt caps, miter limit 10
+    pub(crate) fn canvas_default(width: f32, color: [f32; 4]) -> Self {
+        Self {
+            width,
+            join: LineJoin::Miter,
+            cap: LineCap::Butt,
+            miter_limit: 10.0,
+            color,
+        }
+    }
+}
+
+type V2 = [f32; 2];
+
+fn add(a: V2, b: V2) -> V2 {
+    [a[0] + b[0], a[1] + b[1]]
+}
+
+fn sub(a: V2, b: V2) -> V2 {
+    [a[0] - b[0], a[1] - b[1]]
+}
+
+fn scale(a: V2, s: f32) -> V2 {
+    [a[0] * s, a[1] * s]
+}
+
+fn dot(a: V2, b: V2) -> f32 {
+    a[0] * b[0] + a[1] * b[1]
+}
+
+fn cross(a: V2, b: V2) -> f32 {
+    a[0] * b[1] - a[1] * b[0]
+}
+
+fn length(a: V2) -> f32 {
+    dot(a, a).sqrt()
+}
+
+fn normalize(a: V2) -> V2 {
+    scale(a, 1.0 / length(a))
+}
+
+fn perp(a: V2) -> V2 {
+    [-a[1], a[0]]
+}
+
+struct Stroker<'a> {
+    mesh: &'a mut Mesh<StrokeVertex>,
+    half: f32,
+    color: [f32; 4],
+}
+
+impl Stroker<'_> {
+    fn vertex(&mut self, position: V2, extrude: V2, dist: V2, grow: V2) -> u32 {
+        self.mesh.vertices.push(StrokeVertex {
+            position,
+            extrude,
+            dist,
+            grow,
+            color: self.color,
+            half_width: self.half,
+        });
+        (self.mesh.vertices.len() - 1) as u32
+    }
+
+    fn quad(&mut self, v: [u32; 4]) {
+        self.mesh
+            .indices
+            .extend_from_slice(&[v[0], v[1], v[2], v[0], v[2], v[3]]);
+    }
+
+    /// Body of the segment `a -> b` with unit normal `n`
+    fn segment(&mut self, a: V2, b: V2, n: V2) {
+        let h = self.half;
+        let v = [
+            self.vertex(add(a, scale(n, h)), n, [h, INSIDE], [1.0, 0.0]),
+            self.vertex(
+                sub(a, scale(n, h)),
+                scale(n, -1.0),
+                [-h, INSIDE],
+                [-1.0, 0.0],
+            ),
+            self.vertex(
+                sub(b, scale(n, h)),
+                scale(n, -1.0),
+                [-h, INSIDE],
+                [-1.0, 0.0],
+            ),
+            self.vertex(add(b, scale(n, h)), n, [h, INSIDE], [1.0, 0.0]),
+        ];
+        self.quad(v);
+    }
+
+    /// Triangle fan from `center` over rim directions; each rim point sits
+    /// at `center + dir * half` and extrudes along `dir`.
+    fn fan(&mut self, center: V2, rim: &[V2]) {
+        let c = self.vertex(center, [0.0, 0.0], [0.0, INSIDE], [0.0, 0.0]);
+        let first = self.mesh.vertices.len() as u32;
+        for dir in rim {
+            let p = add(center, scale(*dir, self.half));
+            self.vertex(p, *dir, [self.half, INSIDE], [1.0, 0.0]);
+        }
+        for k in 0..rim.len().saturating_sub(1) as u32 {
+            self.mesh
+                .indices
+                .extend_from_slice(&[c, first + k, first + k + 1]);
+        }
+    }
+
+    fn arc_directions(&self, from: f32, sweep: f32) -> Vec<V2> {
+        let step = if self.half > ARC_TOLERANCE {
+            2.0 * (1.0 - ARC_TOLERANCE / self.half).acos()
+        } else {
+            PI / 2.0
+        };
+        let steps = ((sweep.abs() / step).ceil() as usize).clamp(1, 64);
+        (0..=steps)
+            .map(|i| {
+                let a = from + sweep * i as f32 / steps as f32;
+                [a.cos(), a.sin()]
+            })
+            .collect()
+    }
+
+    /// Fills the outer wedge between segments with unit directions `d0`, `d1`
+    /// meeting at `p`
+    fn join(&mut self, p: V2, d0: V2, d1: V2, style: &StrokeStyle) {
+        let turn = cross(d0, d1);
+        let straight = dot(d0, d1);
+        if turn.abs() < 1e-6 && straight > 0.0 {
+            return;
+        }
+        // outer side of the corner
+        let side = if turn > 0.0 { -1.0 } else { 1.0 };
+        let n0 = scale(perp(d0), side);
+        let n1 = scale(perp(d1), side);
+
+        match style.join {
+            LineJoin::Round => {
+                let from = n0[1].atan2(n0[0]);
+                let mut sweep = n1[1].atan2(n1[0]) - from;
+                if sweep > PI {
+                    sweep -= 2.0 * PI;
+                } else if sweep < -PI {
+                    sweep += 2.0 * PI;
+                }
+                let rim = self.arc_directions(from, sweep);
+                self.fan(p, &rim);
+            }
+            LineJoin::Miter | LineJoin::Bevel => {
+                let bisector = add(n0, n1);
+                let cos_half = if length(bisector) > 1e-6 {
+                    dot(normalize(bisector), n0)
+                } else {
+                    0.0
+                };
+                let miter_ok = style.join == LineJoin::Miter
+                    && cos_half > 1e-6
+                    && 1.0 / cos_half <= style.miter_limit;
+                if miter_ok {
+                    let m = scale(normalize(bisector), 1.0 / cos_half);
+                    self.fan(p, &[n0, m, n1]);
+                } else {
+                    self.fan(p, &[n0, n1]);
+                }
+            }
+        }
+    }
+
+    /// Cap at end point `e` where `t` points away from the stroke
+    fn cap(&mut self, e: V2, t: V2, cap: LineCap) {
+        let n = perp(t);
+        match cap {
+            LineCap::Round => {
+                let from = n[1].atan2(n[0]);
+                let rim = self.arc_directions(from, -PI);
+                self.fan(e, &rim);
+            }
+            LineCap::Butt | LineCap::Square => {
+                let e = if cap == LineCap::Square {
+                    let ext = add(e, scale(t, self.half));
+                    self.segment(e, ext, n);
+                    ext
+                } else {
+                    e
+                };
+                // zero-area strip that the vertex shader opens up to one pixel
+                let h = self.half;
+                let v = [
+                    self.vertex(add(e, scale(n, h)), n, [h, 0.0], [1.0, 0.0]),
+                    self.vertex(sub(e, scale(n, h)), scale(n, -1.0), [-h, 0.0], [-1.0, 0.0]),
+                    self.vertex(sub(e, scale(n, h)), sub(t, n), [-h, 0.0], [-1.0, 1.0]),
+                    self.vertex(add(e, scale(n, h)), add(t, n), [h, 0.0], [1.0, 1.0]),
+                ];
+                self.quad(v);
+            }
+        }
+    }
+}
+
+/// Strokes a polyline into `mesh`. Consecutive duplicate points are ignored;
+/// a single point produces a dot for round and square caps, like canvas.
+pub(crate) fn stroke_polyline(
+    points: &[[f32; 2]],
+    closed: bool,
+    style: &StrokeStyle,
+    mesh: &mut Mesh<StrokeVertex>,
+) {
+    let half = style.width * 0.5;
+    if half <= 0.0 || !half.is_finite() {
+        return;
+    }
+
+    let mut pts: Vec<V2> = Vec::with_capacity(points.len());
+    for p in points {
+        if pts.last().is_none_or(|last| length(sub(*p, *last)) > 1e-5) {
+            pts.push(*p);
+        }
+    }
+    if closed && pts.len() > 2 && length(sub(pts[0], pts[pts.len() - 1])) <= 1e-5 {
+        pts.pop();
+    }
+
+    let mut s = Stroker {
+        mesh,
+        half,
+        color: style.color,
+    };
+
+    if pts.len() == 1 {
+        let p = pts[0];
+        match style.cap {
+            LineCap::Round => {
+                let rim = s.arc_directions(0.0, 2.0 * PI);
+                s.fan(p, &rim);
+            }
+            LineCap::Square => {
+                let o = sub(p, [half, 0.0]);
+                s.segment(o, add(p, [half, 0.0]), [0.0, 1.0]);
+                s.cap(add(p, [half, 0.0]), [1.0, 0.0], LineCap::Butt);
+                s.cap(o, [-1.0, 0.0], LineCap::Butt);
+            }
+            LineCap::Butt => {}
+        }
+        return;
+    }
+    if pts.len() < 2 {
+        return;
+    }
+
+    let closed = closed && pts.len() > 2;
+    let segment_count = if closed { pts.len() } else { pts.len() - 1 };
+    let dirs: Vec<V2> = (0..segment_count)
+        .map(|i| normalize(sub(pts[(i + 1) % pts.len()], pts[i])))
+        .collect();
+
+    for (i, d) in dirs.iter().enumerate() {
+        s.segment(pts[i], pts[(i + 1) % pts.len()], perp(*d));
+    }
+
+    if closed {
+        for i in 0..pts.len() {
+            let prev = dirs[(i + segment_count - 1) % segment_count];
+            s.join(pts[i], prev, dirs[i], style);
+        }
+    } else {
+        for i in 1..pts.len() - 1 {
+            s.join(pts[i], dirs[i - 1], dirs[i], style);
+        }
+        s.cap(pts[0], scale(dirs[0], -1.0), style.cap);
+        s.cap(pts[pts.len() - 1], dirs[segment_count - 1], style.cap);
+    }
+}
+
+/// Strokes a polyline whose half width changes from point to point, with
+/// round joins and caps sized to the point they sit on. `halves` holds one
+/// half width per point.
+pub(crate) fn stroke_tapered(
+    points: &[[f32; 2]],
+    halves: &[f32],
+    color: [f32; 4],
+    mesh: &mut Mesh<StrokeVertex>,
+) {
+    let mut pts: Vec<(V2, f32)> = Vec::with_capacity(points.len());
+    for (p, h) in points.iter().zip(halves) {
+        if !(h.is_finite() && *h > 0.0) {
+            continue;
+        }
+        match pts.last_mut() {
+            // keep the wider of two samples at the same spot
+            Some(last) if length(sub(*p, last.0)) <= 1e-5 => last.1 = last.1.max(*h),
+            _ => pts.push((*p, *h)),
+        }
+    }
+    let Some(&(first, first_half)) = pts.first() else {
+        return;
+    };
+    let mut s = Stroker {
+        mesh,
+        half: first_half,
+        color,
+    };
+    if pts.len() == 1 {
+        let rim = s.arc_directions(0.0, 2.0 * PI);
+        s.fan(first, &rim);
+        return;
+    }
+
+    let round = StrokeStyle {
+        join: LineJoin::Round,
+        cap: LineCap::Round,
+        ..StrokeStyle::canvas_default(0.0, color)
+    };
+    let dirs: Vec<V2> = pts
+        .windows(2)
+        .map(|w| normalize(sub(w[1].0, w[0].0)))
+        .collect();
+    for (i, d) in dirs.iter().enumerate() {
+        let ((a, ha), (b, hb)) = (pts[i], pts[i + 1]);
+        let n = perp(*d);
+        let mut v = [0; 4];
+        s.half = ha;
+        v[0] = s.vertex(add(a, scale(n, ha)), n, [ha, INSIDE], [1.0, 0.0]);
+        v[1] = s.vertex(
+            sub(a, scale(n, ha)),
+            scale(n, -1.0),
+            [-ha, INSIDE],
+            [-1.0, 0.0],
+        );
+        s.half = hb;
+        v[2] = s.vertex(
+            sub(b, scale(n, hb)),
+            scale(n, -1.0),
+            [-hb, INSIDE],
+            [-1.0, 0.0],
+        );
+        v[3] = s.vertex(add(b, scale(n, hb)), n, [hb, INSIDE], [1.0, 0.0]);
+        s.quad(v);
+    }
+    for i in 1..pts.len() - 1 {
+        s.half = pts[i].1;
+        s.join(pts[i].0, dirs[i - 1], dirs[i], &round);
+    }
+    s.half = first_half;
+    s.cap(first, scale(dirs[0], -1.0), LineCap::Round);
+    let (last, last_half) = pts[pts.len() - 1];
+    s.half = last_half;
+    s.cap(last, dirs[dirs.len() - 1], LineCap::Round);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
+    /// Area covered at rest, i.e. before the one-pixel AA extrusion
+    fn area(mesh: &Mesh<StrokeVertex>) -> f32 {
+        mesh.indices
+            .chunks(3)
+            .map(|t| {
+                let [a, b, c] = [0, 1, 2].map(|k| mesh.vertices[t[k] as usize].position);
+                cross(sub(b, a), sub(c, a)).abs() * 0.5
+            })
+            .sum()
+    }
+
+    fn style(join: LineJoin, cap: LineCap) -> StrokeStyle {
+        StrokeStyle {
+            width: 4.0,
+            join,
+            cap,
+            miter_limit: 10.0,
+            color: BLACK,
+        }
+    }
+
+    #[test]
+    fn caps_extend_the_segment() {
+        let line = [[0.0, 0.0], [10.0, 0.0]];
+        let mut butt = Mesh::default();
+        stroke_polyline(
+            &line,
+            false,
+            &style(LineJoin::Miter, LineCap::Butt),
+            &mut butt,
+        );
+        assert!((area(&butt) - 40.0).abs() < 1e-3);
+
+        let mut square = Mesh::default();
+        stroke_polyline(
+            &line,
+            false,
+            &style(LineJoin::Miter, LineCap::Square),
+            &mut square,
+        );
+        assert!((area(&square) - 56.0).abs() < 1e-3);
+
+        let mut round = Mesh::default();
+        stroke_polyline(
+            &line,
+            false,
+            &style(LineJoin::Miter, LineCap::Round),
+            &mut round,
+        );
+        let disc = PI * 4.0;
+        assert!((area(&round) - (40.0 + disc)).abs() < disc * 0.05);
+    }
+
+    #[test]
+    fn right_angle_joins() {
+        let corner = [[0.0, 0.0], [10.0, 0.0], [10.0, 10.0]];
+        let body = 2.0 * 40.0;
+        let mut bevel = Mesh::default();
+        stroke_polyline(
+            &corner,
+            false,
+            &style(LineJoin::Bevel, LineCap::Butt),
+            &mut bevel,
+        );
+        assert!((area(&bevel) - (body + 2.0)).abs() < 1e-3);
+
+        let mut miter = Mesh::default();
+        stroke_polyline(
+            &corner,
+            false,
+            &style(LineJoin::Miter, LineCap::Butt),
+            &mut miter,
+        );
+        assert!((area(&miter) - (body + 4.0)).abs() < 1e-3);
+
+        let mut round = Mesh::default();
+        stroke_polyline(
+            &corner,
+            false,
+            &style(LineJoin::Round, LineCap::Butt),
+            &mut round,
+        );
+        assert!((area(&round) - (body + PI)).abs() < PI * 0.05);
+    }
+
+    #[test]
+    fn sharp_miter_falls_back_to_bevel() {
+        let spike = [[0.0, 0.0], [10.0, 0.0], [0.0, 0.5]];
+        let mut mesh = Mesh::default();
+        stroke_polyline(
+            &spike,
+            false,
+            &style(LineJoin::Miter, LineCap::Butt),
+            &mut mesh,
+        );
+        let far = mesh
+            .vertices
+            .iter()
+            .map(|v| v.position[0])
+            .fold(f32::MIN, f32::max);
+        assert!(far < 13.0, "miter tip not clipped: {far}");
+    }
+
+    #[test]
+    fn closed_outline_has_no_caps() {
+        let square = [[0.0, 0.0], [10.0, 0.0], [10.0, 10.0], [0.0, 10.0]];
+        let mut mesh = Mesh::default();
+        stroke_polyline(
+            &square,
+            true,
+            &style(LineJoin::Miter, LineCap::Round),
+            &mut mesh,
+        );
+        // (14 * 14) - (6 * 6), with overlapping inner corners counted twice
+        let band = 14.0 * 14.0 - 6.0 * 6.0;
+        assert!(area(&mesh) >= band - 1e-3);
+        assert!(mesh.vertices.iter().all(|v| v.dist[1] == INSIDE));
+    }
+
+    #[test]
+    fn single_point_dot() {
+        let mut mesh = Mesh::default();
+        stroke_polyline(
+            &[[3.0, 3.0]],
+            false,
+            &style(LineJoin::Round, LineCap::Butt),
+            &mut mesh,
+        );
+        assert!(mesh.indices.is_empty());
+        stroke_polyline(
+            &[[3.0, 3.0]],
+            false,
+            &style(LineJoin::Round, LineCap::Round),
+            &mut mesh,
+        );
+        assert!((area(&mesh) - PI * 4.0).abs() < PI * 4.0 * 0.05);
+    }
+}
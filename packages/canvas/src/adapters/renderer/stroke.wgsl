@@ -0,0 +1,63 @@
+struct VertexInput {
+  @location(0) position: vec2<f32>,
+  @location(1) extrude: vec2<f32>,
+  @location(2) dist: vec2<f32>,
+  @location(3) grow: vec2<f32>,
+  @location(4) color: vec4<f32>,
+  @location(5) half_width: f32,
+}
+
+struct VertexOutput {
+  // both passes of a stroke must rasterise the same fragments
+  @invariant @builtin(position) position: vec4<f32>,
+  @location(0) color: vec4<f32>,
+  @location(1) dist: vec2<f32>,
+  @location(2) half_width: f32,
+  @location(3) @interpolate(flat) ordinal: u32,
+}
+
+struct FragmentOutput {
+  @location(0) color: vec4<f32>,
+  @builtin(frag_depth) depth: f32,
+}
+
+struct Camera {
+  scale: vec2<f32>,
+  translate: vec2<f32>,
+  world_per_pixel: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+@vertex
+fn vs_main(in: VertexInput, @builtin(instance_index) ordinal: u32) -> VertexOutput {
+  // push every outline vertex one pixel outwards to make room for the AA ramp
+  let px = camera.world_per_pixel;
+  let world = in.position + in.extrude * px;
+
+  var out: VertexOutput;
+  out.position = vec4<f32>(world * camera.scale + camera.translate, 0.0, 1.0);
+  out.color = in.color;
+  out.dist = in.dist + in.grow * px;
+  out.half_width = in.half_width;
+  out.ordinal = ordinal;
+  return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+  let aa = max(fwidth(in.dist), vec2<f32>(1e-6));
+  let across = clamp((in.half_width - abs(in.dist.x)) / aa.x + 0.5, 0.0, 1.0);
+  let along = clamp(-in.dist.y / aa.y + 0.5, 0.0, 1.0);
+  let coverage = across * along;
+
+  // 64 coverage levels above the ordinal of the stroke, so a stroke's
+  // depths are higher than every earlier stroke's; see `stroke_pipeline.rs`
+  let level = in.ordinal * 64u + u32(round(coverage * 63.0));
+
+  var out: FragmentOutput;
+  out.color = vec4<f32>(in.color.rgb, in.color.a * coverage);
+  out.depth = f32(level) / 16777216.0;
+  return out;
+}
@@ -0,0 +1,124 @@
+use crate::adapters::renderer::targets::DEPTH_STENCIL_FORMAT;
+use crate::adapters::renderer::vertex::StrokeVertex;
+
+/// Most strokes one render pass can tell apart in the depth buffer
+pub(crate) const MAX_STROKES_PER_PASS: u32 = (1 << 17) - 1;
+
+/// The two draws of every stroked shape. Joins, caps and the AA fringes of
+/// neighbouring segments overlap, so blending every triangle would darken
+/// translucent strokes wherever they meet. Instead each shape is drawn with
+/// its ordinal in the pass as instance index, first for [`Coverage`] and
+/// then for [`Blend`], so every pixel blends once with the highest coverage
+/// any of the shape's triangles gives it.
+///
+/// [`Coverage`]: StrokePass::Coverage
+/// [`Blend`]: StrokePass::Blend
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum StrokePass {
+    /// Writes no colour: keeps the highest coverage per pixel as depth, which
+    /// the ordinal puts above every earlier shape, and zeroes the stencil
+    Coverage,
+    /// Blends only fragments at the kept depth and bumps the stencil, so
+    /// later fragments with the same coverage are dropped
+    Blend,
+}
+
+/// Pipeline for `stroke.rs` geometry: coverage comes from the fragment
+/// shader, so strokes stay smooth with MSAA off; `sample_count` only has to
+/// match the colour target.
+pub(crate) fn create_stroke_pipeline(
+    device: &wgpu::Device,
+    surface_format: wgpu::TextureFormat,
+    camera_layout: &wgpu::BindGroupLayout,
+    sample_count: u32,
+    pass: StrokePass,
+) -> wgpu::RenderPipeline {
+    let shader = device.create_shader_module(wgpu::include_wgsl!("stroke.wgsl"));
+
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Stroke Pipeline Layout"),
+        bind_group_layouts: &[camera_layout],
+        immediate_size: 0,
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(match pass {
+            StrokePass::Coverage => "Stroke Coverage Pipeline",
+            StrokePass::Blend => "Stroke Pipeline",
+        }),
+        layout: Some(&layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: Some("vs_main"),
+            compilation_options: Default::default(),
+            buffers: &[StrokeVertex::desc()],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            compilation_options: Default::default(),
+            targets: &[Some(wgpu::ColorTargetState {
+                format: surface_format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: match pass {
+                    StrokePass::Coverage => wgpu::ColorWrites::empty(),
+                    StrokePass::Blend => wgpu::ColorWrites::ALL,
+                },
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        depth_stencil: Some(depth_stencil(pass)),
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview_mask: None,
+        cache: None,
+    })
+}
+
+fn depth_stencil(pass: StrokePass) -> wgpu::DepthStencilState {
+    let (depth_write_enabled, depth_compare, compare, fail_op, pass_op) = match pass {
+        StrokePass::Coverage => (
+            true,
+            wgpu::CompareFunction::Greater,
+            wgpu::CompareFunction::Always,
+            wgpu::StencilOperation::Zero,
+            wgpu::StencilOperation::Zero,
+        ),
+        StrokePass::Blend => (
+            false,
+            wgpu::CompareFunction::Equal,
+            wgpu::CompareFunction::Equal,
+            wgpu::StencilOperation::Keep,
+            wgpu::StencilOperation::IncrementClamp,
+        ),
+    };
+    let face = wgpu::StencilFaceState {
+        compare,
+        fail_op,
+        depth_fail_op: fail_op,
+        pass_op,
+    };
+    wgpu::DepthStencilState {
+        format: DEPTH_STENCIL_FORMAT,
+        depth_write_enabled,
+        depth_compare,
+        stencil: wgpu::StencilState {
+            front: face,
+            back: face,
+            read_mask: 0xff,
+            write_mask: 0xff,
+        },
+        bias: wgpu::DepthBiasState::default(),
+    }
+}
@@ -0,0 +1,126 @@
+/// Multisampled colour attachment that resolves into the frame's target view.
+/// Shared by both renderers; `None` from [`MsaaTarget::new`] means MSAA is off.
+#[derive(Debug)]
+pub(crate) struct MsaaTarget {
+    _texture: wgpu::Texture,
+    view: wgpu::TextureView,
+}
+
+impl MsaaTarget {
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+        sample_count: u32,
+    ) -> Option<Self> {
+        if sample_count <= 1 {
+            return None;
+        }
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("MSAA Colour Texture"),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Some(Self {
+            _texture: texture,
+            view,
+        })
+    }
+}
+
+/// Colour attachment that clears and draws into `msaa` when present and
+/// resolves into `target`, or draws into `target` directly otherwise
+pub(crate) fn color_attachment<'a>(
+    msaa: Option<&'a MsaaTarget>,
+    target: &'a wgpu::TextureView,
+    clear: wgpu::Color,
+) -> wgpu::RenderPassColorAttachment<'a> {
+    let (view, resolve_target, store) = match msaa {
+        Some(msaa) => (&msaa.view, Some(target), wgpu::StoreOp::Discard),
+        None => (target, None, wgpu::StoreOp::Store),
+    };
+    wgpu::RenderPassColorAttachment {
+        view,
+        resolve_target,
+        depth_slice: None,
+        ops: wgpu::Operations {
+            load: wgpu::LoadOp::Clear(clear),
+            store,
+        },
+    }
+}
+
+/// Format of [`DepthStencilTarget`]
+pub(crate) const DEPTH_STENCIL_FORMAT: wgpu::TextureFormat =
+    wgpu::TextureFormat::Depth24PlusStencil8;
+
+/// Depth and stencil buffer the 2D scene uses to blend each stroke once per
+/// pixel; see `stroke_pipeline.rs`. Matches the colour target's size and
+/// sample count.
+#[derive(Debug)]
+pub(crate) struct DepthStencilTarget {
+    _texture: wgpu::Texture,
+    view: wgpu::TextureView,
+}
+
+impl DepthStencilTarget {
+    pub(crate) fn new(device: &wgpu::Device, width: u32, height: u32, sample_count: u32) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Depth Stencil Texture"),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: DEPTH_STENCIL_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self {
+            _texture: texture,
+            view,
+        }
+    }
+
+    /// Attachment cleared to depth 0 and stencil 0 at the start of a pass
+    pub(crate) fn attachment(&self) -> wgpu::RenderPassDepthStencilAttachment<'_> {
+        wgpu::RenderPassDepthStencilAttachment {
+            view: &self.view,
+            depth_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(0.0),
+                store: wgpu::StoreOp::Discard,
+            }),
+            stencil_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(0),
+                store: wgpu::StoreOp::Discard,
+            }),
+        }
+    }
+}
+
+/// Depth-stencil state for pipelines that draw into a [`DepthStencilTarget`]
+/// without testing or writing it
+pub(crate) fn ignore_depth_stencil() -> wgpu::DepthStencilState {
+    wgpu::DepthStencilState {
+        format: DEPTH_STENCIL_FORMAT,
+        depth_write_enabled: false,
+        depth_compare: wgpu::CompareFunction::Always,
+        stencil: wgpu::StencilState::default(),
+        bias: wgpu::DepthBiasState::default(),
+    }
+}
@@ -0,0 +1,474 @@
+use std::f32::consts::PI;
+
+use crate::adapters::renderer::stroke::{
+    stroke_polyline, stroke_tapered, LineCap, LineJoin, StrokeStyle,
+};
+use crate::adapters::renderer::vertex::{StrokeVertex, TextVertex, Vertex};
+use crate::ink;
+use crate::model::color::Rgba;
+use crate::model::{shape, Shape, ShapeKind};
+use crate::text::layout::{TextLayout, TextStyle};
+use crate::text::Typesetter;
+
+const ARROW_HEAD_SIZE: f32 = shape::ARROW_HEAD_SIZE as f32;
+
+/// Triangle list in world coordinates, ready to upload
+#[derive(Debug, Clone)]
+pub(crate) struct Mesh<V = Vertex> {
+    pub(crate) vertices: Vec<V>,
+    pub(crate) indices: Vec<u32>,
+}
+
+impl<V> Default for Mesh<V> {
+    fn default() -> Self {
+        Self {
+            vertices: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+}
+
+impl Mesh {
+    fn push_vertex(&mut self, p: [f32; 2], color: [f32; 4]) -> u32 {
+        self.vertices.push(Vertex { position: p, color });
+        (self.vertices.len() - 1) as u32
+    }
+
+    pub(crate) fn push_triangle(&mut self, a: [f32; 2], b: [f32; 2], c: [f32; 2], color: [f32; 4]) {
+        let i = self.push_vertex(a, color);
+        self.push_vertex(b, color);
+        self.push_vertex(c, color);
+        self.indices.extend_from_slice(&[i, i + 1, i + 2]);
+    }
+
+    /// Convex polygon as a fan around its first point
+    pub(crate) fn push_fan(&mut self, points: &[[f32; 2]], color: [f32; 4]) {
+        if points.len() < 3 {
+            return;
+        }
+        let first = self.vertices.len() as u32;
+        for p in points {
+            self.push_vertex(*p, color);
+        }
+        for k in 1..points.len() as u32 - 1 {
+            self.indices
+                .extend_from_slice(&[first, first + k, first + k + 1]);
+        }
+    }
+}
+
+/// Appends the fill triangles of one shape to `fill` and its outline to
+/// `stroke`. Text is not tessellated here, see [`tessellate_text`].
+pub(crate) fn tessellate_shape(shape: &Shape, fill: &mut Mesh, stroke: &mut Mesh<StrokeVertex>) {
+    let stroke_color = Rgba::parse(&shape.stroke)
+        .unwrap_or(Rgba::BLACK)
+        .to_linear()
+        .to_array();
+    let fill_color = shape
+        .fill
+        .as_deref()
+        .and_then(Rgba::parse)
+        .filter(Rgba::is_visible)
+        .map(|c| c.to_linear().to_array());
+    let width = shape.stroke_width.max(0.0) as f32;
+    let style = StrokeStyle::canvas_default(width, stroke_color);
+
+    match &shape.kind {
+        ShapeKind::Rectangle { x, y, w, h } => {
+            let (x0, y0, x1, y1) = normalized_rect(*x, *y, *w, *h);
+            let corners = [[x0, y0], [x1, y0], [x1, y1], [x0, y1]];
+            if let Some(color) = fill_color {
+                fill.push_fan(&corners, color);
+            }
+            stroke_polyline(&corners, true, &style, stroke);
+        }
+        ShapeKind::Ellipse { x, y, w, h } => {
+            let (x0, y0, x1, y1) = normalized_rect(*x, *y, *w, *h);
+            let (cx, cy) = ((x0 + x1) * 0.5, (y0 + y1) * 0.5);
+            let (rx, ry) = ((x1 - x0) * 0.5, (y1 - y0) * 0.5);
+            let outline = ellipse_points(cx, cy, rx, ry, ellipse_segments(rx, ry));
+            if let Some(color) = fill_color {
+                fill.push_fan(&outline, color);
+            }
+            // the outline is already finely subdivided, so skip the miter math
+            let style = StrokeStyle {
+                join: LineJoin::Bevel,
+                ..style
+            };
+            stroke_polyline(&outline, true, &style, stroke);
+        }
+        ShapeKind::Line { a, b } => {
+            stroke_polyline(&[to_f32(a), to_f32(b)], false, &style, stroke);
+        }
+        ShapeKind::Arrow { a, b } => {
+            let (a, b) = (to_f32(a), to_f32(b));
+            stroke_polyline(&[a, b], false, &style, stroke);
+            let angle = (b[1] - a[1]).atan2(b[0] - a[0]);
+            let wing = |da: f32| {
+                [
+                    b[0] - ARROW_HEAD_SIZE * (angle + da).cos(),
+                    b[1] - ARROW_HEAD_SIZE * (angle + da).sin(),
+                ]
+            };
+            fill.push_triangle(b, wing(-PI / 6.0), wing(PI / 6.0), stroke_color);
+        }
+        ShapeKind::Pencil { points, pressure } => {
+            let points: Vec<[f32; 2]> = points.iter().map(to_f32).collect();
+            if pressure.is_empty() {
+                let ink = StrokeStyle {
+                    join: LineJoin::Round,
+                    cap: LineCap::Round,
+                    ..style
+                };
+                stroke_polyline(&points, false, &ink, stroke);
+            } else {
+                let halves: Vec<f32> = ink::half_widths(shape.stroke_width, pressure, points.len())
+                    .into_iter()
+                    .map(|h| h as f32)
+                    .collect();
+                stroke_tapered(&points, &halves, stroke_color, stroke);
+            }
+        }
+        ShapeKind::Text { .. } => {}
+    }
+}
+
+/// Appends a quad per glyph of a text shape to `mesh`, adding the glyphs
+/// the atlas lacks. Text is filled with the stroke colour, as `renderText`
+/// does; other shapes and text without a loaded font add nothing.
+pub(crate) fn tessellate_text(shape: &Shape, text: &mut Typesetter, mesh: &mut Mesh<TextVertex>) {
+    let (
+        ShapeKind::Text {
+            x,
+            y,
+            text: content,
+            ..
+        },
+        Some(style),
+    ) = (&shape.kind, TextStyle::of(&shape.kind))
+    else {
+        return;
+    };
+    let color = Rgba::parse(&shape.stroke)
+        .unwrap_or(Rgba::BLACK)
+        .to_linear()
+        .to_array();
+    let layout = text.layout(content, &style);
+    if !set_text(*x, *y, style.font_size, color, &layout, text, mesh) {
+        tracing::warn!("text of shape {} does not fit in the glyph atlas", shape.id);
+    }
+}
+
+/// Appends a quad per glyph of `layout`, set at `size` with its anchor at
+/// (`x`, `y`). The atlas may start over while it takes the glyphs, moving
+/// those placed before, so then the text is set once more against the fresh
+/// atlas; text that cannot fit even then is left out and `false` returned.
+pub(crate) fn set_text(
+    x: f64,
+    y: f64,
+    size: f64,
+    color: [f32; 4],
+    layout: &TextLayout,
+    text: &mut Typesetter,
+    mesh: &mut Mesh<TextVertex>,
+) -> bool {
+    let start = (mesh.vertices.len(), mesh.indices.len());
+    for _ in 0..2 {
+        let generation = text.atlas().generation();
+        push_glyphs(x, y, size as f32, color, layout, text, mesh);
+        if text.atlas().generation() == generation {
+            return true;
+        }
+        mesh.vertices.truncate(start.0);
+        mesh.indices.truncate(start.1);
+    }
+    false
+}
+
+fn push_glyphs(
+    x: f64,
+    y: f64,
+    size: f32,
+    color: [f32; 4],
+    layout: &TextLayout,
+    text: &mut Typesetter,
+    mesh: &mut Mesh<TextVertex>,
+) {
+    for glyph in &layout.glyphs {
+        let Some(placed) = text.glyph(glyph) else {
+            continue;
+        };
+        let origin = [(x + glyph.x) as f32, (y + glyph.y) as f32];
+        let [l, t, r, b] = placed.plane.map(|v| v * size);
+        let [u0, v0, u1, v1] = placed.uv;
+        let first = mesh.vertices.len() as u32;
+        for (position, uv) in [
+            ([l, t], [u0, v0]),
+            ([r, t], [u1, v0]),
+            ([r, b], [u1, v1]),
+            ([l, b], [u0, v1]),
+        ] {
+            mesh.vertices.push(TextVertex {
+                position: [origin[0] + position[0], origin[1] + position[1]],
+                uv,
+                color,
+            });
+        }
+        mesh.indices
+            .extend_from_slice(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+    }
+}
+
+fn to_f32(p: &crate::model::Point) -> [f32; 2] {
+    [p.x as f32, p.y as f32]
+}
+
+fn normalized_rect(x: f64, y: f64, w: f64, h: f64) -> (f32, f32, f32, f32) {
+    let (x0, x1) = (x.min(x + w) as f32, x.max(x + w) as f32);
+    let (y0, y1) = (y.min(y + h) as f32, y.max(y + h) as f32);
+    (x0, y0, x1, y1)
+}
+
+fn ellipse_segments(rx: f32, ry: f32) -> usize {
+    let r = rx.max(ry);
+    ((r * 0.5).ceil() as usize).clamp(16, 128)
+}
+
+fn ellipse_points(cx: f32, cy: f32, rx: f32, ry: f32, segments: usize) -> Vec<[f32; 2]> {
+    (0..segments)
+        .map(|i| {
+            let t = i as f32 / segments as f32 * 2.0 * PI;
+            [cx + rx * t.cos(), cy + ry * t.sin()]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Point, WhiteboardDoc};
+
+    fn shape(kind: ShapeKind, fill: Option<&str>) -> Shape {
+        Shape {
+            id: "s".to_string(),
+            stroke: "#000000".to_string(),
+            fill: fill.map(str::to_string),
+            stroke_width: 2.0,
+            kind,
+        }
+    }
+
+    fn fill_area(mesh: &Mesh) -> f32 {
+        mesh.indices
+            .chunks(3)
+            .map(|t| {
+                let [a, b, c] = [0, 1, 2].map(|k| mesh.vertices[t[k] as usize].position);
+                ((b[0] - a[0]) * (c[1] - a[1]) - (c[0] - a[0]) * (b[1] - a[1])).abs() * 0.5
+            })
+            .sum()
+    }
+
+    #[test]
+    fn vertex_layouts_match_structs() {
+        assert_eq!(
+            Vertex::desc().array_stride,
+            std::mem::size_of::<Vertex>() as u64
+        );
+        assert_eq!(
+            StrokeVertex::desc().array_stride,
+            std::mem::size_of::<StrokeVertex>() as u64
+        );
+        assert_eq!(
+            TextVertex::desc().array_stride,
+            std::mem::size_of::<TextVertex>() as u64
+        );
+    }
+
+    #[test]
+    fn rectangle_fill_is_separate_from_outline() {
+        let rect = ShapeKind::Rectangle {
+            x: 10.0,
+            y: 10.0,
+            w: -100.0,
+            h: 50.0,
+        };
+        let (mut fill, mut stroke) = (Mesh::default(), Mesh::default());
+        tessellate_shape(&shape(rect.clone(), None), &mut fill, &mut stroke);
+        assert!(fill.indices.is_empty());
+        assert!(!stroke.indices.is_empty());
+
+        let (mut fill, mut stroke) = (Mesh::default(), Mesh::default());
+        tessellate_shape(&shape(rect, Some("#3b82f6")), &mut fill, &mut stroke);
+        assert!((fill_area(&fill) - 5000.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn transparent_fill_and_zero_width_are_skipped() {
+        let (mut fill, mut stroke) = (Mesh::default(), Mesh::default());
+        let mut s = shape(
+            ShapeKind::Ellipse {
+                x: 0.0,
+                y: 0.0,
+                w: 40.0,
+                h: 20.0,
+            },
+            Some("transparent"),
+        );
+        s.stroke_width = 0.0;
+        tessellate_shape(&s, &mut fill, &mut stroke);
+        assert!(fill.indices.is_empty());
+        assert!(stroke.indices.is_empty());
+    }
+
+    #[test]
+    fn pressure_tapers_pencil_strokes() {
+        let points: Vec<Point> = (0..5).map(|i| Point::new(i as f64 * 10.0, 0.0)).collect();
+        let pencil = |pressure: Vec<f64>| {
+            let (mut fill, mut stroke) = (Mesh::default(), Mesh::default());
+            let kind = ShapeKind::Pencil {
+                points: points.clone(),
+                pressure,
+            };
+            tessellate_shape(&shape(kind, None), &mut fill, &mut stroke);
+            assert!(stroke
+                .indices
+                .iter()
+                .all(|&i| (i as usize) < stroke.vertices.len()));
+            stroke
+                .vertices
+                .iter()
+                .map(|v| v.half_width)
+                .fold((f32::MAX, f32::MIN), |(lo, hi), w| (lo.min(w), hi.max(w)))
+        };
+        assert_eq!(pencil(Vec::new()), (1.0, 1.0));
+        // light at the start, twice the set width at full pressure
+        assert_eq!(pencil(vec![0.0, 0.25, 0.5, 0.75, 1.0]), (0.5, 1.5));
+        // a list that does not match the points keeps the width even
+        assert_eq!(pencil(vec![1.0]), (1.0, 1.0));
+    }
+
+    #[test]
+    fn text_quads_follow_the_shape_style() {
+        let mut text = Typesetter::new();
+        text.add_font(
+            crate::text::font::Font::from_bytes(crate::text::test_font::test_font(true, false))
+                .unwrap(),
+        );
+        let label = shape(
+            ShapeKind::Text {
+                x: 100.0,
+                y: 50.0,
+                text: "aa aa".to_string(),
+                font_size: 10.0,
+                align: Some(crate::text::layout::TextAlign::Center),
+                max_width: Some(12.0),
+                line_height: Some(2.0),
+            },
+            None,
+        );
+        let mut mesh = Mesh::default();
+        tessellate_text(&label, &mut text, &mut mesh);
+        // four glyphs on two lines, each centred on x = 100
+        assert_eq!((mesh.vertices.len(), mesh.indices.len()), (16, 24));
+        let xs: Vec<f32> = mesh.vertices.iter().map(|v| v.position[0]).collect();
+        let (left, right) = (
+            xs.iter().copied().fold(f32::MAX, f32::min),
+            xs.iter().copied().fold(f32::MIN, f32::max),
+        );
+        assert!(
+            (100.0 - left - (right - 100.0)).abs() < 1.5,
+            "{left}..{right}"
+        );
+        let baselines: Vec<f32> = mesh.vertices.iter().map(|v| v.position[1]).collect();
+        let bottom = baselines.iter().copied().fold(f32::MIN, f32::max);
+        assert!(bottom > 70.0 && bottom < 75.0, "{bottom}");
+    }
+
+    #[test]
+    fn text_is_set_again_when_the_atlas_starts_over() {
+        use crate::text::atlas::{ATLAS_WIDTH, GUTTER, MAX_ATLAS_HEIGHT};
+        use crate::text::layout::TextStyle;
+        use crate::text::sdf::GlyphField;
+
+        let mut text = Typesetter::new();
+        text.add_font(
+            crate::text::font::Font::from_bytes(crate::text::test_font::test_font(true, true))
+                .unwrap(),
+        );
+        // `!` is placed, then the rest of the atlas is filled so that `ก`
+        // starts it over and moves `!`
+        let layout = text.layout("!", &TextStyle::new(20.0));
+        let bang = text.glyph(&layout.glyphs[0]).unwrap();
+        let mut filler = |key: u16, width: u32, height: u32| {
+            let field = GlyphField {
+                width,
+                height,
+                data: vec![0; (width * height) as usize],
+                left: 0.0,
+                top: 0.0,
+            };
+            text.atlas_mut().insert((9, key), Some(field)).unwrap();
+        };
+        let shelf = bang.uv[3] as u32 + GUTTER;
+        filler(0, ATLAS_WIDTH - bang.uv[2] as u32 - 2 * GUTTER, 1);
+        filler(1, ATLAS_WIDTH - GUTTER, MAX_ATLAS_HEIGHT - shelf - GUTTER);
+
+        let label = shape(
+            ShapeKind::Text {
+                x: 0.0,
+                y: 50.0,
+                text: "!ก".to_string(),
+                font_size: 20.0,
+                align: None,
+                max_width: None,
+                line_height: None,
+            },
+            None,
+        );
+        let mut mesh = Mesh::default();
+        tessellate_text(&label, &mut text, &mut mesh);
+        assert_eq!(text.atlas().generation(), 1);
+        assert_eq!(mesh.vertices.len(), 8);
+        let layout = text.layout("!ก", &TextStyle::new(20.0));
+        for (quad, glyph) in mesh.vertices.chunks(4).zip(&layout.glyphs) {
+            let placed = text.atlas().get((glyph.font, glyph.glyph)).unwrap();
+            assert_eq!(quad[0].uv, [placed.unwrap().uv[0], placed.unwrap().uv[1]]);
+        }
+    }
+
+    #[test]
+    fn doc_is_tessellated_in_order_and_indices_are_valid() {
+        let mut doc = WhiteboardDoc::new();
+        let mut line = shape(
+            ShapeKind::Line {
+                a: Point::new(0.0, 0.0),
+                b: Point::new(10.0, 0.0),
+            },
+            None,
+        );
+        line.id = "line".to_string();
+        let mut arrow = shape(
+            ShapeKind::Arrow {
+                a: Point::new(0.0, 0.0),
+                b: Point::new(5.0, 5.0),
+            },
+            None,
+        );
+        arrow.id = "arrow".to_string();
+        doc.add_shape(line);
+        doc.add_shape(arrow);
+
+        let (mut fill, mut stroke) = (Mesh::default(), Mesh::default());
+        for shape in doc.ordered_shapes() {
+            tessellate_shape(shape, &mut fill, &mut stroke);
+        }
+        assert_eq!(fill.indices.len(), 3, "arrow head");
+        assert_eq!(stroke.indices.len() % 3, 0);
+        assert!(stroke
+            .indices
+            .iter()
+            .all(|&i| (i as usize) < stroke.vertices.len()));
+        // the line body comes first
+        assert_eq!(stroke.vertices[0].position, [0.0, 1.0]);
+    }
+}
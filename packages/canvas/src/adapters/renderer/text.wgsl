@@ -0,0 +1,50 @@
+struct VertexInput {
+  @location(0) position: vec2<f32>,
+  @location(1) uv: vec2<f32>,
+  @location(2) color: vec4<f32>,
+}
+
+struct VertexOutput {
+  @builtin(position) position: vec4<f32>,
+  @location(0) uv: vec2<f32>,
+  @location(1) color: vec4<f32>,
+}
+
+struct Camera {
+  scale: vec2<f32>,
+  translate: vec2<f32>,
+  world_per_pixel: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+@group(1) @binding(0)
+var atlas: texture_2d<f32>;
+
+@group(1) @binding(1)
+var atlas_sampler: sampler;
+
+// atlas pixels the distance field spans on either side of an outline,
+// `SDF_RANGE` in text/sdf.rs
+const SDF_RANGE: f32 = 4.0;
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+  var out: VertexOutput;
+  out.position = vec4<f32>(in.position * camera.scale + camera.translate, 0.0, 1.0);
+  out.uv = in.uv;
+  out.color = in.color;
+  return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+  let size = vec2<f32>(textureDimensions(atlas));
+  let field = textureSample(atlas, atlas_sampler, in.uv / size).r;
+  // atlas pixels per screen pixel turn the field into a one pixel edge ramp
+  let w = fwidth(in.uv);
+  let texels = max(0.5 * (w.x + w.y), 1e-6);
+  let coverage = clamp((field - 0.5) * 2.0 * SDF_RANGE / texels + 0.5, 0.0, 1.0);
+  return vec4<f32>(in.color.rgb, in.color.a * coverage);
+}
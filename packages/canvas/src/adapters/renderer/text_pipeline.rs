@@ -0,0 +1,204 @@
+use crate::adapters::renderer::targets;
+use crate::adapters::renderer::vertex::TextVertex;
+use crate::text::atlas::GlyphAtlas;
+
+/// Size of the texture before the first sync, when no glyph is drawn yet
+const PLACEHOLDER_SIZE: (u32, u32) = (1, 1);
+
+/// Pipeline for glyph quads: coverage is cut out of the distance field in
+/// the atlas, so like strokes the edges are smooth with MSAA off.
+pub(crate) fn create_text_pipeline(
+    device: &wgpu::Device,
+    surface_format: wgpu::TextureFormat,
+    camera_layout: &wgpu::BindGroupLayout,
+    atlas_layout: &wgpu::BindGroupLayout,
+    sample_count: u32,
+) -> wgpu::RenderPipeline {
+    let shader = device.create_shader_module(wgpu::include_wgsl!("text.wgsl"));
+
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Text Pipeline Layout"),
+        bind_group_layouts: &[camera_layout, atlas_layout],
+        immediate_size: 0,
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Text Pipeline"),
+        layout: Some(&layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: Some("vs_main"),
+            compilation_options: Default::default(),
+            buffers: &[TextVertex::desc()],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            compilation_options: Default::default(),
+            targets: &[Some(wgpu::ColorTargetState {
+                format: surface_format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        depth_stencil: Some(targets::ignore_depth_stencil()),
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview_mask: None,
+        cache: None,
+    })
+}
+
+/// The glyph atlas on the GPU, kept in step with a [`GlyphAtlas`] by
+/// uploading the rows it changed
+#[derive(Debug)]
+pub(crate) struct GlyphTexture {
+    pub(crate) layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    texture: wgpu::Texture,
+    pub(crate) bind_group: wgpu::BindGroup,
+    size: (u32, u32),
+}
+
+impl GlyphTexture {
+    pub(crate) fn new(device: &wgpu::Device) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Glyph Atlas BGL"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Glyph Atlas Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let size = PLACEHOLDER_SIZE;
+        let texture = create_texture(device, size);
+        let bind_group = create_bind_group(device, &layout, &texture, &sampler);
+        Self {
+            layout,
+            sampler,
+            texture,
+            bind_group,
+            size,
+        }
+    }
+
+    /// Uploads what changed in `atlas` since the last sync, recreating the
+    /// texture when the atlas grew
+    pub(crate) fn sync(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        atlas: &mut GlyphAtlas,
+    ) {
+        let dirty = atlas.take_dirty();
+        let (width, height) = atlas.size();
+        let rows = if self.size != (width, height) {
+            self.texture = create_texture(device, (width, height));
+            self.bind_group = create_bind_group(device, &self.layout, &self.texture, &self.sampler);
+            self.size = (width, height);
+            0..height
+        } else if let Some(rows) = dirty {
+            rows
+        } else {
+            return;
+        };
+        let start = (rows.start * width) as usize;
+        let end = (rows.end * width) as usize;
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: 0,
+                    y: rows.start,
+                    z: 0,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            &atlas.pixels()[start..end],
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(width),
+                rows_per_image: None,
+            },
+            wgpu::Extent3d {
+                width,
+                height: rows.end - rows.start,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+}
+
+fn create_texture(device: &wgpu::Device, (width, height): (u32, u32)) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Glyph Atlas"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::R8Unorm,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    })
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    texture: &wgpu::Texture,
+    sampler: &wgpu::Sampler,
+) -> wgpu::BindGroup {
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Glyph Atlas Bind Group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+    })
+}
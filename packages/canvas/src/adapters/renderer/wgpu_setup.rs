@@ -1,6 +1,9 @@
+use crate::config::app_config::WebGpuConfig;
 use crate::error::CanvasError;
+#[cfg(target_arch = "wasm32")]
 use crate::types::Size;
 
+#[cfg(target_arch = "wasm32")]
 pub(crate) struct WgpuContext {
     pub(crate) surface: wgpu::Surface<'static>,
     pub(crate) device: wgpu::Device,
@@ -11,12 +14,19 @@ pub(crate) struct WgpuContext {
 }
 
 pub(crate) fn create_instance() -> wgpu::Instance {
+    let backends = if cfg!(target_arch = "wasm32") {
+        wgpu::Backends::BROWSER_WEBGPU
+    } else {
+        // GL picks up Mesa's llvmpipe on GPU-less Linux machines
+        wgpu::Backends::PRIMARY | wgpu::Backends::GL
+    };
     wgpu::Instance::new(&wgpu::InstanceDescriptor {
-        backends: wgpu::Backends::BROWSER_WEBGPU,
+        backends,
         ..Default::default()
     })
 }
 
+#[cfg(target_arch = "wasm32")]
 pub(crate) fn create_surface(
     instance: &wgpu::Instance,
     canvas: web_sys::HtmlCanvasElement,
@@ -28,26 +38,36 @@ pub(crate) fn create_surface(
 
 pub(crate) async fn request_adapter(
     instance: &wgpu::Instance,
-    surface: &wgpu::Surface<'static>,
+    surface: Option<&wgpu::Surface<'static>>,
+    webgpu: &WebGpuConfig,
 ) -> Result<wgpu::Adapter, CanvasError> {
     instance
         .request_adapter(&wgpu::RequestAdapterOptions {
-            power_preference: wgpu::PowerPreference::default(),
-            compatible_surface: Some(surface),
-            force_fallback_adapter: false,
+            power_preference: webgpu.power_preference()?,
+            compatible_surface: surface,
+            force_fallback_adapter: webgpu.force_fallback,
         })
         .await
         .map_err(|e| CanvasError::AdapterRequest(format!("{:?}", e)))
 }
 
+/// Fallback and CPU adapters often miss the default limits, so they only
+/// get the downlevel set, which is all the renderers need.
 pub(crate) async fn request_device(
     adapter: &wgpu::Adapter,
+    webgpu: &WebGpuConfig,
 ) -> Result<(wgpu::Device, wgpu::Queue), CanvasError> {
+    let software = adapter.get_info().device_type == wgpu::DeviceType::Cpu;
+    let required_limits = if webgpu.force_fallback || software {
+        wgpu::Limits::downlevel_defaults().using_resolution(adapter.limits())
+    } else {
+        wgpu::Limits::default()
+    };
     adapter
         .request_device(&wgpu::DeviceDescriptor {
             label: None,
             required_features: wgpu::Features::empty(),
-            required_limits: wgpu::Limits::default(),
+            required_limits,
             memory_hints: Default::default(),
             trace: Default::default(),
             experimental_features: Default::default(),
@@ -56,11 +76,28 @@ pub(crate) async fn request_device(
         .map_err(|e| CanvasError::DeviceRequest(format!("{:?}", e)))
 }
 
+pub(crate) fn check_sample_count(
+    adapter: &wgpu::Adapter,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+) -> Result<(), CanvasError> {
+    let features = adapter.get_texture_format_features(format);
+    if features.flags.sample_count_supported(sample_count) {
+        Ok(())
+    } else {
+        Err(CanvasError::Config(format!(
+            "{sample_count}x MSAA is not supported for {format:?}"
+        )))
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
 pub(crate) fn surface_config_for_size(
     surface: &wgpu::Surface<'static>,
     adapter: &wgpu::Adapter,
     size: Size,
-) -> (wgpu::SurfaceConfiguration, wgpu::TextureFormat) {
+    webgpu: &WebGpuConfig,
+) -> Result<(wgpu::SurfaceConfiguration, wgpu::TextureFormat), CanvasError> {
     let surface_caps = surface.get_capabilities(adapter);
     let surface_format = surface_caps
         .formats
@@ -69,6 +106,8 @@ pub(crate) fn surface_config_for_size(
         .copied()
         .unwrap_or(surface_caps.formats[0]);
 
+    check_sample_count(adapter, surface_format, webgpu.sample_count)?;
+
     let config = wgpu::SurfaceConfiguration {
         usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
         format: surface_format,
@@ -80,10 +119,14 @@ pub(crate) fn surface_config_for_size(
         desired_maximum_frame_latency: 2,
     };
 
-    (config, surface_format)
+    Ok((config, surface_format))
 }
 
-pub(crate) async fn init(canvas: web_sys::HtmlCanvasElement) -> Result<WgpuContext, CanvasError> {
+#[cfg(target_arch = "wasm32")]
+pub(crate) async fn init(
+    canvas: web_sys::HtmlCanvasElement,
+    webgpu: &WebGpuConfig,
+) -> Result<WgpuContext, CanvasError> {
     let size = Size {
         width: canvas.width(),
         height: canvas.height(),
@@ -91,9 +134,9 @@ pub(crate) async fn init(canvas: web_sys::HtmlCanvasElement) -> Result<WgpuConte
 
     let instance = create_instance();
     let surface = create_surface(&instance, canvas)?;
-    let adapter = request_adapter(&instance, &surface).await?;
-    let (device, queue) = request_device(&adapter).await?;
-    let (config, surface_format) = surface_config_for_size(&surface, &adapter, size);
+    let adapter = request_adapter(&instance, Some(&surface), webgpu).await?;
+    let (device, queue) = request_device(&adapter, webgpu).await?;
+    let (config, surface_format) = surface_config_for_size(&surface, &adapter, size, webgpu)?;
 
     surface.configure(&device, &config);
 
@@ -1,3 +1,11 @@
+#[cfg(target_arch = "wasm32")]
 pub mod client;
+
+#[cfg(any(target_arch = "wasm32", test))]
 mod pipeline;
+
+#[cfg(any(target_arch = "wasm32", test))]
 mod resources;
+
+#[cfg(any(target_arch = "wasm32", test))]
+pub(crate) mod scene;
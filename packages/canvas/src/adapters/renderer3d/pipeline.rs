@@ -83,6 +83,7 @@ pub(crate) struct Uniforms {
 pub(crate) fn create_pipeline(
     device: &wgpu::Device,
     surface_format: wgpu::TextureFormat,
+    sample_count: u32,
 ) -> (wgpu::RenderPipeline, wgpu::BindGroupLayout) {
     let shader = device.create_shader_module(wgpu::include_wgsl!("shader.wgsl"));
 
@@ -142,7 +143,7 @@ pub(crate) fn create_pipeline(
             bias: Default::default(),
         }),
         multisample: wgpu::MultisampleState {
-            count: 1,
+            count: sample_count,
             mask: !0,
             alpha_to_coverage_enabled: false,
         },
@@ -1,10 +1,13 @@
+use crate::types::Size;
+
 pub(crate) fn create_depth_texture(
     device: &wgpu::Device,
-    config: &wgpu::SurfaceConfiguration,
+    size: Size,
+    sample_count: u32,
 ) -> (wgpu::Texture, wgpu::TextureView) {
     let size = wgpu::Extent3d {
-        width: config.width.max(1),
-        height: config.height.max(1),
+        width: size.width.max(1),
+        height: size.height.max(1),
         depth_or_array_layers: 1,
     };
 
@@ -12,7 +15,7 @@ pub(crate) fn create_depth_texture(
         label: Some("Depth Texture"),
         size,
         mip_level_count: 1,
-        sample_count: 1,
+        sample_count,
         dimension: wgpu::TextureDimension::D2,
         format: wgpu::TextureFormat::Depth24Plus,
         usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
@@ -0,0 +1,194 @@
+use crate::adapters::renderer::targets::{self, MsaaTarget};
+use crate::adapters::renderer3d::{pipeline, resources};
+use crate::types::Size;
+
+const CLEAR_COLOR: wgpu::Color = wgpu::Color {
+    r: 0.06,
+    g: 0.08,
+    b: 0.10,
+    a: 1.0,
+};
+
+/// GPU state for the spinning cube, independent of the surface it is drawn
+/// to, so the golden tests render exactly what `Client3d` does
+#[derive(Debug)]
+pub(crate) struct CubeRenderer {
+    pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    index_count: u32,
+
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+    msaa: Option<MsaaTarget>,
+    _depth_texture: wgpu::Texture,
+    depth_view: wgpu::TextureView,
+}
+
+impl CubeRenderer {
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        size: Size,
+        sample_count: u32,
+    ) -> Self {
+        let (pipeline, bind_group_layout) = pipeline::create_pipeline(device, format, sample_count);
+        let (vertex_buffer, index_buffer, index_count) = pipeline::create_mesh_buffers(device);
+
+        let uniforms = pipeline::Uniforms {
+            mvp: resources::mat4_identity(),
+        };
+        let uniform_buffer = pipeline::create_uniform_buffer(device, &uniforms);
+        let bind_group = pipeline::create_bind_group(device, &bind_group_layout, &uniform_buffer);
+
+        let msaa = MsaaTarget::new(device, format, size.width, size.height, sample_count);
+        let (depth_texture, depth_view) =
+            resources::create_depth_texture(device, size, sample_count);
+
+        Self {
+            pipeline,
+            vertex_buffer,
+            index_buffer,
+            index_count,
+            uniform_buffer,
+            bind_group,
+            format,
+            sample_count,
+            msaa,
+            _depth_texture: depth_texture,
+            depth_view,
+        }
+    }
+
+    /// Recreates the multisampled and depth targets for a new target size
+    pub(crate) fn resize(&mut self, device: &wgpu::Device, size: Size) {
+        self.msaa = MsaaTarget::new(
+            device,
+            self.format,
+            size.width,
+            size.height,
+            self.sample_count,
+        );
+        let (depth_texture, depth_view) =
+            resources::create_depth_texture(device, size, self.sample_count);
+        self._depth_texture = depth_texture;
+        self.depth_view = depth_view;
+    }
+
+    /// Records one pass drawing the cube turned by `angle` radians
+    pub(crate) fn encode(
+        &self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        size: Size,
+        angle: f32,
+    ) {
+        let aspect = (size.width.max(1) as f32) / (size.height.max(1) as f32);
+        let proj = resources::mat4_perspective(aspect, 60.0f32.to_radians(), 0.1, 100.0);
+        let view = resources::mat4_translate(0.0, 0.0, -2.5);
+        let model = resources::mat4_rotate_y(angle);
+        let mv = resources::mat4_mul(view, model);
+        let mvp = resources::mat4_mul(proj, mv);
+
+        let uniforms = pipeline::Uniforms { mvp };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
+
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("3D Render Pass"),
+            color_attachments: &[Some(targets::color_attachment(
+                self.msaa.as_ref(),
+                target,
+                CLEAR_COLOR,
+            ))],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+            multiview_mask: None,
+        });
+
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        pass.draw_indexed(0..self.index_count, 0, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::golden::{assert_golden, require_adapter};
+    use crate::adapters::renderer::{readback, wgpu_setup};
+    use crate::config::app_config::AppConfig;
+
+    const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    fn render(size: Size, sample_count: u32, angle: f32) -> Option<Vec<u8>> {
+        let mut config = AppConfig::default();
+        config.webgpu.force_fallback = true;
+        config.webgpu.sample_count = sample_count;
+        let instance = wgpu_setup::create_instance();
+        let adapter =
+            pollster::block_on(wgpu_setup::request_adapter(&instance, None, &config.webgpu))
+                .ok()?;
+        let (device, queue) =
+            pollster::block_on(wgpu_setup::request_device(&adapter, &config.webgpu)).ok()?;
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width: size.width,
+                height: size.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&Default::default());
+        // start small so the targets `Client3d::resize` rebuilds are exercised
+        let mut renderer = CubeRenderer::new(
+            &device,
+            FORMAT,
+            Size {
+                width: 1,
+                height: 1,
+            },
+            sample_count,
+        );
+        renderer.resize(&device, size);
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        renderer.encode(&queue, &mut encoder, &view, size, angle);
+        let buffer = readback::copy_texture_to_buffer(&device, &mut encoder, &texture);
+        queue.submit(std::iter::once(encoder.finish()));
+        Some(pollster::block_on(readback::read(&device, &buffer, size.width, size.height)).unwrap())
+    }
+
+    #[test]
+    fn golden_cube() {
+        let size = Size {
+            width: 96,
+            height: 64,
+        };
+        let Some(pixels) = render(size, 4, 0.6) else {
+            require_adapter("golden_cube");
+            return;
+        };
+        assert_golden("cube_msaa4", size.width, size.height, &pixels);
+    }
+}
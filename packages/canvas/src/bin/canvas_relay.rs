@@ -0,0 +1,75 @@
+//! `canvas-relay [--bind ADDR] [--storage DIR]`
+//! `canvas-relay token BOARD [viewer|editor]`
+//!
+//! Collaboration relay for whiteboards, see `canvas::relay`. Settings come
+//! from the `[relay]` section of `Config.toml` and `CANVAS_RELAY__*`
+//! variables, with the flags above taking precedence. `token` prints the
+//! share token for a board, signed with `relay.secret`.
+
+#[cfg(not(target_arch = "wasm32"))]
+#[tokio::main]
+async fn main() -> std::process::ExitCode {
+    use canvas::config::app_config::AppConfig;
+    use canvas::relay::{share_token, Relay, Role};
+    use tracing_subscriber::EnvFilter;
+
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()))
+        .init();
+
+    let mut config = match AppConfig::load() {
+        Ok(config) => config.relay,
+        Err(e) => {
+            eprintln!("{e}");
+            return std::process::ExitCode::FAILURE;
+        }
+    };
+    let mut args = std::env::args().skip(1).peekable();
+    if args.peek().map(String::as_str) == Some("token") {
+        let args: Vec<String> = args.skip(1).collect();
+        let role = match args.get(1).map(String::as_str) {
+            Some("viewer") => Role::Viewer,
+            None | Some("editor") => Role::Editor,
+            Some(other) => {
+                eprintln!("unknown role {other:?}");
+                return std::process::ExitCode::FAILURE;
+            }
+        };
+        let (Some(board), Some(secret), 1..=2) = (args.first(), &config.secret, args.len()) else {
+            eprintln!("usage: canvas-relay token BOARD [viewer|editor], with relay.secret set");
+            return std::process::ExitCode::FAILURE;
+        };
+        if let Err(e) = config.validate() {
+            eprintln!("{e}");
+            return std::process::ExitCode::FAILURE;
+        }
+        println!("{}", share_token(secret, board, role));
+        return std::process::ExitCode::SUCCESS;
+    }
+    while let Some(flag) = args.next() {
+        let value = args.next();
+        match (flag.as_str(), value) {
+            ("--bind", Some(v)) => config.bind = v,
+            ("--storage", Some(v)) => config.storage = v,
+            _ => {
+                eprintln!("usage: canvas-relay [--bind ADDR] [--storage DIR]");
+                return std::process::ExitCode::FAILURE;
+            }
+        }
+    }
+    if let Err(e) = config.validate() {
+        eprintln!("{e}");
+        return std::process::ExitCode::FAILURE;
+    }
+
+    match Relay::run(&config).await {
+        Ok(()) => std::process::ExitCode::SUCCESS,
+        Err(e) => {
+            tracing::error!("{e}");
+            std::process::ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn main() {}
@@ -1,19 +1,77 @@
-use figment::providers::Format;
-use figment::{
-    providers::{Env, Toml},
-    Figment,
-};
-use serde::Deserialize;
-
-#[derive(Deserialize, Debug)]
+#[cfg(not(target_arch = "wasm32"))]
+use figment::providers::{Env, Format, Toml};
+use figment::{providers::Serialized, Figment};
+use serde::{Deserialize, Serialize};
+
+use crate::error::CanvasError;
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct AppConfig {
     pub webgpu: WebGpuConfig,
+    #[serde(default)]
+    pub history: HistoryConfig,
+    #[serde(default)]
+    pub relay: RelayConfig,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct WebGpuConfig {
+    /// `default`, `low-power` or `high-performance`
     pub power_preference: String,
     pub force_fallback: bool,
+    /// MSAA samples per pixel for the colour target, 1 (off) or 4
+    #[serde(default = "default_sample_count")]
+    pub sample_count: u32,
+}
+
+fn default_sample_count() -> u32 {
+    4
+}
+
+/// Limits of the undo stack; the oldest steps are dropped past either
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct HistoryConfig {
+    pub max_transactions: usize,
+    /// Approximate size of the kept steps in bytes
+    pub max_bytes: usize,
+}
+
+/// Shortest `relay.secret` accepted, so tokens cannot be forged by guessing it
+const MIN_RELAY_SECRET: usize = 16;
+
+/// Settings of the `canvas-relay` server
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct RelayConfig {
+    /// Address to listen on, localhost only by default
+    pub bind: String,
+    /// Directory holding one snapshot file per board
+    pub storage: String,
+    /// Milliseconds between saves of boards with unsaved changes
+    pub autosave: u64,
+    /// Key that signs share tokens, see `canvas::relay`. Without one every
+    /// member is a viewer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+}
+
+impl Default for RelayConfig {
+    fn default() -> Self {
+        Self {
+            bind: "127.0.0.1:8787".to_string(),
+            storage: "relay-data".to_string(),
+            autosave: 2000,
+            secret: None,
+        }
+    }
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            max_transactions: 500,
+            max_bytes: 32 * 1024 * 1024,
+        }
+    }
 }
 
 impl Default for AppConfig {
@@ -22,16 +80,276 @@ impl Default for AppConfig {
             webgpu: WebGpuConfig {
                 power_preference: "default".to_string(),
                 force_fallback: false,
+                sample_count: default_sample_count(),
             },
+            history: HistoryConfig::default(),
+            relay: RelayConfig::default(),
+        }
+    }
+}
+
+/// Partial config passed to `createClient`/`createClient3d` from JS, e.g.
+/// `{ webgpu: { powerPreference: "low-power", sampleCount: 1 } }`. Unset
+/// fields keep the value from the lower layers.
+#[derive(Deserialize, Serialize, Debug, Default, Clone)]
+#[serde(rename_all(deserialize = "camelCase"))]
+pub struct ConfigOverrides {
+    #[serde(default)]
+    pub webgpu: WebGpuOverrides,
+    #[serde(default)]
+    pub history: HistoryOverrides,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone)]
+#[serde(rename_all(deserialize = "camelCase"))]
+pub struct WebGpuOverrides {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub power_preference: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub force_fallback: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample_count: Option<u32>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone)]
+#[serde(rename_all(deserialize = "camelCase"))]
+pub struct HistoryOverrides {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_transactions: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_bytes: Option<usize>,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl ConfigOverrides {
+    /// Reads the optional config object given to a client constructor
+    pub(crate) fn from_js(value: Option<wasm_bindgen::JsValue>) -> Result<Self, CanvasError> {
+        match value {
+            Some(value) if !value.is_null() && !value.is_undefined() => {
+                serde_wasm_bindgen::from_value(value)
+                    .map_err(|e| CanvasError::Config(e.to_string()))
+            }
+            _ => Ok(Self::default()),
         }
     }
 }
 
 impl AppConfig {
-    pub fn load() -> Result<Self, figment::Error> {
-        Figment::new()
+    /// Built-in defaults, then `Config.toml`, then `CANVAS_` environment
+    /// variables with `__` between section and key, as in
+    /// `CANVAS_WEBGPU__FORCE_FALLBACK`
+    pub fn load() -> Result<Self, CanvasError> {
+        Self::resolve(ConfigOverrides::default())
+    }
+
+    /// Like [`AppConfig::load`] with `overrides` merged last. The browser has
+    /// no config file or environment, so there only defaults and overrides apply.
+    pub fn resolve(overrides: ConfigOverrides) -> Result<Self, CanvasError> {
+        let figment = Figment::from(Serialized::defaults(AppConfig::default()));
+        #[cfg(not(target_arch = "wasm32"))]
+        let figment = figment
             .merge(Toml::file("Config.toml"))
-            .merge(Env::prefixed("CANVAS_").split("_"))
+            .merge(Env::prefixed("CANVAS_").split("__"));
+        Self::extract(figment.merge(Serialized::defaults(overrides)))
+    }
+
+    fn extract(figment: Figment) -> Result<Self, CanvasError> {
+        let config: AppConfig = figment
             .extract()
+            .map_err(|e| CanvasError::Config(e.to_string()))?;
+        config.webgpu.validate()?;
+        config.history.validate()?;
+        config.relay.validate()?;
+        Ok(config)
+    }
+}
+
+impl WebGpuConfig {
+    pub fn validate(&self) -> Result<(), CanvasError> {
+        self.power_preference()?;
+        if !matches!(self.sample_count, 1 | 4) {
+            return Err(CanvasError::Config(format!(
+                "webgpu.sample_count must be 1 or 4, got {}",
+                self.sample_count
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn power_preference(&self) -> Result<wgpu::PowerPreference, CanvasError> {
+        match self.power_preference.as_str() {
+            "default" => Ok(wgpu::PowerPreference::default()),
+            "low-power" => Ok(wgpu::PowerPreference::LowPower),
+            "high-performance" => Ok(wgpu::PowerPreference::HighPerformance),
+            other => Err(CanvasError::Config(format!(
+                "unknown webgpu.power_preference {other:?}"
+            ))),
+        }
+    }
+}
+
+impl HistoryConfig {
+    pub fn validate(&self) -> Result<(), CanvasError> {
+        if self.max_transactions == 0 {
+            return Err(CanvasError::Config(
+                "history.max_transactions must be at least 1".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl RelayConfig {
+    pub fn validate(&self) -> Result<(), CanvasError> {
+        // a socket address, or a host name with a port for the resolver
+        let named = |(host, port): (&str, &str)| {
+            !host.is_empty()
+                && !host.contains(|c: char| c == ':' || c.is_whitespace())
+                && port.parse::<u16>().is_ok()
+        };
+        if self.bind.parse::<std::net::SocketAddr>().is_err()
+            && !self.bind.rsplit_once(':').is_some_and(named)
+        {
+            return Err(CanvasError::Config(format!(
+                "relay.bind {:?} is not host:port",
+                self.bind
+            )));
+        }
+        if self.autosave == 0 {
+            return Err(CanvasError::Config(
+                "relay.autosave must be at least 1 ms".to_string(),
+            ));
+        }
+        if self
+            .secret
+            .as_ref()
+            .is_some_and(|s| s.len() < MIN_RELAY_SECRET)
+        {
+            return Err(CanvasError::Config(format!(
+                "relay.secret must be at least {MIN_RELAY_SECRET} bytes"
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use figment::providers::{Format, Toml};
+
+    use super::*;
+
+    fn from_toml(toml: &str, overrides: ConfigOverrides) -> Result<AppConfig, CanvasError> {
+        AppConfig::extract(
+            Figment::from(Serialized::defaults(AppConfig::default()))
+                .merge(Toml::string(toml))
+                .merge(Serialized::defaults(overrides)),
+        )
+    }
+
+    #[test]
+    fn sample_count_defaults_to_4x() {
+        let config = from_toml(
+            "[webgpu]\npower_preference = \"high-performance\"\nforce_fallback = false",
+            ConfigOverrides::default(),
+        )
+        .unwrap();
+        assert_eq!(config.webgpu.sample_count, 4);
+        assert_eq!(AppConfig::default().webgpu.sample_count, 4);
+    }
+
+    #[test]
+    fn overrides_win_over_file() {
+        let overrides: ConfigOverrides =
+            serde_json::from_str(r#"{"webgpu":{"powerPreference":"low-power","sampleCount":1}}"#)
+                .unwrap();
+        let config = from_toml(
+            "[webgpu]\npower_preference = \"high-performance\"\nforce_fallback = true",
+            overrides,
+        )
+        .unwrap();
+        assert_eq!(
+            config.webgpu.power_preference().unwrap(),
+            wgpu::PowerPreference::LowPower
+        );
+        assert_eq!(config.webgpu.sample_count, 1);
+        assert!(config.webgpu.force_fallback);
+        assert_eq!(config.history, HistoryConfig::default());
+        assert_eq!(config.relay, RelayConfig::default());
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        let bad_samples: ConfigOverrides =
+            serde_json::from_str(r#"{"webgpu":{"sampleCount":2}}"#).unwrap();
+        assert!(matches!(
+            from_toml("", bad_samples),
+            Err(CanvasError::Config(_))
+        ));
+        assert!(matches!(
+            from_toml(
+                "[webgpu]\npower_preference = \"fast\"",
+                ConfigOverrides::default()
+            ),
+            Err(CanvasError::Config(_))
+        ));
+        let no_history: ConfigOverrides =
+            serde_json::from_str(r#"{"history":{"maxTransactions":0}}"#).unwrap();
+        assert!(matches!(
+            from_toml("[history]\nmax_bytes = 1024", no_history),
+            Err(CanvasError::Config(_))
+        ));
+        assert!(matches!(
+            from_toml("[relay]\nsecret = \"hunter2\"", ConfigOverrides::default()),
+            Err(CanvasError::Config(_))
+        ));
+        for bind in ["localhost", "localhost:http", ":8787", "::1:8787"] {
+            assert!(matches!(
+                from_toml(
+                    &format!("[relay]\nbind = \"{bind}\""),
+                    ConfigOverrides::default()
+                ),
+                Err(CanvasError::Config(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn relay_binds_to_addresses_and_host_names() {
+        for bind in [
+            "localhost:8787",
+            "[::1]:8787",
+            "0.0.0.0:80",
+            "relay.internal:9000",
+        ] {
+            let config = from_toml(
+                &format!("[relay]\nbind = \"{bind}\""),
+                ConfigOverrides::default(),
+            )
+            .unwrap();
+            assert_eq!(config.relay.bind, bind);
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    // the closure's error type is figment's
+    #[allow(clippy::result_large_err)]
+    fn env_vars_reach_keys_with_underscores() {
+        figment::Jail::expect_with(|jail| {
+            jail.create_file(
+                "Config.toml",
+                "[webgpu]\npower_preference = \"low-power\"\nforce_fallback = false",
+            )?;
+            jail.set_env("CANVAS_WEBGPU__FORCE_FALLBACK", "true");
+            jail.set_env("CANVAS_HISTORY__MAX_TRANSACTIONS", "7");
+            jail.set_env("CANVAS_RELAY__BIND", "0.0.0.0:9000");
+            let config = AppConfig::load().map_err(|e| e.to_string())?;
+            assert!(config.webgpu.force_fallback);
+            assert_eq!(config.webgpu.power_preference, "low-power");
+            assert_eq!(config.history.max_transactions, 7);
+            assert_eq!(config.relay.bind, "0.0.0.0:9000");
+            Ok(())
+        });
     }
 }
@@ -0,0 +1,720 @@
+//! Replicated whiteboard document for real-time collaboration.
+//!
+//! Each top-level field of a shape's JSON is a last-writer-wins register
+//! stamped with a Lamport clock and the writing peer, deletes are permanent
+//! tombstones, and the z-order is an RGA sequence of placement elements: a
+//! move adds a new element for the shape and the shape sits at its element
+//! with the highest stamp. Every operation commutes, is idempotent and waits
+//! only for the element it is placed after, so replicas that have received
+//! the same updates hold the same document in whatever order they arrived.
+
+pub mod update;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde_json::{Map, Value};
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::*;
+
+use crate::error::CanvasError;
+use crate::history::{ChangeSet, Edit};
+use crate::model::{Shape, ShapeId, WhiteboardDoc};
+
+use update::Op;
+
+/// Identifies a replica; every peer editing a board needs a distinct one
+pub type PeerId = u64;
+
+/// Largest clock a stamp may carry. Editing never gets near it, and updates
+/// with later clocks are rejected so one cannot use up the clock of every
+/// peer that merges it.
+pub const MAX_CLOCK: u64 = 1 << 48;
+
+/// Lamport timestamp of an operation. Ties between peers go to the higher
+/// peer id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Stamp {
+    pub clock: u64,
+    pub peer: PeerId,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Entry {
+    fields: BTreeMap<String, (Stamp, Value)>,
+    deleted: Option<Stamp>,
+    /// Element the shape sits at, the highest placed for it
+    position: Option<Stamp>,
+}
+
+impl Entry {
+    fn materialize(&self, id: &str) -> Option<Shape> {
+        if self.deleted.is_some() || self.position.is_none() {
+            return None;
+        }
+        let mut object: Map<String, Value> = self
+            .fields
+            .iter()
+            .filter(|(_, (_, v))| !v.is_null())
+            .map(|(k, (_, v))| (k.clone(), v.clone()))
+            .collect();
+        object.insert("id".to_string(), Value::String(id.to_string()));
+        // incomplete until every field of the insert has arrived
+        serde_json::from_value(Value::Object(object)).ok()
+    }
+}
+
+/// A z-order element, kept after its shape moves on or is deleted because
+/// later elements may be placed after it
+#[derive(Debug, Clone)]
+struct Element {
+    id: Stamp,
+    shape: ShapeId,
+    after: Option<Stamp>,
+}
+
+/// One peer's replica of a board page
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen(js_name = "SharedDoc"))]
+#[derive(Debug, Clone)]
+pub struct CrdtDoc {
+    peer: PeerId,
+    clock: u64,
+    entries: BTreeMap<ShapeId, Entry>,
+    /// Elements in sequence order, bottom first
+    sequence: Vec<Element>,
+    /// Placements waiting for the element they follow
+    pending: Vec<Op>,
+    /// The visible state, kept in step with every change
+    doc: WhiteboardDoc,
+}
+
+impl CrdtDoc {
+    pub fn new(peer: PeerId) -> Self {
+        Self {
+            peer,
+            clock: 0,
+            entries: BTreeMap::new(),
+            sequence: Vec::new(),
+            pending: Vec::new(),
+            doc: WhiteboardDoc::default(),
+        }
+    }
+
+    /// Seeds a replica with the shapes of `doc`. Other peers should join
+    /// from its [`CrdtDoc::encode_state`] rather than seed their own copy.
+    pub fn from_doc(peer: PeerId, doc: &WhiteboardDoc) -> Result<Self, CanvasError> {
+        let mut crdt = Self::new(peer);
+        for shape in doc.ordered_shapes() {
+            crdt.edit(Edit::Insert {
+                shape: shape.clone(),
+            })?;
+        }
+        Ok(crdt)
+    }
+
+    pub fn peer(&self) -> PeerId {
+        self.peer
+    }
+
+    pub fn doc(&self) -> &WhiteboardDoc {
+        &self.doc
+    }
+
+    /// Applies a local edit and returns the update to send to other peers.
+    /// Inserting an existing id writes only the fields that changed.
+    pub fn edit(&mut self, edit: Edit) -> Result<Vec<u8>, CanvasError> {
+        let ops = match edit {
+            Edit::Insert { shape } => self.upsert_ops(&shape)?,
+            Edit::Delete { id } => {
+                self.visible(&id)?;
+                vec![Op::Delete {
+                    shape: id,
+                    stamp: self.tick()?,
+                }]
+            }
+            Edit::Patch { id, key, value } => {
+                let shape = self.visible(&id)?;
+                if key == "id" || key == "type" {
+                    return Err(CanvasError::InvalidDocument(format!(
+                        "cannot patch the {key} of a shape"
+                    )));
+                }
+                let mut object = shape.to_object()?;
+                object.insert(key.clone(), value.clone());
+                serde_json::from_value::<Shape>(Value::Object(object))
+                    .map_err(|e| CanvasError::InvalidDocument(format!("{key}: {e}")))?;
+                vec![Op::Set {
+                    shape: id,
+                    key,
+                    stamp: self.tick()?,
+                    value,
+                }]
+            }
+            Edit::Reorder { order } => self.reorder_ops(&order)?,
+        };
+        let mut touched = BTreeSet::new();
+        for op in &ops {
+            self.integrate(op, &mut touched);
+        }
+        self.refresh(&touched);
+        Ok(update::encode(&ops))
+    }
+
+    /// Merges an update from another peer, returning what changed for the
+    /// renderer. Updates may arrive in any order and more than once.
+    pub fn apply_update(&mut self, bytes: &[u8]) -> Result<ChangeSet, CanvasError> {
+        let ops = update::decode(bytes)?;
+        let before = self.doc.clone();
+        let mut touched = BTreeSet::new();
+        for op in ops {
+            if !self.integrate(&op, &mut touched) {
+                self.pending.push(op);
+            }
+        }
+        // retry placements until none of them finds its predecessor anymore
+        loop {
+            let waiting = std::mem::take(&mut self.pending);
+            let count = waiting.len();
+            for op in waiting {
+                if !self.integrate(&op, &mut touched) {
+                    self.pending.push(op);
+                }
+            }
+            if self.pending.len() == count {
+                break;
+            }
+        }
+        self.refresh(&touched);
+        Ok(self.changes_since(&before, &touched))
+    }
+
+    /// The whole replica as one update, for peers joining the board
+    pub fn encode_state(&self) -> Vec<u8> {
+        let mut ops = Vec::new();
+        for (id, entry) in &self.entries {
+            if let Some(stamp) = entry.deleted {
+                ops.push(Op::Delete {
+                    shape: id.clone(),
+                    stamp,
+                });
+                continue;
+            }
+            for (key, (stamp, value)) in &entry.fields {
+                ops.push(Op::Set {
+                    shape: id.clone(),
+                    key: key.clone(),
+                    stamp: *stamp,
+                    value: value.clone(),
+                });
+            }
+        }
+        // an element always comes after the one it follows
+        ops.extend(self.sequence.iter().map(|e| Op::Place {
+            shape: e.shape.clone(),
+            element: e.id,
+            after: e.after,
+        }));
+        ops.extend(self.pending.iter().cloned());
+        update::encode(&ops)
+    }
+
+    fn tick(&mut self) -> Result<Stamp, CanvasError> {
+        self.clock = self
+            .clock
+            .checked_add(1)
+            .filter(|clock| *clock <= MAX_CLOCK)
+            .ok_or_else(|| CanvasError::InvalidDocument("the clock ran out".to_string()))?;
+        Ok(Stamp {
+            clock: self.clock,
+            peer: self.peer,
+        })
+    }
+
+    fn visible(&self, id: &str) -> Result<&Shape, CanvasError> {
+        self.doc
+            .get(id)
+            .ok_or_else(|| CanvasError::InvalidDocument(format!("no shape with id {id}")))
+    }
+
+    fn upsert_ops(&mut self, shape: &Shape) -> Result<Vec<Op>, CanvasError> {
+        let entry = self.entries.get(&shape.id);
+        if entry.is_some_and(|e| e.deleted.is_some()) {
+            return Err(CanvasError::InvalidDocument(format!(
+                "shape {} was deleted",
+                shape.id
+            )));
+        }
+        let mut object = shape.to_object()?;
+        object.remove("id");
+        let mut writes: Vec<(String, Value)> = Vec::new();
+        if let Some(entry) = entry {
+            // fields of the previous kind that the new one lacks
+            for (key, (_, value)) in &entry.fields {
+                if !value.is_null() && !object.contains_key(key) {
+                    writes.push((key.clone(), Value::Null));
+                }
+            }
+        }
+        for (key, value) in object {
+            if entry.and_then(|e| e.fields.get(&key)).map(|(_, v)| v) != Some(&value) {
+                writes.push((key, value));
+            }
+        }
+        let placed = entry.is_some_and(|e| e.position.is_some());
+
+        let mut ops = writes
+            .into_iter()
+            .map(|(key, value)| {
+                Ok(Op::Set {
+                    shape: shape.id.clone(),
+                    key,
+                    stamp: self.tick()?,
+                    value,
+                })
+            })
+            .collect::<Result<Vec<Op>, CanvasError>>()?;
+        if !placed {
+            ops.push(Op::Place {
+                shape: shape.id.clone(),
+                element: self.tick()?,
+                after: self.sequence.last().map(|e| e.id),
+            });
+        }
+        Ok(ops)
+    }
+
+    /// Placements that turn the current order into `ids`, interpreted like
+    /// [`WhiteboardDoc::reorder`]. Shapes on the longest run already in order
+    /// stay where they are.
+    fn reorder_ops(&mut self, ids: &[ShapeId]) -> Result<Vec<Op>, CanvasError> {
+        let mut target = self.doc.clone();
+        target.reorder(ids);
+        let target = target.order;
+        let current: BTreeMap<&ShapeId, usize> = self
+            .doc
+            .order
+            .iter()
+            .enumerate()
+            .map(|(i, id)| (id, i))
+            .collect();
+        let ranks: Vec<usize> = target.iter().map(|id| current[id]).collect();
+        let keep = longest_increasing(&ranks);
+
+        let mut ops = Vec::new();
+        let mut after = None;
+        for (i, id) in target.iter().enumerate() {
+            if keep.contains(&i) {
+                after = self.entries[id].position;
+                continue;
+            }
+            let element = self.tick()?;
+            ops.push(Op::Place {
+                shape: id.clone(),
+                element,
+                after,
+            });
+            after = Some(element);
+        }
+        Ok(ops)
+    }
+
+    /// Applies `op` unless it has to wait for the element it follows. Ops
+    /// stamped past [`MAX_CLOCK`], which `update::decode` already rejects,
+    /// are dropped rather than let the clock run out.
+    fn integrate(&mut self, op: &Op, touched: &mut BTreeSet<ShapeId>) -> bool {
+        if op.stamp().clock > MAX_CLOCK {
+            return true;
+        }
+        self.clock = self.clock.max(op.stamp().clock);
+        match op {
+            Op::Set {
+                shape,
+                key,
+                stamp,
+                value,
+            } => {
+                let entry = self.entries.entry(shape.clone()).or_default();
+                if entry.deleted.is_some() {
+                    return true;
+                }
+                if entry.fields.get(key).is_some_and(|(s, _)| s >= stamp) {
+                    return true;
+                }
+                entry.fields.insert(key.clone(), (*stamp, value.clone()));
+            }
+            Op::Delete { shape, stamp } => {
+                let entry = self.entries.entry(shape.clone()).or_default();
+                entry.deleted = entry.deleted.max(Some(*stamp));
+                entry.fields.clear();
+            }
+            Op::Place {
+                shape,
+                element,
+                after,
+            } => {
+                if self.sequence.iter().any(|e| e.id == *element) {
+                    return true;
+                }
+                let mut i = match after {
+                    None => 0,
+                    Some(after) => match self.sequence.iter().position(|e| e.id == *after) {
+                        Some(i) => i + 1,
+                        None => return false,
+                    },
+                };
+                // concurrent placements after the same element go newest first
+                while i < self.sequence.len() && self.sequence[i].id > *element {
+                    i += 1;
+                }
+                self.sequence.insert(
+                    i,
+                    Element {
+                        id: *element,
+                        shape: shape.clone(),
+                        after: *after,
+                    },
+                );
+                let entry = self.entries.entry(shape.clone()).or_default();
+                entry.position = entry.position.max(Some(*element));
+            }
+        }
+        touched.insert(op.shape().clone());
+        true
+    }
+
+    /// Rebuilds the visible state of the `touched` shapes and the order
+    fn refresh(&mut self, touched: &BTreeSet<ShapeId>) {
+        for id in touched {
+            match self.entries.get(id).and_then(|e| e.materialize(id)) {
+                Some(shape) => {
+                    self.doc.shapes.insert(id.clone(), shape);
+                }
+                None => {
+                    self.doc.shapes.remove(id);
+                }
+            }
+        }
+        self.doc.order = self
+            .sequence
+            .iter()
+            .filter(|e| {
+                self.doc.shapes.contains_key(&e.shape)
+                    && self.entries[&e.shape].position == Some(e.id)
+            })
+            .map(|e| e.shape.clone())
+            .collect();
+    }
+
+    fn changes_since(&self, before: &WhiteboardDoc, touched: &BTreeSet<ShapeId>) -> ChangeSet {
+        let mut changes = ChangeSet::default();
+        for id in touched {
+            match (before.get(id), self.doc.get(id)) {
+                (old, Some(new)) if old != Some(new) => changes.upserted.push(new.clone()),
+                (Some(_), None) => changes.removed.push(id.clone()),
+                _ => {}
+            }
+        }
+        if before.order != self.doc.order {
+            changes.order = Some(self.doc.order.clone());
+        }
+        changes
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen(js_class = "SharedDoc")]
+impl CrdtDoc {
+    /// `peer` is a `bigint` on the JS side, so every 64-bit id can be used
+    #[wasm_bindgen(constructor)]
+    pub fn js_new(peer: PeerId) -> CrdtDoc {
+        CrdtDoc::new(peer)
+    }
+
+    /// Applies an edit in the JSON form `Client.edit` takes and returns the
+    /// update to broadcast
+    #[wasm_bindgen(js_name = "edit")]
+    pub fn edit_json(&mut self, edit_json: &str) -> Result<Vec<u8>, JsValue> {
+        let edit: Edit = serde_json::from_str(edit_json)
+            .map_err(|e| CanvasError::InvalidDocument(e.to_string()))?;
+        Ok(self.edit(edit)?)
+    }
+
+    /// Merges a peer's update and returns the change set as JSON
+    #[wasm_bindgen(js_name = "applyUpdate")]
+    pub fn apply_update_json(&mut self, bytes: &[u8]) -> Result<String, JsValue> {
+        let changes = self.apply_update(bytes)?;
+        serde_json::to_string(&changes)
+            .map_err(|e| CanvasError::InvalidDocument(e.to_string()).into())
+    }
+
+    #[wasm_bindgen(js_name = "encodeState")]
+    pub fn encode_state_js(&self) -> Vec<u8> {
+        self.encode_state()
+    }
+
+    /// The visible `WhiteboardDoc` as JSON
+    #[wasm_bindgen(js_name = "toJson")]
+    pub fn to_json(&self) -> Result<String, JsValue> {
+        Ok(self.doc.to_json()?)
+    }
+}
+
+/// Indices of one longest strictly increasing subsequence of `values`
+fn longest_increasing(values: &[usize]) -> BTreeSet<usize> {
+    // tails[k]: index of the smallest tail of an increasing run of length k + 1
+    let mut tails: Vec<usize> = Vec::new();
+    let mut prev = vec![None; values.len()];
+    for (i, v) in values.iter().enumerate() {
+        let k = tails.partition_point(|&t| values[t] < *v);
+        prev[i] = k.checked_sub(1).map(|k| tails[k]);
+        if k == tails.len() {
+            tails.push(i);
+        } else {
+            tails[k] = i;
+        }
+    }
+    let mut keep = BTreeSet::new();
+    let mut next = tails.last().copied();
+    while let Some(i) = next {
+        keep.insert(i);
+        next = prev[i];
+    }
+    keep
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(not(target_arch = "wasm32"))]
+    use proptest::prelude::*;
+    use serde_json::json;
+
+    fn rect(id: &str, x: f64) -> Shape {
+        serde_json::from_value(
+            json!({ "id": id, "type": "rectangle", "x": x, "y": 0, "w": 10,
+                                       "h": 10, "stroke": "#000", "fill": null, "strokeWidth": 2 }),
+        )
+        .unwrap()
+    }
+
+    fn insert(shape: Shape) -> Edit {
+        Edit::Insert { shape }
+    }
+
+    fn patch(id: &str, key: &str, value: Value) -> Edit {
+        Edit::Patch {
+            id: id.into(),
+            key: key.into(),
+            value,
+        }
+    }
+
+    /// Peers that queue their outgoing updates until the test delivers them
+    struct Network {
+        peers: Vec<CrdtDoc>,
+        /// (from, update) in send order
+        sent: Vec<(usize, Vec<u8>)>,
+    }
+
+    impl Network {
+        fn new(n: usize) -> Self {
+            Self {
+                peers: (1..=n as PeerId).map(CrdtDoc::new).collect(),
+                sent: Vec::new(),
+            }
+        }
+
+        fn edit(&mut self, peer: usize, edit: Edit) {
+            let update = self.peers[peer].edit(edit).unwrap();
+            self.sent.push((peer, update));
+        }
+
+        /// Delivers everything sent so far to every other peer, each in its
+        /// own order given by `seed`, some updates twice
+        fn deliver(&mut self, mut seed: u64) {
+            let sent = std::mem::take(&mut self.sent);
+            for (to, peer) in self.peers.iter_mut().enumerate() {
+                let mut inbox: Vec<&Vec<u8>> = sent
+                    .iter()
+                    .filter(|(from, _)| *from != to)
+                    .map(|(_, u)| u)
+                    .collect();
+                // Fisher-Yates with a small LCG
+                for i in (1..inbox.len()).rev() {
+                    seed = seed
+                        .wrapping_mul(6364136223846793005)
+                        .wrapping_add(1442695040888963407);
+                    inbox.swap(i, (seed >> 33) as usize % (i + 1));
+                }
+                let again = inbox.first().copied();
+                for update in inbox.into_iter().chain(again) {
+                    peer.apply_update(update).unwrap();
+                }
+            }
+        }
+
+        fn assert_converged(&self) {
+            for peer in &self.peers[1..] {
+                assert_eq!(peer.doc(), self.peers[0].doc());
+            }
+        }
+    }
+
+    #[test]
+    fn concurrent_edits_converge() {
+        for seed in 0..20 {
+            let mut net = Network::new(3);
+            net.edit(0, insert(rect("a", 0.0)));
+            net.edit(0, insert(rect("b", 20.0)));
+            net.edit(0, insert(rect("c", 40.0)));
+            net.deliver(seed);
+            net.assert_converged();
+
+            // conflicting writes to one field, a move each and a delete
+            net.edit(0, patch("a", "stroke", json!("#f00")));
+            net.edit(1, patch("a", "stroke", json!("#0f0")));
+            net.edit(1, patch("a", "x", json!(5)));
+            net.edit(2, Edit::Delete { id: "b".into() });
+            net.edit(1, patch("b", "fill", json!("#00f")));
+            net.edit(
+                0,
+                Edit::Reorder {
+                    order: vec!["c".into(), "a".into(), "b".into()],
+                },
+            );
+            net.edit(
+                2,
+                Edit::Reorder {
+                    order: vec!["a".into(), "c".into()],
+                },
+            );
+            net.deliver(seed + 100);
+            net.assert_converged();
+
+            let doc = net.peers[0].doc();
+            // the higher peer id wins the tie at equal clocks
+            assert_eq!(doc.get("a").unwrap().stroke, "#0f0");
+            assert!(doc.get("b").is_none());
+            assert_eq!(doc.order.len(), 2);
+        }
+    }
+
+    #[test]
+    fn clocks_cannot_be_run_out() {
+        let mut a = CrdtDoc::new(1);
+        a.edit(insert(rect("x", 0.0))).unwrap();
+        let before = a.clone();
+        for clock in [MAX_CLOCK + 1, u64::MAX] {
+            let update = update::encode(&[Op::Set {
+                shape: "x".into(),
+                key: "stroke".into(),
+                stamp: Stamp { clock, peer: 2 },
+                value: json!("#f00"),
+            }]);
+            assert!(matches!(
+                a.apply_update(&update),
+                Err(CanvasError::Decode(_))
+            ));
+        }
+        assert_eq!(a.clock, before.clock);
+        assert_eq!(a.doc(), before.doc());
+
+        // the last clock is still accepted, after which edits fail
+        let update = update::encode(&[Op::Set {
+            shape: "x".into(),
+            key: "stroke".into(),
+            stamp: Stamp {
+                clock: MAX_CLOCK,
+                peer: 2,
+            },
+            value: json!("#f00"),
+        }]);
+        a.apply_update(&update).unwrap();
+        assert_eq!(a.doc().get("x").unwrap().stroke, "#f00");
+        assert!(a.edit(patch("x", "x", json!(5))).is_err());
+        assert_eq!(a.clock, MAX_CLOCK);
+    }
+
+    #[test]
+    fn placements_wait_for_their_predecessor() {
+        let mut a = CrdtDoc::new(1);
+        let first = a.edit(insert(rect("x", 0.0))).unwrap();
+        let second = a.edit(insert(rect("y", 0.0))).unwrap();
+        let moved = a
+            .edit(Edit::Reorder {
+                order: vec!["y".into(), "x".into()],
+            })
+            .unwrap();
+
+        let mut b = CrdtDoc::new(2);
+        let changes = b.apply_update(&moved).unwrap();
+        assert!(changes.is_empty());
+        // the move put y at the bottom, which needs no other element
+        b.apply_update(&second).unwrap();
+        assert_eq!(b.doc().order, ["y"]);
+        let changes = b.apply_update(&first).unwrap();
+        assert_eq!(changes.upserted.len(), 1);
+        assert_eq!(changes.order.unwrap(), ["y", "x"]);
+        assert_eq!(b.doc(), a.doc());
+    }
+
+    #[test]
+    fn joins_from_encoded_state() {
+        let doc = WhiteboardDoc {
+            shapes: [("r".into(), rect("r", 1.0)), ("s".into(), rect("s", 2.0))].into(),
+            order: vec!["s".into(), "r".into()],
+        };
+        let mut host = CrdtDoc::from_doc(1, &doc).unwrap();
+        host.edit(Edit::Delete { id: "r".into() }).unwrap();
+        let mut guest = CrdtDoc::new(2);
+        guest.apply_update(&host.encode_state()).unwrap();
+        assert_eq!(guest.doc(), host.doc());
+        // a kind change clears the old kind's fields
+        let line = serde_json::from_value(json!({ "id": "s", "type": "line",
+            "a": { "x": 0, "y": 0 }, "b": { "x": 1, "y": 1 },
+            "stroke": "#000", "fill": null, "strokeWidth": 2 }))
+        .unwrap();
+        let update = guest.edit(insert(line)).unwrap();
+        host.apply_update(&update).unwrap();
+        assert_eq!(guest.doc(), host.doc());
+        assert!(host.entries["s"].fields["w"].1.is_null());
+        assert!(host.apply_update(b"WBUPD\x02\x00").is_err());
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+        #[test]
+        fn random_sessions_converge(
+            steps in prop::collection::vec((0usize..3, 0u8..4, 0usize..4, -50i32..50), 1..40),
+            seed in any::<u64>(),
+        ) {
+            let mut net = Network::new(3);
+            let ids = ["p", "q", "r", "s"];
+            for (i, (peer, action, target, x)) in steps.into_iter().enumerate() {
+                let id = ids[target];
+                let present = net.peers[peer].doc().get(id).is_some();
+                let edit = match action {
+                    0 => insert(rect(id, x as f64)),
+                    1 if present => patch(id, "x", json!(x)),
+                    2 if present => Edit::Delete { id: id.into() },
+                    3 => {
+                        let mut order = net.peers[peer].doc().order.clone();
+                        let by = target.min(order.len());
+                        order.rotate_left(by);
+                        Edit::Reorder { order }
+                    }
+                    _ => continue,
+                };
+                // deleted ids cannot come back, so some inserts fail
+                if let Ok(update) = net.peers[peer].edit(edit) {
+                    net.sent.push((peer, update));
+                }
+                if i % 7 == 6 {
+                    net.deliver(seed ^ i as u64);
+                }
+            }
+            net.deliver(seed);
+            net.assert_converged();
+        }
+    }
+}
@@ -0,0 +1,239 @@
+//! Binary update messages. Layout of version 1, with the integer encodings of
+//! [`crate::model::binary`]:
+//!
+//! ```text
+//! magic     b"WBUPD"
+//! version   u16
+//! ops       varint count, then per op a u8 tag and its fields:
+//!   0 set      shape id, field name, stamp, value
+//!   1 delete   shape id, stamp
+//!   2 place    shape id, element stamp, varint 0 or 1 then the stamp it
+//!              follows
+//! stamp     varint clock up to MAX_CLOCK, varint peer
+//! value     u8 tag: 0 null, 1 false, 2 true, 3 f64, 4 string,
+//!           5 varint count + values, 6 varint count + (key string, value)
+//! ```
+//!
+//! Strings are a varint length and UTF-8 bytes.
+
+use serde_json::{Map, Number, Value};
+
+use crate::error::CanvasError;
+use crate::model::binary::{Reader, Writer};
+use crate::model::ShapeId;
+
+use super::{Stamp, MAX_CLOCK};
+
+pub const MAGIC: &[u8; 5] = b"WBUPD";
+
+pub const VERSION: u16 = 1;
+
+/// Nesting allowed in values; shape fields go two levels deep at most
+const MAX_DEPTH: usize = 16;
+
+/// One change to the replicated document
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Op {
+    /// Last-writer-wins write of one top-level field of a shape's JSON
+    Set {
+        shape: ShapeId,
+        key: String,
+        stamp: Stamp,
+        value: Value,
+    },
+    /// Tombstones the shape for good; deletes win over concurrent writes
+    Delete { shape: ShapeId, stamp: Stamp },
+    /// A z-order element for `shape` right after the element `after`, or at
+    /// the bottom. The shape sits at its element with the highest stamp.
+    Place {
+        shape: ShapeId,
+        element: Stamp,
+        after: Option<Stamp>,
+    },
+}
+
+impl Op {
+    pub(crate) fn shape(&self) -> &ShapeId {
+        match self {
+            Op::Set { shape, .. } | Op::Delete { shape, .. } | Op::Place { shape, .. } => shape,
+        }
+    }
+
+    pub(crate) fn stamp(&self) -> Stamp {
+        match self {
+            Op::Set { stamp, .. } | Op::Delete { stamp, .. } => *stamp,
+            Op::Place { element, .. } => *element,
+        }
+    }
+}
+
+pub(crate) fn encode(ops: &[Op]) -> Vec<u8> {
+    let mut w = Writer::default();
+    w.bytes(MAGIC);
+    w.bytes(&VERSION.to_le_bytes());
+    w.varint(ops.len() as u64);
+    for op in ops {
+        match op {
+            Op::Set {
+                shape,
+                key,
+                stamp,
+                value,
+            } => {
+                w.bytes(&[0]);
+                w.string(shape);
+                w.string(key);
+                write_stamp(&mut w, *stamp);
+                write_value(&mut w, value);
+            }
+            Op::Delete { shape, stamp } => {
+                w.bytes(&[1]);
+                w.string(shape);
+                write_stamp(&mut w, *stamp);
+            }
+            Op::Place {
+                shape,
+                element,
+                after,
+            } => {
+                w.bytes(&[2]);
+                w.string(shape);
+                write_stamp(&mut w, *element);
+                match after {
+                    Some(after) => {
+                        w.varint(1);
+                        write_stamp(&mut w, *after);
+                    }
+                    None => w.varint(0),
+                }
+            }
+        }
+    }
+    w.out
+}
+
+pub(crate) fn decode(bytes: &[u8]) -> Result<Vec<Op>, CanvasError> {
+    let mut r = Reader { bytes, pos: 0 };
+    if r.take(MAGIC.len())? != MAGIC {
+        return Err(CanvasError::Decode("not a whiteboard update".to_string()));
+    }
+    let version = u16::from_le_bytes(r.array()?);
+    if version != VERSION {
+        return Err(CanvasError::Decode(format!(
+            "unknown update version {version}"
+        )));
+    }
+    let n = r.len()?;
+    let mut ops = Vec::with_capacity(n);
+    for _ in 0..n {
+        let tag = r.array::<1>()?[0];
+        let shape = r.string()?;
+        ops.push(match tag {
+            0 => Op::Set {
+                shape,
+                key: r.string()?,
+                stamp: read_stamp(&mut r)?,
+                value: read_value(&mut r, 0)?,
+            },
+            1 => Op::Delete {
+                shape,
+                stamp: read_stamp(&mut r)?,
+            },
+            2 => Op::Place {
+                shape,
+                element: read_stamp(&mut r)?,
+                after: match r.varint()? {
+                    0 => None,
+                    1 => Some(read_stamp(&mut r)?),
+                    v => return Err(CanvasError::Decode(format!("bad origin flag {v}"))),
+                },
+            },
+            t => return Err(CanvasError::Decode(format!("unknown op {t}"))),
+        });
+    }
+    if r.pos != bytes.len() {
+        return Err(CanvasError::Decode(format!(
+            "{} trailing bytes",
+            bytes.len() - r.pos
+        )));
+    }
+    Ok(ops)
+}
+
+fn write_stamp(w: &mut Writer, stamp: Stamp) {
+    w.varint(stamp.clock);
+    w.varint(stamp.peer);
+}
+
+fn read_stamp(r: &mut Reader) -> Result<Stamp, CanvasError> {
+    let clock = r.varint()?;
+    if clock > MAX_CLOCK {
+        return Err(CanvasError::Decode(format!("clock {clock} out of range")));
+    }
+    Ok(Stamp {
+        clock,
+        peer: r.varint()?,
+    })
+}
+
+fn write_value(w: &mut Writer, value: &Value) {
+    match value {
+        Value::Null => w.bytes(&[0]),
+        Value::Bool(false) => w.bytes(&[1]),
+        Value::Bool(true) => w.bytes(&[2]),
+        Value::Number(n) => {
+            w.bytes(&[3]);
+            w.f64(n.as_f64().unwrap_or(0.0));
+        }
+        Value::String(s) => {
+            w.bytes(&[4]);
+            w.string(s);
+        }
+        Value::Array(items) => {
+            w.bytes(&[5]);
+            w.varint(items.len() as u64);
+            for item in items {
+                write_value(w, item);
+            }
+        }
+        Value::Object(map) => {
+            w.bytes(&[6]);
+            w.varint(map.len() as u64);
+            for (key, item) in map {
+                w.string(key);
+                write_value(w, item);
+            }
+        }
+    }
+}
+
+fn read_value(r: &mut Reader, depth: usize) -> Result<Value, CanvasError> {
+    if depth > MAX_DEPTH {
+        return Err(CanvasError::Decode("value nested too deeply".to_string()));
+    }
+    Ok(match r.array::<1>()?[0] {
+        0 => Value::Null,
+        1 => Value::Bool(false),
+        2 => Value::Bool(true),
+        3 => Number::from_f64(r.f64()?).map_or(Value::Null, Value::Number),
+        4 => Value::String(r.string()?),
+        5 => {
+            let n = r.len()?;
+            let mut items = Vec::with_capacity(n);
+            for _ in 0..n {
+                items.push(read_value(r, depth + 1)?);
+            }
+            Value::Array(items)
+        }
+        6 => {
+            let n = r.len()?;
+            let mut map = Map::new();
+            for _ in 0..n {
+                let key = r.string()?;
+                map.insert(key, read_value(r, depth + 1)?);
+            }
+            Value::Object(map)
+        }
+        t => return Err(CanvasError::Decode(format!("unknown value tag {t}"))),
+    })
+}
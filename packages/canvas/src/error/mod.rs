@@ -20,6 +20,33 @@ pub enum CanvasError {
 
     #[error("Failed to configure surface: {0}")]
     SurfaceConfigure(String),
+
+    #[error("Failed to read back pixels: {0}")]
+    Readback(String),
+
+    #[error("Export failed: {0}")]
+    Export(String),
+
+    #[error("Invalid config: {0}")]
+    Config(String),
+
+    #[error("Invalid document: {0}")]
+    InvalidDocument(String),
+
+    #[error("Failed to decode document: {0}")]
+    Decode(String),
+
+    #[error("Import failed: {0}")]
+    Import(String),
+
+    #[error("Unknown tool: {0}")]
+    UnknownTool(String),
+
+    #[error("Invalid font: {0}")]
+    InvalidFont(String),
+
+    #[error("Relay failed: {0}")]
+    Relay(String),
 }
 
 impl From<CanvasError> for wasm_bindgen::JsValue {
@@ -0,0 +1,14 @@
+//! Exporting documents to files other apps can open
+
+pub mod pdf;
+pub mod png;
+pub mod svg;
+
+/// Margin in world units around the content when no bounds are given
+pub const CONTENT_PADDING: f64 = 16.0;
+
+/// Rounds to 1/1000 of a unit and drops trailing zeros, for the text formats
+fn num(v: f64) -> String {
+    // adding 0.0 turns -0 into 0
+    format!("{}", (v * 1000.0).round() / 1000.0 + 0.0)
+}
@@ -0,0 +1,869 @@
+//! PDF export. Every page of a board becomes one PDF page fitted to its
+//! content, with the shapes as vector paths in z-order and the page names as
+//! bookmarks. Text is laid out like the renderers do and set in subsets of
+//! the loaded TrueType fonts, addressed by glyph id through `Identity-H` with
+//! a `ToUnicode` map so it can be searched and copied; without such a font it
+//! falls back to the standard Helvetica font, which only has Latin-1. One
+//! world unit is one point. The file is plain PDF 1.4 with uncompressed
+//! content streams.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use rustybuzz::ttf_parser::{name_id, GlyphId};
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::*;
+
+use super::{num, CONTENT_PADDING};
+use crate::geometry::text::SansMetrics;
+use crate::ink::{self, Piece};
+use crate::model::color::Rgba;
+use crate::model::shape::ARROW_HEAD_SIZE;
+use crate::model::{Board, Point, Rect, Shape, ShapeKind, WhiteboardPage};
+use crate::text::font::Font;
+use crate::text::layout::{self, TextStyle};
+use crate::text::subset;
+use crate::text::Typesetter;
+
+/// Size of pages without any shapes, A4 landscape
+const EMPTY_PAGE: Rect = Rect {
+    x: 0.0,
+    y: 0.0,
+    w: 842.0,
+    h: 595.0,
+};
+
+/// Largest page side Acrobat accepts; bigger boards are scaled down to fit
+const MAX_PAGE_SIDE: f64 = 14_400.0;
+
+/// Control point distance for a quarter circle of radius 1 as a cubic Bézier
+const KAPPA: f64 = 0.552_284_749_831;
+
+const CATALOG: usize = 1;
+const PAGES: usize = 2;
+const OUTLINES: usize = 3;
+const FONT: usize = 4;
+
+/// Objects per embedded font: the `Type0` font, its CID font, the
+/// descriptor, the font file and the `ToUnicode` map
+const FONT_OBJECTS: usize = 5;
+
+/// Glyph space units per em in PDF font dictionaries
+const GLYPH_UNITS: f64 = 1000.0;
+
+/// Most `bfchar` entries a CMap may have in one block
+const MAX_BFCHAR: usize = 100;
+
+/// Serialises a `Board` given as JSON to PDF bytes with text in Helvetica;
+/// the client's `exportPdf` embeds the loaded fonts instead. Call the store's
+/// `saveActivePageSnapshot` first so the active page is up to date.
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen(js_name = "exportPdf")]
+pub fn export_pdf(board_json: &str) -> Result<Vec<u8>, JsValue> {
+    Ok(to_pdf(&Board::from_json(board_json)?, &[]))
+}
+
+/// PDF document with one page per page of `board`, in board order. Text is
+/// set in `fonts`, falling back along the list like on screen; fonts without
+/// TrueType outlines cannot be embedded and are skipped.
+pub fn to_pdf(board: &Board, fonts: &[Font]) -> Vec<u8> {
+    // three objects per page: the page, its content stream and its bookmark,
+    // then the fonts the pages use
+    let page_id = |i: usize| FONT + 1 + i * 3;
+    let n = board.pages.len();
+    let fonts: Vec<Font> = fonts
+        .iter()
+        .filter(|f| subset::has_glyf(f))
+        .cloned()
+        .collect();
+    let pages: Vec<_> = board
+        .pages
+        .iter()
+        .map(|page| render_page(page, &fonts))
+        .collect();
+    let mut glyphs: BTreeMap<usize, BTreeMap<u16, Option<char>>> = BTreeMap::new();
+    for (_, content) in &pages {
+        for (font, used) in &content.glyphs {
+            let all = glyphs.entry(*font).or_default();
+            for (glyph, ch) in used {
+                let slot = all.entry(*glyph).or_default();
+                *slot = slot.or(*ch);
+            }
+        }
+    }
+    let font_id: BTreeMap<usize, usize> = glyphs
+        .keys()
+        .enumerate()
+        .map(|(i, font)| (*font, page_id(n) + i * FONT_OBJECTS))
+        .collect();
+    let mut pdf = Writer::new();
+
+    pdf.object(
+        CATALOG,
+        format!(
+            "<< /Type /Catalog /Pages {PAGES} 0 R /Outlines {OUTLINES} 0 R /PageMode /UseOutlines >>"
+        ),
+    );
+    let kids: Vec<String> = (0..n).map(|i| format!("{} 0 R", page_id(i))).collect();
+    pdf.object(
+        PAGES,
+        format!("<< /Type /Pages /Kids [{}] /Count {n} >>", kids.join(" ")),
+    );
+    pdf.object(
+        OUTLINES,
+        match n {
+            0 => "<< /Type /Outlines /Count 0 >>".to_string(),
+            _ => format!(
+                "<< /Type /Outlines /First {} 0 R /Last {} 0 R /Count {n} >>",
+                page_id(0) + 2,
+                page_id(n - 1) + 2
+            ),
+        },
+    );
+    pdf.object(
+        FONT,
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>"
+            .to_string(),
+    );
+
+    for (i, (page, (media_box, content))) in board.pages.iter().zip(pages).enumerate() {
+        let id = page_id(i);
+        let mut font_names = format!("/F1 {FONT} 0 R");
+        for font in content.glyphs.keys() {
+            let _ = write!(font_names, " /T{font} {} 0 R", font_id[font]);
+        }
+        pdf.object(
+            id,
+            format!(
+                "<< /Type /Page /Parent {PAGES} 0 R /MediaBox [0 0 {} {}] \
+                 /Resources << /Font << {font_names} >> /ExtGState << {} >> >> \
+                 /Contents {} 0 R >>",
+                num(media_box.0),
+                num(media_box.1),
+                content.ext_g_states(),
+                id + 1
+            ),
+        );
+        pdf.object_bytes(id + 1, stream("", content.ops.as_bytes()));
+
+        let mut bookmark = format!(
+            "<< /Title {} /Parent {OUTLINES} 0 R /Dest [{id} 0 R /Fit]",
+            text_string(&page.name)
+        );
+        if i > 0 {
+            let _ = write!(bookmark, " /Prev {} 0 R", page_id(i - 1) + 2);
+        }
+        if i + 1 < n {
+            let _ = write!(bookmark, " /Next {} 0 R", page_id(i + 1) + 2);
+        }
+        bookmark.push_str(" >>");
+        pdf.object(id + 2, bookmark);
+    }
+
+    for (font, used) in &glyphs {
+        embed_font(&mut pdf, font_id[font], *font, &fonts[*font], used);
+    }
+
+    pdf.finish()
+}
+
+/// Writes the objects of a `Type0` font holding the subset of `font` with
+/// the glyphs in `used`, each mapped back to the character it shows
+fn embed_font(
+    pdf: &mut Writer,
+    id: usize,
+    index: usize,
+    font: &Font,
+    used: &BTreeMap<u16, Option<char>>,
+) {
+    let face = font.face();
+    let scale = GLYPH_UNITS / face.units_per_em() as f64;
+    let units = |v: f64| num((v * scale).round());
+    let glyphs: BTreeSet<u16> = used.keys().copied().collect();
+    // a malformed font is embedded whole rather than losing its text
+    let file = subset::subset(font, &glyphs).unwrap_or_else(|| font.data().to_vec());
+    let name = format!(
+        "{}+{}",
+        subset_tag(index, &glyphs),
+        postscript_name(font, index)
+    );
+
+    pdf.object(
+        id,
+        format!(
+            "<< /Type /Font /Subtype /Type0 /BaseFont /{name} /Encoding /Identity-H \
+             /DescendantFonts [{} 0 R] /ToUnicode {} 0 R >>",
+            id + 1,
+            id + 4
+        ),
+    );
+    let widths: Vec<String> = glyphs
+        .iter()
+        .map(|&g| {
+            let advance = face.glyph_hor_advance(GlyphId(g)).unwrap_or(0);
+            format!("{g} [{}]", units(advance as f64))
+        })
+        .collect();
+    pdf.object(
+        id + 1,
+        format!(
+            "<< /Type /Font /Subtype /CIDFontType2 /BaseFont /{name} \
+             /CIDSystemInfo << /Registry (Adobe) /Ordering (Identity) /Supplement 0 >> \
+             /FontDescriptor {} 0 R /CIDToGIDMap /Identity /DW {} /W [{}] >>",
+            id + 2,
+            num(GLYPH_UNITS),
+            widths.join(" ")
+        ),
+    );
+    let bbox = face.global_bounding_box();
+    let ascent = face.ascender() as f64;
+    pdf.object(
+        id + 2,
+        format!(
+            "<< /Type /FontDescriptor /FontName /{name} /Flags 4 \
+             /FontBBox [{} {} {} {}] /ItalicAngle 0 /Ascent {} /Descent {} \
+             /CapHeight {} /StemV 80 /FontFile2 {} 0 R >>",
+            units(bbox.x_min as f64),
+            units(bbox.y_min as f64),
+            units(bbox.x_max as f64),
+            units(bbox.y_max as f64),
+            units(ascent),
+            units(face.descender() as f64),
+            units(face.capital_height().map_or(ascent, f64::from)),
+            id + 3
+        ),
+    );
+    pdf.object_bytes(id + 3, stream(&format!(" /Length1 {}", file.len()), &file));
+    pdf.object_bytes(id + 4, stream("", to_unicode(used).as_bytes()));
+}
+
+/// CMap from glyph ids to the UTF-16 text they show, for search and copy
+fn to_unicode(used: &BTreeMap<u16, Option<char>>) -> String {
+    let mut cmap = String::from(
+        "/CIDInit /ProcSet findresource begin\n12 dict begin\nbegincmap\n\
+         /CIDSystemInfo << /Registry (Adobe) /Ordering (UCS) /Supplement 0 >> def\n\
+         /CMapName /Adobe-Identity-UCS def\n/CMapType 2 def\n\
+         1 begincodespacerange\n<0000> <FFFF>\nendcodespacerange\n",
+    );
+    let mapped: Vec<(u16, char)> = used
+        .iter()
+        .filter_map(|(glyph, ch)| Some((*glyph, (*ch)?)))
+        .collect();
+    for block in mapped.chunks(MAX_BFCHAR) {
+        let _ = writeln!(cmap, "{} beginbfchar", block.len());
+        for (glyph, ch) in block {
+            let mut units = [0; 2];
+            let hex: String = ch
+                .encode_utf16(&mut units)
+                .iter()
+                .map(|u| format!("{u:04X}"))
+                .collect();
+            let _ = writeln!(cmap, "<{glyph:04X}> <{hex}>");
+        }
+        cmap.push_str("endbfchar\n");
+    }
+    cmap.push_str("endcmap\nCMapName currentdict /CMap defineresource pop\nend\nend");
+    cmap
+}
+
+/// Six capital letters that tell this subset apart from others of the same
+/// font, as the PDF format asks of subset font names
+fn subset_tag(index: usize, glyphs: &BTreeSet<u16>) -> String {
+    // FNV-1a, so the same glyphs always give the same tag
+    let mut hash = glyphs
+        .iter()
+        .map(|&g| g as u64)
+        .chain([index as u64])
+        .fold(0xcbf2_9ce4_8422_2325u64, |h, v| {
+            (h ^ v).wrapping_mul(0x0100_0000_01b3)
+        });
+    (0..6)
+        .map(|_| {
+            let letter = (b'A' + (hash % 26) as u8) as char;
+            hash /= 26;
+            letter
+        })
+        .collect()
+}
+
+/// The font's PostScript name with the characters a PDF name cannot hold
+/// dropped, or a made-up one when it has none
+fn postscript_name(font: &Font, index: usize) -> String {
+    let name: String = font
+        .face()
+        .names()
+        .into_iter()
+        .filter(|n| n.name_id == name_id::POST_SCRIPT_NAME)
+        .find_map(|n| n.to_string())
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| c.is_ascii_graphic() && !"()<>[]{}/%#".contains(*c))
+        .collect();
+    if name.is_empty() {
+        format!("Font{index}")
+    } else {
+        name
+    }
+}
+
+/// A stream object holding `data`, with `dict` added to its dictionary
+fn stream(dict: &str, data: &[u8]) -> Vec<u8> {
+    let mut body = format!("<< /Length {}{dict} >>\nstream\n", data.len()).into_bytes();
+    body.extend_from_slice(data);
+    body.extend_from_slice(b"\nendstream");
+    body
+}
+
+/// Page size in points and the drawing operators for one page
+fn render_page<'a>(page: &WhiteboardPage, fonts: &'a [Font]) -> ((f64, f64), Content<'a>) {
+    // text is measured in the fonts it is set in, Helvetica without any
+    let mut metrics = Typesetter::new();
+    for font in fonts {
+        metrics.add_font(font.clone());
+    }
+    let view = page
+        .doc
+        .content_bounds(&metrics)
+        .map(|r| r.expand(CONTENT_PADDING))
+        .unwrap_or(EMPTY_PAGE);
+    let scale = (MAX_PAGE_SIDE / view.w.max(view.h)).min(1.0);
+    let mut content = Content::new(fonts);
+    // PDF's y axis points up; flip it so shapes keep their world coordinates
+    let _ = writeln!(
+        content.ops,
+        "{s} 0 0 {} {} {} cm 10 M",
+        num(-scale),
+        num(-view.x * scale),
+        num(view.bottom() * scale),
+        s = num(scale),
+    );
+    for shape in page.doc.ordered_shapes() {
+        content.shape(shape);
+    }
+    ((view.w * scale, view.h * scale), content)
+}
+
+/// A page's content stream plus the opacity states and glyphs it refers to
+#[derive(Debug)]
+struct Content<'a> {
+    ops: String,
+    /// Opacity in thousandths to the `ExtGState` setting it
+    alphas: BTreeMap<u32, String>,
+    alpha: Option<u32>,
+    /// Fonts text is set in; Helvetica when empty
+    fonts: &'a [Font],
+    /// Glyphs shown from each font, with the character each stands for
+    glyphs: BTreeMap<usize, BTreeMap<u16, Option<char>>>,
+}
+
+impl<'a> Content<'a> {
+    fn new(fonts: &'a [Font]) -> Self {
+        Self {
+            ops: String::new(),
+            alphas: BTreeMap::new(),
+            alpha: None,
+            fonts,
+            glyphs: BTreeMap::new(),
+        }
+    }
+
+    fn shape(&mut self, shape: &Shape) {
+        // unparseable colours fall back like `tessellate_shape` does
+        let ink = Rgba::parse(&shape.stroke).unwrap_or(Rgba::BLACK);
+        let fill = shape
+            .fill
+            .as_deref()
+            .and_then(Rgba::parse)
+            .filter(Rgba::is_visible);
+        let stroke = (shape.stroke_width > 0.0 && ink.is_visible()).then_some(ink);
+        let width = shape.stroke_width;
+
+        match &shape.kind {
+            ShapeKind::Rectangle { x, y, w, h } => {
+                let r = Rect::from_points(Point::new(*x, *y), Point::new(x + w, y + h));
+                let path = format!("{} {} {} {} re", num(r.x), num(r.y), num(r.w), num(r.h));
+                self.draw(&path, fill, stroke, width, false);
+            }
+            ShapeKind::Ellipse { x, y, w, h } => {
+                let r = Rect::from_points(Point::new(*x, *y), Point::new(x + w, y + h));
+                self.draw(&ellipse_path(r), fill, stroke, width, false);
+            }
+            ShapeKind::Line { a, b } => {
+                self.draw(&polyline_path(&[*a, *b]), None, stroke, width, false);
+            }
+            ShapeKind::Arrow { a, b } => {
+                self.draw(&polyline_path(&[*a, *b]), None, stroke, width, false);
+                // the head is filled with the stroke colour, as `drawArrowHead` does
+                let angle = (b.y - a.y).atan2(b.x - a.x);
+                let wing = |da: f64| {
+                    Point::new(
+                        b.x - ARROW_HEAD_SIZE * (angle + da).cos(),
+                        b.y - ARROW_HEAD_SIZE * (angle + da).sin(),
+                    )
+                };
+                let head = [
+                    *b,
+                    wing(-std::f64::consts::FRAC_PI_6),
+                    wing(std::f64::consts::FRAC_PI_6),
+                ];
+                let path = format!("{} h", polyline_path(&head));
+                self.draw(&path, ink.is_visible().then_some(ink), None, 0.0, false);
+            }
+            ShapeKind::Pencil { points, pressure } if !pressure.is_empty() => {
+                // the width changes along the stroke, so its ink is filled instead
+                let halves = ink::half_widths(width, pressure, points.len());
+                self.draw(
+                    &pieces_path(&ink::pieces(points, &halves)),
+                    stroke,
+                    None,
+                    0.0,
+                    false,
+                );
+            }
+            ShapeKind::Pencil { points, .. } => {
+                // a lone point still gets a round dot from the zero-length segment
+                let points = match points.as_slice() {
+                    [p] => vec![*p, *p],
+                    _ => points.clone(),
+                };
+                self.draw(&polyline_path(&points), None, stroke, width, true);
+            }
+            ShapeKind::Text {
+                x,
+                y,
+                text,
+                font_size,
+                ..
+            } => {
+                if !ink.is_visible() || text.is_empty() {
+                    return;
+                }
+                // text is filled with the stroke colour, as `renderText` does
+                self.color(ink, false);
+                if !self.fonts.is_empty() {
+                    self.glyphs_of(*x, *y, text, &shape.kind);
+                    return;
+                }
+                let Some(style) = TextStyle::of(&shape.kind) else {
+                    return;
+                };
+                // Helvetica's widths are the ones `SansMetrics` measures with
+                let _ = write!(self.ops, "BT /F1 {} Tf", num(*font_size));
+                for line in layout::measure(&SansMetrics, text, &style) {
+                    let line_text = text[line.text].trim_end();
+                    if line_text.is_empty() {
+                        continue;
+                    }
+                    // the text matrix flips glyphs back upright around the baseline
+                    let _ = write!(
+                        self.ops,
+                        " 1 0 0 -1 {} {} Tm ({}) Tj",
+                        num(x + line.x),
+                        num(y + line.baseline),
+                        win_ansi(line_text)
+                    );
+                }
+                self.ops.push_str(" ET\n");
+            }
+        }
+    }
+
+    /// Shows every glyph of the text laid out at `(x, y)` by its id in the
+    /// font it comes from
+    fn glyphs_of(&mut self, x: f64, y: f64, text: &str, kind: &ShapeKind) {
+        let Some(style) = TextStyle::of(kind) else {
+            return;
+        };
+        let layout = layout::layout(self.fonts, text, &style);
+        // glyphs the cmap maps a character to show that character; ligatures
+        // and shaped forms are left without one
+        let mut chars = BTreeMap::new();
+        for (i, font) in self.fonts.iter().enumerate() {
+            let face = font.face();
+            for ch in text.chars() {
+                if let Some(GlyphId(glyph)) = face.glyph_index(ch) {
+                    chars.entry((i, glyph)).or_insert(ch);
+                }
+            }
+        }
+        let size = num(style.font_size);
+        let mut font = None;
+        self.ops.push_str("BT");
+        for glyph in &layout.glyphs {
+            if font != Some(glyph.font) {
+                let _ = write!(self.ops, " /T{} {size} Tf", glyph.font);
+                font = Some(glyph.font);
+            }
+            // the text matrix flips glyphs back upright around the baseline
+            let _ = write!(
+                self.ops,
+                " 1 0 0 -1 {} {} Tm <{:04X}> Tj",
+                num(x + glyph.x),
+                num(y + glyph.y),
+                glyph.glyph
+            );
+            let ch = chars.get(&(glyph.font, glyph.glyph)).copied();
+            let slot = self
+                .glyphs
+                .entry(glyph.font)
+                .or_default()
+                .entry(glyph.glyph)
+                .or_default();
+            *slot = slot.or(ch);
+        }
+        self.ops.push_str(" ET\n");
+    }
+
+    /// Fills and then strokes `path`, each in its own colour and opacity
+    fn draw(
+        &mut self,
+        path: &str,
+        fill: Option<Rgba>,
+        stroke: Option<Rgba>,
+        width: f64,
+        round: bool,
+    ) {
+        if let Some(color) = fill {
+            self.color(color, false);
+            let _ = writeln!(self.ops, "{path} f");
+        }
+        if let Some(color) = stroke {
+            self.color(color, true);
+            let style = if round { "1 J 1 j" } else { "0 J 0 j" };
+            let _ = writeln!(self.ops, "{} w {style} {path} S", num(width));
+        }
+    }
+
+    fn color(&mut self, c: Rgba, stroke: bool) {
+        let alpha = (c.a.clamp(0.0, 1.0) * 1000.0).round() as u32;
+        if self.alpha.unwrap_or(1000) != alpha {
+            let next = self.alphas.len();
+            let name = self
+                .alphas
+                .entry(alpha)
+                .or_insert_with(|| format!("A{next}"));
+            let _ = write!(self.ops, "/{name} gs ");
+            self.alpha = Some(alpha);
+        }
+        let _ = writeln!(
+            self.ops,
+            "{} {} {} {}",
+            num(c.r as f64),
+            num(c.g as f64),
+            num(c.b as f64),
+            if stroke { "RG" } else { "rg" }
+        );
+    }
+
+    fn ext_g_states(&self) -> String {
+        self.alphas
+            .iter()
+            .map(|(alpha, name)| {
+                let a = num(*alpha as f64 / 1000.0);
+                format!("/{name} << /ca {a} /CA {a} >>")
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+fn polyline_path(points: &[Point]) -> String {
+    let mut path = String::new();
+    for (i, p) in points.iter().enumerate() {
+        let op = if i == 0 { "m" } else { "l" };
+        let sep = if i == 0 { "" } else { " " };
+        let _ = write!(path, "{sep}{} {} {op}", num(p.x), num(p.y));
+    }
+    path
+}
+
+/// Pen pieces as closed subpaths all running the same way, for a nonzero
+/// fill
+fn pieces_path(pieces: &[Piece]) -> String {
+    let paths: Vec<String> = pieces
+        .iter()
+        .map(|piece| match piece {
+            Piece::Disc { center, radius } => ellipse_path(Rect::new(
+                center.x - radius,
+                center.y - radius,
+                radius * 2.0,
+                radius * 2.0,
+            )),
+            Piece::Quad(corners) => format!("{} h", polyline_path(corners)),
+        })
+        .collect();
+    paths.join(" ")
+}
+
+/// Four cubic arcs starting at the rightmost point
+fn ellipse_path(r: Rect) -> String {
+    let (cx, cy) = (r.x + r.w / 2.0, r.y + r.h / 2.0);
+    let (rx, ry) = (r.w / 2.0, r.h / 2.0);
+    let (kx, ky) = (rx * KAPPA, ry * KAPPA);
+    let p = |x: f64, y: f64| format!("{} {}", num(x), num(y));
+    format!(
+        "{} m {} {} {} c {} {} {} c {} {} {} c {} {} {} c h",
+        p(cx + rx, cy),
+        p(cx + rx, cy + ky),
+        p(cx + kx, cy + ry),
+        p(cx, cy + ry),
+        p(cx - kx, cy + ry),
+        p(cx - rx, cy + ky),
+        p(cx - rx, cy),
+        p(cx - rx, cy - ky),
+        p(cx - kx, cy - ry),
+        p(cx, cy - ry),
+        p(cx + kx, cy - ry),
+        p(cx + rx, cy - ky),
+        p(cx + rx, cy),
+    )
+}
+
+/// Body of a literal string in the font's WinAnsi encoding. Characters the
+/// standard fonts cannot show become `?`; embedded fonts have no such limit.
+fn win_ansi(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        let byte = match ch {
+            '\t' | '\n' | '\r' => b' ',
+            ' '..='~' => ch as u8,
+            '\u{a0}'..='\u{ff}' => ch as u32 as u8,
+            '€' => 0x80,
+            '…' => 0x85,
+            '‘' => 0x91,
+            '’' => 0x92,
+            '“' => 0x93,
+            '”' => 0x94,
+            '•' => 0x95,
+            '–' => 0x96,
+            '—' => 0x97,
+            _ => b'?',
+        };
+        match byte {
+            b'(' | b')' | b'\\' => {
+                out.push('\\');
+                out.push(byte as char);
+            }
+            0x20..=0x7e => out.push(byte as char),
+            _ => {
+                let _ = write!(out, "\\{byte:03o}");
+            }
+        }
+    }
+    out
+}
+
+/// Hex string in UTF-16BE with a byte order mark, which viewers show
+/// correctly for any script
+fn text_string(text: &str) -> String {
+    let mut out = String::from("<FEFF");
+    for unit in text.encode_utf16() {
+        let _ = write!(out, "{unit:04X}");
+    }
+    out.push('>');
+    out
+}
+
+/// Appends numbered objects and builds the cross-reference table
+#[derive(Debug)]
+struct Writer {
+    out: Vec<u8>,
+    offsets: BTreeMap<usize, usize>,
+}
+
+impl Writer {
+    fn new() -> Self {
+        // the binary comment tells transfer tools the file is not text
+        Self {
+            out: b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec(),
+            offsets: BTreeMap::new(),
+        }
+    }
+
+    fn object(&mut self, id: usize, body: String) {
+        self.object_bytes(id, body.into_bytes());
+    }
+
+    fn object_bytes(&mut self, id: usize, body: Vec<u8>) {
+        self.offsets.insert(id, self.out.len());
+        self.out
+            .extend_from_slice(format!("{id} 0 obj\n").as_bytes());
+        self.out.extend_from_slice(&body);
+        self.out.extend_from_slice(b"\nendobj\n");
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let xref = self.out.len();
+        let size = self.offsets.len() + 1;
+        let mut table = format!("xref\n0 {size}\n0000000000 65535 f \n");
+        for offset in self.offsets.values() {
+            let _ = writeln!(table, "{offset:010} 00000 n ");
+        }
+        let _ = write!(
+            table,
+            "trailer\n<< /Size {size} /Root {CATALOG} 0 R >>\nstartxref\n{xref}\n%%EOF\n"
+        );
+        self.out.extend_from_slice(table.as_bytes());
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::WhiteboardDoc;
+    use crate::text::test_font::*;
+    use rustybuzz::ttf_parser::Face;
+
+    fn board() -> Board {
+        let mut first = WhiteboardPage::new("page-1", "Übersicht");
+        first.doc = WhiteboardDoc::from_json(
+            r##"{
+                "shapes": {
+                    "r": { "id": "r", "type": "rectangle", "x": 0, "y": 0, "w": 100, "h": 50,
+                           "stroke": "#000000", "fill": "rgba(255,0,0,0.5)", "strokeWidth": 2 },
+                    "t": { "id": "t", "type": "text", "x": 10, "y": 30, "text": "(Größe)",
+                           "stroke": "#111827", "fill": null, "strokeWidth": 2, "fontSize": 12 }
+                },
+                "order": ["r", "t"]
+            }"##,
+        )
+        .unwrap();
+        Board {
+            pages: vec![first, WhiteboardPage::new("page-2", "Page 2")],
+            active_page_id: "page-1".to_string(),
+        }
+    }
+
+    fn text(pdf: &[u8]) -> String {
+        String::from_utf8_lossy(pdf).into_owned()
+    }
+
+    #[test]
+    fn xref_points_at_every_object() {
+        let pdf = to_pdf(&board(), &[]);
+        let s = text(&pdf);
+        let start: usize = s
+            .rsplit("startxref\n")
+            .next()
+            .and_then(|t| t.lines().next())
+            .unwrap()
+            .parse()
+            .unwrap();
+        let table = std::str::from_utf8(&pdf[start..]).unwrap();
+        assert!(table.starts_with("xref\n0 11\n"));
+        let entries: Vec<&str> = table.lines().skip(3).take(10).collect();
+        for (i, entry) in entries.iter().enumerate() {
+            let offset: usize = entry[..10].parse().unwrap();
+            let header = format!("{} 0 obj\n", i + 1);
+            assert!(
+                pdf[offset..].starts_with(header.as_bytes()),
+                "object {}",
+                i + 1
+            );
+        }
+        // the stream length matches what sits between the keywords
+        let (_, stream) = s.split_once(">>\nstream\n").unwrap();
+        let len = stream.find("\nendstream").unwrap();
+        assert!(s.contains(&format!("<< /Length {len} >>")));
+    }
+
+    #[test]
+    fn one_page_and_bookmark_per_whiteboard_page() {
+        let s = text(&to_pdf(&board(), &[]));
+        assert_eq!(s.matches("/Type /Page ").count(), 2);
+        assert!(s.contains("/Kids [5 0 R 8 0 R] /Count 2"));
+        // content plus padding, and A4 for the empty page
+        assert!(s.contains("/MediaBox [0 0 134 84]"));
+        assert!(s.contains("/MediaBox [0 0 842 595]"));
+        assert!(s.contains("/Title <FEFF00DC00620065007200730069006300680074> /Parent 3 0 R /Dest [5 0 R /Fit] /Next 10 0 R"));
+        assert!(s.contains(
+            "/Title <FEFF005000610067006500200032> /Parent 3 0 R /Dest [8 0 R /Fit] /Prev 7 0 R"
+        ));
+    }
+
+    #[test]
+    fn shapes_become_paths_and_text() {
+        let s = text(&to_pdf(&board(), &[]));
+        assert!(s.contains("1 0 0 -1 17 67 cm"));
+        assert!(s.contains("/A0 gs 1 0 0 rg\n0 0 100 50 re f\n"));
+        assert!(s.contains("/A1 gs 0 0 0 RG\n2 w 0 J 0 j 0 0 100 50 re S\n"));
+        assert!(s.contains("/ExtGState << /A0 << /ca 0.5 /CA 0.5 >> /A1 << /ca 1 /CA 1 >> >>"));
+        assert!(s.contains("BT /F1 12 Tf 1 0 0 -1 10 30 Tm (\\(Gr\\366\\337e\\)) Tj ET"));
+    }
+
+    #[test]
+    fn helvetica_text_is_set_line_by_line() {
+        let mut page = WhiteboardPage::new("page-1", "Lines");
+        page.doc = WhiteboardDoc::from_json(
+            r##"{
+                "shapes": {
+                    "t": { "id": "t", "type": "text", "x": 50, "y": 20, "text": "Hi\nthere",
+                           "stroke": "#000000", "fill": null, "strokeWidth": 1, "fontSize": 10,
+                           "align": "center", "lineHeight": 2 }
+                },
+                "order": ["t"]
+            }"##,
+        )
+        .unwrap();
+        let board = Board {
+            pages: vec![page],
+            active_page_id: "page-1".to_string(),
+        };
+        let s = text(&to_pdf(&board, &[]));
+        // each line centred on x = 50 by its Helvetica width, 20 apart
+        assert!(s.contains(
+            "BT /F1 10 Tf 1 0 0 -1 45.28 20 Tm (Hi) Tj 1 0 0 -1 38.605 40 Tm (there) Tj ET"
+        ));
+    }
+
+    #[test]
+    fn text_is_set_in_embedded_font_subsets() {
+        let font = Font::from_bytes(test_font(true, true)).unwrap();
+        let mut page = WhiteboardPage::new("page-1", "ไทย");
+        page.doc = WhiteboardDoc::from_json(
+            r##"{
+                "shapes": {
+                    "t": { "id": "t", "type": "text", "x": 10, "y": 30, "text": "กา",
+                           "stroke": "#000000", "fill": null, "strokeWidth": 2, "fontSize": 10 }
+                },
+                "order": ["t"]
+            }"##,
+        )
+        .unwrap();
+        let board = Board {
+            pages: vec![page],
+            active_page_id: "page-1".to_string(),
+        };
+        let pdf = to_pdf(&board, &[font]);
+        let s = text(&pdf);
+
+        assert!(s.contains("BT /T0 10 Tf 1 0 0 -1 10 30 Tm <0003> Tj 1 0 0 -1 "));
+        assert!(s.contains("<0006> Tj ET"));
+        assert!(s.contains("/Resources << /Font << /F1 4 0 R /T0 8 0 R >>"));
+        assert!(s.contains("/Subtype /Type0"));
+        assert!(s.contains("/Encoding /Identity-H /DescendantFonts [9 0 R] /ToUnicode 12 0 R"));
+        assert!(s.contains("/CIDToGIDMap /Identity"));
+        assert!(s.contains("2 beginbfchar\n<0003> <0E01>\n<0006> <0E32>\nendbfchar"));
+        assert!(s.contains("xref\n0 13\n"));
+
+        // the embedded file is a font with the shown glyphs under their ids
+        let find = |needle: &[u8], from: usize| {
+            from + pdf[from..]
+                .windows(needle.len())
+                .position(|w| w == needle)
+                .unwrap()
+        };
+        let at = find(b"/Length1 ", 0) + 9;
+        let len: usize = text(&pdf[at..find(b" ", at)]).parse().unwrap();
+        let start = find(b"stream\n", at) + 7;
+        let face = Face::parse(&pdf[start..start + len], 0).unwrap();
+        assert!(face.glyph_bounding_box(GlyphId(THAI_CONSONANT)).is_some());
+        assert!(face.glyph_bounding_box(GlyphId(SARA_AA)).is_some());
+        assert!(face.glyph_bounding_box(GlyphId(LATIN)).is_none());
+
+        // without a TrueType font the text falls back to Helvetica
+        assert!(text(&to_pdf(&board, &[])).contains("(??) Tj"));
+    }
+}
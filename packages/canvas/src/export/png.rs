@@ -0,0 +1,169 @@
+//! PNG export. The renderers produce the pixels; this module maps a world
+//! region onto an image and encodes the result.
+
+use super::CONTENT_PADDING;
+use crate::error::CanvasError;
+use crate::geometry::text::TextMetrics;
+use crate::model::color::Rgba;
+use crate::model::{Camera, Rect, WhiteboardDoc};
+use crate::types::Size;
+
+/// Image size and camera that put a world rect onto the whole image
+#[derive(Debug, Clone, Copy)]
+pub struct ExportRegion {
+    pub size: Size,
+    pub camera: Camera,
+}
+
+impl ExportRegion {
+    /// `bounds` at `scale` image pixels per world unit, rejecting images with
+    /// a side over `max_dimension`
+    pub fn new(bounds: Rect, scale: f64, max_dimension: u32) -> Result<Self, CanvasError> {
+        if !(scale.is_finite() && scale > 0.0) {
+            return Err(CanvasError::Export(format!("invalid scale {scale}")));
+        }
+        if !(bounds.w > 0.0 && bounds.h > 0.0) {
+            return Err(CanvasError::Export(format!("empty bounds {bounds:?}")));
+        }
+        let width = (bounds.w * scale).ceil();
+        let height = (bounds.h * scale).ceil();
+        if width > max_dimension as f64 || height > max_dimension as f64 {
+            return Err(CanvasError::Export(format!(
+                "{width}x{height} image exceeds the {max_dimension}px limit"
+            )));
+        }
+        Ok(Self {
+            size: Size {
+                width: width as u32,
+                height: height as u32,
+            },
+            camera: Camera {
+                x: -bounds.x * scale,
+                y: -bounds.y * scale,
+                zoom: scale,
+            },
+        })
+    }
+
+    /// Like [`ExportRegion::new`], defaulting to the doc's content, with
+    /// text measured by `metrics`, plus [`CONTENT_PADDING`]
+    pub fn for_doc(
+        doc: &WhiteboardDoc,
+        metrics: &dyn TextMetrics,
+        bounds: Option<Rect>,
+        scale: f64,
+        max_dimension: u32,
+    ) -> Result<Self, CanvasError> {
+        let bounds = match bounds {
+            Some(bounds) => bounds,
+            None => doc
+                .content_bounds(metrics)
+                .ok_or_else(|| CanvasError::Export("document is empty".to_string()))?
+                .expand(CONTENT_PADDING),
+        };
+        Self::new(bounds, scale, max_dimension)
+    }
+}
+
+/// Premultiplied linear clear colour for an export, transparent when `None`.
+/// Shapes are blended over it, so the rendered pixels stay premultiplied.
+pub fn background(css: Option<&str>) -> Result<Rgba, CanvasError> {
+    let Some(css) = css else {
+        return Ok(Rgba::TRANSPARENT);
+    };
+    let c = Rgba::parse(css)
+        .ok_or_else(|| CanvasError::Export(format!("unsupported colour {css}")))?
+        .to_linear();
+    Ok(Rgba::new(c.r * c.a, c.g * c.a, c.b * c.a, c.a))
+}
+
+/// Encodes pixels rendered over a [`background`] colour, converting the
+/// translucent ones back to the straight alpha PNG expects
+pub fn encode_rendered(size: Size, mut pixels: Vec<u8>) -> Result<Vec<u8>, CanvasError> {
+    unpremultiply(&mut pixels);
+    encode(size.width, size.height, &pixels)
+}
+
+/// Encodes tightly packed RGBA8 rows
+pub fn encode(width: u32, height: u32, rgba: &[u8]) -> Result<Vec<u8>, CanvasError> {
+    let err = |e: png::EncodingError| CanvasError::Export(e.to_string());
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(&mut out, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(err)?;
+    writer.write_image_data(rgba).map_err(err)?;
+    writer.finish().map_err(err)?;
+    Ok(out)
+}
+
+/// sRGB-encoded premultiplied RGBA8 to straight alpha, dividing in linear
+/// space where the blending happened
+fn unpremultiply(pixels: &mut [u8]) {
+    for px in pixels.chunks_exact_mut(4) {
+        let a = px[3];
+        if a == 0 {
+            px.fill(0);
+            continue;
+        }
+        if a == 255 {
+            continue;
+        }
+        let alpha = a as f32 / 255.0;
+        let c = Rgba::new(
+            px[0] as f32 / 255.0,
+            px[1] as f32 / 255.0,
+            px[2] as f32 / 255.0,
+            alpha,
+        )
+        .to_linear();
+        let straight = Rgba::new(c.r / alpha, c.g / alpha, c.b / alpha, alpha).to_srgb();
+        px[0] = (straight.r.clamp(0.0, 1.0) * 255.0).round() as u8;
+        px[1] = (straight.g.clamp(0.0, 1.0) * 255.0).round() as u8;
+        px[2] = (straight.b.clamp(0.0, 1.0) * 255.0).round() as u8;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::text::SansMetrics;
+    use crate::model::Point;
+
+    #[test]
+    fn region_maps_bounds_onto_the_image() {
+        let bounds = Rect::new(-10.0, 20.0, 100.5, 50.0);
+        let region = ExportRegion::new(bounds, 2.0, 4096).unwrap();
+        assert_eq!((region.size.width, region.size.height), (201, 100));
+        let top_left = region.camera.world_to_screen(Point::new(-10.0, 20.0));
+        let bottom_right = region.camera.world_to_screen(Point::new(90.5, 70.0));
+        assert_eq!((top_left.x, top_left.y), (0.0, 0.0));
+        assert_eq!((bottom_right.x, bottom_right.y), (201.0, 100.0));
+
+        assert!(ExportRegion::new(bounds, 0.0, 4096).is_err());
+        assert!(ExportRegion::new(bounds, 100.0, 4096).is_err());
+        assert!(
+            ExportRegion::for_doc(&WhiteboardDoc::new(), &SansMetrics, None, 1.0, 4096).is_err()
+        );
+    }
+
+    #[test]
+    fn translucent_pixels_become_straight_alpha() {
+        // red at alpha 128 over transparent, as the GPU writes it
+        let red = Rgba::new(128.0 / 255.0, 0.0, 0.0, 1.0).to_srgb().r;
+        let mut pixels = vec![(red * 255.0).round() as u8, 0, 0, 128, 10, 20, 30, 255];
+        unpremultiply(&mut pixels);
+        assert!(pixels[0] >= 254);
+        assert_eq!(pixels[1..], [0, 0, 128, 10, 20, 30, 255]);
+
+        let bg = background(Some("rgba(255,255,255,0.5)")).unwrap();
+        assert_eq!(bg, Rgba::new(0.5, 0.5, 0.5, 0.5));
+        assert_eq!(background(None).unwrap(), Rgba::TRANSPARENT);
+    }
+
+    #[test]
+    fn encodes_png() {
+        let bytes = encode(2, 1, &[255, 0, 0, 255, 0, 0, 255, 128]).unwrap();
+        assert_eq!(&bytes[..8], b"\x89PNG\r\n\x1a\n");
+    }
+}
@@ -0,0 +1,335 @@
+//! SVG export. Each shape becomes the element the canvas renderer in
+//! `apps/web/app/utils/whiteboard/renderer` would draw, in z-order, inside a
+//! viewBox fitted to the content. Colours are written as `#rrggbb` plus an
+//! opacity so the files open in editors without CSS colour level 4 support.
+
+use std::f64::consts::PI;
+use std::fmt::Write;
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::*;
+
+use super::{num, CONTENT_PADDING};
+use crate::geometry::text::SansMetrics;
+use crate::ink::{self, Piece};
+use crate::model::color::Rgba;
+use crate::model::shape::ARROW_HEAD_SIZE;
+use crate::model::{Point, Rect, Shape, ShapeKind, WhiteboardDoc};
+use crate::text::layout::{self, TextAlign, TextStyle};
+
+/// `renderText` in text.ts
+const FONT_FAMILY: &str = "ui-sans-serif, system-ui, sans-serif";
+
+/// Serialises a `WhiteboardDoc` given as JSON to a standalone SVG document
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen(js_name = "exportSvg")]
+pub fn export_svg(doc_json: &str) -> Result<String, JsValue> {
+    Ok(to_svg(&WhiteboardDoc::from_json(doc_json)?))
+}
+
+/// Standalone SVG document of `doc`, one user unit per world unit
+pub fn to_svg(doc: &WhiteboardDoc) -> String {
+    // viewers set the text in their own sans-serif, measured like Helvetica
+    let view = doc
+        .content_bounds(&SansMetrics)
+        .map(|r| r.expand(CONTENT_PADDING))
+        .unwrap_or_default();
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="{x} {y} {w} {h}" stroke-miterlimit="10">"#,
+        x = num(view.x),
+        y = num(view.y),
+        w = num(view.w),
+        h = num(view.h),
+    );
+    for shape in doc.ordered_shapes() {
+        write_shape(&mut out, shape);
+    }
+    out.push_str("</svg>\n");
+    out
+}
+
+fn write_shape(out: &mut String, shape: &Shape) {
+    // unparseable colours fall back like `tessellate_shape` does
+    let ink = Rgba::parse(&shape.stroke).unwrap_or(Rgba::BLACK);
+    let stroke = if shape.stroke_width > 0.0 && ink.is_visible() {
+        format!(
+            r#"{} stroke-width="{}""#,
+            paint("stroke", Some(ink)),
+            num(shape.stroke_width)
+        )
+    } else {
+        r#"stroke="none""#.to_string()
+    };
+    let fill = paint("fill", shape.fill.as_deref().and_then(Rgba::parse));
+
+    let _ = match &shape.kind {
+        ShapeKind::Rectangle { x, y, w, h } => {
+            let r = Rect::from_points(Point::new(*x, *y), Point::new(x + w, y + h));
+            writeln!(
+                out,
+                r#"  <rect x="{}" y="{}" width="{}" height="{}" {fill} {stroke}/>"#,
+                num(r.x),
+                num(r.y),
+                num(r.w),
+                num(r.h),
+            )
+        }
+        ShapeKind::Ellipse { x, y, w, h } => writeln!(
+            out,
+            r#"  <ellipse cx="{}" cy="{}" rx="{}" ry="{}" {fill} {stroke}/>"#,
+            num(x + w / 2.0),
+            num(y + h / 2.0),
+            num(w.abs() / 2.0),
+            num(h.abs() / 2.0),
+        ),
+        ShapeKind::Line { a, b } => writeln!(
+            out,
+            r#"  <line x1="{}" y1="{}" x2="{}" y2="{}" {stroke}/>"#,
+            num(a.x),
+            num(a.y),
+            num(b.x),
+            num(b.y),
+        ),
+        ShapeKind::Arrow { a, b } => {
+            // the head is filled with the stroke colour, as `drawArrowHead` does
+            let head = paint("fill", Some(ink));
+            let angle = (b.y - a.y).atan2(b.x - a.x);
+            let wing = |da: f64| {
+                Point::new(
+                    b.x - ARROW_HEAD_SIZE * (angle + da).cos(),
+                    b.y - ARROW_HEAD_SIZE * (angle + da).sin(),
+                )
+            };
+            writeln!(
+                out,
+                r#"  <g><line x1="{}" y1="{}" x2="{}" y2="{}" {stroke}/><polygon points="{}" {head}/></g>"#,
+                num(a.x),
+                num(a.y),
+                num(b.x),
+                num(b.y),
+                points(&[*b, wing(-PI / 6.0), wing(PI / 6.0)]),
+            )
+        }
+        ShapeKind::Pencil {
+            points: pts,
+            pressure,
+        } if pressure.is_empty() => writeln!(
+            out,
+            r#"  <polyline points="{}" fill="none" {stroke} stroke-linecap="round" stroke-linejoin="round"/>"#,
+            points(pts),
+        ),
+        ShapeKind::Pencil {
+            points: pts,
+            pressure,
+        } => {
+            // the width changes along the stroke, so its ink is filled instead
+            let color = paint("fill", (shape.stroke_width > 0.0).then_some(ink));
+            let halves = ink::half_widths(shape.stroke_width, pressure, pts.len());
+            writeln!(
+                out,
+                r#"  <path d="{}" {color}/>"#,
+                pieces_path(&ink::pieces(pts, &halves))
+            )
+        }
+        ShapeKind::Text {
+            x,
+            y,
+            text,
+            font_size,
+            align,
+            ..
+        } => {
+            // text is filled with the stroke colour, as `renderText` does
+            let color = paint("fill", Some(ink));
+            let anchor = match align.unwrap_or_default() {
+                TextAlign::Left => "",
+                TextAlign::Center => r#" text-anchor="middle""#,
+                TextAlign::Right => r#" text-anchor="end""#,
+            };
+            writeln!(
+                out,
+                r#"  <text x="{}" y="{}" font-size="{}" font-family="{FONT_FAMILY}"{anchor} {color} xml:space="preserve">{}</text>"#,
+                num(*x),
+                num(*y),
+                num(*font_size),
+                tspans(*x, text, &shape.kind),
+            )
+        }
+    };
+}
+
+/// One `tspan` per line of a text shape, broken like the renderers break
+/// them and each anchored on `x`. Blank lines get no `tspan`; the next line
+/// moves down past them.
+fn tspans(x: f64, text: &str, kind: &ShapeKind) -> String {
+    let Some(style) = TextStyle::of(kind) else {
+        return String::new();
+    };
+    let mut out = String::new();
+    let mut baseline = 0.0;
+    for line in layout::measure(&SansMetrics, text, &style) {
+        // trailing spaces would push aligned lines off their anchor
+        let line_text = text[line.text].trim_end();
+        if line_text.is_empty() {
+            continue;
+        }
+        let _ = write!(
+            out,
+            r#"<tspan x="{}" dy="{}">{}</tspan>"#,
+            num(x),
+            num(line.baseline - baseline),
+            escape(line_text),
+        );
+        baseline = line.baseline;
+    }
+    out
+}
+
+/// `name="#rrggbb"` plus `name-opacity` when translucent, or `name="none"`
+fn paint(name: &str, color: Option<Rgba>) -> String {
+    let Some(c) = color.filter(Rgba::is_visible) else {
+        return format!(r#"{name}="none""#);
+    };
+    let byte = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+    let mut attr = format!(
+        r##"{name}="#{:02x}{:02x}{:02x}""##,
+        byte(c.r),
+        byte(c.g),
+        byte(c.b)
+    );
+    if c.a < 1.0 {
+        let _ = write!(attr, r#" {name}-opacity="{}""#, num(c.a as f64));
+    }
+    attr
+}
+
+fn points(points: &[Point]) -> String {
+    points
+        .iter()
+        .map(|p| format!("{},{}", num(p.x), num(p.y)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Path data of pen pieces, each a closed subpath, for a nonzero fill
+fn pieces_path(pieces: &[Piece]) -> String {
+    let mut d = String::new();
+    for piece in pieces {
+        let _ = match piece {
+            Piece::Disc {
+                center: c,
+                radius: r,
+            } => write!(
+                d,
+                "M{},{}A{r},{r} 0 1 1 {},{}A{r},{r} 0 1 1 {},{}Z",
+                num(c.x + r),
+                num(c.y),
+                num(c.x - r),
+                num(c.y),
+                num(c.x + r),
+                num(c.y),
+                r = num(*r),
+            ),
+            Piece::Quad([a, b, c, e]) => write!(
+                d,
+                "M{},{}L{},{}L{},{}L{},{}Z",
+                num(a.x),
+                num(a.y),
+                num(b.x),
+                num(b.y),
+                num(c.x),
+                num(c.y),
+                num(e.x),
+                num(e.y),
+            ),
+        };
+    }
+    d
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            // characters XML 1.0 cannot represent at all
+            c if (c as u32) < 0x20 && !matches!(c, '\t' | '\n' | '\r') => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOC_JSON: &str = r##"{
+        "shapes": {
+            "r": { "id": "r", "type": "rectangle", "x": 40, "y": 30, "w": -30, "h": 20,
+                   "stroke": "#111827", "fill": "rgba(59,130,246,0.5)", "strokeWidth": 2 },
+            "a": { "id": "a", "type": "arrow", "a": { "x": 0, "y": 0 }, "b": { "x": 20, "y": 0 },
+                   "stroke": "#ff0000", "fill": null, "strokeWidth": 1 },
+            "t": { "id": "t", "type": "text", "x": 10, "y": 80, "text": "a < b & \"c\"",
+                   "stroke": "#000", "fill": null, "strokeWidth": 2, "fontSize": 10 },
+            "p": { "id": "p", "type": "pencil", "points": [{ "x": 1, "y": 2 }, { "x": 3.25, "y": 4 }],
+                   "stroke": "transparent", "fill": null, "strokeWidth": 0 }
+        },
+        "order": ["t", "r", "a", "p"]
+    }"##;
+
+    #[test]
+    fn writes_shapes_in_z_order() {
+        let svg = to_svg(&WhiteboardDoc::from_json(DOC_JSON).unwrap());
+        let lines: Vec<&str> = svg.lines().collect();
+        assert_eq!(lines.len(), 6);
+        assert!(lines[1].starts_with("  <text "));
+        assert!(lines[1].contains(r##"fill="#000000""##));
+        assert!(lines[1]
+            .contains(r#"><tspan x="10" dy="0">a &lt; b &amp; &quot;c&quot;</tspan></text>"#));
+        assert_eq!(
+            lines[2],
+            r##"  <rect x="10" y="30" width="30" height="20" fill="#3b82f6" fill-opacity="0.5" stroke="#111827" stroke-width="2"/>"##
+        );
+        assert!(lines[3].contains(r##"<polygon points="20,0 11.34,5 11.34,-5" fill="#ff0000"/>"##));
+        assert!(lines[4].contains(r#"points="1,2 3.25,4" fill="none" stroke="none""#));
+        assert_eq!(lines[5], "</svg>");
+    }
+
+    #[test]
+    fn view_box_covers_content() {
+        let svg = to_svg(&WhiteboardDoc::from_json(DOC_JSON).unwrap());
+        // arrow head reaches -10.5 on both axes; text ends at 10 + 46.85 in
+        // Helvetica and its descent at 82.12
+        assert!(svg.starts_with(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="99.35" height="124.62" viewBox="-26.5 -26.5 99.35 124.62""#
+        ));
+        assert!(to_svg(&WhiteboardDoc::new()).contains(r#"viewBox="0 0 0 0""#));
+    }
+
+    #[test]
+    fn text_is_written_line_by_line() {
+        let doc = WhiteboardDoc::from_json(
+            r##"{
+                "shapes": {
+                    "t": { "id": "t", "type": "text", "x": 100, "y": 50, "text": "WWWW WWWW\n\nW",
+                           "fontSize": 10, "align": "right", "maxWidth": 50, "lineHeight": 1.5,
+                           "stroke": "#000", "fill": null, "strokeWidth": 1 }
+                },
+                "order": ["t"]
+            }"##,
+        )
+        .unwrap();
+        let svg = to_svg(&doc);
+        assert!(svg.contains(concat!(
+            r##"text-anchor="end" fill="#000000" xml:space="preserve">"##,
+            r#"<tspan x="100" dy="0">WWWW</tspan><tspan x="100" dy="15">WWWW</tspan>"#,
+            r#"<tspan x="100" dy="30">W</tspan></text>"#,
+        )));
+    }
+}
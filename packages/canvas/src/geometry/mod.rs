@@ -0,0 +1,572 @@
+//! Exact geometry of painted shapes, replacing the approximations in
+//! `hitTestShape` (geometry.ts). Distances are measured to what the renderer
+//! actually paints: the fill when it is visible, the stroke band with its
+//! real joins and caps, arrowheads and the text's glyph box.
+
+pub mod polygon;
+pub mod text;
+
+use std::f64::consts::PI;
+
+use crate::ink;
+use crate::model::color::Rgba;
+use crate::model::shape::ARROW_HEAD_SIZE;
+use crate::model::{Point, Rect, Shape, ShapeKind};
+use crate::text::layout::TextStyle;
+
+use self::text::TextMetrics;
+
+/// Bisection steps when solving for the closest point on an ellipse; enough
+/// to reach the precision of an `f64`
+const ELLIPSE_MAX_ITERATIONS: usize = 160;
+
+/// Distance from `p` to the segment `a`–`b`, `distancePointToSegment` in
+/// geometry.ts
+pub fn distance_to_segment(p: Point, a: Point, b: Point) -> f64 {
+    let (vx, vy) = (b.x - a.x, b.y - a.y);
+    let (wx, wy) = (p.x - a.x, p.y - a.y);
+    let c1 = vx * wx + vy * wy;
+    let c2 = vx * vx + vy * vy;
+    let t = if c1 <= 0.0 {
+        0.0
+    } else if c2 <= c1 {
+        1.0
+    } else {
+        c1 / c2
+    };
+    (p.x - (a.x + t * vx)).hypot(p.y - (a.y + t * vy))
+}
+
+/// Distance from `p` to the nearest segment of an open polyline; a single
+/// point counts as a polyline of zero length and an empty one is infinitely
+/// far away
+pub fn distance_to_polyline(points: &[Point], p: Point) -> f64 {
+    match points {
+        [] => f64::INFINITY,
+        [only] => (p.x - only.x).hypot(p.y - only.y),
+        _ => points
+            .windows(2)
+            .map(|s| distance_to_segment(p, s[0], s[1]))
+            .fold(f64::INFINITY, f64::min),
+    }
+}
+
+/// A rectangle rotated by `angle` radians about its centre
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OrientedRect {
+    pub center: Point,
+    pub half_width: f64,
+    pub half_height: f64,
+    pub angle: f64,
+}
+
+impl OrientedRect {
+    /// `rect` turned by `angle` about its centre, as Excalidraw and tldraw
+    /// rotate shapes
+    pub fn from_rect(rect: Rect, angle: f64) -> Self {
+        Self {
+            center: Point::new(rect.x + rect.w / 2.0, rect.y + rect.h / 2.0),
+            half_width: rect.w.abs() / 2.0,
+            half_height: rect.h.abs() / 2.0,
+            angle,
+        }
+    }
+
+    /// The butt-capped band a stroke of `width` paints along `a`–`b`
+    pub fn along_segment(a: Point, b: Point, width: f64) -> Self {
+        Self {
+            center: Point::new((a.x + b.x) / 2.0, (a.y + b.y) / 2.0),
+            half_width: (b.x - a.x).hypot(b.y - a.y) / 2.0,
+            half_height: width.abs() / 2.0,
+            angle: (b.y - a.y).atan2(b.x - a.x),
+        }
+    }
+
+    /// `p` in the rectangle's frame, centred and unrotated
+    pub fn to_local(&self, p: Point) -> Point {
+        let (sin, cos) = self.angle.sin_cos();
+        let (dx, dy) = (p.x - self.center.x, p.y - self.center.y);
+        Point::new(dx * cos + dy * sin, -dx * sin + dy * cos)
+    }
+
+    pub fn corners(&self) -> [Point; 4] {
+        let (sin, cos) = self.angle.sin_cos();
+        let (w, h) = (self.half_width, self.half_height);
+        [(-w, -h), (w, -h), (w, h), (-w, h)].map(|(x, y)| {
+            Point::new(
+                self.center.x + x * cos - y * sin,
+                self.center.y + x * sin + y * cos,
+            )
+        })
+    }
+
+    pub fn contains(&self, p: Point) -> bool {
+        let l = self.to_local(p);
+        l.x.abs() <= self.half_width && l.y.abs() <= self.half_height
+    }
+
+    /// Distance from `p` to the filled rectangle, 0 inside
+    pub fn distance(&self, p: Point) -> f64 {
+        let l = self.to_local(p);
+        let dx = (l.x.abs() - self.half_width).max(0.0);
+        let dy = (l.y.abs() - self.half_height).max(0.0);
+        dx.hypot(dy)
+    }
+
+    /// Distance from `p` to the rectangle's edges, from either side
+    pub fn distance_to_outline(&self, p: Point) -> f64 {
+        let l = self.to_local(p);
+        let dx = l.x.abs() - self.half_width;
+        let dy = l.y.abs() - self.half_height;
+        if dx <= 0.0 && dy <= 0.0 {
+            -dx.max(dy)
+        } else {
+            dx.max(0.0).hypot(dy.max(0.0))
+        }
+    }
+
+    /// Bounding box of the rotated corners
+    pub fn bounds(&self) -> Rect {
+        let [first, rest @ ..] = self.corners();
+        rest.iter().fold(Rect::from_points(first, first), |r, c| {
+            r.union(&Rect::from_points(*c, *c))
+        })
+    }
+}
+
+/// An axis-aligned ellipse
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ellipse {
+    pub center: Point,
+    pub rx: f64,
+    pub ry: f64,
+}
+
+impl Ellipse {
+    /// The ellipse inscribed in `rect`, as the renderers draw `ellipse` shapes
+    pub fn from_rect(rect: Rect) -> Self {
+        Self {
+            center: Point::new(rect.x + rect.w / 2.0, rect.y + rect.h / 2.0),
+            rx: rect.w.abs() / 2.0,
+            ry: rect.h.abs() / 2.0,
+        }
+    }
+
+    pub fn contains(&self, p: Point) -> bool {
+        if self.rx <= 0.0 || self.ry <= 0.0 {
+            return self.distance_to_outline(p) == 0.0;
+        }
+        let nx = (p.x - self.center.x) / self.rx;
+        let ny = (p.y - self.center.y) / self.ry;
+        nx * nx + ny * ny <= 1.0
+    }
+
+    /// Distance from `p` to the curve, from either side
+    pub fn distance_to_outline(&self, p: Point) -> f64 {
+        let (x, y) = ((p.x - self.center.x).abs(), (p.y - self.center.y).abs());
+        // the closest point solver wants the longer axis first
+        if self.rx >= self.ry {
+            distance_to_ellipse(self.rx, self.ry, x, y)
+        } else {
+            distance_to_ellipse(self.ry, self.rx, y, x)
+        }
+    }
+}
+
+/// Distance from (`y0`, `y1`) in the first quadrant to the ellipse with
+/// semi-axes `e0 >= e1`, after Eberly's "Distance from a Point to an Ellipse"
+fn distance_to_ellipse(e0: f64, e1: f64, y0: f64, y1: f64) -> f64 {
+    if e1 <= 0.0 {
+        // flat: the segment from -e0 to e0
+        return (y0 - y0.min(e0)).hypot(y1);
+    }
+    if y1 > 0.0 {
+        if y0 > 0.0 {
+            let z0 = y0 / e0;
+            let z1 = y1 / e1;
+            let g = z0 * z0 + z1 * z1 - 1.0;
+            if g == 0.0 {
+                return 0.0;
+            }
+            let r0 = (e0 / e1).powi(2);
+            let s = ellipse_root(r0, z0, z1, g);
+            let x0 = r0 * y0 / (s + r0);
+            let x1 = y1 / (s + 1.0);
+            return (x0 - y0).hypot(x1 - y1);
+        }
+        return (y1 - e1).abs();
+    }
+    let numer = e0 * y0;
+    let denom = e0 * e0 - e1 * e1;
+    if numer < denom {
+        let xde0 = numer / denom;
+        let x0 = e0 * xde0;
+        let x1 = e1 * (1.0 - xde0 * xde0).max(0.0).sqrt();
+        (x0 - y0).hypot(x1)
+    } else {
+        (y0 - e0).abs()
+    }
+}
+
+/// Root of `(r0 z0 / (s + r0))^2 + (z1 / (s + 1))^2 = 1` by bisection
+fn ellipse_root(r0: f64, z0: f64, z1: f64, g: f64) -> f64 {
+    let n0 = r0 * z0;
+    let mut s0 = z1 - 1.0;
+    let mut s1 = if g < 0.0 { 0.0 } else { n0.hypot(z1) - 1.0 };
+    let mut s = 0.0;
+    for _ in 0..ELLIPSE_MAX_ITERATIONS {
+        s = (s0 + s1) / 2.0;
+        if s == s0 || s == s1 {
+            break;
+        }
+        let g = (n0 / (s + r0)).powi(2) + (z1 / (s + 1.0)).powi(2) - 1.0;
+        if g > 0.0 {
+            s0 = s;
+        } else if g < 0.0 {
+            s1 = s;
+        } else {
+            break;
+        }
+    }
+    s
+}
+
+/// Tip and wings of the head the renderers draw at `b`, filled in the
+/// stroke colour
+pub fn arrow_head(a: Point, b: Point) -> [Point; 3] {
+    let angle = (b.y - a.y).atan2(b.x - a.x);
+    let wing = |da: f64| {
+        Point::new(
+            b.x - ARROW_HEAD_SIZE * (angle + da).cos(),
+            b.y - ARROW_HEAD_SIZE * (angle + da).sin(),
+        )
+    };
+    [b, wing(-PI / 6.0), wing(PI / 6.0)]
+}
+
+/// Distance from `p` to a filled triangle, 0 inside
+pub fn distance_to_triangle(t: &[Point; 3], p: Point) -> f64 {
+    let side = |a: Point, b: Point| (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x);
+    let d = [side(t[0], t[1]), side(t[1], t[2]), side(t[2], t[0])];
+    let inside = d.iter().all(|v| *v >= 0.0) || d.iter().all(|v| *v <= 0.0);
+    if inside {
+        return 0.0;
+    }
+    distance_to_polyline(&[t[0], t[1], t[2], t[0]], p)
+}
+
+/// Distance from `p` to what the renderer paints for `shape`, 0 on it. Fills
+/// count when their colour is visible; strokes count at their full width
+/// whatever their colour, so a shape with invisible ink can still be picked
+/// along its outline.
+pub fn distance(shape: &Shape, p: Point, metrics: &dyn TextMetrics) -> f64 {
+    let half = shape.stroke_width.max(0.0) / 2.0;
+    let filled = shape
+        .fill
+        .as_deref()
+        .and_then(Rgba::parse)
+        .is_some_and(|c| c.is_visible());
+    match &shape.kind {
+        ShapeKind::Rectangle { x, y, w, h } => {
+            let r = OrientedRect::from_rect(Rect::new(*x, *y, *w, *h), 0.0);
+            if filled && r.contains(p) {
+                return 0.0;
+            }
+            // mitred corners: the band's outside is the rect grown by `half`
+            if r.contains(p) {
+                (r.distance_to_outline(p) - half).max(0.0)
+            } else {
+                OrientedRect {
+                    half_width: r.half_width + half,
+                    half_height: r.half_height + half,
+                    ..r
+                }
+                .distance(p)
+            }
+        }
+        ShapeKind::Ellipse { x, y, w, h } => {
+            let e = Ellipse::from_rect(Rect::new(*x, *y, *w, *h));
+            if filled && e.contains(p) {
+                0.0
+            } else {
+                (e.distance_to_outline(p) - half).max(0.0)
+            }
+        }
+        ShapeKind::Line { a, b } => segment_band(*a, *b, half, p),
+        ShapeKind::Arrow { a, b } => {
+            segment_band(*a, *b, half, p).min(distance_to_triangle(&arrow_head(*a, *b), p))
+        }
+        // round joins and caps: everything within `half` of the centre line
+        ShapeKind::Pencil { points, pressure } if pressure.is_empty() => {
+            (distance_to_polyline(points, p) - half).max(0.0)
+        }
+        ShapeKind::Pencil { points, pressure } => {
+            let halves = ink::half_widths(shape.stroke_width, pressure, points.len());
+            ink::distance(points, &halves, p)
+        }
+        ShapeKind::Text { .. } => {
+            let r = text_box(shape, metrics).unwrap_or_default();
+            OrientedRect::from_rect(r, 0.0).distance(p)
+        }
+    }
+}
+
+/// Whether `p` is within `tolerance` of the painted shape
+pub fn hit_test(shape: &Shape, p: Point, tolerance: f64, metrics: &dyn TextMetrics) -> bool {
+    distance(shape, p, metrics) <= tolerance.max(0.0)
+}
+
+/// Box around everything [`distance`] measures to: [`Shape::visual_bounds`]
+/// with text measured by `metrics`
+pub fn painted_bounds(shape: &Shape, metrics: &dyn TextMetrics) -> Rect {
+    text_box(shape, metrics).unwrap_or_else(|| shape.visual_bounds())
+}
+
+/// Box around the lines of a text shape as `metrics` sets them, `None` for
+/// other kinds
+pub(crate) fn text_box(shape: &Shape, metrics: &dyn TextMetrics) -> Option<Rect> {
+    let ShapeKind::Text { x, y, text, .. } = &shape.kind else {
+        return None;
+    };
+    let style = TextStyle::of(&shape.kind)?;
+    Some(metrics.text_box(*x, *y, text, &style))
+}
+
+/// Distance to a butt-capped stroke along `a`–`b`; a zero-length one paints
+/// nothing, so it is measured to the point instead
+fn segment_band(a: Point, b: Point, half: f64, p: Point) -> f64 {
+    if a == b {
+        return (p.x - a.x).hypot(p.y - a.y);
+    }
+    OrientedRect::along_segment(a, b, half * 2.0).distance(p)
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(not(target_arch = "wasm32"))]
+    use proptest::prelude::*;
+
+    use super::text::SansMetrics;
+    use super::*;
+
+    fn shape(kind: ShapeKind, fill: Option<&str>, stroke_width: f64) -> Shape {
+        Shape {
+            id: "s".to_string(),
+            stroke: "#000000".to_string(),
+            fill: fill.map(str::to_string),
+            stroke_width,
+            kind,
+        }
+    }
+
+    fn at(shape: &Shape, x: f64, y: f64) -> f64 {
+        distance(shape, Point::new(x, y), &SansMetrics)
+    }
+
+    #[test]
+    fn ellipse_fill_decides_the_inside() {
+        let kind = ShapeKind::Ellipse {
+            x: 0.0,
+            y: 0.0,
+            w: 200.0,
+            h: 100.0,
+        };
+        let hollow = shape(kind.clone(), None, 4.0);
+        let filled = shape(kind.clone(), Some("#ff0000"), 4.0);
+        let clear = shape(kind, Some("transparent"), 4.0);
+        assert_eq!(at(&filled, 100.0, 50.0), 0.0);
+        assert!((at(&hollow, 100.0, 50.0) - 48.0).abs() < 1e-9);
+        assert_eq!(at(&clear, 100.0, 50.0), at(&hollow, 100.0, 50.0));
+        // on the stroke, and just past it at the end of the long axis
+        assert_eq!(at(&hollow, 201.0, 50.0), 0.0);
+        assert!((at(&hollow, 205.0, 50.0) - 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn rectangle_corners_are_mitred() {
+        let r = shape(
+            ShapeKind::Rectangle {
+                x: 10.0,
+                y: 0.0,
+                w: -10.0,
+                h: 10.0,
+            },
+            None,
+            2.0,
+        );
+        assert_eq!(at(&r, -1.0, -1.0), 0.0);
+        assert!((at(&r, -2.0, -2.0) - 2f64.sqrt()).abs() < 1e-9);
+        assert!((at(&r, 5.0, 5.0) - 4.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn lines_have_butt_caps_and_arrows_heads() {
+        let (a, b) = (Point::new(0.0, 0.0), Point::new(100.0, 0.0));
+        let line = shape(ShapeKind::Line { a, b }, None, 4.0);
+        let arrow = shape(ShapeKind::Arrow { a, b }, None, 4.0);
+        assert_eq!(at(&line, 50.0, 2.0), 0.0);
+        assert!((at(&line, -3.0, 0.0) - 3.0).abs() < 1e-9);
+        // inside the head but off the shaft
+        assert!((at(&line, 95.0, 2.5) - 0.5).abs() < 1e-9);
+        assert_eq!(at(&arrow, 95.0, 2.5), 0.0);
+        assert!(at(&arrow, 95.0, 4.0) > 0.0);
+        assert!((at(&arrow, 50.0, 8.0) - 6.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn pencil_strokes_are_round() {
+        let pencil = shape(
+            ShapeKind::Pencil {
+                points: vec![Point::new(0.0, 0.0), Point::new(10.0, 0.0)],
+                pressure: Vec::new(),
+            },
+            None,
+            4.0,
+        );
+        assert!((at(&pencil, -5.0, 0.0) - 3.0).abs() < 1e-9);
+        assert!((at(&pencil, 13.0, 4.0) - 3.0).abs() < 1e-9);
+        assert!(hit_test(&pencil, Point::new(5.0, 8.0), 6.0, &SansMetrics));
+        assert!(!hit_test(&pencil, Point::new(5.0, 9.0), 6.0, &SansMetrics));
+    }
+
+    #[test]
+    fn text_uses_glyph_metrics() {
+        let text = shape(
+            ShapeKind::Text {
+                x: 0.0,
+                y: 100.0,
+                text: "WWWW".to_string(),
+                font_size: 10.0,
+                align: None,
+                max_width: None,
+                line_height: None,
+            },
+            None,
+            1.0,
+        );
+        // past the 0.6 em estimate of geometry.ts but still on the glyphs
+        assert_eq!(at(&text, 30.0, 95.0), 0.0);
+        assert!((at(&text, 40.76, 95.0) - 3.0).abs() < 1e-9);
+        assert_eq!(text.bounds(), painted_bounds(&text, &SansMetrics));
+    }
+
+    #[test]
+    fn text_covers_every_line() {
+        let text = shape(
+            ShapeKind::Text {
+                x: 0.0,
+                y: 100.0,
+                text: "W\nWWWW".to_string(),
+                font_size: 10.0,
+                align: None,
+                max_width: None,
+                line_height: Some(2.0),
+            },
+            None,
+            1.0,
+        );
+        // the second line, 20 below the first and wider than it
+        assert_eq!(at(&text, 30.0, 118.0), 0.0);
+        let r = painted_bounds(&text, &SansMetrics);
+        assert!((r.bottom() - 122.12).abs() < 1e-9);
+        assert_eq!(r.w, SansMetrics.advance("WWWW", 10.0));
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn point() -> impl Strategy<Value = Point> {
+        (-500.0..500.0, -500.0..500.0).prop_map(|(x, y)| Point::new(x, y))
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    proptest! {
+        #[test]
+        fn segment_distance_is_symmetric_and_bounded(p in point(), a in point(), b in point()) {
+            let d = distance_to_segment(p, a, b);
+            prop_assert!((d - distance_to_segment(p, b, a)).abs() < 1e-9);
+            prop_assert!(d <= (p.x - a.x).hypot(p.y - a.y) + 1e-9);
+            prop_assert!(d <= (p.x - b.x).hypot(p.y - b.y) + 1e-9);
+            for t in [0.0, 0.25, 0.5, 1.0] {
+                let q = Point::new(a.x + (b.x - a.x) * t, a.y + (b.y - a.y) * t);
+                prop_assert!(distance_to_segment(q, a, b) < 1e-9);
+            }
+        }
+
+        #[test]
+        fn rotated_rect_distance_agrees_with_contains(
+            p in point(),
+            c in point(),
+            w in 0.0..300.0,
+            h in 0.0..300.0,
+            angle in -PI..PI,
+        ) {
+            let r = OrientedRect { center: c, half_width: w, half_height: h, angle };
+            prop_assert_eq!(r.distance(p) == 0.0, r.contains(p));
+            // the outline is never further than the nearest corner
+            let corner = r.corners().iter().map(|q| (p.x - q.x).hypot(p.y - q.y)).fold(f64::INFINITY, f64::min);
+            prop_assert!(r.distance_to_outline(p) <= corner + 1e-9);
+            // and turning the point with the rect changes nothing
+            let turned = OrientedRect { angle: angle + 0.7, ..r };
+            let (sin, cos) = 0.7f64.sin_cos();
+            let (dx, dy) = (p.x - c.x, p.y - c.y);
+            let q = Point::new(c.x + dx * cos - dy * sin, c.y + dx * sin + dy * cos);
+            prop_assert!((turned.distance(q) - r.distance(p)).abs() < 1e-6);
+        }
+
+        #[test]
+        fn ellipse_distance_matches_dense_sampling(
+            p in point(),
+            rx in 0.0..300.0,
+            ry in 0.0..300.0,
+        ) {
+            let e = Ellipse { center: Point::new(0.0, 0.0), rx, ry };
+            let exact = e.distance_to_outline(p);
+            let samples = 20_000;
+            let sampled = (0..samples)
+                .map(|i| {
+                    let t = i as f64 / samples as f64 * 2.0 * PI;
+                    (p.x - rx * t.cos()).hypot(p.y - ry * t.sin())
+                })
+                .fold(f64::INFINITY, f64::min);
+            // samples are at most 0.1 apart along a 300 unit radius
+            prop_assert!(exact <= sampled + 1e-6, "{exact} > {sampled}");
+            prop_assert!(sampled - exact < 0.1, "{exact} vs {sampled}");
+        }
+
+        #[test]
+        fn circles_measure_radially(p in point(), r in 0.0..300.0) {
+            let e = Ellipse { center: Point::new(0.0, 0.0), rx: r, ry: r };
+            let expected = (p.x.hypot(p.y) - r).abs();
+            prop_assert!((e.distance_to_outline(p) - expected).abs() < 1e-6);
+        }
+
+        #[test]
+        fn hits_grow_with_tolerance(p in point(), a in point(), b in point(), width in 0.0..20.0) {
+            for kind in [
+                ShapeKind::Line { a, b },
+                ShapeKind::Arrow { a, b },
+                ShapeKind::Pencil {
+                    points: vec![a, b],
+                    pressure: Vec::new(),
+                },
+                ShapeKind::Rectangle { x: a.x, y: a.y, w: b.x - a.x, h: b.y - a.y },
+                ShapeKind::Ellipse { x: a.x, y: a.y, w: b.x - a.x, h: b.y - a.y },
+            ] {
+                let s = shape(kind, None, width);
+                let d = at(&s, p.x, p.y);
+                prop_assert!(d >= 0.0);
+                prop_assert!(hit_test(&s, p, d, &SansMetrics));
+                // everything painted lies inside the painted bounds
+                if d == 0.0 {
+                    prop_assert!(painted_bounds(&s, &SansMetrics).expand(1e-6).contains(p));
+                }
+            }
+        }
+
+        #[test]
+        fn text_advance_adds_up(a in "\\PC{0,12}", b in "\\PC{0,12}", size in 1.0..200.0) {
+            let m = SansMetrics;
+            let joined = m.advance(&format!("{a}{b}"), size);
+            prop_assert!((joined - m.advance(&a, size) - m.advance(&b, size)).abs() < 1e-6);
+        }
+    }
+}
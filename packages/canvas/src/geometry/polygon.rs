@@ -0,0 +1,139 @@
+//! Polygons given as their corners, closed implicitly from the last corner
+//! back to the first. Lassos may cross themselves, so insideness follows the
+//! even-odd rule like the canvas `isPointInPath` default.
+
+use crate::model::{Point, Rect};
+
+use super::distance_to_segment;
+
+/// Corners of `rect`, clockwise on screen from the top left
+pub fn from_rect(rect: Rect) -> Vec<Point> {
+    let r = Rect::from_points(
+        Point::new(rect.x, rect.y),
+        Point::new(rect.right(), rect.bottom()),
+    );
+    vec![
+        Point::new(r.x, r.y),
+        Point::new(r.right(), r.y),
+        Point::new(r.right(), r.bottom()),
+        Point::new(r.x, r.bottom()),
+    ]
+}
+
+/// Edges as corner pairs, including the closing one
+pub fn edges(polygon: &[Point]) -> impl Iterator<Item = (Point, Point)> + '_ {
+    polygon
+        .iter()
+        .zip(polygon.iter().cycle().skip(1))
+        .map(|(a, b)| (*a, *b))
+}
+
+/// Even-odd insideness of `p`
+pub fn contains(polygon: &[Point], p: Point) -> bool {
+    let mut inside = false;
+    for (a, b) in edges(polygon) {
+        if (a.y > p.y) != (b.y > p.y) {
+            let x = a.x + (p.y - a.y) / (b.y - a.y) * (b.x - a.x);
+            if p.x < x {
+                inside = !inside;
+            }
+        }
+    }
+    inside
+}
+
+pub fn bounds(polygon: &[Point]) -> Option<Rect> {
+    let (first, rest) = polygon.split_first()?;
+    Some(rest.iter().fold(Rect::from_points(*first, *first), |r, p| {
+        r.union(&Rect::from_points(*p, *p))
+    }))
+}
+
+/// Whether the segments `a`–`b` and `c`–`d` share a point, touching included
+pub fn segments_intersect(a: Point, b: Point, c: Point, d: Point) -> bool {
+    let cross =
+        |o: Point, p: Point, q: Point| (p.x - o.x) * (q.y - o.y) - (p.y - o.y) * (q.x - o.x);
+    let (d1, d2) = (cross(c, d, a), cross(c, d, b));
+    let (d3, d4) = (cross(a, b, c), cross(a, b, d));
+    if ((d1 > 0.0 && d2 < 0.0) || (d1 < 0.0 && d2 > 0.0))
+        && ((d3 > 0.0 && d4 < 0.0) || (d3 < 0.0 && d4 > 0.0))
+    {
+        return true;
+    }
+    // collinear or touching cases come down to an endpoint on the other segment
+    (d1 == 0.0 && distance_to_segment(a, c, d) == 0.0)
+        || (d2 == 0.0 && distance_to_segment(b, c, d) == 0.0)
+        || (d3 == 0.0 && distance_to_segment(c, a, b) == 0.0)
+        || (d4 == 0.0 && distance_to_segment(d, a, b) == 0.0)
+}
+
+/// Shortest distance between the segments `a`–`b` and `c`–`d`
+pub fn segment_distance(a: Point, b: Point, c: Point, d: Point) -> f64 {
+    if segments_intersect(a, b, c, d) {
+        return 0.0;
+    }
+    distance_to_segment(a, c, d)
+        .min(distance_to_segment(b, c, d))
+        .min(distance_to_segment(c, a, b))
+        .min(distance_to_segment(d, a, b))
+}
+
+/// Whether two polygons share any point
+pub fn polygons_intersect(p: &[Point], q: &[Point]) -> bool {
+    q.iter().any(|v| contains(p, *v))
+        || p.iter().any(|v| contains(q, *v))
+        || edges(p).any(|(a, b)| edges(q).any(|(c, d)| segments_intersect(a, b, c, d)))
+}
+
+/// Whether `inner` lies entirely inside `outer`
+pub fn polygon_inside(inner: &[Point], outer: &[Point]) -> bool {
+    inner.iter().all(|v| contains(outer, *v))
+        && !edges(inner).any(|(a, b)| edges(outer).any(|(c, d)| segments_intersect(a, b, c, d)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn even_odd_insideness_of_a_crossed_lasso() {
+        // a bow tie: two triangles meeting at (5, 5)
+        let bow = [
+            Point::new(0.0, 0.0),
+            Point::new(10.0, 10.0),
+            Point::new(10.0, 0.0),
+            Point::new(0.0, 10.0),
+        ];
+        assert!(contains(&bow, Point::new(1.0, 5.0)));
+        assert!(contains(&bow, Point::new(9.0, 5.0)));
+        assert!(!contains(&bow, Point::new(5.0, 1.0)));
+        assert!(!contains(&bow, Point::new(11.0, 5.0)));
+    }
+
+    #[test]
+    fn segments_touching_at_an_end_intersect() {
+        let p = Point::new;
+        assert!(segments_intersect(
+            p(0.0, 0.0),
+            p(10.0, 0.0),
+            p(10.0, 0.0),
+            p(10.0, 5.0)
+        ));
+        assert!(segments_intersect(
+            p(0.0, 0.0),
+            p(10.0, 0.0),
+            p(5.0, 0.0),
+            p(20.0, 0.0)
+        ));
+        assert!(!segments_intersect(
+            p(0.0, 0.0),
+            p(10.0, 0.0),
+            p(11.0, 0.0),
+            p(20.0, 0.0)
+        ));
+        assert_eq!(
+            segment_distance(p(0.0, 0.0), p(10.0, 0.0), p(5.0, 3.0), p(5.0, 9.0)),
+            3.0
+        );
+    }
+}
@@ -0,0 +1,168 @@
+//! Text measurement for hit testing and bounds. The renderers draw text in
+//! `ui-sans-serif, system-ui, sans-serif`; [`SansMetrics`] uses the widths of
+//! Helvetica and Arial (which share their metrics) so boxes follow the
+//! actual glyphs instead of a flat 0.6 em per character. Once the GPU
+//! renderers have a font, [`crate::text::Typesetter`] measures with it.
+
+use crate::model::Rect;
+use crate::text::layout::{self, TextLayout, TextStyle};
+
+/// Measures text. Lengths are in the units of `font_size`.
+pub trait TextMetrics {
+    /// Advance width of `text` set on one line
+    fn advance(&self, text: &str, font_size: f64) -> f64;
+
+    /// Height of the line box above the baseline, in ems
+    fn ascent(&self) -> f64;
+
+    /// Depth of the line box below the baseline, in ems
+    fn descent(&self) -> f64;
+
+    /// Box covered by `text` drawn with its alphabetic baseline at (`x`, `y`)
+    fn line_box(&self, x: f64, y: f64, text: &str, font_size: f64) -> Rect {
+        Rect::new(
+            x,
+            y - self.ascent() * font_size,
+            self.advance(text, font_size),
+            (self.ascent() + self.descent()) * font_size,
+        )
+    }
+
+    /// Box covered by the lines of `text` set in `style`, the first baseline
+    /// at `y` and each line aligned on `x`. Lines break where
+    /// [`layout::layout`] breaks them, see [`layout::measure`].
+    fn text_box(&self, x: f64, y: f64, text: &str, style: &TextStyle) -> Rect {
+        let lines = TextLayout {
+            glyphs: Vec::new(),
+            lines: layout::measure(self, text, style),
+            ascent: self.ascent() * style.font_size,
+            descent: self.descent() * style.font_size,
+        };
+        let r = lines.bounds();
+        Rect::new(x + r.x, y + r.y, r.w, r.h)
+    }
+}
+
+/// Helvetica advance widths in 1/1000 em for `' '..='~'`, sixteen per row
+#[rustfmt::skip]
+const ASCII_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278,
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556,
+    1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778,
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556,
+    333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556,
+    556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+/// Width of characters the table does not cover, Helvetica's digit width
+const DEFAULT_WIDTH: f64 = 0.556;
+
+/// Metrics of the default sans-serif face, see the module docs
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SansMetrics;
+
+impl SansMetrics {
+    /// Advance of `c` in ems
+    fn char_width(c: char) -> f64 {
+        let code = c as u32;
+        match code {
+            0x20..=0x7e => ASCII_WIDTHS[(code - 0x20) as usize] as f64 / 1000.0,
+            // no-break space and the rest of Latin-1 punctuation, near enough
+            0xa0 => 0.278,
+            _ if is_combining(code) => 0.0,
+            _ if is_wide(code) => 1.0,
+            _ if c.is_control() => 0.0,
+            _ => DEFAULT_WIDTH,
+        }
+    }
+}
+
+impl TextMetrics for SansMetrics {
+    fn advance(&self, text: &str, font_size: f64) -> f64 {
+        text.chars().map(Self::char_width).sum::<f64>() * font_size
+    }
+
+    /// Arial's `hhea` ascender
+    fn ascent(&self) -> f64 {
+        0.905
+    }
+
+    /// Arial's `hhea` descender
+    fn descent(&self) -> f64 {
+        0.212
+    }
+}
+
+/// Marks that stack on the previous character, such as Thai vowels above and
+/// below the consonant or tone marks
+pub(crate) fn is_combining(code: u32) -> bool {
+    matches!(
+        code,
+        0x0300..=0x036f
+            | 0x0e31
+            | 0x0e34..=0x0e3a
+            | 0x0e47..=0x0e4e
+            | 0x200b..=0x200f
+            | 0x20d0..=0x20ff
+            | 0xfe00..=0xfe0f
+            | 0xfe20..=0xfe2f
+    )
+}
+
+/// Full-width characters: CJK, Hangul, full-width forms and emoji
+fn is_wide(code: u32) -> bool {
+    matches!(
+        code,
+        0x1100..=0x115f
+            | 0x2e80..=0xa4cf
+            | 0xac00..=0xd7a3
+            | 0xf900..=0xfaff
+            | 0xfe30..=0xfe4f
+            | 0xff00..=0xff60
+            | 0xffe0..=0xffe6
+            | 0x1f300..=0x1faff
+            | 0x20000..=0x3fffd
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text::layout::TextAlign;
+
+    #[test]
+    fn measures_with_glyph_widths() {
+        let m = SansMetrics;
+        assert!((m.advance("Hi", 10.0) - (7.22 + 2.22)).abs() < 1e-9);
+        assert!(m.advance("WWW", 10.0) > 3.0 * 0.6 * 10.0);
+        assert!(m.advance("iii", 10.0) < 3.0 * 0.6 * 10.0);
+        // tone and vowel marks take no room of their own
+        assert_eq!(m.advance("กี่", 20.0), m.advance("ก", 20.0));
+        assert_eq!(m.advance("漢字", 16.0), 32.0);
+
+        let r = m.line_box(5.0, 100.0, "Hi", 10.0);
+        assert!((r.y - 90.95).abs() < 1e-9 && (r.bottom() - 102.12).abs() < 1e-9);
+    }
+
+    #[test]
+    fn text_boxes_cover_every_line() {
+        let m = SansMetrics;
+        let style = TextStyle {
+            line_height: 2.0,
+            ..TextStyle::new(10.0)
+        };
+        let r = m.text_box(5.0, 100.0, "Hi\nWWW", &style);
+        assert_eq!(r.w, m.advance("WWW", 10.0));
+        assert!((r.y - 90.95).abs() < 1e-9 && (r.bottom() - 122.12).abs() < 1e-9);
+
+        let centred = TextStyle {
+            align: TextAlign::Center,
+            max_width: Some(m.advance("Hi Hi", 10.0)),
+            ..TextStyle::new(10.0)
+        };
+        let r = m.text_box(50.0, 0.0, "Hi Hi Hi", &centred);
+        assert_eq!((r.x + r.right()) / 2.0, 50.0);
+        assert_eq!(r.w, m.advance("Hi Hi", 10.0));
+        assert!((r.h - (1.2 + 0.905 + 0.212) * 10.0).abs() < 1e-9);
+    }
+}
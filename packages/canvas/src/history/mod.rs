@@ -0,0 +1,736 @@
+//! Undo and redo. Every edit is recorded as a reversible [`Op`] and ops are
+//! grouped into transactions, so a whole pencil drag or a burst of typing
+//! undoes in one step. Undoing or redoing returns a [`ChangeSet`] naming the
+//! shapes the renderer has to refresh.
+
+use std::collections::{BTreeSet, VecDeque};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::config::app_config::HistoryConfig;
+use crate::error::CanvasError;
+use crate::model::{Shape, ShapeId, WhiteboardDoc};
+
+/// An edit as the UI asks for it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "op", rename_all = "camelCase")]
+pub enum Edit {
+    /// Adds a shape on top, or replaces the shape with the same id in place
+    Insert {
+        shape: Shape,
+    },
+    Delete {
+        id: ShapeId,
+    },
+    /// Sets one top-level field of the shape's JSON, such as `points` or
+    /// `stroke`
+    Patch {
+        id: ShapeId,
+        key: String,
+        value: Value,
+    },
+    /// New z-order, bottom first, as [`WhiteboardDoc::reorder`] takes it
+    Reorder {
+        order: Vec<ShapeId>,
+    },
+}
+
+/// A recorded edit, holding what it takes to revert it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "op", rename_all = "camelCase")]
+pub enum Op {
+    Insert {
+        index: usize,
+        shape: Shape,
+    },
+    Delete {
+        index: usize,
+        shape: Shape,
+    },
+    Patch {
+        id: ShapeId,
+        key: String,
+        before: Value,
+        after: Value,
+    },
+    Reorder {
+        before: Vec<ShapeId>,
+        after: Vec<ShapeId>,
+    },
+}
+
+impl Op {
+    pub fn inverse(&self) -> Op {
+        match self.clone() {
+            Op::Insert { index, shape } => Op::Delete { index, shape },
+            Op::Delete { index, shape } => Op::Insert { index, shape },
+            Op::Patch {
+                id,
+                key,
+                before,
+                after,
+            } => Op::Patch {
+                id,
+                key,
+                before: after,
+                after: before,
+            },
+            Op::Reorder { before, after } => Op::Reorder {
+                before: after,
+                after: before,
+            },
+        }
+    }
+
+    /// Id of the shape the op touches, `None` for reorders
+    fn target(&self) -> Option<&str> {
+        match self {
+            Op::Insert { shape, .. } | Op::Delete { shape, .. } => Some(&shape.id),
+            Op::Patch { id, .. } => Some(id),
+            Op::Reorder { .. } => None,
+        }
+    }
+
+    fn apply(&self, doc: &mut WhiteboardDoc) -> Result<(), CanvasError> {
+        match self {
+            Op::Insert { index, shape } => doc.insert_shape_at(*index, shape.clone()),
+            Op::Delete { shape, .. } => {
+                doc.remove_shape(&shape.id)
+                    .ok_or_else(|| missing(&shape.id))?;
+            }
+            Op::Patch { id, key, after, .. } => {
+                let shape = doc.get(id).ok_or_else(|| missing(id))?;
+                let patched = patch(shape, key, after.clone())?;
+                doc.add_shape(patched);
+            }
+            Op::Reorder { after, .. } => doc.reorder(after),
+        }
+        Ok(())
+    }
+
+    /// Rough memory held by the op, its JSON length
+    fn size(&self) -> usize {
+        serde_json::to_vec(self).map_or(0, |v| v.len())
+    }
+}
+
+/// Shapes the renderer has to refresh after an edit, undo or redo
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangeSet {
+    /// Shapes added or changed, in their new state
+    pub upserted: Vec<Shape>,
+    pub removed: Vec<ShapeId>,
+    /// The whole z-order, bottom first, when it changed
+    pub order: Option<Vec<ShapeId>>,
+}
+
+impl ChangeSet {
+    fn collect(doc: &WhiteboardDoc, touched: BTreeSet<ShapeId>, order_before: &[ShapeId]) -> Self {
+        let mut changes = ChangeSet::default();
+        for id in touched {
+            match doc.get(&id) {
+                Some(shape) => changes.upserted.push(shape.clone()),
+                None => changes.removed.push(id),
+            }
+        }
+        if doc.order != order_before {
+            changes.order = Some(doc.order.clone());
+        }
+        changes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.upserted.is_empty() && self.removed.is_empty() && self.order.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Transaction {
+    ops: Vec<Op>,
+    merge_key: Option<String>,
+    bytes: usize,
+}
+
+impl Transaction {
+    /// Records `op`, folding it into the previous op where the pair collapses
+    /// into one: repeated patches of a field, patches of a shape inserted just
+    /// before, an insert deleted again and back-to-back reorders.
+    fn push(&mut self, op: Op) {
+        let Some(last) = self.ops.last_mut() else {
+            self.ops.push(op);
+            return;
+        };
+        match (last, op) {
+            (
+                Op::Patch { id, key, after, .. },
+                Op::Patch {
+                    id: next_id,
+                    key: next_key,
+                    after: next_after,
+                    ..
+                },
+            ) if *id == next_id && *key == next_key => {
+                *after = next_after;
+                if let Some(Op::Patch { before, after, .. }) = self.ops.last() {
+                    if before == after {
+                        self.ops.pop();
+                    }
+                }
+            }
+            (Op::Insert { shape, .. }, Op::Patch { id, key, after, .. }) if shape.id == id => {
+                // the value was checked against this same shape when recorded
+                if let Ok(patched) = patch(shape, &key, after) {
+                    *shape = patched;
+                }
+            }
+            (Op::Insert { shape, .. }, Op::Delete { shape: deleted, .. })
+                if shape.id == deleted.id =>
+            {
+                self.ops.pop();
+            }
+            (Op::Reorder { after, .. }, Op::Reorder { after: next, .. }) => *after = next,
+            (_, op) => self.ops.push(op),
+        }
+    }
+
+    fn measure(&mut self) {
+        self.bytes = self.ops.iter().map(Op::size).sum();
+    }
+}
+
+/// Undo and redo stacks over one [`WhiteboardDoc`]
+#[derive(Debug, Clone)]
+pub struct History {
+    undo: VecDeque<Transaction>,
+    redo: Vec<Transaction>,
+    open: Option<Transaction>,
+    /// Nesting depth of [`History::begin`] calls
+    depth: usize,
+    bytes: usize,
+    max_transactions: usize,
+    max_bytes: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(&HistoryConfig::default())
+    }
+}
+
+impl History {
+    pub fn new(config: &HistoryConfig) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            open: None,
+            depth: 0,
+            bytes: 0,
+            max_transactions: config.max_transactions.max(1),
+            max_bytes: config.max_bytes,
+        }
+    }
+
+    /// Opens a transaction; edits until the matching [`History::commit`]
+    /// undo together. Nested calls join the outer transaction. Transactions
+    /// committed one after another with the same `merge_key`, such as the
+    /// keystrokes of one text edit, also merge into a single step.
+    pub fn begin(&mut self, merge_key: Option<String>) {
+        if self.depth == 0 {
+            self.open = Some(Transaction {
+                merge_key,
+                ..Transaction::default()
+            });
+        }
+        self.depth += 1;
+    }
+
+    /// Closes the innermost transaction, recording it once the outermost one
+    /// closes. Does nothing when none is open.
+    pub fn commit(&mut self) {
+        if self.depth == 0 {
+            return;
+        }
+        self.depth -= 1;
+        if self.depth > 0 {
+            return;
+        }
+        let Some(mut tx) = self.open.take() else {
+            return;
+        };
+        if tx.ops.is_empty() {
+            return;
+        }
+        let merges = tx.merge_key.is_some()
+            && self
+                .undo
+                .back()
+                .is_some_and(|top| top.merge_key == tx.merge_key);
+        if merges {
+            let mut top = self.undo.pop_back().expect("checked above");
+            self.bytes -= top.bytes;
+            for op in tx.ops {
+                top.push(op);
+            }
+            tx = top;
+            if tx.ops.is_empty() {
+                return;
+            }
+        }
+        tx.measure();
+        self.bytes += tx.bytes;
+        self.undo.push_back(tx);
+        self.trim();
+    }
+
+    /// Applies `edit` and records it, in its own transaction unless one is
+    /// open. Clears the redo stack.
+    pub fn apply(&mut self, doc: &mut WhiteboardDoc, edit: Edit) -> Result<ChangeSet, CanvasError> {
+        let ops = record(doc, edit)?;
+        let changes = run(doc, &ops)?;
+        self.redo.clear();
+        let auto = self.depth == 0;
+        if auto {
+            self.begin(None);
+        }
+        let tx = self.open.as_mut().expect("transaction is open");
+        for op in ops {
+            tx.push(op);
+        }
+        if auto {
+            self.commit();
+        }
+        Ok(changes)
+    }
+
+    /// Reverts the last transaction, committing any open one first. `None`
+    /// when there is nothing to undo. When the doc has changed under the
+    /// transaction so that it no longer reverts, both are left as they were.
+    pub fn undo(&mut self, doc: &mut WhiteboardDoc) -> Result<Option<ChangeSet>, CanvasError> {
+        self.close();
+        let Some(tx) = self.undo.back() else {
+            return Ok(None);
+        };
+        let inverse: Vec<Op> = tx.ops.iter().rev().map(Op::inverse).collect();
+        let changes = run_all(doc, &inverse)?;
+        let tx = self.undo.pop_back().expect("undo step was there");
+        self.bytes -= tx.bytes;
+        self.redo.push(tx);
+        Ok(Some(changes))
+    }
+
+    /// Reapplies the last undone transaction, all of it or, like
+    /// [`History::undo`], none of it
+    pub fn redo(&mut self, doc: &mut WhiteboardDoc) -> Result<Option<ChangeSet>, CanvasError> {
+        self.close();
+        let Some(tx) = self.redo.last() else {
+            return Ok(None);
+        };
+        let changes = run_all(doc, &tx.ops)?;
+        let tx = self.redo.pop().expect("redo step was there");
+        self.bytes += tx.bytes;
+        self.undo.push_back(tx);
+        self.trim();
+        Ok(Some(changes))
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty() || self.open.as_ref().is_some_and(|tx| !tx.ops.is_empty())
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Number of recorded undo steps
+    pub fn len(&self) -> usize {
+        self.undo.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.undo.is_empty()
+    }
+
+    /// Approximate memory held by the undo steps, in bytes
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Forgets everything, for when the document is replaced wholesale
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.open = None;
+        self.depth = 0;
+        self.bytes = 0;
+    }
+
+    fn close(&mut self) {
+        while self.depth > 0 {
+            self.commit();
+        }
+    }
+
+    /// Drops the oldest steps past the limits, always keeping the newest
+    fn trim(&mut self) {
+        while self.undo.len() > self.max_transactions
+            || (self.bytes > self.max_bytes && self.undo.len() > 1)
+        {
+            if let Some(old) = self.undo.pop_front() {
+                self.bytes -= old.bytes;
+            }
+        }
+    }
+}
+
+/// Turns `edit` into ops against the current state of `doc`
+fn record(doc: &WhiteboardDoc, edit: Edit) -> Result<Vec<Op>, CanvasError> {
+    Ok(match edit {
+        Edit::Insert { shape } => match doc.get(&shape.id) {
+            // a replacement may change the kind, so it swaps the whole shape
+            Some(existing) => {
+                let index = position(doc, &shape.id);
+                vec![
+                    Op::Delete {
+                        index,
+                        shape: existing.clone(),
+                    },
+                    Op::Insert { index, shape },
+                ]
+            }
+            None => vec![Op::Insert {
+                index: doc.order.len(),
+                shape,
+            }],
+        },
+        Edit::Delete { id } => {
+            let shape = doc.get(&id).ok_or_else(|| missing(&id))?;
+            vec![Op::Delete {
+                index: position(doc, &id),
+                shape: shape.clone(),
+            }]
+        }
+        Edit::Patch { id, key, value } => {
+            let shape = doc.get(&id).ok_or_else(|| missing(&id))?;
+            // fail before anything is recorded when the value does not fit
+            patch(shape, &key, value.clone())?;
+            vec![Op::Patch {
+                before: field(shape, &key)?,
+                id,
+                key,
+                after: value,
+            }]
+        }
+        Edit::Reorder { order } => {
+            let mut next = doc.clone();
+            next.reorder(&order);
+            vec![Op::Reorder {
+                before: doc.order.clone(),
+                after: next.order,
+            }]
+        }
+    })
+}
+
+fn position(doc: &WhiteboardDoc, id: &str) -> usize {
+    doc.order
+        .iter()
+        .position(|x| x == id)
+        .unwrap_or(doc.order.len())
+}
+
+fn missing(id: &str) -> CanvasError {
+    CanvasError::InvalidDocument(format!("no shape with id {id}"))
+}
+
+/// Applies `ops` in order and reports what they touched
+fn run(doc: &mut WhiteboardDoc, ops: &[Op]) -> Result<ChangeSet, CanvasError> {
+    let order_before = doc.order.clone();
+    let mut touched = BTreeSet::new();
+    for op in ops {
+        op.apply(doc)?;
+        touched.extend(op.target().map(str::to_owned));
+    }
+    Ok(ChangeSet::collect(doc, touched, &order_before))
+}
+
+/// Like [`run`], but on a copy of `doc` that replaces it only once every op
+/// has applied. [`record`] checks a single edit up front; a recorded
+/// transaction may have stopped fitting the doc since.
+fn run_all(doc: &mut WhiteboardDoc, ops: &[Op]) -> Result<ChangeSet, CanvasError> {
+    let mut next = doc.clone();
+    let changes = run(&mut next, ops)?;
+    *doc = next;
+    Ok(changes)
+}
+
+fn check_key(key: &str) -> Result<(), CanvasError> {
+    if key == "id" || key == "type" {
+        return Err(CanvasError::InvalidDocument(format!(
+            "cannot patch the {key} of a shape"
+        )));
+    }
+    Ok(())
+}
+
+/// Current value of the field `key`, `null` when unset
+fn field(shape: &Shape, key: &str) -> Result<Value, CanvasError> {
+    check_key(key)?;
+    Ok(shape.to_object()?.remove(key).unwrap_or(Value::Null))
+}
+
+/// `shape` with the field `key` set to `value`
+fn patch(shape: &Shape, key: &str, value: Value) -> Result<Shape, CanvasError> {
+    check_key(key)?;
+    let mut object = shape.to_object()?;
+    object.insert(key.to_string(), value);
+    serde_json::from_value(Value::Object(object))
+        .map_err(|e| CanvasError::InvalidDocument(format!("{key}: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const DOC_JSON: &str = r##"{
+        "shapes": {
+            "a": { "id": "a", "type": "rectangle", "x": 0, "y": 0, "w": 10, "h": 10,
+                   "stroke": "#000", "fill": null, "strokeWidth": 2 },
+            "b": { "id": "b", "type": "ellipse", "x": 20, "y": 0, "w": 10, "h": 10,
+                   "stroke": "#000", "fill": null, "strokeWidth": 2 },
+            "c": { "id": "c", "type": "line", "a": { "x": 0, "y": 0 }, "b": { "x": 5, "y": 5 },
+                   "stroke": "#000", "fill": null, "strokeWidth": 2 }
+        },
+        "order": ["a", "b", "c"]
+    }"##;
+
+    fn doc() -> WhiteboardDoc {
+        WhiteboardDoc::from_json(DOC_JSON).unwrap()
+    }
+
+    fn edit(json: serde_json::Value) -> Edit {
+        serde_json::from_value(json).unwrap()
+    }
+
+    fn stroke(doc: &WhiteboardDoc, id: &str) -> String {
+        doc.get(id).unwrap().stroke.clone()
+    }
+
+    #[test]
+    fn pencil_drag_undoes_as_one_step() {
+        let mut doc = doc();
+        let before = doc.clone();
+        let mut history = History::default();
+        history.begin(None);
+        let pencil = json!({ "id": "p", "type": "pencil", "points": [{ "x": 0, "y": 0 }],
+                             "stroke": "#000", "fill": null, "strokeWidth": 3 });
+        let changes = history
+            .apply(&mut doc, edit(json!({ "op": "insert", "shape": pencil })))
+            .unwrap();
+        assert_eq!(changes.order.as_deref().unwrap().last().unwrap(), "p");
+        let mut points = vec![json!({ "x": 0, "y": 0 })];
+        for i in 1..50 {
+            points.push(json!({ "x": i, "y": i }));
+            let changes = history
+                .apply(
+                    &mut doc,
+                    edit(json!({ "op": "patch", "id": "p", "key": "points", "value": points })),
+                )
+                .unwrap();
+            assert_eq!(changes.upserted.len(), 1);
+            assert_eq!(changes.order, None);
+        }
+        history.commit();
+        assert_eq!(history.len(), 1);
+        // the patches folded into the insert
+        assert_eq!(history.undo[0].ops.len(), 1);
+        let after = doc.clone();
+
+        let undone = history.undo(&mut doc).unwrap().unwrap();
+        assert_eq!(doc, before);
+        assert_eq!(undone.removed, ["p"]);
+        assert_eq!(undone.order.unwrap(), ["a", "b", "c"]);
+        assert!(history.undo(&mut doc).unwrap().is_none());
+
+        let redone = history.redo(&mut doc).unwrap().unwrap();
+        assert_eq!(doc, after);
+        assert_eq!(redone.upserted[0].id, "p");
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn delete_and_reorder_restore_positions() {
+        let mut doc = doc();
+        let mut history = History::default();
+        history
+            .apply(&mut doc, edit(json!({ "op": "delete", "id": "b" })))
+            .unwrap();
+        history
+            .apply(
+                &mut doc,
+                edit(json!({ "op": "reorder", "order": ["c", "a"] })),
+            )
+            .unwrap();
+        assert_eq!(doc.order, ["c", "a"]);
+
+        history.undo(&mut doc).unwrap();
+        assert_eq!(doc.order, ["a", "c"]);
+        let changes = history.undo(&mut doc).unwrap().unwrap();
+        assert_eq!(doc, self::doc());
+        assert_eq!(changes.upserted[0].id, "b");
+        assert_eq!(changes.order.unwrap(), ["a", "b", "c"]);
+
+        // a new edit drops what could be redone
+        history.redo(&mut doc).unwrap();
+        history
+            .apply(
+                &mut doc,
+                edit(json!({ "op": "patch", "id": "a", "key": "stroke", "value": "#f00" })),
+            )
+            .unwrap();
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn merge_keys_join_transactions() {
+        let mut doc = doc();
+        let mut history = History::default();
+        for color in ["#100", "#200", "#300"] {
+            history.begin(Some("stroke:a".into()));
+            history
+                .apply(
+                    &mut doc,
+                    edit(json!({ "op": "patch", "id": "a", "key": "stroke", "value": color })),
+                )
+                .unwrap();
+            history.commit();
+        }
+        history.begin(Some("stroke:b".into()));
+        history
+            .apply(
+                &mut doc,
+                edit(json!({ "op": "patch", "id": "b", "key": "stroke", "value": "#400" })),
+            )
+            .unwrap();
+        history.commit();
+        assert_eq!(history.len(), 2);
+
+        history.undo(&mut doc).unwrap();
+        assert_eq!(stroke(&doc, "b"), "#000");
+        assert_eq!(stroke(&doc, "a"), "#300");
+        history.undo(&mut doc).unwrap();
+        assert_eq!(stroke(&doc, "a"), "#000");
+    }
+
+    #[test]
+    fn inserting_then_deleting_leaves_nothing() {
+        let mut doc = doc();
+        let mut history = History::default();
+        history.begin(None);
+        let shape = doc.get("a").unwrap().clone();
+        let shape = Shape {
+            id: "tmp".into(),
+            ..shape
+        };
+        history.apply(&mut doc, Edit::Insert { shape }).unwrap();
+        history
+            .apply(&mut doc, Edit::Delete { id: "tmp".into() })
+            .unwrap();
+        history.commit();
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn evicts_oldest_steps_past_the_limits() {
+        let mut doc = doc();
+        let config = HistoryConfig {
+            max_transactions: 3,
+            ..HistoryConfig::default()
+        };
+        let mut history = History::new(&config);
+        for i in 0..5 {
+            let color = format!("#{i}{i}{i}");
+            history
+                .apply(
+                    &mut doc,
+                    edit(json!({ "op": "patch", "id": "a", "key": "stroke", "value": color })),
+                )
+                .unwrap();
+        }
+        assert_eq!(history.len(), 3);
+        while history.undo(&mut doc).unwrap().is_some() {}
+        assert_eq!(stroke(&doc, "a"), "#111");
+
+        let mut history = History::new(&HistoryConfig {
+            max_bytes: 1,
+            ..HistoryConfig::default()
+        });
+        for id in ["a", "b"] {
+            history
+                .apply(&mut doc, edit(json!({ "op": "delete", "id": id })))
+                .unwrap();
+        }
+        // the newest step is kept even when it alone is over the limit
+        assert_eq!(history.len(), 1);
+        assert!(history.bytes() > 1);
+    }
+
+    #[test]
+    fn rejects_bad_edits_without_recording() {
+        let mut doc = doc();
+        let mut history = History::default();
+        for bad in [
+            json!({ "op": "delete", "id": "nope" }),
+            json!({ "op": "patch", "id": "a", "key": "id", "value": "z" }),
+            json!({ "op": "patch", "id": "a", "key": "w", "value": "wide" }),
+        ] {
+            assert!(history.apply(&mut doc, edit(bad)).is_err());
+        }
+        assert_eq!(doc, self::doc());
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn conflicting_edits_leave_undo_and_redo_untouched() {
+        let mut doc = doc();
+        let mut history = History::default();
+        history.begin(None);
+        history
+            .apply(&mut doc, edit(json!({ "op": "delete", "id": "b" })))
+            .unwrap();
+        history
+            .apply(
+                &mut doc,
+                edit(json!({ "op": "patch", "id": "a", "key": "stroke", "value": "#f00" })),
+            )
+            .unwrap();
+        history.commit();
+
+        // someone else deletes `a`, so the step can restore `b` but not
+        // unpatch `a`
+        let mut remote = doc.clone();
+        remote.remove_shape("a");
+        let before = remote.clone();
+        assert!(history.undo(&mut remote).is_err());
+        assert_eq!(remote, before);
+        assert_eq!(history.len(), 1);
+        assert!(!history.can_redo());
+
+        // the same step still undoes on the doc it was recorded against
+        history.undo(&mut doc).unwrap().unwrap();
+        assert_eq!(doc, self::doc());
+
+        // and redoes only as a whole
+        let mut remote = doc.clone();
+        remote.remove_shape("a");
+        let before = remote.clone();
+        assert!(history.redo(&mut remote).is_err());
+        assert_eq!(remote, before);
+        assert!(history.can_redo() && !history.can_undo());
+        history.redo(&mut doc).unwrap().unwrap();
+        assert!(doc.get("b").is_none());
+        assert_eq!(stroke(&doc, "a"), "#f00");
+    }
+}
@@ -0,0 +1,631 @@
+//! `.excalidraw` files and Excalidraw clipboard JSON.
+//!
+//! Elements keep their ids and array order, which is Excalidraw's z-order.
+//! Lines, arrows and freehand strokes are rotated into world coordinates
+//! exactly; shapes the whiteboard cannot rotate or has no type for become
+//! closed pencil outlines. Rounded corners and curved lines come in sharp,
+//! with an issue. Deleted elements are left out without an issue, as
+//! Excalidraw itself no longer shows them.
+
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::*;
+
+use super::{ArrowBinding, Import, IssueAction};
+use crate::error::CanvasError;
+use crate::model::color::Rgba;
+use crate::model::{Point, Shape, ShapeKind};
+use crate::text::layout::TextAlign;
+
+/// Excalidraw's default `lineHeight` for its fonts
+const DEFAULT_LINE_HEIGHT: f64 = 1.25;
+
+/// Segments used for rotated ellipses
+const ELLIPSE_SEGMENTS: usize = 64;
+
+/// Converts an Excalidraw file to `{ doc, issues, bindings }` JSON
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen(js_name = "importExcalidraw")]
+pub fn import_excalidraw(json: &str) -> Result<String, JsValue> {
+    let import = import(json)?;
+    Ok(serde_json::to_string(&import).map_err(|e| CanvasError::Import(e.to_string()))?)
+}
+
+#[derive(Deserialize, Debug)]
+struct File {
+    #[serde(rename = "type", default)]
+    kind: Option<String>,
+    elements: Vec<Element>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase", default)]
+struct Element {
+    id: String,
+    #[serde(rename = "type")]
+    kind: String,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    angle: f64,
+    stroke_color: String,
+    background_color: String,
+    fill_style: String,
+    stroke_width: f64,
+    stroke_style: String,
+    opacity: f64,
+    is_deleted: bool,
+    points: Vec<[f64; 2]>,
+    /// Pen pressure per freedraw point, unless `simulate_pressure`
+    pressures: Vec<f64>,
+    simulate_pressure: bool,
+    text: String,
+    /// `text` before Excalidraw wrapped it to the box
+    original_text: Option<String>,
+    /// Whether the box grows with the text instead of wrapping it
+    auto_resize: bool,
+    font_size: f64,
+    text_align: String,
+    line_height: Option<f64>,
+    start_arrowhead: Option<String>,
+    end_arrowhead: Option<String>,
+    start_binding: Option<Binding>,
+    end_binding: Option<Binding>,
+    /// Rounded corners or curved lines; `null` for sharp ones
+    roundness: Option<IgnoredAny>,
+}
+
+impl Default for Element {
+    fn default() -> Self {
+        Self {
+            id: String::new(),
+            kind: String::new(),
+            x: 0.0,
+            y: 0.0,
+            width: 0.0,
+            height: 0.0,
+            angle: 0.0,
+            stroke_color: "#1e1e1e".to_string(),
+            background_color: "transparent".to_string(),
+            fill_style: "solid".to_string(),
+            stroke_width: 2.0,
+            stroke_style: "solid".to_string(),
+            opacity: 100.0,
+            is_deleted: false,
+            points: Vec::new(),
+            pressures: Vec::new(),
+            simulate_pressure: true,
+            text: String::new(),
+            original_text: None,
+            auto_resize: true,
+            font_size: 20.0,
+            text_align: "left".to_string(),
+            line_height: None,
+            start_arrowhead: None,
+            end_arrowhead: None,
+            start_binding: None,
+            end_binding: None,
+            roundness: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Binding {
+    element_id: String,
+}
+
+/// Converts the JSON of an `.excalidraw` file or an Excalidraw clipboard
+pub fn import(json: &str) -> Result<Import, CanvasError> {
+    let file: File = serde_json::from_str(json).map_err(|e| CanvasError::Import(e.to_string()))?;
+    if let Some(kind) = file
+        .kind
+        .as_deref()
+        .filter(|k| !k.starts_with("excalidraw"))
+    {
+        return Err(CanvasError::Import(format!(
+            "expected an Excalidraw file, got type {kind}"
+        )));
+    }
+
+    let mut out = Import::default();
+    let mut arrows = Vec::new();
+    for el in file.elements.iter().filter(|el| !el.is_deleted) {
+        match el.kind.as_str() {
+            "rectangle" | "ellipse" | "diamond" => closed_shape(&mut out, el),
+            "line" | "arrow" => {
+                if linear(&mut out, el) && el.kind == "arrow" {
+                    arrows.push(el);
+                }
+            }
+            "freedraw" => {
+                let points = world_points(el);
+                // recorded pen pressure; otherwise Excalidraw makes it up from speed
+                let pressure = if el.simulate_pressure || el.pressures.len() != points.len() {
+                    Vec::new()
+                } else {
+                    el.pressures.clone()
+                };
+                let kind = ShapeKind::Pencil { points, pressure };
+                out.doc.add_shape(shape(el, el.id.clone(), kind, None));
+            }
+            "text" => text(&mut out, el),
+            other => out.issue(
+                &el.id,
+                other,
+                IssueAction::Skipped,
+                "element type has no whiteboard equivalent",
+            ),
+        }
+        if el.stroke_style != "solid" && out.doc.get(&el.id).is_some() {
+            out.issue(
+                &el.id,
+                &el.kind,
+                IssueAction::Approximated,
+                "dashed and dotted strokes are drawn solid",
+            );
+        }
+    }
+
+    // only bindings between shapes that made it into the document
+    for el in arrows {
+        let target = |b: &Option<Binding>| {
+            b.as_ref()
+                .map(|b| b.element_id.clone())
+                .filter(|id| out.doc.get(id).is_some())
+        };
+        let (start, end) = (target(&el.start_binding), target(&el.end_binding));
+        if start.is_some() || end.is_some() {
+            out.bindings.push(ArrowBinding {
+                arrow: el.id.clone(),
+                start,
+                end,
+            });
+        }
+    }
+    Ok(out)
+}
+
+fn closed_shape(out: &mut Import, el: &Element) {
+    let fill = fill(el);
+    if fill.is_some() && el.fill_style != "solid" {
+        out.issue(
+            &el.id,
+            &el.kind,
+            IssueAction::Approximated,
+            "hatched fills are drawn solid",
+        );
+    }
+    if el.roundness.is_some() && el.kind != "ellipse" {
+        out.issue(
+            &el.id,
+            &el.kind,
+            IssueAction::Approximated,
+            "rounded corners are drawn sharp",
+        );
+    }
+    let rotated = el.angle.abs() > 1e-9;
+    let (x, y, w, h) = (el.x, el.y, el.width, el.height);
+    let kind = match el.kind.as_str() {
+        "rectangle" if !rotated => ShapeKind::Rectangle { x, y, w, h },
+        "ellipse" if !rotated => ShapeKind::Ellipse { x, y, w, h },
+        kind => {
+            let outline: Vec<[f64; 2]> = match kind {
+                "rectangle" => vec![[0.0, 0.0], [w, 0.0], [w, h], [0.0, h]],
+                "diamond" => vec![[w / 2.0, 0.0], [w, h / 2.0], [w / 2.0, h], [0.0, h / 2.0]],
+                _ => (0..ELLIPSE_SEGMENTS)
+                    .map(|i| {
+                        let t = i as f64 / ELLIPSE_SEGMENTS as f64 * std::f64::consts::TAU;
+                        [w / 2.0 * (1.0 + t.cos()), h / 2.0 * (1.0 + t.sin())]
+                    })
+                    .collect(),
+            };
+            if fill.is_some() {
+                out.issue(
+                    &el.id,
+                    &el.kind,
+                    IssueAction::Approximated,
+                    "drawn as an outline without its fill",
+                );
+            }
+            let centre = [w / 2.0, h / 2.0];
+            let mut points: Vec<Point> = outline.iter().map(|p| to_world(el, centre, *p)).collect();
+            points.extend(points.first().copied());
+            let kind = ShapeKind::Pencil {
+                points,
+                pressure: Vec::new(),
+            };
+            out.doc.add_shape(shape(el, el.id.clone(), kind, None));
+            return;
+        }
+    };
+    out.doc.add_shape(shape(el, el.id.clone(), kind, fill));
+}
+
+/// Returns whether anything was added under `el.id`
+fn linear(out: &mut Import, el: &Element) -> bool {
+    let mut points = world_points(el);
+    if points.len() < 2 {
+        out.issue(
+            &el.id,
+            &el.kind,
+            IssueAction::Skipped,
+            "fewer than two points",
+        );
+        return false;
+    }
+    let (start_head, end_head) = (el.start_arrowhead.is_some(), el.end_arrowhead.is_some());
+    let arrow = el.kind == "arrow" && (start_head || end_head);
+    if arrow && start_head && !end_head {
+        points.reverse();
+    }
+    if arrow && start_head && end_head {
+        out.issue(
+            &el.id,
+            &el.kind,
+            IssueAction::Approximated,
+            "only the end arrowhead is kept",
+        );
+    }
+    if el.roundness.is_some() && points.len() > 2 {
+        out.issue(
+            &el.id,
+            &el.kind,
+            IssueAction::Approximated,
+            "curves are drawn as straight segments",
+        );
+    }
+    if fill(el).is_some() && points.len() > 2 {
+        out.issue(
+            &el.id,
+            &el.kind,
+            IssueAction::Approximated,
+            "filled polygons are drawn as outlines",
+        );
+    }
+
+    let n = points.len();
+    match (n, arrow) {
+        (2, false) => {
+            let kind = ShapeKind::Line {
+                a: points[0],
+                b: points[1],
+            };
+            out.doc.add_shape(shape(el, el.id.clone(), kind, None));
+        }
+        (2, true) => {
+            let kind = ShapeKind::Arrow {
+                a: points[0],
+                b: points[1],
+            };
+            out.doc.add_shape(shape(el, el.id.clone(), kind, None));
+        }
+        (_, false) => {
+            let kind = ShapeKind::Pencil {
+                points,
+                pressure: Vec::new(),
+            };
+            out.doc.add_shape(shape(el, el.id.clone(), kind, None));
+        }
+        (_, true) => {
+            // the bend becomes a path under the final segment's arrow
+            let path = ShapeKind::Pencil {
+                points: points[..n - 1].to_vec(),
+                pressure: Vec::new(),
+            };
+            out.doc
+                .add_shape(shape(el, format!("{}:path", el.id), path, None));
+            let head = ShapeKind::Arrow {
+                a: points[n - 2],
+                b: points[n - 1],
+            };
+            out.doc.add_shape(shape(el, el.id.clone(), head, None));
+            out.issue(
+                &el.id,
+                &el.kind,
+                IssueAction::Approximated,
+                "bent arrow split into a path and a straight arrow",
+            );
+        }
+    }
+    true
+}
+
+fn text(out: &mut Import, el: &Element) {
+    if el.angle.abs() > 1e-9 {
+        out.issue(
+            &el.id,
+            &el.kind,
+            IssueAction::Approximated,
+            "rotation dropped",
+        );
+    }
+    // lines are anchored on the side of the box they are aligned to
+    let (x, align) = match el.text_align.as_str() {
+        "center" => (el.x + el.width / 2.0, Some(TextAlign::Center)),
+        "right" => (el.x + el.width, Some(TextAlign::Right)),
+        _ => (el.x, None),
+    };
+    // text in a fixed-width box is stored wrapped for Excalidraw's font;
+    // the unwrapped original rewraps at the box width in ours
+    let (text, max_width) = match (el.auto_resize, &el.original_text) {
+        (false, Some(original)) => (original, Some(el.width)),
+        _ => (&el.text, None),
+    };
+    let kind = ShapeKind::Text {
+        x,
+        // whiteboard text sits on its baseline, a font size below the top
+        y: el.y + el.font_size,
+        text: text.clone(),
+        font_size: el.font_size,
+        align,
+        max_width,
+        line_height: Some(el.line_height.unwrap_or(DEFAULT_LINE_HEIGHT)),
+    };
+    out.doc.add_shape(shape(el, el.id.clone(), kind, None));
+}
+
+fn shape(el: &Element, id: String, kind: ShapeKind, fill: Option<String>) -> Shape {
+    Shape {
+        id,
+        stroke: colour(&el.stroke_color, el.opacity).unwrap_or_else(|| "#000000".to_string()),
+        fill,
+        stroke_width: el.stroke_width,
+        kind,
+    }
+}
+
+fn fill(el: &Element) -> Option<String> {
+    colour(&el.background_color, el.opacity)
+}
+
+/// `css` with Excalidraw's 0-100 element opacity folded into its alpha, or
+/// `None` when it is invisible
+fn colour(css: &str, opacity: f64) -> Option<String> {
+    let c = Rgba::parse(css)?;
+    let alpha = c.a * (opacity / 100.0).clamp(0.0, 1.0) as f32;
+    if alpha <= 0.0 {
+        return None;
+    }
+    if alpha >= 1.0 {
+        return Some(css.to_string());
+    }
+    let byte = |v: f32| (v * 255.0).round() as u8;
+    Some(format!(
+        "rgba({},{},{},{})",
+        byte(c.r),
+        byte(c.g),
+        byte(c.b),
+        (alpha * 1000.0).round() / 1000.0
+    ))
+}
+
+/// Linear elements store points relative to `x, y`, and turn around the
+/// centre of the points' bounding box, which may start left of or above the
+/// origin
+fn world_points(el: &Element) -> Vec<Point> {
+    let (min, max) = el.points.iter().fold(
+        ([f64::INFINITY; 2], [f64::NEG_INFINITY; 2]),
+        |(min, max), p| {
+            (
+                [min[0].min(p[0]), min[1].min(p[1])],
+                [max[0].max(p[0]), max[1].max(p[1])],
+            )
+        },
+    );
+    let centre = [(min[0] + max[0]) / 2.0, (min[1] + max[1]) / 2.0];
+    el.points.iter().map(|p| to_world(el, centre, *p)).collect()
+}
+
+/// A point relative to the element's origin, rotated by `angle` around
+/// `centre`, also relative to the origin, as Excalidraw does
+fn to_world(el: &Element, centre: [f64; 2], p: [f64; 2]) -> Point {
+    let [cx, cy] = centre;
+    let (sin, cos) = el.angle.sin_cos();
+    let (dx, dy) = (p[0] - cx, p[1] - cy);
+    Point::new(
+        el.x + cx + dx * cos - dy * sin,
+        el.y + cy + dx * sin + dy * cos,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::Issue;
+
+    const FILE: &str = r##"{
+        "type": "excalidraw",
+        "version": 2,
+        "source": "https://excalidraw.com",
+        "elements": [
+            { "id": "box", "type": "rectangle", "x": 10, "y": 20, "width": 100, "height": 50,
+              "angle": 0, "strokeColor": "#1e1e1e", "backgroundColor": "#a5d8ff",
+              "fillStyle": "hachure", "strokeWidth": 2, "strokeStyle": "solid", "opacity": 100 },
+            { "id": "gone", "type": "rectangle", "x": 0, "y": 0, "width": 1, "height": 1,
+              "isDeleted": true },
+            { "id": "oval", "type": "ellipse", "x": 200, "y": 20, "width": 80, "height": 40,
+              "strokeColor": "#e03131", "backgroundColor": "transparent", "strokeWidth": 1,
+              "opacity": 50 },
+            { "id": "gem", "type": "diamond", "x": 0, "y": 100, "width": 40, "height": 20,
+              "backgroundColor": "transparent", "roundness": { "type": 2 } },
+            { "id": "tilt", "type": "rectangle", "x": 0, "y": 0, "width": 20, "height": 10,
+              "angle": 1.5707963267948966 },
+            { "id": "link", "type": "arrow", "x": 110, "y": 45, "width": 90, "height": 0,
+              "points": [[0, 0], [90, 0]], "startArrowhead": null, "endArrowhead": "arrow",
+              "startBinding": { "elementId": "box", "focus": 0, "gap": 1 },
+              "endBinding": { "elementId": "oval", "focus": 0, "gap": 1 } },
+            { "id": "back", "type": "arrow", "x": 0, "y": 0, "width": 10, "height": 0,
+              "points": [[0, 0], [10, 0]], "startArrowhead": "triangle", "endArrowhead": null,
+              "startBinding": { "elementId": "pic", "focus": 0, "gap": 1 } },
+            { "id": "bend", "type": "arrow", "x": 0, "y": 0, "width": 10, "height": 10,
+              "points": [[0, 0], [10, 0], [10, 10]], "endArrowhead": "arrow", "strokeStyle": "dashed" },
+            { "id": "ink", "type": "freedraw", "x": 5, "y": 5, "width": 2, "height": 2,
+              "points": [[0, 0], [1, 1], [2, 2]], "pressures": [0.5, 0.5, 0.5] },
+            { "id": "curve", "type": "line", "x": 0, "y": 300, "width": 20, "height": 10,
+              "points": [[0, 0], [10, 10], [20, 0]], "roundness": { "type": 2 } },
+            { "id": "label", "type": "text", "x": 10, "y": 200, "width": 120, "height": 50,
+              "text": "Hello\nWorld!", "fontSize": 20, "textAlign": "center", "lineHeight": 1.25,
+              "strokeColor": "#1971c2" },
+            { "id": "note", "type": "text", "x": 0, "y": 400, "width": 80, "height": 50,
+              "text": "a long\nnote", "originalText": "a long note", "autoResize": false,
+              "fontSize": 16, "textAlign": "right" },
+            { "id": "pic", "type": "image", "x": 0, "y": 0, "width": 10, "height": 10,
+              "fileId": "abc" }
+        ],
+        "appState": { "viewBackgroundColor": "#ffffff" },
+        "files": {}
+    }"##;
+
+    fn issues_for<'a>(import: &'a Import, id: &str) -> Vec<&'a Issue> {
+        import.issues.iter().filter(|i| i.id == id).collect()
+    }
+
+    #[test]
+    fn maps_elements_in_z_order() {
+        let import = import(FILE).unwrap();
+        let doc = &import.doc;
+        assert_eq!(
+            doc.order,
+            [
+                "box",
+                "oval",
+                "gem",
+                "tilt",
+                "link",
+                "back",
+                "bend:path",
+                "bend",
+                "ink",
+                "curve",
+                "label",
+                "note"
+            ]
+        );
+
+        let b = doc.get("box").unwrap();
+        assert_eq!(
+            b.kind,
+            ShapeKind::Rectangle {
+                x: 10.0,
+                y: 20.0,
+                w: 100.0,
+                h: 50.0
+            }
+        );
+        assert_eq!(b.fill.as_deref(), Some("#a5d8ff"));
+        assert_eq!(doc.get("oval").unwrap().stroke, "rgba(224,49,49,0.5)");
+        assert_eq!(doc.get("oval").unwrap().fill, None);
+        assert_eq!(doc.get("gem").unwrap().kind.type_name(), "pencil");
+
+        // rotated a quarter turn around its centre (10, 5)
+        let ShapeKind::Pencil { points, .. } = &doc.get("tilt").unwrap().kind else {
+            panic!("rotated rectangle should be an outline");
+        };
+        assert_eq!(points.len(), 5);
+        assert!((points[0].x - 15.0).abs() < 1e-9 && (points[0].y + 5.0).abs() < 1e-9);
+
+        let ShapeKind::Arrow { a, b } = doc.get("back").unwrap().kind else {
+            panic!("arrow expected");
+        };
+        assert_eq!((a, b), (Point::new(10.0, 0.0), Point::new(0.0, 0.0)));
+
+        assert_eq!(
+            doc.get("label").unwrap().kind,
+            ShapeKind::Text {
+                x: 70.0,
+                y: 220.0,
+                text: "Hello\nWorld!".to_string(),
+                font_size: 20.0,
+                align: Some(TextAlign::Center),
+                max_width: None,
+                line_height: Some(1.25),
+            }
+        );
+        assert_eq!(
+            doc.get("note").unwrap().kind,
+            ShapeKind::Text {
+                x: 80.0,
+                y: 416.0,
+                text: "a long note".to_string(),
+                font_size: 16.0,
+                align: Some(TextAlign::Right),
+                max_width: Some(80.0),
+                line_height: Some(1.25),
+            }
+        );
+    }
+
+    #[test]
+    fn reports_losses_and_bindings() {
+        let import = import(FILE).unwrap();
+        assert_eq!(issues_for(&import, "pic")[0].action, IssueAction::Skipped);
+        assert_eq!(
+            issues_for(&import, "box")[0].reason,
+            "hatched fills are drawn solid"
+        );
+        assert_eq!(issues_for(&import, "bend").len(), 2);
+        assert_eq!(
+            issues_for(&import, "gem")[0].reason,
+            "rounded corners are drawn sharp"
+        );
+        assert_eq!(
+            issues_for(&import, "curve")[0].reason,
+            "curves are drawn as straight segments"
+        );
+        assert!(issues_for(&import, "label").is_empty());
+        assert!(issues_for(&import, "oval").is_empty());
+        assert!(issues_for(&import, "gone").is_empty());
+
+        assert_eq!(
+            import.bindings,
+            [ArrowBinding {
+                arrow: "link".to_string(),
+                start: Some("box".to_string()),
+                end: Some("oval".to_string()),
+            }]
+        );
+        let json = serde_json::to_value(&import).unwrap();
+        assert_eq!(json["issues"][0]["elementType"], "rectangle");
+        assert_eq!(json["issues"][0]["action"], "approximated");
+    }
+
+    #[test]
+    fn turns_lines_around_the_centre_of_their_points() {
+        // points running up and left of the origin, centred on (-10, -5)
+        // relative to it, turned a half turn
+        let import = import(
+            r##"{ "type": "excalidraw", "elements": [
+                { "id": "l", "type": "line", "x": 100, "y": 100, "width": 20, "height": 10,
+                  "angle": 3.141592653589793, "points": [[0, 0], [-20, -10]] },
+                { "id": "d", "type": "freedraw", "x": 100, "y": 100, "width": 20, "height": 10,
+                  "angle": 3.141592653589793, "points": [[0, 0], [-10, -10], [-20, 0]] }
+            ] }"##,
+        )
+        .unwrap();
+        let close = |p: Point, x: f64, y: f64| (p.x - x).abs() < 1e-9 && (p.y - y).abs() < 1e-9;
+        let ShapeKind::Line { a, b } = import.doc.get("l").unwrap().kind else {
+            panic!("line expected");
+        };
+        // the box keeps its place and the ends swap corners
+        assert!(close(a, 80.0, 90.0), "{a:?}");
+        assert!(close(b, 100.0, 100.0), "{b:?}");
+        let ShapeKind::Pencil { points, .. } = &import.doc.get("d").unwrap().kind else {
+            panic!("pencil expected");
+        };
+        assert!(close(points[0], 80.0, 90.0), "{:?}", points[0]);
+        assert!(close(points[1], 90.0, 100.0), "{:?}", points[1]);
+        assert!(close(points[2], 100.0, 90.0), "{:?}", points[2]);
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(import(r#"{ "type": "tldraw", "elements": [] }"#).is_err());
+        assert!(import("[]").is_err());
+        assert!(import(r#"{ "type": "excalidraw/clipboard", "elements": [] }"#).is_ok());
+    }
+}
@@ -0,0 +1,67 @@
+//! Converters for other whiteboard apps' files. Each maps what it can onto a
+//! [`WhiteboardDoc`] (or back) and lists everything it had to drop or
+//! approximate, so the app can tell the user instead of losing content
+//! silently.
+
+use serde::Serialize;
+
+use crate::model::{ShapeId, WhiteboardDoc};
+
+pub mod excalidraw;
+pub mod tldraw;
+
+/// A document converted from another format plus what did not survive
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Import {
+    pub doc: WhiteboardDoc,
+    pub issues: Vec<Issue>,
+    /// Arrows that were attached to shapes in the source, by imported id.
+    /// Whiteboard arrows are free-standing, so this is kept for reference.
+    pub bindings: Vec<ArrowBinding>,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Issue {
+    /// Id of the element in the file it was converted from
+    pub id: String,
+    /// That file's name for the element type
+    pub element_type: String,
+    pub action: IssueAction,
+    pub reason: String,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum IssueAction {
+    /// Nothing was imported for the element
+    Skipped,
+    /// The element was imported but looks different
+    Approximated,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ArrowBinding {
+    pub arrow: ShapeId,
+    pub start: Option<ShapeId>,
+    pub end: Option<ShapeId>,
+}
+
+impl Issue {
+    fn new(id: &str, element_type: &str, action: IssueAction, reason: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            element_type: element_type.to_string(),
+            action,
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl Import {
+    fn issue(&mut self, id: &str, element_type: &str, action: IssueAction, reason: &str) {
+        self.issues
+            .push(Issue::new(id, element_type, action, reason));
+    }
+}
@@ -0,0 +1,1161 @@
+//! tldraw store snapshots, in both directions.
+//!
+//! Import reads the `{ store, schema }` snapshots of `store.getSnapshot()`
+//! as well as the `{ document, session }` ones of `getSnapshot(editor.store)`.
+//! Every page becomes a [`WhiteboardPage`], keeping the part of the record id
+//! after `page:` / `shape:` so a board survives a round trip with its ids.
+//! Shapes inside groups and frames are flattened into their page in tldraw's
+//! paint order.
+//!
+//! Export writes a store snapshot declaring the tldraw 3.0 schema; newer
+//! tldraw versions migrate it on load. tldraw only knows a fixed palette,
+//! four stroke sizes and fills tinted from the stroke colour, so colours and
+//! widths snap to the nearest of those and each change is reported.
+
+use std::collections::HashMap;
+use std::f64::consts::{PI, TAU};
+
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::*;
+
+use super::{ArrowBinding, Issue, IssueAction};
+use crate::error::CanvasError;
+use crate::ink;
+use crate::model::color::Rgba;
+use crate::model::{Board, Camera, Point, Rect, Shape, ShapeKind, WhiteboardDoc, WhiteboardPage};
+use crate::text::layout::TextAlign;
+
+/// tldraw's light theme colours
+const PALETTE: [(&str, &str); 13] = [
+    ("black", "#1d1d1d"),
+    ("grey", "#9fa8b2"),
+    ("light-violet", "#e085f4"),
+    ("violet", "#ae3ec9"),
+    ("blue", "#4465e9"),
+    ("light-blue", "#4ba1f1"),
+    ("yellow", "#f1ac4b"),
+    ("orange", "#e16919"),
+    ("green", "#099268"),
+    ("light-green", "#4cb05e"),
+    ("light-red", "#f87777"),
+    ("red", "#e03131"),
+    ("white", "#ffffff"),
+];
+
+/// Stroke width per `size`, at `scale` 1
+const STROKE_SIZES: [(&str, f64); 4] = [("s", 2.0), ("m", 3.5), ("l", 5.0), ("xl", 10.0)];
+
+/// Font size per `size` for text shapes, at `scale` 1
+const FONT_SIZES: [(&str, f64); 4] = [("s", 18.0), ("m", 24.0), ("l", 36.0), ("xl", 44.0)];
+
+/// tldraw's text line height as a multiple of the font size
+const LINE_HEIGHT: f64 = 1.35;
+
+/// Stroke width given to text, which the renderers ignore; the app's default
+const TEXT_STROKE_WIDTH: f64 = 2.0;
+
+/// Share of the stroke colour in the light fill tldraw paints for `solid`
+const TINT: f32 = 0.25;
+
+/// Segments used for ellipses that have to become outlines
+const ELLIPSE_SEGMENTS: usize = 64;
+
+/// A board converted from a tldraw snapshot plus what did not survive
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TldrawImport {
+    pub board: Board,
+    pub issues: Vec<Issue>,
+    /// Arrows that were bound to shapes in tldraw, by imported id
+    pub bindings: Vec<ArrowBinding>,
+}
+
+/// A tldraw store snapshot of a board plus what could not be mapped
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TldrawExport {
+    pub snapshot: Value,
+    pub issues: Vec<Issue>,
+}
+
+/// Converts a tldraw snapshot to `{ board, issues, bindings }` JSON
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen(js_name = "importTldraw")]
+pub fn import_tldraw(json: &str) -> Result<String, JsValue> {
+    let import = import(json)?;
+    Ok(serde_json::to_string(&import).map_err(|e| CanvasError::Import(e.to_string()))?)
+}
+
+/// Converts a `Board` given as JSON to `{ snapshot, issues }` JSON
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen(js_name = "exportTldraw")]
+pub fn export_tldraw(board_json: &str) -> Result<String, JsValue> {
+    let export = export(&Board::from_json(board_json)?);
+    Ok(serde_json::to_string(&export).map_err(|e| CanvasError::Export(e.to_string()))?)
+}
+
+/// Converts the JSON of a tldraw store snapshot
+pub fn import(json: &str) -> Result<TldrawImport, CanvasError> {
+    let root: Value = serde_json::from_str(json).map_err(|e| CanvasError::Import(e.to_string()))?;
+    let store = root
+        .pointer("/document/store")
+        .or_else(|| root.get("store"))
+        .and_then(Value::as_object)
+        .ok_or_else(|| CanvasError::Import("not a tldraw snapshot".to_string()))?;
+
+    let records: HashMap<&str, &Value> = store
+        .values()
+        .filter_map(|r| Some((r.get("id")?.as_str()?, r)))
+        .collect();
+    let of_type = |name: &str| {
+        let mut found: Vec<&Value> = store
+            .values()
+            .filter(|r| r.get("typeName").and_then(Value::as_str) == Some(name))
+            .collect();
+        // fractional index keys sort like plain strings
+        found.sort_by(|a, b| text(a, "index").cmp(text(b, "index")));
+        found
+    };
+    let pages = of_type("page");
+    if pages.is_empty() {
+        return Err(CanvasError::Import("snapshot has no pages".to_string()));
+    }
+    let mut children: HashMap<&str, Vec<&Value>> = HashMap::new();
+    for shape in of_type("shape") {
+        children
+            .entry(text(shape, "parentId"))
+            .or_default()
+            .push(shape);
+    }
+
+    let mut import = Importer {
+        records,
+        arrow_bindings: HashMap::new(),
+        issues: Vec::new(),
+        bindings: Vec::new(),
+    };
+    for binding in of_type("binding") {
+        let terminal = binding.pointer("/props/terminal").and_then(Value::as_str);
+        if text(binding, "type") == "arrow" {
+            if let Some(terminal) = terminal {
+                import
+                    .arrow_bindings
+                    .insert((text(binding, "fromId"), terminal), binding);
+            }
+        }
+    }
+
+    let cameras = cameras(&root, store);
+    let mut board = Board {
+        pages: Vec::new(),
+        active_page_id: String::new(),
+    };
+    for page in &pages {
+        let page_id = text(page, "id");
+        let mut doc = WhiteboardDoc::new();
+        import.children(&children, page_id, &mut doc);
+        board.pages.push(WhiteboardPage {
+            id: local_id(page_id).to_string(),
+            name: text(page, "name").to_string(),
+            doc,
+            camera: cameras.get(page_id).copied().unwrap_or_default(),
+        });
+    }
+    let current = root
+        .pointer("/session/currentPageId")
+        .and_then(Value::as_str)
+        .or_else(|| {
+            of_type("instance")
+                .first()
+                .and_then(|i| i.get("currentPageId")?.as_str())
+        })
+        .unwrap_or(text(pages[0], "id"));
+    board.active_page_id = local_id(current).to_string();
+
+    // only bindings between shapes that made it onto a page
+    let imported = |id: &str| board.pages.iter().any(|p| p.doc.get(id).is_some());
+    import.bindings.retain_mut(|b| {
+        b.start = b.start.take().filter(|id| imported(id));
+        b.end = b.end.take().filter(|id| imported(id));
+        imported(&b.arrow) && (b.start.is_some() || b.end.is_some())
+    });
+    Ok(TldrawImport {
+        board,
+        issues: import.issues,
+        bindings: import.bindings,
+    })
+}
+
+/// Cameras keyed by page record id, from `camera:` records or session state,
+/// converted from tldraw's `(world + camera) * z` to the store's convention
+fn cameras(root: &Value, store: &Map<String, Value>) -> HashMap<String, Camera> {
+    let camera = |c: &Value| {
+        let z = number(c, "z", 1.0);
+        Camera {
+            x: number(c, "x", 0.0) * z,
+            y: number(c, "y", 0.0) * z,
+            zoom: z,
+        }
+    };
+    let mut out = HashMap::new();
+    for (id, record) in store {
+        if let Some(page) = id.strip_prefix("camera:") {
+            out.insert(page.to_string(), camera(record));
+        }
+    }
+    let states = root
+        .pointer("/session/pageStates")
+        .and_then(Value::as_array);
+    for state in states.into_iter().flatten() {
+        if let Some(c) = state.get("camera") {
+            out.insert(text(state, "pageId").to_string(), camera(c));
+        }
+    }
+    out
+}
+
+struct Importer<'a> {
+    records: HashMap<&'a str, &'a Value>,
+    /// Binding records keyed by arrow id and `start` / `end`
+    arrow_bindings: HashMap<(&'a str, &'a str), &'a Value>,
+    issues: Vec<Issue>,
+    bindings: Vec<ArrowBinding>,
+}
+
+impl<'a> Importer<'a> {
+    fn children(
+        &mut self,
+        children: &HashMap<&str, Vec<&'a Value>>,
+        parent: &str,
+        doc: &mut WhiteboardDoc,
+    ) {
+        for shape in children.get(parent).into_iter().flatten() {
+            self.shape(shape, doc);
+            // groups and frames paint their children above themselves
+            self.children(children, text(shape, "id"), doc);
+        }
+    }
+
+    fn issue(&mut self, record: &Value, action: IssueAction, reason: &str) {
+        self.issues.push(Issue::new(
+            text(record, "id"),
+            text(record, "type"),
+            action,
+            reason,
+        ));
+    }
+
+    /// Position of the shape's origin on its page and its total rotation
+    fn placement(&self, shape: &Value) -> (Point, f64) {
+        let mut origin = Point::new(number(shape, "x", 0.0), number(shape, "y", 0.0));
+        let mut rotation = number(shape, "rotation", 0.0);
+        let mut parent = self.records.get(text(shape, "parentId"));
+        while let Some(p) = parent.filter(|p| text(p, "typeName") == "shape") {
+            let (sin, cos) = number(p, "rotation", 0.0).sin_cos();
+            origin = Point::new(
+                number(p, "x", 0.0) + origin.x * cos - origin.y * sin,
+                number(p, "y", 0.0) + origin.x * sin + origin.y * cos,
+            );
+            rotation += number(p, "rotation", 0.0);
+            parent = self.records.get(text(p, "parentId"));
+        }
+        (origin, rotation)
+    }
+
+    fn shape(&mut self, record: &'a Value, doc: &mut WhiteboardDoc) {
+        let id = local_id(text(record, "id")).to_string();
+        let props = record.get("props").unwrap_or(&Value::Null);
+        let (origin, rotation) = self.placement(record);
+        let place = |p: Point| rotate(origin, rotation, p);
+        let scale = number(props, "scale", 1.0);
+        let opacity = number(record, "opacity", 1.0);
+        let color = palette(text(props, "color"));
+        let stroke = css(color, opacity);
+        let stroke_width = lookup(&STROKE_SIZES, text(props, "size")).unwrap_or(3.5) * scale;
+        let base = |kind| Shape {
+            id: id.clone(),
+            stroke: stroke.clone(),
+            fill: None,
+            stroke_width,
+            kind,
+        };
+        if matches!(text(props, "dash"), "dashed" | "dotted") {
+            self.issue(
+                record,
+                IssueAction::Approximated,
+                "dashed and dotted strokes are drawn solid",
+            );
+        }
+
+        match text(record, "type") {
+            "geo" => {
+                let (w, h) = (number(props, "w", 0.0), number(props, "h", 0.0));
+                let fill = match text(props, "fill") {
+                    "none" | "" => None,
+                    "semi" => Some(mix(Rgba::WHITE, color, TINT / 2.0)),
+                    "fill" => Some(color),
+                    "pattern" => {
+                        self.issue(
+                            record,
+                            IssueAction::Approximated,
+                            "pattern fill drawn solid",
+                        );
+                        Some(mix(Rgba::WHITE, color, TINT))
+                    }
+                    _ => Some(mix(Rgba::WHITE, color, TINT)),
+                };
+                let geo = text(props, "geo");
+                let outline = geo_outline(geo, w, h);
+                if outline.is_none() && !matches!(geo, "rectangle" | "ellipse" | "oval" | "") {
+                    self.issue(
+                        record,
+                        IssueAction::Approximated,
+                        &format!("{geo} drawn as a rectangle"),
+                    );
+                }
+                if geo == "oval" {
+                    self.issue(
+                        record,
+                        IssueAction::Approximated,
+                        "oval drawn as an ellipse",
+                    );
+                }
+                let is_ellipse = matches!(geo, "ellipse" | "oval");
+                let kind = match outline {
+                    // rotated shapes keep their geometry as an outline
+                    None if rotation.abs() > 1e-9 => {
+                        let points = if is_ellipse {
+                            ellipse_outline(w, h)
+                        } else {
+                            rect_outline(w, h)
+                        };
+                        ShapeKind::Pencil {
+                            points: closed(points.into_iter().map(place).collect()),
+                            pressure: Vec::new(),
+                        }
+                    }
+                    None if is_ellipse => ShapeKind::Ellipse {
+                        x: origin.x,
+                        y: origin.y,
+                        w,
+                        h,
+                    },
+                    None => ShapeKind::Rectangle {
+                        x: origin.x,
+                        y: origin.y,
+                        w,
+                        h,
+                    },
+                    Some(points) => ShapeKind::Pencil {
+                        points: closed(points.into_iter().map(place).collect()),
+                        pressure: Vec::new(),
+                    },
+                };
+                let is_outline = matches!(kind, ShapeKind::Pencil { .. });
+                if is_outline && fill.is_some() {
+                    self.issue(
+                        record,
+                        IssueAction::Approximated,
+                        "drawn as an outline without its fill",
+                    );
+                }
+                let mut shape = base(kind);
+                if !is_outline {
+                    shape.fill = fill.map(|f| css(f, opacity));
+                }
+                doc.add_shape(shape);
+                self.label(record, props, place(Point::new(w / 2.0, h / 2.0)), doc);
+            }
+            "draw" | "highlight" => {
+                if text(record, "type") == "highlight" {
+                    self.issue(
+                        record,
+                        IssueAction::Approximated,
+                        "highlighter drawn as a normal stroke",
+                    );
+                }
+                let samples: Vec<&Value> = props
+                    .get("segments")
+                    .and_then(Value::as_array)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|s| s.get("points")?.as_array())
+                    .flatten()
+                    .collect();
+                let mut points: Vec<Point> = samples
+                    .iter()
+                    .map(|p| place(Point::new(number(p, "x", 0.0), number(p, "y", 0.0))))
+                    .collect();
+                // `z` is the pen's pressure; mouse strokes hold a constant 0.5
+                let mut pressure: Vec<f64> =
+                    if props.get("isPen").and_then(Value::as_bool) == Some(true) {
+                        samples
+                            .iter()
+                            .map(|p| number(p, "z", ink::DEFAULT_PRESSURE))
+                            .collect()
+                    } else {
+                        Vec::new()
+                    };
+                if props.get("isClosed").and_then(Value::as_bool) == Some(true) {
+                    let open = points.len();
+                    points = closed(points);
+                    if points.len() > open {
+                        pressure.extend(pressure.first().copied());
+                    }
+                }
+                doc.add_shape(base(ShapeKind::Pencil { points, pressure }));
+            }
+            "line" => {
+                let mut handles: Vec<&Value> = match props.get("points") {
+                    Some(Value::Object(points)) => points.values().collect(),
+                    Some(Value::Array(points)) => points.iter().collect(),
+                    _ => Vec::new(),
+                };
+                handles.sort_by(|a, b| text(a, "index").cmp(text(b, "index")));
+                let points: Vec<Point> = handles
+                    .iter()
+                    .map(|p| place(Point::new(number(p, "x", 0.0), number(p, "y", 0.0))))
+                    .collect();
+                if text(props, "spline") == "cubic" {
+                    self.issue(record, IssueAction::Approximated, "curve drawn straight");
+                }
+                let kind = match points.as_slice() {
+                    [a, b] => ShapeKind::Line { a: *a, b: *b },
+                    _ => ShapeKind::Pencil {
+                        points,
+                        pressure: Vec::new(),
+                    },
+                };
+                doc.add_shape(base(kind));
+            }
+            "arrow" => self.arrow(record, props, place, base, doc),
+            "text" => {
+                let font_size = lookup(&FONT_SIZES, text(props, "size")).unwrap_or(24.0) * scale;
+                if rotation.abs() > 1e-9 {
+                    self.issue(record, IssueAction::Approximated, "rotation dropped");
+                }
+                let box_width = number(props, "w", 0.0) * scale;
+                let align = props
+                    .get("textAlign")
+                    .or_else(|| props.get("align"))
+                    .and_then(Value::as_str)
+                    .unwrap_or("start");
+                // lines are anchored on the side of the box they are aligned to
+                let (dx, align) = match align {
+                    "middle" => (box_width / 2.0, Some(TextAlign::Center)),
+                    "end" => (box_width, Some(TextAlign::Right)),
+                    _ => (0.0, None),
+                };
+                // a box that does not grow with its text wraps it
+                let auto_size = props
+                    .get("autoSize")
+                    .and_then(Value::as_bool)
+                    .unwrap_or(true);
+                let mut shape = base(ShapeKind::Text {
+                    x: origin.x + dx,
+                    y: origin.y + font_size,
+                    text: plain_text(props),
+                    font_size,
+                    align,
+                    max_width: (!auto_size).then_some(box_width),
+                    line_height: Some(LINE_HEIGHT),
+                });
+                shape.stroke_width = TEXT_STROKE_WIDTH;
+                doc.add_shape(shape);
+            }
+            "group" => {}
+            "frame" => self.issue(
+                record,
+                IssueAction::Approximated,
+                "frame border and name dropped, its shapes kept",
+            ),
+            _ => self.issue(
+                record,
+                IssueAction::Skipped,
+                "shape type has no whiteboard equivalent",
+            ),
+        }
+    }
+
+    fn arrow(
+        &mut self,
+        record: &'a Value,
+        props: &Value,
+        place: impl Fn(Point) -> Point,
+        base: impl Fn(ShapeKind) -> Shape,
+        doc: &mut WhiteboardDoc,
+    ) {
+        let arrow_id = text(record, "id");
+        let mut ends = [None, None];
+        let mut point = |terminal: &str| -> Point {
+            let local = props.get(terminal).unwrap_or(&Value::Null);
+            let slot = usize::from(terminal == "end");
+            // bindings live in their own records since tldraw 2.2, before that
+            // on the terminal itself
+            let binding = self
+                .arrow_bindings
+                .get(&(arrow_id, terminal))
+                .map(|b| (text(b, "toId"), b.pointer("/props/normalizedAnchor")))
+                .or_else(|| {
+                    (text(local, "type") == "binding")
+                        .then(|| (text(local, "boundShapeId"), local.get("normalizedAnchor")))
+                });
+            if let Some((target_id, anchor)) = binding {
+                if let Some(target) = self.records.get(target_id) {
+                    ends[slot] = Some(local_id(target_id).to_string());
+                    let (origin, rotation) = self.placement(target);
+                    let anchor = anchor.unwrap_or(&Value::Null);
+                    let tp = target.get("props").unwrap_or(&Value::Null);
+                    let p = Point::new(
+                        number(anchor, "x", 0.5) * number(tp, "w", 0.0),
+                        number(anchor, "y", 0.5) * number(tp, "h", 0.0),
+                    );
+                    return rotate(origin, rotation, p);
+                }
+            }
+            place(Point::new(number(local, "x", 0.0), number(local, "y", 0.0)))
+        };
+        let (mut a, mut b) = (point("start"), point("end"));
+
+        let head = |key: &str| !matches!(text(props, key), "none" | "");
+        let (start_head, end_head) = (head("arrowheadStart"), head("arrowheadEnd"));
+        if start_head && !end_head {
+            std::mem::swap(&mut a, &mut b);
+            ends.swap(0, 1);
+        }
+        if start_head && end_head {
+            self.issue(
+                record,
+                IssueAction::Approximated,
+                "only the end arrowhead is kept",
+            );
+        }
+        if number(props, "bend", 0.0).abs() > 1e-9 {
+            self.issue(
+                record,
+                IssueAction::Approximated,
+                "curved arrow drawn straight",
+            );
+        }
+        let kind = if start_head || end_head {
+            ShapeKind::Arrow { a, b }
+        } else {
+            ShapeKind::Line { a, b }
+        };
+        doc.add_shape(base(kind));
+        if ends.iter().any(Option::is_some) {
+            let [start, end] = ends;
+            self.bindings.push(ArrowBinding {
+                arrow: local_id(arrow_id).to_string(),
+                start,
+                end,
+            });
+        }
+        let mid = Point::new((a.x + b.x) / 2.0, (a.y + b.y) / 2.0);
+        self.label(record, props, mid, doc);
+    }
+
+    /// Labels of geo shapes and arrows become text centred on `centre`
+    fn label(&mut self, record: &Value, props: &Value, centre: Point, doc: &mut WhiteboardDoc) {
+        let label = plain_text(props);
+        if label.trim().is_empty() {
+            return;
+        }
+        self.issue(
+            record,
+            IssueAction::Approximated,
+            "label imported as a separate text shape",
+        );
+        let font_size =
+            lookup(&FONT_SIZES, text(props, "size")).unwrap_or(24.0) * number(props, "scale", 1.0);
+        let color = palette(
+            props
+                .get("labelColor")
+                .and_then(Value::as_str)
+                .unwrap_or("black"),
+        );
+        let height = label.lines().count() as f64 * font_size * LINE_HEIGHT;
+        doc.add_shape(Shape {
+            id: format!("{}:label", local_id(text(record, "id"))),
+            stroke: css(color, number(record, "opacity", 1.0)),
+            fill: None,
+            stroke_width: TEXT_STROKE_WIDTH,
+            kind: ShapeKind::Text {
+                x: centre.x,
+                y: centre.y - height / 2.0 + font_size,
+                text: label,
+                font_size,
+                align: Some(TextAlign::Center),
+                max_width: None,
+                line_height: Some(LINE_HEIGHT),
+            },
+        });
+    }
+}
+
+/// Writes `board` as a tldraw store snapshot
+pub fn export(board: &Board) -> TldrawExport {
+    let mut store = Map::new();
+    let mut issues = Vec::new();
+    store.insert(
+        "document:document".to_string(),
+        json!({ "gridSize": 10, "name": "", "meta": {}, "id": "document:document", "typeName": "document" }),
+    );
+    for (i, page) in board.pages.iter().enumerate() {
+        let page_id = format!("page:{}", page.id);
+        store.insert(
+            page_id.clone(),
+            json!({ "meta": {}, "id": page_id, "name": page.name, "index": index_key(i), "typeName": "page" }),
+        );
+        for (j, shape) in page.doc.ordered_shapes().enumerate() {
+            let record = shape_record(shape, &page_id, &index_key(j), &mut issues);
+            store.insert(
+                record["id"].as_str().unwrap_or_default().to_string(),
+                record,
+            );
+        }
+    }
+    TldrawExport {
+        snapshot: json!({ "store": store, "schema": schema() }),
+        issues,
+    }
+}
+
+fn shape_record(shape: &Shape, page_id: &str, index: &str, issues: &mut Vec<Issue>) -> Value {
+    let mut issue = |reason: String| {
+        issues.push(Issue::new(
+            &shape.id,
+            shape.kind.type_name(),
+            IssueAction::Approximated,
+            &reason,
+        ))
+    };
+    let ink = Rgba::parse(&shape.stroke).unwrap_or(Rgba::BLACK);
+    let (color, color_hex) = nearest_color(ink);
+    if hex(ink) != color_hex {
+        issue(format!("colour {} became tldraw {color}", shape.stroke));
+    }
+    let (size, width) = STROKE_SIZES
+        .iter()
+        .min_by(|a, b| {
+            (a.1 - shape.stroke_width)
+                .abs()
+                .total_cmp(&(b.1 - shape.stroke_width).abs())
+        })
+        .copied()
+        .unwrap_or(STROKE_SIZES[1]);
+    let is_text = matches!(shape.kind, ShapeKind::Text { .. });
+    if !is_text && (width - shape.stroke_width).abs() > 1e-9 {
+        issue(format!(
+            "stroke width {} became tldraw size {size} ({width})",
+            shape.stroke_width
+        ));
+    }
+
+    let mut record = json!({
+        "id": format!("shape:{}", shape.id),
+        "typeName": "shape",
+        "parentId": page_id,
+        "index": index,
+        "x": 0.0,
+        "y": 0.0,
+        "rotation": 0.0,
+        "isLocked": false,
+        "opacity": ink.a.clamp(0.0, 1.0),
+        "meta": {},
+    });
+    let (kind, x, y, props) = match &shape.kind {
+        ShapeKind::Rectangle { x, y, w, h } | ShapeKind::Ellipse { x, y, w, h } => {
+            let r = Rect::from_points(Point::new(*x, *y), Point::new(x + w, y + h));
+            let fill = match shape
+                .fill
+                .as_deref()
+                .and_then(Rgba::parse)
+                .filter(Rgba::is_visible)
+            {
+                None => "none",
+                Some(f) => {
+                    let (fill, reproduced) = if hex(f) == color_hex {
+                        ("fill", hex(f))
+                    } else {
+                        ("solid", hex(mix(Rgba::WHITE, parse_hex(color_hex), TINT)))
+                    };
+                    if hex(f) != reproduced {
+                        issue(format!(
+                            "fill {} became tldraw's {fill} fill of {color}",
+                            shape.fill.as_deref().unwrap_or_default()
+                        ));
+                    }
+                    fill
+                }
+            };
+            let geo = shape.kind.type_name();
+            let props = json!({
+                "w": r.w, "h": r.h, "geo": geo, "color": color, "labelColor": "black",
+                "fill": fill, "dash": "solid", "size": size, "font": "sans", "text": "",
+                "align": "middle", "verticalAlign": "middle", "growY": 0, "url": "", "scale": 1,
+            });
+            ("geo", r.x, r.y, props)
+        }
+        ShapeKind::Line { a, b } | ShapeKind::Arrow { a, b } => {
+            let head = if matches!(shape.kind, ShapeKind::Arrow { .. }) {
+                "arrow"
+            } else {
+                "none"
+            };
+            let props = json!({
+                "dash": "solid", "size": size, "fill": "none", "color": color,
+                "labelColor": "black", "bend": 0, "start": { "x": 0, "y": 0 },
+                "end": { "x": b.x - a.x, "y": b.y - a.y }, "arrowheadStart": "none",
+                "arrowheadEnd": head, "text": "", "labelPosition": 0.5, "font": "sans",
+                "scale": 1,
+            });
+            ("arrow", a.x, a.y, props)
+        }
+        ShapeKind::Pencil { points, pressure } => {
+            let origin = points.first().copied().unwrap_or_default();
+            let is_pen = pressure.len() == points.len() && !points.is_empty();
+            let points: Vec<Value> = points
+                .iter()
+                .enumerate()
+                .map(|(i, p)| {
+                    let z = if is_pen {
+                        pressure[i]
+                    } else {
+                        ink::DEFAULT_PRESSURE
+                    };
+                    json!({ "x": p.x - origin.x, "y": p.y - origin.y, "z": z })
+                })
+                .collect();
+            let props = json!({
+                "segments": [{ "type": "free", "points": points }], "color": color,
+                "fill": "none", "dash": "solid", "size": size, "isComplete": true,
+                "isClosed": false, "isPen": is_pen, "scale": 1,
+            });
+            ("draw", origin.x, origin.y, props)
+        }
+        ShapeKind::Text {
+            x,
+            y,
+            text,
+            font_size,
+            align,
+            max_width,
+            ..
+        } => {
+            // the box is as wide as the wrapping width or, for text that
+            // does not wrap, its widest line as `Shape::bounds` measures it
+            let width = max_width.unwrap_or_else(|| shape.bounds().w);
+            let (text_align, left) = match align.unwrap_or_default() {
+                TextAlign::Left => ("start", *x),
+                TextAlign::Center => ("middle", x - width / 2.0),
+                TextAlign::Right => ("end", x - width),
+            };
+            // `m` scaled to the exact font size
+            let scale = font_size / 24.0;
+            let props = json!({
+                "color": color, "size": "m", "w": width / scale, "text": text, "font": "sans",
+                "textAlign": text_align, "autoSize": max_width.is_none(), "scale": scale,
+            });
+            ("text", left, y - font_size, props)
+        }
+    };
+    record["type"] = json!(kind);
+    record["x"] = json!(x);
+    record["y"] = json!(y);
+    record["props"] = props;
+    record
+}
+
+/// Record type versions of tldraw 3.0, the format [`export`] writes
+fn schema() -> Value {
+    json!({
+        "schemaVersion": 2,
+        "sequences": {
+            "com.tldraw.store": 4, "com.tldraw.asset": 1, "com.tldraw.camera": 1,
+            "com.tldraw.document": 2, "com.tldraw.instance": 25,
+            "com.tldraw.instance_page_state": 5, "com.tldraw.page": 1,
+            "com.tldraw.instance_presence": 5, "com.tldraw.pointer": 1, "com.tldraw.shape": 4,
+            "com.tldraw.asset.bookmark": 2, "com.tldraw.asset.image": 4,
+            "com.tldraw.asset.video": 4, "com.tldraw.shape.group": 0,
+            "com.tldraw.shape.text": 2, "com.tldraw.shape.bookmark": 2,
+            "com.tldraw.shape.draw": 2, "com.tldraw.shape.geo": 9, "com.tldraw.shape.note": 7,
+            "com.tldraw.shape.line": 5, "com.tldraw.shape.frame": 0,
+            "com.tldraw.shape.arrow": 5, "com.tldraw.shape.highlight": 1,
+            "com.tldraw.shape.embed": 4, "com.tldraw.shape.image": 4,
+            "com.tldraw.shape.video": 2, "com.tldraw.binding.arrow": 0,
+        },
+    })
+}
+
+/// The `i`th fractional index key: `a0`..`az`, then `b00`.. and so on
+fn index_key(i: usize) -> String {
+    const DIGITS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+    let base = DIGITS.len();
+    let (mut len, mut first, mut n) = (1, 0, i);
+    while n >= base.pow(len as u32) {
+        n -= base.pow(len as u32);
+        len += 1;
+        first += 1;
+    }
+    let mut key = vec![b'a' + first as u8];
+    for k in (0..len).rev() {
+        key.push(DIGITS[n / base.pow(k as u32) % base]);
+    }
+    String::from_utf8(key).unwrap_or_default()
+}
+
+fn text<'v>(v: &'v Value, key: &str) -> &'v str {
+    v.get(key).and_then(Value::as_str).unwrap_or_default()
+}
+
+fn number(v: &Value, key: &str, default: f64) -> f64 {
+    v.get(key).and_then(Value::as_f64).unwrap_or(default)
+}
+
+fn local_id(id: &str) -> &str {
+    id.split_once(':').map_or(id, |(_, rest)| rest)
+}
+
+/// `text`, or the plain text of the `richText` document newer tldraw uses
+fn plain_text(props: &Value) -> String {
+    fn collect(node: &Value, out: &mut String) {
+        if let Some(t) = node.get("text").and_then(Value::as_str) {
+            out.push_str(t);
+        }
+        let children = node.get("content").and_then(Value::as_array);
+        for (i, child) in children.into_iter().flatten().enumerate() {
+            if i > 0 && text(child, "type") == "paragraph" {
+                out.push('\n');
+            }
+            collect(child, out);
+        }
+    }
+    match props.get("richText") {
+        Some(doc) => {
+            let mut out = String::new();
+            collect(doc, &mut out);
+            out
+        }
+        None => text(props, "text").to_string(),
+    }
+}
+
+fn lookup(table: &[(&str, f64)], key: &str) -> Option<f64> {
+    table.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+}
+
+fn palette(name: &str) -> Rgba {
+    let hex = PALETTE
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map_or(PALETTE[0].1, |(_, hex)| hex);
+    parse_hex(hex)
+}
+
+fn parse_hex(hex: &str) -> Rgba {
+    Rgba::parse(hex).unwrap_or(Rgba::BLACK)
+}
+
+fn nearest_color(c: Rgba) -> (&'static str, &'static str) {
+    let distance = |hex: &str| {
+        let p = parse_hex(hex);
+        (p.r - c.r).powi(2) + (p.g - c.g).powi(2) + (p.b - c.b).powi(2)
+    };
+    PALETTE
+        .iter()
+        .copied()
+        .min_by(|a, b| distance(a.1).total_cmp(&distance(b.1)))
+        .unwrap_or(PALETTE[0])
+}
+
+/// `#rrggbb`, ignoring alpha
+fn hex(c: Rgba) -> String {
+    let byte = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+    format!("#{:02x}{:02x}{:02x}", byte(c.r), byte(c.g), byte(c.b))
+}
+
+/// `#rrggbb`, or `rgba(..)` when `opacity` is below 1
+fn css(c: Rgba, opacity: f64) -> String {
+    if opacity >= 1.0 {
+        return hex(c);
+    }
+    let byte = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+    format!(
+        "rgba({},{},{},{})",
+        byte(c.r),
+        byte(c.g),
+        byte(c.b),
+        (opacity.max(0.0) * 1000.0).round() / 1000.0
+    )
+}
+
+/// `t` of the way from `a` to `b`, rounded to whole bytes
+fn mix(a: Rgba, b: Rgba, t: f32) -> Rgba {
+    let ch = |x: f32, y: f32| ((x + (y - x) * t) * 255.0).round() / 255.0;
+    Rgba::new(ch(a.r, b.r), ch(a.g, b.g), ch(a.b, b.b), 1.0)
+}
+
+fn rotate(origin: Point, rotation: f64, p: Point) -> Point {
+    let (sin, cos) = rotation.sin_cos();
+    Point::new(
+        origin.x + p.x * cos - p.y * sin,
+        origin.y + p.x * sin + p.y * cos,
+    )
+}
+
+fn closed(mut points: Vec<Point>) -> Vec<Point> {
+    if let (Some(first), Some(last)) = (points.first().copied(), points.last()) {
+        if first != *last {
+            points.push(first);
+        }
+    }
+    points
+}
+
+fn rect_outline(w: f64, h: f64) -> Vec<Point> {
+    vec![
+        Point::new(0.0, 0.0),
+        Point::new(w, 0.0),
+        Point::new(w, h),
+        Point::new(0.0, h),
+    ]
+}
+
+fn ellipse_outline(w: f64, h: f64) -> Vec<Point> {
+    regular(w, h, ELLIPSE_SEGMENTS, 0.0)
+}
+
+/// Outline in shape space of the geo types that are polygons
+fn geo_outline(geo: &str, w: f64, h: f64) -> Option<Vec<Point>> {
+    let offset = (w * 0.38).min(h * 0.38);
+    let p = Point::new;
+    Some(match geo {
+        "triangle" => vec![p(w / 2.0, 0.0), p(w, h), p(0.0, h)],
+        "diamond" => vec![
+            p(w / 2.0, 0.0),
+            p(w, h / 2.0),
+            p(w / 2.0, h),
+            p(0.0, h / 2.0),
+        ],
+        "rhombus" => vec![p(offset, 0.0), p(w, 0.0), p(w - offset, h), p(0.0, h)],
+        "trapezoid" => vec![p(offset, 0.0), p(w - offset, 0.0), p(w, h), p(0.0, h)],
+        "pentagon" => regular(w, h, 5, -PI / 2.0),
+        "hexagon" => regular(w, h, 6, 0.0),
+        "octagon" => regular(w, h, 8, PI / 8.0),
+        "star" => (0..10)
+            .map(|i| {
+                let r = if i % 2 == 0 { 0.5 } else { 0.2 };
+                let t = -PI / 2.0 + i as f64 * PI / 5.0;
+                p(w * (0.5 + r * t.cos()), h * (0.5 + r * t.sin()))
+            })
+            .collect(),
+        _ => return None,
+    })
+}
+
+/// `n` corners on the ellipse inscribed in `w` x `h`, starting at `start`
+fn regular(w: f64, h: f64, n: usize, start: f64) -> Vec<Point> {
+    (0..n)
+        .map(|i| {
+            let t = start + i as f64 / n as f64 * TAU;
+            Point::new(w / 2.0 * (1.0 + t.cos()), h / 2.0 * (1.0 + t.sin()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SNAPSHOT: &str = r##"{
+        "document": {
+            "store": {
+                "document:document": { "id": "document:document", "typeName": "document", "name": "" },
+                "page:b": { "id": "page:b", "typeName": "page", "name": "Second", "index": "a2" },
+                "page:a": { "id": "page:a", "typeName": "page", "name": "First", "index": "a1" },
+                "shape:box": { "id": "shape:box", "typeName": "shape", "type": "geo", "parentId": "page:a",
+                    "index": "a2", "x": 10, "y": 20, "rotation": 0, "opacity": 1,
+                    "props": { "w": 100, "h": 50, "geo": "rectangle", "color": "blue", "fill": "fill",
+                               "dash": "draw", "size": "s", "text": "", "scale": 1 } },
+                "shape:frame": { "id": "shape:frame", "typeName": "shape", "type": "frame",
+                    "parentId": "page:a", "index": "a1", "x": 100, "y": 100, "rotation": 0,
+                    "props": { "w": 200, "h": 200, "name": "Frame" } },
+                "shape:ink": { "id": "shape:ink", "typeName": "shape", "type": "draw", "parentId": "shape:frame",
+                    "index": "a1", "x": 5, "y": 5, "rotation": 0, "opacity": 0.5,
+                    "props": { "segments": [{ "type": "free", "points": [{ "x": 0, "y": 0, "z": 0.5 },
+                               { "x": 10, "y": 0, "z": 0.5 }] }], "color": "red", "size": "m",
+                               "dash": "dashed", "isClosed": false, "scale": 1 } },
+                "shape:link": { "id": "shape:link", "typeName": "shape", "type": "arrow", "parentId": "page:a",
+                    "index": "a3", "x": 0, "y": 0, "rotation": 0,
+                    "props": { "start": { "x": 0, "y": 0 }, "end": { "x": 5, "y": 5 }, "bend": 0,
+                               "arrowheadStart": "none", "arrowheadEnd": "arrow", "color": "black",
+                               "size": "l", "text": "" } },
+                "binding:1": { "id": "binding:1", "typeName": "binding", "type": "arrow",
+                    "fromId": "shape:link", "toId": "shape:box",
+                    "props": { "terminal": "end", "normalizedAnchor": { "x": 0, "y": 0.5 } } },
+                "shape:hello": { "id": "shape:hello", "typeName": "shape", "type": "text", "parentId": "page:b",
+                    "index": "a1", "x": 0, "y": 0, "rotation": 0,
+                    "props": { "color": "black", "size": "m", "scale": 2, "w": 100,
+                               "textAlign": "middle", "autoSize": false,
+                               "richText": { "type": "doc", "content": [
+                                   { "type": "paragraph", "content": [{ "type": "text", "text": "Hi" }] },
+                                   { "type": "paragraph", "content": [{ "type": "text", "text": "there" }] }
+                               ] } } },
+                "shape:sticky": { "id": "shape:sticky", "typeName": "shape", "type": "note", "parentId": "page:b",
+                    "index": "a2", "x": 0, "y": 0, "rotation": 0, "props": {} }
+            },
+            "schema": { "schemaVersion": 2, "sequences": {} }
+        },
+        "session": {
+            "currentPageId": "page:b",
+            "pageStates": [{ "pageId": "page:a", "camera": { "x": 10, "y": -5, "z": 2 } }]
+        }
+    }"##;
+
+    #[test]
+    fn imports_pages_shapes_and_bindings() {
+        let import = import(SNAPSHOT).unwrap();
+        let board = &import.board;
+        assert_eq!(board.active_page_id, "b");
+        let names: Vec<&str> = board.pages.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["First", "Second"]);
+
+        let first = &board.pages[0];
+        assert_eq!(first.doc.order, ["ink", "box", "link"]);
+        assert_eq!(
+            first.camera,
+            Camera {
+                x: 20.0,
+                y: -10.0,
+                zoom: 2.0
+            }
+        );
+        let b = first.doc.get("box").unwrap();
+        assert_eq!(
+            (b.stroke.as_str(), b.fill.as_deref()),
+            ("#4465e9", Some("#4465e9"))
+        );
+        assert_eq!(b.stroke_width, 2.0);
+
+        let ink = first.doc.get("ink").unwrap();
+        assert_eq!(ink.stroke, "rgba(224,49,49,0.5)");
+        let ShapeKind::Pencil { points, .. } = &ink.kind else {
+            panic!("draw should be a pencil stroke");
+        };
+        assert_eq!(points[1], Point::new(115.0, 105.0));
+
+        // the bound end sits on the box's left edge, not the stale end point
+        let ShapeKind::Arrow { b: end, .. } = first.doc.get("link").unwrap().kind else {
+            panic!("arrow expected");
+        };
+        assert_eq!(end, Point::new(10.0, 45.0));
+        assert_eq!(
+            import.bindings,
+            [ArrowBinding {
+                arrow: "link".to_string(),
+                start: None,
+                end: Some("box".to_string()),
+            }]
+        );
+
+        let second = &board.pages[1].doc;
+        assert_eq!(second.order, ["hello"]);
+        assert_eq!(
+            second.get("hello").unwrap().kind,
+            ShapeKind::Text {
+                x: 100.0,
+                y: 48.0,
+                text: "Hi\nthere".to_string(),
+                font_size: 48.0,
+                align: Some(TextAlign::Center),
+                max_width: Some(200.0),
+                line_height: Some(LINE_HEIGHT),
+            }
+        );
+
+        let reported: Vec<(&str, IssueAction)> = import
+            .issues
+            .iter()
+            .map(|i| (i.id.as_str(), i.action))
+            .collect();
+        assert_eq!(
+            reported,
+            [
+                ("shape:frame", IssueAction::Approximated),
+                ("shape:ink", IssueAction::Approximated),
+                ("shape:sticky", IssueAction::Skipped),
+            ]
+        );
+    }
+
+    #[test]
+    fn export_round_trips_palette_shapes() {
+        let mut page = WhiteboardPage::new("page-1", "Plan");
+        page.doc = WhiteboardDoc::from_json(
+            r##"{
+                "shapes": {
+                    "r": { "id": "r", "type": "rectangle", "x": 1, "y": 2, "w": 30, "h": 40,
+                           "stroke": "#1d1d1d", "fill": "#e03131", "strokeWidth": 5 },
+                    "e": { "id": "e", "type": "ellipse", "x": 5, "y": 5, "w": 10, "h": 10,
+                           "stroke": "#4465e9", "fill": null, "strokeWidth": 2 },
+                    "a": { "id": "a", "type": "arrow", "a": { "x": 0, "y": 0 }, "b": { "x": 9, "y": 3 },
+                           "stroke": "#099268", "fill": null, "strokeWidth": 10 },
+                    "l": { "id": "l", "type": "line", "a": { "x": 1, "y": 1 }, "b": { "x": 2, "y": 2 },
+                           "stroke": "#1d1d1d", "fill": null, "strokeWidth": 3.5 },
+                    "p": { "id": "p", "type": "pencil", "points": [{ "x": 3, "y": 4 }, { "x": 5, "y": 7 }],
+                           "stroke": "#1d1d1d", "fill": null, "strokeWidth": 2 },
+                    "t": { "id": "t", "type": "text", "x": 10, "y": 50, "text": "Hi", "fontSize": 30,
+                           "stroke": "#1d1d1d", "fill": null, "strokeWidth": 2 }
+                },
+                "order": ["r", "e", "a", "l", "p", "t"]
+            }"##,
+        )
+        .unwrap();
+        let board = Board {
+            pages: vec![page],
+            active_page_id: "page-1".to_string(),
+        };
+
+        let export = export(&board);
+        let r = &export.snapshot["store"]["shape:r"];
+        assert_eq!(r["type"], "geo");
+        assert_eq!(r["props"]["fill"], "solid");
+        assert_eq!(r["index"], "a0");
+        // only the rectangle's fill is off the palette
+        assert_eq!(export.issues.len(), 1);
+        assert_eq!(export.issues[0].id, "r");
+
+        let back = import(&export.snapshot.to_string()).unwrap();
+        assert_eq!(back.board.active_page_id, "page-1");
+        let doc = &back.board.pages[0].doc;
+        assert_eq!(doc.order, board.pages[0].doc.order);
+        for id in ["e", "a", "l", "p"] {
+            assert_eq!(doc.get(id), board.pages[0].doc.get(id), "{id}");
+        }
+        // text comes back spaced the way tldraw draws it
+        let mut t = board.pages[0].doc.get("t").unwrap().clone();
+        if let ShapeKind::Text { line_height, .. } = &mut t.kind {
+            *line_height = Some(LINE_HEIGHT);
+        }
+        assert_eq!(doc.get("t"), Some(&t));
+        assert_eq!(doc.get("r").unwrap().fill.as_deref(), Some("#c7c7c7"));
+    }
+
+    #[test]
+    fn index_keys_sort_in_order() {
+        let keys: Vec<String> = (0..5000).map(index_key).collect();
+        assert_eq!(&keys[..3], ["a0", "a1", "a2"]);
+        assert_eq!(keys[62], "b00");
+        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(import(r#"{ "type": "excalidraw", "elements": [] }"#).is_err());
+        assert!(import(r#"{ "store": {} }"#).is_err());
+    }
+}
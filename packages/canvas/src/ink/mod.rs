@@ -0,0 +1,440 @@
+//! Pen input for pencil strokes. Samples arrive with pressure and a time,
+//! are smoothed by a [`OneEuroFilter`] while the pen moves, and once it lifts
+//! [`finish`] drops redundant points with Ramer–Douglas–Peucker and resamples
+//! the rest along a centripetal Catmull-Rom spline. What gets stored is
+//! still a plain polyline, now with a pressure per point, so renderers that
+//! know nothing of pressure draw it as before. Width follows pressure
+//! through [`width_at`].
+
+use std::f64::consts::PI;
+
+use crate::model::Point;
+
+/// How far pressure moves the width away from the stroke width: 0 keeps it
+/// even, 1 ranges from nothing to twice the stroke width
+pub const THINNING: f64 = 0.5;
+
+/// Pressure of pointers without a sensor, as `PointerEvent.pressure` reports
+/// it for a pressed mouse button
+pub const DEFAULT_PRESSURE: f64 = 0.5;
+
+/// Most points the spline adds between two kept points
+const MAX_SPLINE_STEPS: usize = 16;
+
+/// Width of a stroke at `pressure`, `stroke_width` at [`DEFAULT_PRESSURE`]
+pub fn width_at(stroke_width: f64, pressure: f64) -> f64 {
+    let p = if pressure.is_finite() {
+        pressure.clamp(0.0, 1.0)
+    } else {
+        DEFAULT_PRESSURE
+    };
+    stroke_width.max(0.0) * (1.0 + THINNING * (2.0 * p - 1.0))
+}
+
+/// Width of the widest point, the stroke width for strokes without pressure
+pub fn max_width(stroke_width: f64, pressure: &[f64]) -> f64 {
+    pressure
+        .iter()
+        .map(|p| width_at(stroke_width, *p))
+        .reduce(f64::max)
+        .unwrap_or(stroke_width.max(0.0))
+}
+
+/// Half the width at each of `n` points. A `pressure` list that does not
+/// have one entry per point is ignored and the width stays even.
+pub fn half_widths(stroke_width: f64, pressure: &[f64], n: usize) -> Vec<f64> {
+    if pressure.len() == n {
+        pressure
+            .iter()
+            .map(|p| width_at(stroke_width, *p) / 2.0)
+            .collect()
+    } else {
+        vec![stroke_width.max(0.0) / 2.0; n]
+    }
+}
+
+/// One-euro filter (Casiez et al., CHI 2012) over positions: a low-pass
+/// filter whose cutoff rises with speed, so slow strokes lose their jitter
+/// while fast ones do not lag behind the pen
+#[derive(Debug, Clone, PartialEq)]
+pub struct OneEuroFilter {
+    /// Cutoff in Hz while the pen is still
+    pub min_cutoff: f64,
+    /// Cutoff gained per unit of speed, in Hz per unit per second
+    pub beta: f64,
+    /// Cutoff of the speed estimate itself, in Hz
+    pub derivative_cutoff: f64,
+    /// Time in ms, filtered position and filtered speed of the last sample
+    last: Option<(f64, Point, Point)>,
+}
+
+impl Default for OneEuroFilter {
+    /// Tuned for positions in screen pixels
+    fn default() -> Self {
+        Self::new(2.0, 0.01)
+    }
+}
+
+impl OneEuroFilter {
+    pub fn new(min_cutoff: f64, beta: f64) -> Self {
+        Self {
+            min_cutoff,
+            beta,
+            derivative_cutoff: 1.0,
+            last: None,
+        }
+    }
+
+    /// Filters the sample `p` taken at `time` ms. Repeated or out of order
+    /// times count as one millisecond apart.
+    pub fn filter(&mut self, p: Point, time: f64) -> Point {
+        let Some((last_time, last, last_speed)) = self.last else {
+            self.last = Some((time, p, Point::default()));
+            return p;
+        };
+        let dt = ((time - last_time) / 1000.0).max(1e-3);
+        let raw_speed = Point::new((p.x - last.x) / dt, (p.y - last.y) / dt);
+        let speed = mix(last_speed, raw_speed, smoothing(self.derivative_cutoff, dt));
+        let cutoff = self.min_cutoff + self.beta * speed.x.hypot(speed.y);
+        let out = mix(last, p, smoothing(cutoff, dt));
+        self.last = Some((time, out, speed));
+        out
+    }
+
+    pub fn reset(&mut self) {
+        self.last = None;
+    }
+}
+
+/// Weight of a new sample in an exponential filter with `cutoff` Hz
+fn smoothing(cutoff: f64, dt: f64) -> f64 {
+    let tau = 1.0 / (2.0 * PI * cutoff.max(1e-6));
+    1.0 / (1.0 + tau / dt)
+}
+
+/// Turns a pen stroke into what is stored: the points Ramer–Douglas–Peucker
+/// keeps at `tolerance`, resampled along a spline at most `spacing` apart.
+/// `pressure` is empty or holds one value per point and comes back the same
+/// way. Pressure changes count towards the tolerance by the width they make,
+/// so a stroke pressed harder along a straight run keeps its swell.
+pub fn finish(
+    points: &[Point],
+    pressure: &[f64],
+    stroke_width: f64,
+    tolerance: f64,
+    spacing: f64,
+) -> (Vec<Point>, Vec<f64>) {
+    let pressured = pressure.len() == points.len();
+    let halves = if pressured {
+        half_widths(stroke_width, pressure, points.len())
+    } else {
+        Vec::new()
+    };
+    let kept = simplify(points, &halves, tolerance);
+    let points: Vec<Point> = kept.iter().map(|&i| points[i]).collect();
+    let pressure: Vec<f64> = if pressured {
+        kept.iter().map(|&i| pressure[i]).collect()
+    } else {
+        Vec::new()
+    };
+    catmull_rom(&points, &pressure, spacing)
+}
+
+/// Indices of the points Ramer–Douglas–Peucker keeps at `tolerance`, both
+/// ends included. `widths` is empty or holds one extra coordinate per point,
+/// in the same units, that is simplified along with the position.
+pub fn simplify(points: &[Point], widths: &[f64], tolerance: f64) -> Vec<usize> {
+    let n = points.len();
+    if n < 3 {
+        return (0..n).collect();
+    }
+    let width = |i: usize| widths.get(i).copied().unwrap_or(0.0);
+    let mut keep = vec![false; n];
+    keep[0] = true;
+    keep[n - 1] = true;
+    let mut pending = vec![(0, n - 1)];
+    while let Some((first, last)) = pending.pop() {
+        let (a, b) = (points[first], points[last]);
+        let mut worst = (0.0, first);
+        for (i, p) in points.iter().enumerate().take(last).skip(first + 1) {
+            let t = projection(a, b, *p);
+            let on_chord = mix(a, b, t);
+            let off = (p.x - on_chord.x).hypot(p.y - on_chord.y);
+            let swell = width(i) - (width(first) + (width(last) - width(first)) * t);
+            let error = off.hypot(swell);
+            if error > worst.0 {
+                worst = (error, i);
+            }
+        }
+        if worst.0 > tolerance {
+            keep[worst.1] = true;
+            pending.push((first, worst.1));
+            pending.push((worst.1, last));
+        }
+    }
+    (0..n).filter(|&i| keep[i]).collect()
+}
+
+/// Resamples the polyline along a centripetal Catmull-Rom spline through
+/// its points, adding points until neighbours are at most `spacing` apart.
+/// `values` is empty or holds one value per point, interpolated linearly.
+/// The centripetal form never forms cusps or loops within a segment, which
+/// matters for the tight turns of handwriting.
+pub fn catmull_rom(points: &[Point], values: &[f64], spacing: f64) -> (Vec<Point>, Vec<f64>) {
+    let n = points.len();
+    let valued = values.len() == n;
+    if n < 3 || spacing <= 0.0 || !spacing.is_finite() {
+        return (
+            points.to_vec(),
+            if valued { values.to_vec() } else { Vec::new() },
+        );
+    }
+    // mirrored neighbours let the curve leave the ends in a straight line
+    let mirror = |p: Point, about: Point| Point::new(2.0 * about.x - p.x, 2.0 * about.y - p.y);
+    let mut out = Vec::with_capacity(n);
+    let mut out_values = Vec::new();
+    for i in 0..n - 1 {
+        let p1 = points[i];
+        let p2 = points[i + 1];
+        let p0 = if i == 0 {
+            mirror(p2, p1)
+        } else {
+            points[i - 1]
+        };
+        let p3 = if i + 2 < n {
+            points[i + 2]
+        } else {
+            mirror(p1, p2)
+        };
+        let chord = (p2.x - p1.x).hypot(p2.y - p1.y);
+        let steps = ((chord / spacing).ceil() as usize).clamp(1, MAX_SPLINE_STEPS);
+        for k in 0..steps {
+            let u = k as f64 / steps as f64;
+            out.push(if k == 0 {
+                p1
+            } else {
+                centripetal(p0, p1, p2, p3, u)
+            });
+            if valued {
+                out_values.push(values[i] + (values[i + 1] - values[i]) * u);
+            }
+        }
+    }
+    out.push(points[n - 1]);
+    if valued {
+        out_values.push(values[n - 1]);
+    }
+    (out, out_values)
+}
+
+/// Point `u` of the way from `p1` to `p2` on the centripetal Catmull-Rom
+/// segment, by the Barry–Goldman pyramid
+fn centripetal(p0: Point, p1: Point, p2: Point, p3: Point, u: f64) -> Point {
+    let knot = |a: Point, b: Point| (a.x - b.x).hypot(a.y - b.y).sqrt().max(1e-6);
+    let t0 = 0.0;
+    let t1 = t0 + knot(p0, p1);
+    let t2 = t1 + knot(p1, p2);
+    let t3 = t2 + knot(p2, p3);
+    let t = t1 + (t2 - t1) * u;
+    let lerp = |a: Point, b: Point, ta: f64, tb: f64| mix(a, b, (t - ta) / (tb - ta));
+    let a1 = lerp(p0, p1, t0, t1);
+    let a2 = lerp(p1, p2, t1, t2);
+    let a3 = lerp(p2, p3, t2, t3);
+    let b1 = lerp(a1, a2, t0, t2);
+    let b2 = lerp(a2, a3, t1, t3);
+    lerp(b1, b2, t1, t2)
+}
+
+/// Distance from `p` to the ink of a stroke through `points` with the given
+/// half widths, 0 on it
+pub fn distance(points: &[Point], halves: &[f64], p: Point) -> f64 {
+    let half = |i: usize| halves.get(i).copied().unwrap_or(0.0);
+    let d = match points {
+        [] => f64::INFINITY,
+        [only] => (p.x - only.x).hypot(p.y - only.y) - half(0),
+        _ => points
+            .windows(2)
+            .enumerate()
+            .map(|(i, s)| {
+                let t = projection(s[0], s[1], p);
+                let c = mix(s[0], s[1], t);
+                (p.x - c.x).hypot(p.y - c.y) - (half(i) + (half(i + 1) - half(i)) * t)
+            })
+            .fold(f64::INFINITY, f64::min),
+    };
+    d.max(0.0)
+}
+
+/// Part of the area a stroke inks
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Piece {
+    /// Round end or joint at a point
+    Disc { center: Point, radius: f64 },
+    /// The body between two points, a trapezoid with its corners in the
+    /// direction of increasing angle
+    Quad([Point; 4]),
+}
+
+/// Pieces whose union is the ink of a stroke: a disc at every point and a
+/// trapezoid along every segment. Every piece runs the same way round, so
+/// filling them together with the nonzero rule paints each pixel once even
+/// where they overlap.
+pub fn pieces(points: &[Point], halves: &[f64]) -> Vec<Piece> {
+    let half = |i: usize| halves.get(i).copied().unwrap_or(0.0).max(0.0);
+    let mut out: Vec<Piece> = points
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| half(*i) > 0.0)
+        .map(|(i, p)| Piece::Disc {
+            center: *p,
+            radius: half(i),
+        })
+        .collect();
+    for (i, s) in points.windows(2).enumerate() {
+        let (a, b) = (s[0], s[1]);
+        let length = (b.x - a.x).hypot(b.y - a.y);
+        if length <= 0.0 {
+            continue;
+        }
+        let n = Point::new(-(b.y - a.y) / length, (b.x - a.x) / length);
+        let side = |p: Point, h: f64| Point::new(p.x + n.x * h, p.y + n.y * h);
+        let (ha, hb) = (half(i), half(i + 1));
+        let quad = [side(a, -ha), side(b, -hb), side(b, hb), side(a, ha)];
+        out.push(Piece::Quad(quad));
+    }
+    out
+}
+
+/// Where the projection of `p` falls along `a`–`b`, clamped to the segment
+fn projection(a: Point, b: Point, p: Point) -> f64 {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let len2 = dx * dx + dy * dy;
+    if len2 == 0.0 {
+        return 0.0;
+    }
+    (((p.x - a.x) * dx + (p.y - a.y) * dy) / len2).clamp(0.0, 1.0)
+}
+
+fn mix(a: Point, b: Point, t: f64) -> Point {
+    Point::new(a.x + (b.x - a.x) * t, a.y + (b.y - a.y) * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shoelace(p: &[Point]) -> f64 {
+        (0..p.len())
+            .map(|i| {
+                let (a, b) = (p[i], p[(i + 1) % p.len()]);
+                a.x * b.y - b.x * a.y
+            })
+            .sum::<f64>()
+            / 2.0
+    }
+
+    #[test]
+    fn width_follows_pressure_around_the_stroke_width() {
+        assert_eq!(width_at(4.0, DEFAULT_PRESSURE), 4.0);
+        assert_eq!(width_at(4.0, 0.0), 2.0);
+        assert_eq!(width_at(4.0, 1.0), 6.0);
+        assert_eq!(width_at(4.0, f64::NAN), 4.0);
+        assert_eq!(max_width(4.0, &[0.2, 0.9, 0.5]), width_at(4.0, 0.9));
+        assert_eq!(max_width(4.0, &[]), 4.0);
+        assert_eq!(half_widths(4.0, &[1.0], 2), vec![2.0, 2.0]);
+    }
+
+    #[test]
+    fn one_euro_smooths_jitter_but_follows_fast_moves() {
+        let mut filter = OneEuroFilter::default();
+        let mut out = Point::default();
+        // a pen held still with a pixel of noise, sampled at 120 Hz
+        for i in 0..120 {
+            let jitter = if i % 2 == 0 { 1.0 } else { -1.0 };
+            out = filter.filter(Point::new(100.0 + jitter, 50.0), i as f64 * 8.3);
+        }
+        assert!((out.x - 100.0).abs() < 0.2, "{out:?}");
+
+        // a quick flick keeps up with the pen
+        filter.reset();
+        for i in 0..30 {
+            out = filter.filter(Point::new(i as f64 * 40.0, 0.0), i as f64 * 8.3);
+        }
+        assert!(29.0 * 40.0 - out.x < 40.0, "{out:?}");
+    }
+
+    #[test]
+    fn simplifies_straight_runs_unless_the_width_changes() {
+        let line: Vec<Point> = (0..=10).map(|i| Point::new(i as f64, 0.0)).collect();
+        assert_eq!(simplify(&line, &[], 0.1), vec![0, 10]);
+
+        let mut bent = line.clone();
+        bent[4].y = 1.0;
+        assert_eq!(simplify(&bent, &[], 0.1), vec![0, 3, 4, 5, 10]);
+
+        let swell: Vec<f64> = (0..=10).map(|i| if i == 5 { 3.0 } else { 1.0 }).collect();
+        assert_eq!(simplify(&line, &swell, 0.1), vec![0, 4, 5, 6, 10]);
+    }
+
+    #[test]
+    fn spline_passes_through_the_points_and_rounds_corners() {
+        let corner = [
+            Point::new(0.0, 0.0),
+            Point::new(100.0, 0.0),
+            Point::new(100.0, 100.0),
+        ];
+        let (points, values) = catmull_rom(&corner, &[0.0, 1.0, 0.0], 10.0);
+        assert_eq!(points.len(), 21);
+        assert_eq!(values.len(), points.len());
+        assert!(corner.iter().all(|c| points.contains(c)));
+        assert_eq!(values[10], 1.0);
+        // the curve bulges a little past the corner instead of looping
+        assert!(points.iter().all(|p| distance(&corner, &[], *p) < 10.0));
+        assert!(points
+            .windows(2)
+            .all(|s| (s[1].x - s[0].x).hypot(s[1].y - s[0].y) < 15.0));
+
+        let (short, none) = catmull_rom(&corner[..2], &[], 5.0);
+        assert_eq!((short.len(), none.len()), (2, 0));
+    }
+
+    #[test]
+    fn finishes_a_noisy_stroke_into_fewer_smooth_points() {
+        let raw: Vec<Point> = (0..200)
+            .map(|i| {
+                let t = i as f64 / 199.0 * PI;
+                let noise = if i % 2 == 0 { 0.05 } else { -0.05 };
+                Point::new(t.cos() * 100.0, t.sin() * 100.0 + noise)
+            })
+            .collect();
+        let pressure: Vec<f64> = (0..200).map(|i| i as f64 / 199.0).collect();
+        let (points, kept) = finish(&raw, &pressure, 4.0, 0.25, 8.0);
+        assert!(points.len() < raw.len() / 2, "{}", points.len());
+        assert_eq!(kept.len(), points.len());
+        assert_eq!((points[0], *points.last().unwrap()), (raw[0], raw[199]));
+        for p in &points {
+            assert!((p.x.hypot(p.y) - 100.0).abs() < 0.5, "{p:?}");
+        }
+        assert!(kept.windows(2).all(|w| w[1] >= w[0]));
+    }
+
+    #[test]
+    fn pieces_cover_the_stroke_and_wind_one_way() {
+        let points = [
+            Point::new(0.0, 0.0),
+            Point::new(10.0, 0.0),
+            Point::new(10.0, -10.0),
+        ];
+        let halves = [1.0, 2.0, 3.0];
+        let pieces = pieces(&points, &halves);
+        assert_eq!(pieces.len(), 5);
+        for piece in &pieces {
+            if let Piece::Quad(q) = piece {
+                assert!(shoelace(q) > 0.0);
+            }
+        }
+        assert_eq!(distance(&points, &halves, Point::new(5.0, 1.4)), 0.0);
+        assert!((distance(&points, &halves, Point::new(5.0, 2.5)) - 1.0).abs() < 1e-9);
+        assert!((distance(&points, &halves, Point::new(10.0, -15.0)) - 2.0).abs() < 1e-9);
+    }
+}
@@ -10,12 +10,46 @@ mod types;
 
 mod error;
 
+pub mod config;
+
+pub mod crdt;
+
+pub mod export;
+
+pub mod geometry;
+
+pub mod history;
+
+pub mod import;
+
+pub mod ink;
+
 #[path = "constants/mod.rs"]
 mod constants;
 
+#[cfg(target_arch = "wasm32")]
 mod telemetry;
 
+pub mod model;
+
+pub mod presence;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod relay;
+
+pub mod selection;
+
+pub mod spatial;
+
+pub mod text;
+
+pub mod tools;
+
 pub use crate::adapters::renderer::client::Client;
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::adapters::renderer::headless::HeadlessClient;
+#[cfg(target_arch = "wasm32")]
 pub use crate::adapters::renderer3d::client::create_client_3d as createClient3d;
+#[cfg(target_arch = "wasm32")]
 pub use crate::adapters::renderer3d::client::Client3d;
 pub use crate::types::Size;
@@ -0,0 +1,611 @@
+//! Compact binary encoding of a [`WhiteboardDoc`].
+//!
+//! Layout of schema version 1, all integers little-endian, `varint` being
+//! unsigned LEB128 and `zigzag` a signed varint:
+//!
+//! ```text
+//! magic     b"WBDOC"
+//! version   u16
+//! strings   varint count, then per entry varint length + UTF-8 bytes
+//! shapes    varint count, then per shape, bottom first:
+//!   id            varint length + UTF-8 bytes
+//!   type          u8, see `Tag`
+//!   stroke        varint index into `strings`
+//!   fill          varint, 0 for null, otherwise index + 1
+//!   strokeWidth   f64
+//!   payload       per type; pencil points are a varint count followed by
+//!                 zigzag deltas of the coordinates in 1/100 world units,
+//!                 then a varint count of pressures, none or one per point,
+//!                 each a byte from 0 to 255 for full pressure; text is
+//!                 x, y, the string and the font size, then the alignment
+//!                 as a u8 (0 for none, then left, center and right) and
+//!                 the wrapping width and line height as optional `f64`s,
+//!                 each a u8 of 0 for none or 1 followed by the value
+//! ```
+//!
+//! Every other number is kept as an exact `f64`, so only pencil points lose precision, by at most half a
+//! hundredth of a unit, and pressures by half a step. Shapes missing from
+//! `order` are written after the ordered ones and ids in `order` without a
+//! shape are dropped, as the renderers ignore both anyway.
+//!
+//! Readers also accept the JSON the web app stores today, so saved boards
+//! migrate on load.
+
+use std::collections::{HashMap, HashSet};
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::*;
+
+use crate::error::CanvasError;
+use crate::model::{Point, Shape, ShapeKind, WhiteboardDoc};
+use crate::text::layout::TextAlign;
+
+pub const MAGIC: &[u8; 5] = b"WBDOC";
+
+/// Version written by [`encode`]
+pub const SCHEMA_VERSION: u16 = 1;
+
+/// Pencil points are stored in multiples of `1 / POINT_SCALE` world units
+const POINT_SCALE: f64 = 100.0;
+
+/// Pressures are stored in multiples of `1 / PRESSURE_SCALE`
+const PRESSURE_SCALE: f64 = 255.0;
+
+/// Encodes a `WhiteboardDoc` given as JSON
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen(js_name = "encodeDoc")]
+pub fn encode_doc(doc_json: &str) -> Result<Vec<u8>, JsValue> {
+    Ok(encode(&WhiteboardDoc::from_json(doc_json)?))
+}
+
+/// Decodes the binary format, or legacy JSON, back to `WhiteboardDoc` JSON
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen(js_name = "decodeDoc")]
+pub fn decode_doc(bytes: &[u8]) -> Result<String, JsValue> {
+    Ok(decode(bytes)?.to_json()?)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum Tag {
+    Pencil = 0,
+    Line = 1,
+    Rectangle = 2,
+    Ellipse = 3,
+    Arrow = 4,
+    Text = 5,
+}
+
+impl Tag {
+    fn of(kind: &ShapeKind) -> Self {
+        match kind {
+            ShapeKind::Pencil { .. } => Tag::Pencil,
+            ShapeKind::Line { .. } => Tag::Line,
+            ShapeKind::Rectangle { .. } => Tag::Rectangle,
+            ShapeKind::Ellipse { .. } => Tag::Ellipse,
+            ShapeKind::Arrow { .. } => Tag::Arrow,
+            ShapeKind::Text { .. } => Tag::Text,
+        }
+    }
+
+    fn from_u8(tag: u8) -> Option<Self> {
+        Some(match tag {
+            0 => Tag::Pencil,
+            1 => Tag::Line,
+            2 => Tag::Rectangle,
+            3 => Tag::Ellipse,
+            4 => Tag::Arrow,
+            5 => Tag::Text,
+            _ => return None,
+        })
+    }
+}
+
+/// Encodes `doc` in the current [`SCHEMA_VERSION`]
+pub fn encode(doc: &WhiteboardDoc) -> Vec<u8> {
+    let mut shapes: Vec<&Shape> = doc.ordered_shapes().collect();
+    let mut seen = HashSet::new();
+    shapes.retain(|s| seen.insert(s.id.as_str()));
+    shapes.extend(
+        doc.shapes
+            .values()
+            .filter(|s| !seen.contains(s.id.as_str())),
+    );
+
+    let mut strings: Vec<&str> = Vec::new();
+    let mut index_of = HashMap::new();
+    for shape in &shapes {
+        for colour in std::iter::once(shape.stroke.as_str()).chain(shape.fill.as_deref()) {
+            index_of.entry(colour).or_insert_with(|| {
+                strings.push(colour);
+                strings.len() - 1
+            });
+        }
+    }
+
+    let mut w = Writer::default();
+    w.bytes(MAGIC);
+    w.bytes(&SCHEMA_VERSION.to_le_bytes());
+    w.varint(strings.len() as u64);
+    for s in &strings {
+        w.string(s);
+    }
+    w.varint(shapes.len() as u64);
+    for shape in shapes {
+        w.string(&shape.id);
+        w.bytes(&[Tag::of(&shape.kind) as u8]);
+        w.varint(index_of[shape.stroke.as_str()] as u64);
+        w.varint(match &shape.fill {
+            Some(fill) => index_of[fill.as_str()] as u64 + 1,
+            None => 0,
+        });
+        w.f64(shape.stroke_width);
+        match &shape.kind {
+            ShapeKind::Pencil { points, pressure } => {
+                w.varint(points.len() as u64);
+                let mut prev = (0, 0);
+                for p in points {
+                    let q = (quantise(p.x), quantise(p.y));
+                    // far-off points saturate; the reader wraps back
+                    w.zigzag(q.0.wrapping_sub(prev.0));
+                    w.zigzag(q.1.wrapping_sub(prev.1));
+                    prev = q;
+                }
+                let pressure = if pressure.len() == points.len() {
+                    pressure.as_slice()
+                } else {
+                    &[]
+                };
+                w.varint(pressure.len() as u64);
+                for p in pressure {
+                    let p = if p.is_finite() {
+                        p.clamp(0.0, 1.0)
+                    } else {
+                        crate::ink::DEFAULT_PRESSURE
+                    };
+                    w.bytes(&[(p * PRESSURE_SCALE).round() as u8]);
+                }
+            }
+            ShapeKind::Line { a, b } | ShapeKind::Arrow { a, b } => {
+                w.point(*a);
+                w.point(*b);
+            }
+            ShapeKind::Rectangle { x, y, w: width, h }
+            | ShapeKind::Ellipse { x, y, w: width, h } => {
+                for v in [x, y, width, h] {
+                    w.f64(*v);
+                }
+            }
+            ShapeKind::Text {
+                x,
+                y,
+                text,
+                font_size,
+                align,
+                max_width,
+                line_height,
+            } => {
+                w.f64(*x);
+                w.f64(*y);
+                w.string(text);
+                w.f64(*font_size);
+                w.bytes(&[match align {
+                    None => 0,
+                    Some(TextAlign::Left) => 1,
+                    Some(TextAlign::Center) => 2,
+                    Some(TextAlign::Right) => 3,
+                }]);
+                w.optional_f64(*max_width);
+                w.optional_f64(*line_height);
+            }
+        }
+    }
+    w.out
+}
+
+/// Decodes bytes of the current [`SCHEMA_VERSION`], or a JSON
+/// document as saved before the binary format existed
+pub fn decode(bytes: &[u8]) -> Result<WhiteboardDoc, CanvasError> {
+    if bytes.trim_ascii_start().starts_with(b"{") {
+        let json = std::str::from_utf8(bytes).map_err(|e| CanvasError::Decode(e.to_string()))?;
+        return WhiteboardDoc::from_json(json);
+    }
+    let mut r = Reader { bytes, pos: 0 };
+    if r.take(MAGIC.len())? != MAGIC {
+        return Err(CanvasError::Decode("not a whiteboard document".to_string()));
+    }
+    let version = u16::from_le_bytes(r.array()?);
+    let doc = match version {
+        1 => decode_shapes(&mut r)?,
+        v if v > SCHEMA_VERSION => {
+            return Err(CanvasError::Decode(format!(
+                "schema version {v} is newer than {SCHEMA_VERSION}, update the app"
+            )))
+        }
+        v => return Err(CanvasError::Decode(format!("unknown schema version {v}"))),
+    };
+    if r.pos != bytes.len() {
+        return Err(CanvasError::Decode(format!(
+            "{} trailing bytes",
+            bytes.len() - r.pos
+        )));
+    }
+    Ok(doc)
+}
+
+fn decode_shapes(r: &mut Reader) -> Result<WhiteboardDoc, CanvasError> {
+    let strings = (0..r.len()?)
+        .map(|_| r.string())
+        .collect::<Result<Vec<_>, _>>()?;
+    let string = |i: u64| {
+        strings
+            .get(i as usize)
+            .cloned()
+            .ok_or_else(|| CanvasError::Decode(format!("string {i} out of range")))
+    };
+
+    let mut doc = WhiteboardDoc::new();
+    for _ in 0..r.len()? {
+        let id = r.string()?;
+        let tag = r.array::<1>()?[0];
+        let tag = Tag::from_u8(tag)
+            .ok_or_else(|| CanvasError::Decode(format!("unknown shape type {tag}")))?;
+        let stroke = string(r.varint()?)?;
+        let fill = match r.varint()? {
+            0 => None,
+            i => Some(string(i - 1)?),
+        };
+        let stroke_width = r.f64()?;
+        let kind = match tag {
+            Tag::Pencil => {
+                let n = r.len()?;
+                let mut points = Vec::with_capacity(n);
+                let mut q = (0i64, 0i64);
+                for _ in 0..n {
+                    q.0 = q.0.wrapping_add(r.zigzag()?);
+                    q.1 = q.1.wrapping_add(r.zigzag()?);
+                    points.push(Point::new(
+                        q.0 as f64 / POINT_SCALE,
+                        q.1 as f64 / POINT_SCALE,
+                    ));
+                }
+                let count = r.len()?;
+                if count != 0 && count != n {
+                    return Err(CanvasError::Decode(format!(
+                        "{count} pressures for {n} points"
+                    )));
+                }
+                let pressure = r
+                    .take(count)?
+                    .iter()
+                    .map(|b| *b as f64 / PRESSURE_SCALE)
+                    .collect();
+                ShapeKind::Pencil { points, pressure }
+            }
+            Tag::Line => ShapeKind::Line {
+                a: r.point()?,
+                b: r.point()?,
+            },
+            Tag::Arrow => ShapeKind::Arrow {
+                a: r.point()?,
+                b: r.point()?,
+            },
+            Tag::Rectangle => ShapeKind::Rectangle {
+                x: r.f64()?,
+                y: r.f64()?,
+                w: r.f64()?,
+                h: r.f64()?,
+            },
+            Tag::Ellipse => ShapeKind::Ellipse {
+                x: r.f64()?,
+                y: r.f64()?,
+                w: r.f64()?,
+                h: r.f64()?,
+            },
+            Tag::Text => {
+                let (x, y, text, font_size) = (r.f64()?, r.f64()?, r.string()?, r.f64()?);
+                let align = match r.array::<1>()?[0] {
+                    0 => None,
+                    1 => Some(TextAlign::Left),
+                    2 => Some(TextAlign::Center),
+                    3 => Some(TextAlign::Right),
+                    a => return Err(CanvasError::Decode(format!("unknown alignment {a}"))),
+                };
+                ShapeKind::Text {
+                    x,
+                    y,
+                    text,
+                    font_size,
+                    align,
+                    max_width: r.optional_f64()?,
+                    line_height: r.optional_f64()?,
+                }
+            }
+        };
+        doc.add_shape(Shape {
+            id,
+            stroke,
+            fill,
+            stroke_width,
+            kind,
+        });
+    }
+    Ok(doc)
+}
+
+fn quantise(v: f64) -> i64 {
+    (v * POINT_SCALE).round() as i64
+}
+
+/// Byte writer shared with the other binary formats of the crate
+#[derive(Debug, Default)]
+pub(crate) struct Writer {
+    pub(crate) out: Vec<u8>,
+}
+
+impl Writer {
+    pub(crate) fn bytes(&mut self, bytes: &[u8]) {
+        self.out.extend_from_slice(bytes);
+    }
+
+    pub(crate) fn varint(&mut self, mut v: u64) {
+        while v >= 0x80 {
+            self.out.push(v as u8 | 0x80);
+            v >>= 7;
+        }
+        self.out.push(v as u8);
+    }
+
+    pub(crate) fn zigzag(&mut self, v: i64) {
+        self.varint(((v << 1) ^ (v >> 63)) as u64);
+    }
+
+    pub(crate) fn f64(&mut self, v: f64) {
+        self.bytes(&v.to_le_bytes());
+    }
+
+    pub(crate) fn f32(&mut self, v: f32) {
+        self.bytes(&v.to_le_bytes());
+    }
+
+    pub(crate) fn point(&mut self, p: Point) {
+        self.f64(p.x);
+        self.f64(p.y);
+    }
+
+    /// A u8 of 0 for `None`, or 1 and the value
+    pub(crate) fn optional_f64(&mut self, v: Option<f64>) {
+        match v {
+            Some(v) => {
+                self.bytes(&[1]);
+                self.f64(v);
+            }
+            None => self.bytes(&[0]),
+        }
+    }
+
+    pub(crate) fn string(&mut self, s: &str) {
+        self.varint(s.len() as u64);
+        self.bytes(s.as_bytes());
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Reader<'a> {
+    pub(crate) bytes: &'a [u8],
+    pub(crate) pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn take(&mut self, n: usize) -> Result<&'a [u8], CanvasError> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| CanvasError::Decode(format!("truncated at byte {}", self.pos)))?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    pub(crate) fn array<const N: usize>(&mut self) -> Result<[u8; N], CanvasError> {
+        let mut out = [0; N];
+        out.copy_from_slice(self.take(N)?);
+        Ok(out)
+    }
+
+    pub(crate) fn varint(&mut self) -> Result<u64, CanvasError> {
+        let mut v = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.array::<1>()?[0];
+            v |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(v);
+            }
+        }
+        Err(CanvasError::Decode(format!(
+            "varint too long at byte {}",
+            self.pos
+        )))
+    }
+
+    /// A count of items that each take at least one byte, so corrupt input
+    /// cannot ask for huge allocations
+    pub(crate) fn len(&mut self) -> Result<usize, CanvasError> {
+        let n = self.varint()?;
+        if n > (self.bytes.len() - self.pos) as u64 {
+            return Err(CanvasError::Decode(format!(
+                "count {n} exceeds the remaining input"
+            )));
+        }
+        Ok(n as usize)
+    }
+
+    pub(crate) fn zigzag(&mut self) -> Result<i64, CanvasError> {
+        let v = self.varint()?;
+        Ok((v >> 1) as i64 ^ -((v & 1) as i64))
+    }
+
+    pub(crate) fn f64(&mut self) -> Result<f64, CanvasError> {
+        Ok(f64::from_le_bytes(self.array()?))
+    }
+
+    pub(crate) fn f32(&mut self) -> Result<f32, CanvasError> {
+        Ok(f32::from_le_bytes(self.array()?))
+    }
+
+    pub(crate) fn point(&mut self) -> Result<Point, CanvasError> {
+        Ok(Point::new(self.f64()?, self.f64()?))
+    }
+
+    pub(crate) fn optional_f64(&mut self) -> Result<Option<f64>, CanvasError> {
+        match self.array::<1>()?[0] {
+            0 => Ok(None),
+            1 => Ok(Some(self.f64()?)),
+            b => Err(CanvasError::Decode(format!("bad option tag {b}"))),
+        }
+    }
+
+    pub(crate) fn string(&mut self) -> Result<String, CanvasError> {
+        let n = self.len()?;
+        let bytes = self.take(n)?;
+        String::from_utf8(bytes.to_vec()).map_err(|e| CanvasError::Decode(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOC_JSON: &str = r##"{
+        "shapes": {
+            "p": { "id": "p", "type": "pencil", "stroke": "#111827", "fill": null, "strokeWidth": 2,
+                   "points": [{ "x": 10.25, "y": -3.5 }, { "x": 12, "y": -1 }, { "x": 11.5, "y": 4 }] },
+            "l": { "id": "l", "type": "line", "a": { "x": 0.1, "y": 0.2 }, "b": { "x": 3, "y": 4 },
+                   "stroke": "#111827", "fill": null, "strokeWidth": 1.5 },
+            "r": { "id": "r", "type": "rectangle", "x": 1.23456, "y": 2, "w": -30, "h": 20,
+                   "stroke": "#111827", "fill": "#3b82f6", "strokeWidth": 2 },
+            "e": { "id": "e", "type": "ellipse", "x": 5, "y": 6, "w": 7, "h": 8,
+                   "stroke": "#ef4444", "fill": "#3b82f6", "strokeWidth": 3 },
+            "a": { "id": "a", "type": "arrow", "a": { "x": 1, "y": 2 }, "b": { "x": 3, "y": 4 },
+                   "stroke": "#ef4444", "fill": null, "strokeWidth": 2 },
+            "t": { "id": "t", "type": "text", "x": 9, "y": 10, "text": "สวัสดี ✏️",
+                   "stroke": "#111827", "fill": null, "strokeWidth": 2, "fontSize": 24 },
+            "w": { "id": "w", "type": "text", "x": 9, "y": 40, "text": "wrapped",
+                   "stroke": "#111827", "fill": null, "strokeWidth": 2, "fontSize": 16,
+                   "align": "center", "maxWidth": 120, "lineHeight": 1.5 }
+        },
+        "order": ["t", "p", "l", "r", "e", "a", "w"]
+    }"##;
+
+    #[test]
+    fn round_trips_every_shape_type() {
+        let doc = WhiteboardDoc::from_json(DOC_JSON).unwrap();
+        let bytes = encode(&doc);
+        assert!(bytes.starts_with(b"WBDOC\x01\x00"));
+        assert_eq!(decode(&bytes).unwrap(), doc);
+        // three distinct colours, stored once each
+        assert_eq!(bytes.windows(7).filter(|w| w == b"#111827").count(), 1);
+    }
+
+    #[test]
+    fn pencil_points_are_quantised_and_small() {
+        let points: Vec<Point> = (0..1000)
+            .map(|i| {
+                let t = i as f64 * 0.05;
+                Point::new(100.0 + t.cos() * 40.123_456, 80.0 + t.sin() * 30.987_654)
+            })
+            .collect();
+        let pressure: Vec<f64> = (0..1000).map(|i| (i % 100) as f64 / 99.0).collect();
+        let mut doc = WhiteboardDoc::new();
+        doc.add_shape(Shape {
+            id: "p".to_string(),
+            stroke: "#111827".to_string(),
+            fill: None,
+            stroke_width: 2.0,
+            kind: ShapeKind::Pencil {
+                points: points.clone(),
+                pressure: pressure.clone(),
+            },
+        });
+        let bytes = encode(&doc);
+        assert!(bytes.len() * 10 < doc.to_json().unwrap().len());
+
+        let ShapeKind::Pencil {
+            points: decoded,
+            pressure: pressed,
+        } = &decode(&bytes).unwrap().shapes["p"].kind
+        else {
+            panic!("not a pencil");
+        };
+        for (a, b) in points.iter().zip(decoded) {
+            assert!((a.x - b.x).abs() <= 0.005 && (a.y - b.y).abs() <= 0.005);
+        }
+        assert_eq!(pressed.len(), pressure.len());
+        for (a, b) in pressure.iter().zip(pressed) {
+            assert!((a - b).abs() <= 0.5 / PRESSURE_SCALE);
+        }
+    }
+
+    #[test]
+    fn saturated_pencil_points_round_trip() {
+        let points = vec![
+            Point::new(-1e300, 1e300),
+            Point::new(1e300, f64::NEG_INFINITY),
+            Point::new(f64::NAN, 0.0),
+        ];
+        let mut doc = WhiteboardDoc::new();
+        doc.add_shape(Shape {
+            id: "p".to_string(),
+            stroke: "#111827".to_string(),
+            fill: None,
+            stroke_width: 2.0,
+            kind: ShapeKind::Pencil {
+                points,
+                pressure: Vec::new(),
+            },
+        });
+        let ShapeKind::Pencil { points, .. } = &decode(&encode(&doc)).unwrap().shapes["p"].kind
+        else {
+            panic!("not a pencil");
+        };
+        let (min, max) = (i64::MIN as f64 / POINT_SCALE, i64::MAX as f64 / POINT_SCALE);
+        assert_eq!(
+            points,
+            &[
+                Point::new(min, max),
+                Point::new(max, min),
+                Point::new(0.0, 0.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn migrates_json_and_normalises_order() {
+        let json = r##"{ "shapes": { "a": { "id": "a", "type": "line", "a": { "x": 0, "y": 0 },
+            "b": { "x": 1, "y": 1 }, "stroke": "#000", "fill": null, "strokeWidth": 1 },
+            "b": { "id": "b", "type": "line", "a": { "x": 0, "y": 0 },
+            "b": { "x": 2, "y": 2 }, "stroke": "#000", "fill": null, "strokeWidth": 1 } },
+            "order": ["gone", "b"] }"##;
+        let legacy = decode(json.as_bytes()).unwrap();
+        assert_eq!(legacy.order, ["gone", "b"]);
+        let doc = decode(&encode(&legacy)).unwrap();
+        assert_eq!(doc.order, ["b", "a"]);
+        assert_eq!(doc.shapes, legacy.shapes);
+    }
+
+    #[test]
+    fn rejects_corrupt_input() {
+        let bytes = encode(&WhiteboardDoc::from_json(DOC_JSON).unwrap());
+        for end in [0, 3, 7, bytes.len() / 2, bytes.len() - 1] {
+            assert!(decode(&bytes[..end]).is_err(), "prefix of {end} bytes");
+        }
+        let mut newer = bytes.clone();
+        newer[5] = 2;
+        let err = decode(&newer).unwrap_err().to_string();
+        assert!(err.contains("schema version 2"), "{err}");
+        let mut trailing = bytes;
+        trailing.push(0);
+        assert!(decode(&trailing).is_err());
+    }
+}
@@ -0,0 +1,141 @@
+/// Straight-alpha RGBA colour in `0.0..=1.0`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rgba {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+impl Rgba {
+    pub const TRANSPARENT: Rgba = Rgba::new(0.0, 0.0, 0.0, 0.0);
+    pub const BLACK: Rgba = Rgba::new(0.0, 0.0, 0.0, 1.0);
+    pub const WHITE: Rgba = Rgba::new(1.0, 1.0, 1.0, 1.0);
+
+    pub const fn new(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Self { r, g, b, a }
+    }
+
+    /// Parses the CSS colours the web app produces: `#rgb`, `#rrggbb`,
+    /// `#rrggbbaa`, `rgb(..)`, `rgba(..)` and `transparent`.
+    pub fn parse(css: &str) -> Option<Rgba> {
+        let css = css.trim();
+        if css.eq_ignore_ascii_case("transparent") {
+            return Some(Rgba::TRANSPARENT);
+        }
+        if let Some(hex) = css.strip_prefix('#') {
+            return parse_hex(hex);
+        }
+        let lower = css.to_ascii_lowercase();
+        let args = lower
+            .strip_prefix("rgba(")
+            .or_else(|| lower.strip_prefix("rgb("))?
+            .strip_suffix(')')?;
+        let parts: Vec<&str> = args.split(',').map(str::trim).collect();
+        if parts.len() != 3 && parts.len() != 4 {
+            return None;
+        }
+        let channel = |s: &str| s.parse::<f32>().ok().map(|v| (v / 255.0).clamp(0.0, 1.0));
+        let a = match parts.get(3) {
+            Some(s) => s.parse::<f32>().ok()?.clamp(0.0, 1.0),
+            None => 1.0,
+        };
+        Some(Rgba::new(
+            channel(parts[0])?,
+            channel(parts[1])?,
+            channel(parts[2])?,
+            a,
+        ))
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.a > 0.0
+    }
+
+    pub fn to_array(self) -> [f32; 4] {
+        [self.r, self.g, self.b, self.a]
+    }
+
+    /// sRGB-encoded channel to linear, for writing into `*Srgb` targets
+    pub fn to_linear(self) -> Rgba {
+        fn lin(c: f32) -> f32 {
+            if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        }
+        Rgba::new(lin(self.r), lin(self.g), lin(self.b), self.a)
+    }
+
+    /// `#rrggbb`, or `#rrggbbaa` when not opaque
+    pub fn to_hex(self) -> String {
+        let [r, g, b, a] = self.to_rgba8();
+        if a == 255 {
+            format!("#{r:02x}{g:02x}{b:02x}")
+        } else {
+            format!("#{r:02x}{g:02x}{b:02x}{a:02x}")
+        }
+    }
+
+    pub fn to_rgba8(self) -> [u8; 4] {
+        self.to_array()
+            .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+    }
+
+    pub fn from_rgba8([r, g, b, a]: [u8; 4]) -> Rgba {
+        Rgba::new(
+            r as f32 / 255.0,
+            g as f32 / 255.0,
+            b as f32 / 255.0,
+            a as f32 / 255.0,
+        )
+    }
+
+    /// Inverse of [`Rgba::to_linear`]
+    pub fn to_srgb(self) -> Rgba {
+        fn enc(c: f32) -> f32 {
+            if c <= 0.0031308 {
+                c * 12.92
+            } else {
+                1.055 * c.powf(1.0 / 2.4) - 0.055
+            }
+        }
+        Rgba::new(enc(self.r), enc(self.g), enc(self.b), self.a)
+    }
+}
+
+fn parse_hex(hex: &str) -> Option<Rgba> {
+    let digit = |i: usize| u8::from_str_radix(hex.get(i..i + 1)?, 16).ok();
+    let byte = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    let (r, g, b, a) = match hex.len() {
+        3 => (digit(0)? * 17, digit(1)? * 17, digit(2)? * 17, 255),
+        6 => (byte(0)?, byte(2)?, byte(4)?, 255),
+        8 => (byte(0)?, byte(2)?, byte(4)?, byte(6)?),
+        _ => return None,
+    };
+    Some(Rgba::new(
+        r as f32 / 255.0,
+        g as f32 / 255.0,
+        b as f32 / 255.0,
+        a as f32 / 255.0,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_app_colours() {
+        assert_eq!(Rgba::parse("#ffffff"), Some(Rgba::new(1.0, 1.0, 1.0, 1.0)));
+        assert_eq!(Rgba::parse("#f00"), Some(Rgba::new(1.0, 0.0, 0.0, 1.0)));
+        assert_eq!(
+            Rgba::parse("rgba(0,0,0,0.06)"),
+            Some(Rgba::new(0.0, 0.0, 0.0, 0.06))
+        );
+        assert_eq!(Rgba::parse("transparent"), Some(Rgba::TRANSPARENT));
+        assert_eq!(Rgba::parse("#12345"), None);
+        assert_eq!(Rgba::parse("tomato"), None);
+    }
+}
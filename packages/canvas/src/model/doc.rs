@@ -0,0 +1,210 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::CanvasError;
+use crate::geometry::{self, text::TextMetrics};
+use crate::model::shape::{Rect, Shape, ShapeId};
+
+/// Shapes keyed by id plus their z-order, bottom first
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct WhiteboardDoc {
+    pub shapes: BTreeMap<ShapeId, Shape>,
+    pub order: Vec<ShapeId>,
+}
+
+impl WhiteboardDoc {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, CanvasError> {
+        serde_json::from_str(json).map_err(|e| CanvasError::InvalidDocument(e.to_string()))
+    }
+
+    pub fn to_json(&self) -> Result<String, CanvasError> {
+        serde_json::to_string(self).map_err(|e| CanvasError::InvalidDocument(e.to_string()))
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Shape> {
+        self.shapes.get(id)
+    }
+
+    pub fn get_mut(&mut self, id: &str) -> Option<&mut Shape> {
+        self.shapes.get_mut(id)
+    }
+
+    /// Inserts a new shape on top, or replaces an existing one in place
+    pub fn add_shape(&mut self, shape: Shape) {
+        if !self.shapes.contains_key(&shape.id) {
+            self.order.push(shape.id.clone());
+        }
+        self.shapes.insert(shape.id.clone(), shape);
+    }
+
+    /// Inserts a new shape at `index` in the z-order (clamped to the top), or
+    /// replaces an existing one and moves it there
+    pub fn insert_shape_at(&mut self, index: usize, shape: Shape) {
+        self.order.retain(|x| *x != shape.id);
+        self.order
+            .insert(index.min(self.order.len()), shape.id.clone());
+        self.shapes.insert(shape.id.clone(), shape);
+    }
+
+    pub fn remove_shape(&mut self, id: &str) -> Option<Shape> {
+        self.order.retain(|x| x != id);
+        self.shapes.remove(id)
+    }
+
+    /// Replaces the z-order. Ids that do not exist are dropped and shapes
+    /// missing from `ids` keep their relative order on top.
+    pub fn reorder(&mut self, ids: &[ShapeId]) {
+        let mut next: Vec<ShapeId> = Vec::with_capacity(self.order.len());
+        for id in ids {
+            if self.shapes.contains_key(id) && !next.contains(id) {
+                next.push(id.clone());
+            }
+        }
+        for id in &self.order {
+            if !next.contains(id) {
+                next.push(id.clone());
+            }
+        }
+        self.order = next;
+    }
+
+    /// Shapes in z-order, skipping ids with no shape like the TS renderer does
+    pub fn ordered_shapes(&self) -> impl Iterator<Item = &Shape> {
+        self.order.iter().filter_map(|id| self.shapes.get(id))
+    }
+
+    /// Union of the shapes' [`geometry::painted_bounds`] with text measured
+    /// by `metrics`, `None` when empty
+    pub fn content_bounds(&self, metrics: &dyn TextMetrics) -> Option<Rect> {
+        self.ordered_shapes()
+            .map(|shape| geometry::painted_bounds(shape, metrics))
+            .reduce(|a, b| a.union(&b))
+    }
+
+    pub fn len(&self) -> usize {
+        self.shapes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shapes.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::text::SansMetrics;
+    use crate::model::shape::{Point, ShapeKind};
+
+    const DOC_JSON: &str = r##"{
+        "shapes": {
+            "a": { "id": "a", "type": "rectangle", "x": 1, "y": 2, "w": 30, "h": 40,
+                   "stroke": "#111827", "fill": "#3b82f6", "strokeWidth": 2 },
+            "b": { "id": "b", "type": "pencil", "points": [{ "x": 0, "y": 0 }, { "x": 5, "y": 5 }],
+                   "stroke": "#111827", "fill": null, "strokeWidth": 3 },
+            "c": { "id": "c", "type": "text", "x": 10, "y": 20, "text": "สวัสดี", "fontSize": 24,
+                   "stroke": "#111827", "fill": null, "strokeWidth": 1 }
+        },
+        "order": ["b", "a", "c"]
+    }"##;
+
+    #[test]
+    fn parses_store_json() {
+        let doc = WhiteboardDoc::from_json(DOC_JSON).unwrap();
+        assert_eq!(doc.len(), 3);
+        let ids: Vec<&str> = doc.ordered_shapes().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, ["b", "a", "c"]);
+        assert_eq!(doc.get("b").unwrap().fill, None);
+        assert!(matches!(
+            doc.get("c").unwrap().kind,
+            ShapeKind::Text { font_size, .. } if font_size == 24.0
+        ));
+    }
+
+    #[test]
+    fn json_round_trip_keeps_field_names() {
+        let doc = WhiteboardDoc::from_json(DOC_JSON).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&doc.to_json().unwrap()).unwrap();
+        assert_eq!(value["shapes"]["a"]["type"], "rectangle");
+        assert_eq!(value["shapes"]["a"]["strokeWidth"], 2.0);
+        assert_eq!(value["shapes"]["c"]["fontSize"], 24.0);
+        assert_eq!(
+            WhiteboardDoc::from_json(&doc.to_json().unwrap()).unwrap(),
+            doc
+        );
+    }
+
+    #[test]
+    fn content_bounds_cover_strokes() {
+        let doc = WhiteboardDoc::from_json(DOC_JSON).unwrap();
+        let bounds = doc.content_bounds(&SansMetrics).unwrap();
+        // pencil stroke on the left, text above and to the right, rectangle below
+        let top = 20.0 - SansMetrics.ascent() * 24.0;
+        let right = 10.0 + SansMetrics.advance("สวัสดี", 24.0);
+        let expected = Rect::new(-1.5, top, right + 1.5, 43.0 - top);
+        for (a, b) in [
+            (bounds.x, expected.x),
+            (bounds.y, expected.y),
+            (bounds.w, expected.w),
+            (bounds.h, expected.h),
+        ] {
+            assert!((a - b).abs() < 1e-9, "{bounds:?}");
+        }
+        assert_eq!(WhiteboardDoc::new().content_bounds(&SansMetrics), None);
+    }
+
+    #[test]
+    fn content_bounds_cover_every_line_of_text() {
+        let doc = WhiteboardDoc::from_json(
+            r##"{
+                "shapes": {
+                    "t": { "id": "t", "type": "text", "x": 100, "y": 50, "text": "WWWW WWWW\nW",
+                           "fontSize": 10, "align": "center", "maxWidth": 50,
+                           "stroke": "#111827", "fill": null, "strokeWidth": 1 }
+                },
+                "order": ["t"]
+            }"##,
+        )
+        .unwrap();
+        let bounds = doc.content_bounds(&SansMetrics).unwrap();
+        // three lines, the widest "WWWW" centred on x = 100
+        let expected = Rect::new(81.12, 40.95, 37.76, 2.0 * 12.0 + 11.17);
+        for (a, b) in [
+            (bounds.x, expected.x),
+            (bounds.y, expected.y),
+            (bounds.w, expected.w),
+            (bounds.h, expected.h),
+        ] {
+            assert!((a - b).abs() < 1e-9, "{bounds:?}");
+        }
+        assert_eq!(doc.get("t").unwrap().bounds(), bounds);
+    }
+
+    #[test]
+    fn add_remove_and_reorder() {
+        let mut doc = WhiteboardDoc::new();
+        for id in ["a", "b", "c"] {
+            doc.add_shape(Shape {
+                id: id.to_string(),
+                stroke: "#000000".to_string(),
+                fill: None,
+                stroke_width: 1.0,
+                kind: ShapeKind::Line {
+                    a: Point::new(0.0, 0.0),
+                    b: Point::new(1.0, 1.0),
+                },
+            });
+        }
+        doc.reorder(&["c".to_string(), "missing".to_string(), "a".to_string()]);
+        assert_eq!(doc.order, ["c", "a", "b"]);
+
+        assert!(doc.remove_shape("a").is_some());
+        assert_eq!(doc.order, ["c", "b"]);
+        assert!(doc.get("a").is_none());
+    }
+}
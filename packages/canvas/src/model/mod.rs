@@ -0,0 +1,15 @@
+//! Whiteboard document model.
+//!
+//! Rust mirror of `shared/types/whiteboard.ts`. Every type serialises to the
+//! same JSON the Nuxt store produces, so a `JSON.stringify(state.doc)` can be
+//! handed to the engine unchanged.
+
+pub mod binary;
+pub mod color;
+pub mod doc;
+pub mod page;
+pub mod shape;
+
+pub use doc::WhiteboardDoc;
+pub use page::{Board, WhiteboardPage};
+pub use shape::{Camera, Point, Rect, Shape, ShapeId, ShapeKind};
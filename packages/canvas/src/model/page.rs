@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::CanvasError;
+use crate::model::doc::WhiteboardDoc;
+use crate::model::shape::Camera;
+
+/// One page of a board, as kept in `state.pages` by the pinia store
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct WhiteboardPage {
+    pub id: String,
+    pub name: String,
+    pub doc: WhiteboardDoc,
+    pub camera: Camera,
+}
+
+impl WhiteboardPage {
+    pub fn new(id: impl Into<String>, name: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            name: name.into(),
+            doc: WhiteboardDoc::default(),
+            camera: Camera::default(),
+        }
+    }
+}
+
+/// All pages of a board plus the one currently shown
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Board {
+    pub pages: Vec<WhiteboardPage>,
+    pub active_page_id: String,
+}
+
+impl Default for Board {
+    fn default() -> Self {
+        Self {
+            pages: vec![WhiteboardPage::new("page-1", "Page 1")],
+            active_page_id: "page-1".to_string(),
+        }
+    }
+}
+
+impl Board {
+    pub fn from_json(json: &str) -> Result<Self, CanvasError> {
+        serde_json::from_str(json).map_err(|e| CanvasError::InvalidDocument(e.to_string()))
+    }
+
+    pub fn to_json(&self) -> Result<String, CanvasError> {
+        serde_json::to_string(self).map_err(|e| CanvasError::InvalidDocument(e.to_string()))
+    }
+
+    pub fn page(&self, id: &str) -> Option<&WhiteboardPage> {
+        self.pages.iter().find(|p| p.id == id)
+    }
+
+    pub fn active_page(&self) -> Option<&WhiteboardPage> {
+        self.page(&self.active_page_id)
+    }
+}
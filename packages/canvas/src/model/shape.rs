@@ -0,0 +1,281 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::error::CanvasError;
+use crate::geometry::text::{SansMetrics, TextMetrics};
+use crate::ink;
+use crate::text::layout::{TextAlign, TextStyle};
+
+pub type ShapeId = String;
+
+/// Length of the arrow head sides, matching `drawArrowHead` in renderer/arrow.ts
+pub const ARROW_HEAD_SIZE: f64 = 10.0;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Point {
+    pub fn new(x: f64, y: f64) -> Self {
+        Self { x, y }
+    }
+}
+
+/// Axis-aligned `{ x, y, w, h }` box as used by selection.ts and geometry.ts
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub w: f64,
+    pub h: f64,
+}
+
+impl Rect {
+    pub fn new(x: f64, y: f64, w: f64, h: f64) -> Self {
+        Self { x, y, w, h }
+    }
+
+    /// Smallest rect containing both corners, like `rectFromPoints`
+    pub fn from_points(a: Point, b: Point) -> Self {
+        let (x1, y1) = (a.x.min(b.x), a.y.min(b.y));
+        Self::new(x1, y1, a.x.max(b.x) - x1, a.y.max(b.y) - y1)
+    }
+
+    pub fn right(&self) -> f64 {
+        self.x + self.w
+    }
+
+    pub fn bottom(&self) -> f64 {
+        self.y + self.h
+    }
+
+    pub fn contains(&self, p: Point) -> bool {
+        p.x >= self.x && p.x <= self.right() && p.y >= self.y && p.y <= self.bottom()
+    }
+
+    /// Whether the rects overlap or touch
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.x <= other.right()
+            && other.x <= self.right()
+            && self.y <= other.bottom()
+            && other.y <= self.bottom()
+    }
+
+    pub fn union(&self, other: &Rect) -> Rect {
+        Rect::from_points(
+            Point::new(self.x.min(other.x), self.y.min(other.y)),
+            Point::new(
+                self.right().max(other.right()),
+                self.bottom().max(other.bottom()),
+            ),
+        )
+    }
+
+    /// Grows the rect by `margin` on every side
+    pub fn expand(&self, margin: f64) -> Rect {
+        Rect::new(
+            self.x - margin,
+            self.y - margin,
+            self.w + margin * 2.0,
+            self.h + margin * 2.0,
+        )
+    }
+}
+
+/// Zoom limits of the store's `zoomAt`
+pub const MIN_ZOOM: f64 = 0.2;
+pub const MAX_ZOOM: f64 = 4.0;
+
+/// Screen-space offset and zoom, see `screenToWorld`/`worldToScreen` in geometry.ts
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    pub x: f64,
+    pub y: f64,
+    pub zoom: f64,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            x: 0.0,
+            y: 0.0,
+            zoom: 1.0,
+        }
+    }
+}
+
+impl Camera {
+    pub fn screen_to_world(&self, p: Point) -> Point {
+        Point {
+            x: (p.x - self.x) / self.zoom,
+            y: (p.y - self.y) / self.zoom,
+        }
+    }
+
+    pub fn world_to_screen(&self, p: Point) -> Point {
+        Point {
+            x: p.x * self.zoom + self.x,
+            y: p.y * self.zoom + self.y,
+        }
+    }
+
+    /// Moves the view by a screen-space offset, like `panBy` in the store
+    pub fn pan_by(&mut self, dx: f64, dy: f64) {
+        self.x += dx;
+        self.y += dy;
+    }
+
+    /// Scales the zoom by `factor` within [`MIN_ZOOM`, `MAX_ZOOM`], keeping
+    /// the world point under `screen` in place, like `zoomAt` in the store
+    pub fn zoom_at(&mut self, screen: Point, factor: f64) {
+        let next = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        let scale = next / self.zoom;
+        self.x = screen.x - (screen.x - self.x) * scale;
+        self.y = screen.y - (screen.y - self.y) * scale;
+        self.zoom = next;
+    }
+
+    /// World-space area shown by a viewport of `width` x `height` screen pixels
+    pub fn visible_rect(&self, width: f64, height: f64) -> Rect {
+        let top_left = self.screen_to_world(Point::new(0.0, 0.0));
+        Rect::new(
+            top_left.x,
+            top_left.y,
+            width / self.zoom,
+            height / self.zoom,
+        )
+    }
+}
+
+/// A shape on the board: the `ShapeBase` fields plus the per-type payload
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Shape {
+    pub id: ShapeId,
+    pub stroke: String,
+    pub fill: Option<String>,
+    pub stroke_width: f64,
+    #[serde(flatten)]
+    pub kind: ShapeKind,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(
+    tag = "type",
+    rename_all = "lowercase",
+    rename_all_fields = "camelCase"
+)]
+pub enum ShapeKind {
+    Pencil {
+        points: Vec<Point>,
+        /// Pen pressure from 0 to 1 at each point, empty for strokes of even
+        /// width; see [`crate::ink::width_at`]
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pressure: Vec<f64>,
+    },
+    Line {
+        a: Point,
+        b: Point,
+    },
+    Rectangle {
+        x: f64,
+        y: f64,
+        w: f64,
+        h: f64,
+    },
+    Ellipse {
+        x: f64,
+        y: f64,
+        w: f64,
+        h: f64,
+    },
+    Arrow {
+        a: Point,
+        b: Point,
+    },
+    /// Text anchored at (`x`, `y`) on the first line's baseline
+    Text {
+        x: f64,
+        y: f64,
+        text: String,
+        font_size: f64,
+        /// Where lines sit relative to `x`, left when absent
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        align: Option<TextAlign>,
+        /// Width lines wrap at; only newlines break lines when absent
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_width: Option<f64>,
+        /// Distance between baselines in multiples of `font_size`, see
+        /// [`DEFAULT_LINE_HEIGHT`](crate::text::layout::DEFAULT_LINE_HEIGHT)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        line_height: Option<f64>,
+    },
+}
+
+impl Shape {
+    /// Geometric bounds, the same box `getBounds` in selection.ts draws
+    /// except that text is measured line by line with [`SansMetrics`]
+    pub fn bounds(&self) -> Rect {
+        match &self.kind {
+            ShapeKind::Rectangle { x, y, w, h } | ShapeKind::Ellipse { x, y, w, h } => {
+                Rect::from_points(Point::new(*x, *y), Point::new(x + w, y + h))
+            }
+            ShapeKind::Line { a, b } | ShapeKind::Arrow { a, b } => Rect::from_points(*a, *b),
+            ShapeKind::Pencil { points, .. } => match points.split_first() {
+                Some((first, rest)) => {
+                    rest.iter().fold(Rect::from_points(*first, *first), |r, p| {
+                        r.union(&Rect::from_points(*p, *p))
+                    })
+                }
+                None => Rect::default(),
+            },
+            ShapeKind::Text { x, y, text, .. } => match TextStyle::of(&self.kind) {
+                Some(style) => SansMetrics.text_box(*x, *y, text, &style),
+                None => Rect::default(),
+            },
+        }
+    }
+
+    /// The shape as a JSON object, the form patches and merges address
+    /// fields in
+    pub(crate) fn to_object(&self) -> Result<Map<String, Value>, CanvasError> {
+        match serde_json::to_value(self) {
+            Ok(Value::Object(map)) => Ok(map),
+            Ok(_) => Err(CanvasError::InvalidDocument(
+                "shape is not an object".to_string(),
+            )),
+            Err(e) => Err(CanvasError::InvalidDocument(e.to_string())),
+        }
+    }
+
+    /// Bounds of the painted pixels: [`Shape::bounds`] plus half the stroke
+    /// and, for arrows, the head
+    pub fn visual_bounds(&self) -> Rect {
+        let half_stroke = match &self.kind {
+            ShapeKind::Text { .. } => 0.0,
+            ShapeKind::Pencil { pressure, .. } => ink::max_width(self.stroke_width, pressure) / 2.0,
+            _ => self.stroke_width.max(0.0) / 2.0,
+        };
+        let head = match self.kind {
+            ShapeKind::Arrow { .. } => ARROW_HEAD_SIZE,
+            _ => 0.0,
+        };
+        self.bounds().expand(half_stroke + head)
+    }
+}
+
+impl ShapeKind {
+    /// The `type` discriminator as written in the JSON document
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            ShapeKind::Pencil { .. } => "pencil",
+            ShapeKind::Line { .. } => "line",
+            ShapeKind::Rectangle { .. } => "rectangle",
+            ShapeKind::Ellipse { .. } => "ellipse",
+            ShapeKind::Arrow { .. } => "arrow",
+            ShapeKind::Text { .. } => "text",
+        }
+    }
+}
@@ -0,0 +1,201 @@
+//! Presence: who else is on the board, where their pointer is and what they
+//! have selected. Presence is ephemeral, so it travels as its own small
+//! binary message that the relay forwards without storing.
+//!
+//! Layout of version 1, with the integer encodings of
+//! [`crate::model::binary`]:
+//!
+//! ```text
+//! magic      b"WBPRS"
+//! version    u16
+//! user       varint
+//! name       varint length + UTF-8 bytes
+//! colour     4 bytes of sRGB RGBA
+//! flags      u8, bit 0 cursor, bit 1 viewport
+//! cursor     f32 x, f32 y, when flagged
+//! viewport   f32 x, y, w, h, when flagged
+//! selection  varint count, then per id varint length + UTF-8 bytes
+//! ```
+//!
+//! Positions are world units as `f32`, which is exact to well below a
+//! screen pixel for any board people actually draw on.
+
+use serde::{Deserialize, Serialize};
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::*;
+
+use crate::crdt::PeerId;
+use crate::error::CanvasError;
+use crate::model::binary::{Reader, Writer};
+use crate::model::color::Rgba;
+use crate::model::{Point, Rect, ShapeId};
+
+pub const MAGIC: &[u8; 5] = b"WBPRS";
+
+pub const VERSION: u16 = 1;
+
+const CURSOR: u8 = 1;
+const VIEWPORT: u8 = 2;
+
+/// One collaborator's state, in world coordinates
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Presence {
+    /// Same id as the user's [`crate::crdt::CrdtDoc`] peer
+    pub user_id: PeerId,
+    pub name: String,
+    /// CSS colour of the cursor, label and selection outlines
+    pub color: String,
+    /// `None` while the pointer is off the canvas
+    #[serde(default)]
+    pub cursor: Option<Point>,
+    #[serde(default)]
+    pub selection: Vec<ShapeId>,
+    /// World area the user's canvas shows
+    #[serde(default)]
+    pub viewport: Option<Rect>,
+}
+
+impl Presence {
+    pub fn rgba(&self) -> Rgba {
+        Rgba::parse(&self.color).unwrap_or(Rgba::BLACK)
+    }
+}
+
+/// Encodes presence given as JSON
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen(js_name = "encodePresence")]
+pub fn encode_presence(presence_json: &str) -> Result<Vec<u8>, JsValue> {
+    let presence: Presence = serde_json::from_str(presence_json)
+        .map_err(|e| CanvasError::InvalidDocument(e.to_string()))?;
+    Ok(encode(&presence)?)
+}
+
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen(js_name = "decodePresence")]
+pub fn decode_presence(bytes: &[u8]) -> Result<String, JsValue> {
+    serde_json::to_string(&decode(bytes)?)
+        .map_err(|e| CanvasError::InvalidDocument(e.to_string()).into())
+}
+
+/// Whether `bytes` look like a presence message rather than another format
+pub fn is_presence(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+pub fn encode(presence: &Presence) -> Result<Vec<u8>, CanvasError> {
+    let color = Rgba::parse(&presence.color).ok_or_else(|| {
+        CanvasError::InvalidDocument(format!("unsupported colour {}", presence.color))
+    })?;
+    let mut w = Writer::default();
+    w.bytes(MAGIC);
+    w.bytes(&VERSION.to_le_bytes());
+    w.varint(presence.user_id);
+    w.string(&presence.name);
+    w.bytes(&color.to_rgba8());
+    let mut flags = 0;
+    if presence.cursor.is_some() {
+        flags |= CURSOR;
+    }
+    if presence.viewport.is_some() {
+        flags |= VIEWPORT;
+    }
+    w.bytes(&[flags]);
+    if let Some(p) = presence.cursor {
+        w.f32(p.x as f32);
+        w.f32(p.y as f32);
+    }
+    if let Some(r) = presence.viewport {
+        for v in [r.x, r.y, r.w, r.h] {
+            w.f32(v as f32);
+        }
+    }
+    w.varint(presence.selection.len() as u64);
+    for id in &presence.selection {
+        w.string(id);
+    }
+    Ok(w.out)
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Presence, CanvasError> {
+    let mut r = Reader { bytes, pos: 0 };
+    if r.take(MAGIC.len())? != MAGIC {
+        return Err(CanvasError::Decode("not a presence message".to_string()));
+    }
+    let version = u16::from_le_bytes(r.array()?);
+    if version != VERSION {
+        return Err(CanvasError::Decode(format!(
+            "unknown presence version {version}"
+        )));
+    }
+    let user_id = r.varint()?;
+    let name = r.string()?;
+    let color = Rgba::from_rgba8(r.array()?).to_hex();
+    let flags = r.array::<1>()?[0];
+    let cursor = if flags & CURSOR != 0 {
+        Some(Point::new(r.f32()? as f64, r.f32()? as f64))
+    } else {
+        None
+    };
+    let viewport = if flags & VIEWPORT != 0 {
+        let [x, y, w, h] = [r.f32()?, r.f32()?, r.f32()?, r.f32()?].map(f64::from);
+        Some(Rect::new(x, y, w, h))
+    } else {
+        None
+    };
+    let n = r.len()?;
+    let mut selection = Vec::with_capacity(n);
+    for _ in 0..n {
+        selection.push(r.string()?);
+    }
+    if r.pos != bytes.len() {
+        return Err(CanvasError::Decode(format!(
+            "{} trailing bytes",
+            bytes.len() - r.pos
+        )));
+    }
+    Ok(Presence {
+        user_id,
+        name,
+        color,
+        cursor,
+        selection,
+        viewport,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_compactly() {
+        let presence = Presence {
+            user_id: 300,
+            name: "Ploy".to_string(),
+            color: "#e03131".to_string(),
+            cursor: Some(Point::new(120.5, -40.25)),
+            selection: vec!["a".to_string(), "shape-2".to_string()],
+            viewport: Some(Rect::new(-10.0, 0.0, 800.0, 600.0)),
+        };
+        let bytes = encode(&presence).unwrap();
+        assert!(is_presence(&bytes));
+        assert_eq!(decode(&bytes).unwrap(), presence);
+        // header, id, name, colour, flags, two floats, four floats, ids
+        assert_eq!(bytes.len(), 7 + 2 + 5 + 4 + 1 + 8 + 16 + 1 + 2 + 8);
+
+        let idle = Presence {
+            cursor: None,
+            viewport: None,
+            selection: Vec::new(),
+            ..presence
+        };
+        assert_eq!(decode(&encode(&idle).unwrap()).unwrap(), idle);
+        assert!(decode(&encode(&idle).unwrap()[..10]).is_err());
+        assert!(encode(&Presence {
+            color: "teal-ish".to_string(),
+            ..idle
+        })
+        .is_err());
+    }
+}
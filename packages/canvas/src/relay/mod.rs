@@ -0,0 +1,745 @@
+//! WebSocket relay behind the `canvas-relay` binary.
+//!
+//! Clients connect to `/rooms/<board id>?token=<share token>` and first
+//! receive the room's whole [`CrdtDoc`] state as one binary update. The
+//! token decides the member's role: [`share_token`] signs the board and role
+//! with [`RelayConfig::secret`], so clients cannot pick their own. Without a
+//! token the member is a viewer; a token the relay did not sign for this
+//! board is refused. A relay without a secret takes any token and makes
+//! every member a viewer. After that:
+//!
+//! - binary messages starting with [`update::MAGIC`] are document updates;
+//!   the relay merges them into the room and forwards them to the other
+//!   members, or answers viewers with an error
+//! - any other message, such as presence, is forwarded untouched and not
+//!   stored
+//!
+//! Errors go back to the sender as text `{"type":"error","message":...}`.
+//! Rooms are loaded from `<storage>/<board id>.wbupd` on first join, saved
+//! every [`RelayConfig::autosave`] milliseconds while they have unsaved
+//! changes and once more when the last member leaves.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::{StatusCode, Uri};
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::config::app_config::RelayConfig;
+use crate::crdt::{update, CrdtDoc, PeerId};
+use crate::error::CanvasError;
+
+/// Peer id of the relay's own replica, which never edits
+const RELAY_PEER: PeerId = 0;
+
+/// Longest board id accepted, as ids become file names
+const MAX_BOARD_ID: usize = 128;
+
+/// Messages queued for a member before it counts as too slow and is dropped
+const MEMBER_QUEUE: usize = 256;
+
+/// Signs share tokens
+type TokenMac = Hmac<Sha256>;
+
+/// What a member may do, granted by the share token it connects with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Viewer,
+    Editor,
+}
+
+impl Role {
+    fn as_str(self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+        }
+    }
+}
+
+/// Token for share links granting `role` on `board`: the role and an
+/// HMAC-SHA256 of both under `secret`, such as `editor.<64 hex digits>`
+pub fn share_token(secret: &str, board: &str, role: Role) -> String {
+    let tag = token_mac(secret.as_bytes(), board, role)
+        .finalize()
+        .into_bytes();
+    let hex: String = tag.iter().map(|b| format!("{b:02x}")).collect();
+    format!("{}.{hex}", role.as_str())
+}
+
+fn token_mac(secret: &[u8], board: &str, role: Role) -> TokenMac {
+    let mut mac = TokenMac::new_from_slice(secret).expect("HMAC takes keys of any length");
+    mac.update(board.as_bytes());
+    mac.update(b"\n");
+    mac.update(role.as_str().as_bytes());
+    mac
+}
+
+/// Role granted by `token` when `secret` signed it for `board`. Without a
+/// secret nothing can be signed, so every token grants a viewer.
+fn verify_token(secret: Option<&[u8]>, board: &str, token: &str) -> Option<Role> {
+    let Some(secret) = secret else {
+        return Some(Role::Viewer);
+    };
+    let (role, hex) = token.split_once('.')?;
+    let role = match role {
+        "viewer" => Role::Viewer,
+        "editor" => Role::Editor,
+        _ => return None,
+    };
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        return None;
+    }
+    let tag: Vec<u8> = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect::<Option<_>>()?;
+    token_mac(secret, board, role).verify_slice(&tag).ok()?;
+    Some(role)
+}
+
+#[derive(Debug)]
+struct Room {
+    doc: CrdtDoc,
+    members: HashMap<u64, mpsc::Sender<Message>>,
+    /// Updates applied, and how many of them the last save holds
+    edits: u64,
+    saved: u64,
+}
+
+impl Room {
+    fn dirty(&self) -> bool {
+        self.edits != self.saved
+    }
+}
+
+/// Rooms of connected clients, keyed by board id
+#[derive(Debug)]
+pub struct Relay {
+    rooms: Mutex<HashMap<String, Room>>,
+    storage: PathBuf,
+    /// Held while snapshots are read or written, so saves land in order and
+    /// a room that closes and opens again never reads an older save
+    saving: tokio::sync::Mutex<()>,
+    /// Key of the share tokens, see [`share_token`]
+    secret: Option<Vec<u8>>,
+    next_member: AtomicU64,
+}
+
+impl Relay {
+    /// Keeps rooms in `storage`; only tokens signed with `secret` make
+    /// editors
+    pub fn new(storage: impl Into<PathBuf>, secret: Option<&str>) -> Self {
+        Self {
+            rooms: Mutex::new(HashMap::new()),
+            storage: storage.into(),
+            saving: tokio::sync::Mutex::new(()),
+            secret: secret.map(|s| s.as_bytes().to_vec()),
+            next_member: AtomicU64::new(1),
+        }
+    }
+
+    /// Listens on `config.bind` until Ctrl-C, then saves every open room
+    pub async fn run(config: &RelayConfig) -> Result<(), CanvasError> {
+        if config.secret.is_none() {
+            tracing::warn!("relay.secret is not set, every member is a viewer");
+        }
+        let relay = Arc::new(Relay::new(&config.storage, config.secret.as_deref()));
+        let listener = TcpListener::bind(&config.bind)
+            .await
+            .map_err(|e| CanvasError::Relay(format!("bind {}: {e}", config.bind)))?;
+        tracing::info!("relay listening on {}", config.bind);
+        tokio::spawn(
+            relay
+                .clone()
+                .autosave(Duration::from_millis(config.autosave)),
+        );
+        tokio::select! {
+            result = relay.clone().serve(listener) => result?,
+            _ = tokio::signal::ctrl_c() => tracing::info!("shutting down"),
+        }
+        relay.save_all().await
+    }
+
+    /// Accepts connections from `listener` forever
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> Result<(), CanvasError> {
+        loop {
+            let (stream, addr) = listener
+                .accept()
+                .await
+                .map_err(|e| CanvasError::Relay(e.to_string()))?;
+            tracing::debug!("connection from {addr}");
+            tokio::spawn(self.clone().connect(stream));
+        }
+    }
+
+    /// Saves rooms with unsaved changes every `every`
+    pub async fn autosave(self: Arc<Self>, every: Duration) {
+        let mut ticks = tokio::time::interval(every);
+        loop {
+            ticks.tick().await;
+            for e in self.save_rooms(false).await {
+                tracing::error!("{e}");
+            }
+        }
+    }
+
+    /// Saves every open room, whether it changed or not, reporting the
+    /// rooms that failed together
+    pub async fn save_all(&self) -> Result<(), CanvasError> {
+        let errors = self.save_rooms(true).await;
+        if errors.is_empty() {
+            return Ok(());
+        }
+        let messages: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        Err(CanvasError::Relay(messages.join("; ")))
+    }
+
+    /// Saves the rooms with unsaved changes, or `all` of them. The snapshots
+    /// are encoded under the lock and written after it is released; a room
+    /// that fails to save stays dirty and the others are saved regardless.
+    async fn save_rooms(&self, all: bool) -> Vec<CanvasError> {
+        let _saving = self.saving.lock().await;
+        let snapshots: Vec<(String, Vec<u8>, u64)> = self
+            .rooms()
+            .iter()
+            .filter(|(_, room)| all || room.dirty())
+            .map(|(board, room)| (board.clone(), room.doc.encode_state(), room.edits))
+            .collect();
+        let mut errors = Vec::new();
+        for (board, bytes, edits) in snapshots {
+            match self.write(&board, bytes).await {
+                Ok(()) => {
+                    if let Some(room) = self.rooms().get_mut(&board) {
+                        room.saved = room.saved.max(edits);
+                    }
+                }
+                Err(e) => errors.push(e),
+            }
+        }
+        errors
+    }
+
+    fn rooms(&self) -> MutexGuard<'_, HashMap<String, Room>> {
+        // a panic elsewhere must not take every room down with it
+        self.rooms.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn snapshot_path(&self, board: &str) -> PathBuf {
+        self.storage.join(format!("{board}.wbupd"))
+    }
+
+    async fn load(&self, board: &str) -> Result<Room, CanvasError> {
+        let mut doc = CrdtDoc::new(RELAY_PEER);
+        match tokio::fs::read(self.snapshot_path(board)).await {
+            Ok(bytes) => {
+                doc.apply_update(&bytes)?;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(CanvasError::Relay(format!("load {board}: {e}"))),
+        }
+        Ok(Room {
+            doc,
+            members: HashMap::new(),
+            edits: 0,
+            saved: 0,
+        })
+    }
+
+    /// Writes the snapshot next to the old one and swaps it in, so a crash
+    /// mid-write keeps the previous save
+    async fn write(&self, board: &str, bytes: Vec<u8>) -> Result<(), CanvasError> {
+        let path = self.snapshot_path(board);
+        let partial = path.with_extension("wbupd.partial");
+        let written = async {
+            tokio::fs::create_dir_all(&self.storage).await?;
+            tokio::fs::write(&partial, bytes).await?;
+            tokio::fs::rename(&partial, &path).await
+        };
+        written
+            .await
+            .map_err(|e| CanvasError::Relay(format!("save {board}: {e}")))
+    }
+
+    /// Adds a member, sending it the room state before anything else
+    async fn join(&self, board: &str, tx: mpsc::Sender<Message>) -> Result<u64, CanvasError> {
+        let member = self.next_member.fetch_add(1, Ordering::Relaxed);
+        if self.add_member(board, member, &tx) {
+            return Ok(member);
+        }
+        let _saving = self.saving.lock().await;
+        // another member may have opened the room while we waited
+        if !self.add_member(board, member, &tx) {
+            let room = self.load(board).await?;
+            self.rooms().insert(board.to_string(), room);
+            self.add_member(board, member, &tx);
+        }
+        Ok(member)
+    }
+
+    /// Joins `member` to `board` if the room is open
+    fn add_member(&self, board: &str, member: u64, tx: &mpsc::Sender<Message>) -> bool {
+        let mut rooms = self.rooms();
+        let Some(room) = rooms.get_mut(board) else {
+            return false;
+        };
+        let _ = tx.try_send(Message::Binary(room.doc.encode_state().into()));
+        room.members.insert(member, tx.clone());
+        true
+    }
+
+    /// Removes a member, saving and closing the room after the last one
+    async fn leave(&self, board: &str, member: u64) {
+        let _saving = self.saving.lock().await;
+        let room = {
+            let mut rooms = self.rooms();
+            let Some(room) = rooms.get_mut(board) else {
+                return;
+            };
+            room.members.remove(&member);
+            if !room.members.is_empty() {
+                return;
+            }
+            rooms.remove(board).expect("looked up above")
+        };
+        if !room.dirty() {
+            return;
+        }
+        if let Err(e) = self.write(board, room.doc.encode_state()).await {
+            // keep the room in memory for the next autosave to retry
+            tracing::error!("{e}");
+            self.rooms().insert(board.to_string(), room);
+        }
+    }
+
+    fn apply(&self, board: &str, member: u64, bytes: &[u8]) -> Result<(), CanvasError> {
+        let mut rooms = self.rooms();
+        let room = rooms
+            .get_mut(board)
+            .ok_or_else(|| CanvasError::Relay(format!("room {board} is closed")))?;
+        room.doc.apply_update(bytes)?;
+        room.edits += 1;
+        broadcast(room, member, Message::Binary(bytes.to_vec().into()));
+        Ok(())
+    }
+
+    fn forward(&self, board: &str, member: u64, message: Message) {
+        if let Some(room) = self.rooms().get_mut(board) {
+            broadcast(room, member, message);
+        }
+    }
+
+    fn reply(&self, board: &str, member: u64, message: Message) {
+        if let Some(room) = self.rooms().get_mut(board) {
+            send(room, member, message);
+        }
+    }
+
+    async fn connect(self: Arc<Self>, stream: TcpStream) {
+        let mut target = None;
+        // tungstenite fixes the callback's error type
+        #[allow(clippy::result_large_err)]
+        let callback = |request: &Request, response: Response| match parse_target(
+            request.uri(),
+            self.secret.as_deref(),
+        ) {
+            Ok(t) => {
+                target = Some(t);
+                Ok(response)
+            }
+            Err((status, message)) => Err(reject(status, message)),
+        };
+        let ws = match tokio_tungstenite::accept_hdr_async(stream, callback).await {
+            Ok(ws) => ws,
+            Err(e) => {
+                tracing::debug!("handshake failed: {e}");
+                return;
+            }
+        };
+        let Some((board, role)) = target else {
+            return;
+        };
+        let (mut sink, mut incoming) = ws.split();
+        let (tx, mut rx) = mpsc::channel(MEMBER_QUEUE);
+        let member = match self.join(&board, tx).await {
+            Ok(member) => member,
+            Err(e) => {
+                tracing::error!("{e}");
+                let _ = sink
+                    .send(Message::Close(Some(CloseFrame {
+                        code: CloseCode::Error,
+                        reason: e.to_string().into(),
+                    })))
+                    .await;
+                return;
+            }
+        };
+        // ends once the room drops the member's sender: when it leaves, or
+        // when it falls behind
+        let mut writer = tokio::spawn(async move {
+            while let Some(message) = rx.recv().await {
+                if sink.send(message).await.is_err() {
+                    return;
+                }
+            }
+            let _ = sink.close().await;
+        });
+
+        let mut dropped = false;
+        loop {
+            let message = tokio::select! {
+                message = incoming.next() => message,
+                _ = &mut writer => {
+                    dropped = true;
+                    break;
+                }
+            };
+            let Some(Ok(message)) = message else {
+                break;
+            };
+            match message {
+                Message::Binary(bytes) if bytes.starts_with(update::MAGIC) => {
+                    let result = match role {
+                        Role::Viewer => Err(CanvasError::Relay(
+                            "viewers cannot edit this board".to_string(),
+                        )),
+                        Role::Editor => self.apply(&board, member, &bytes),
+                    };
+                    if let Err(e) = result {
+                        self.reply(&board, member, error_message(&e));
+                    }
+                }
+                Message::Binary(_) | Message::Text(_) => self.forward(&board, member, message),
+                Message::Close(_) => break,
+                _ => {}
+            }
+        }
+        self.leave(&board, member).await;
+        if !dropped {
+            let _ = writer.await;
+        }
+    }
+}
+
+fn broadcast(room: &mut Room, from: u64, message: Message) {
+    let others: Vec<u64> = room
+        .members
+        .keys()
+        .copied()
+        .filter(|m| *m != from)
+        .collect();
+    for member in others {
+        send(room, member, message.clone());
+    }
+}
+
+/// Queues `message` for `member`, dropping the member from the room when
+/// its queue is full; it catches up with the whole state when it rejoins
+fn send(room: &mut Room, member: u64, message: Message) {
+    let Some(tx) = room.members.get(&member) else {
+        return;
+    };
+    if let Err(mpsc::error::TrySendError::Full(_)) = tx.try_send(message) {
+        tracing::warn!("member {member} is not keeping up, disconnecting it");
+        room.members.remove(&member);
+    }
+}
+
+fn error_message(error: &CanvasError) -> Message {
+    let json = serde_json::json!({ "type": "error", "message": error.to_string() });
+    Message::Text(json.to_string().into())
+}
+
+fn reject(status: StatusCode, message: String) -> ErrorResponse {
+    let mut response = ErrorResponse::new(Some(message));
+    *response.status_mut() = status;
+    response
+}
+
+/// Board id and role from `/rooms/<board id>?token=<share token>`, checking
+/// the token against `secret`
+fn parse_target(uri: &Uri, secret: Option<&[u8]>) -> Result<(String, Role), (StatusCode, String)> {
+    let bad = |message| (StatusCode::BAD_REQUEST, message);
+    let board = uri
+        .path()
+        .strip_prefix("/rooms/")
+        .ok_or_else(|| bad(format!("unknown path {}", uri.path())))?;
+    let valid = !board.is_empty()
+        && board.len() <= MAX_BOARD_ID
+        && board
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+    if !valid {
+        return Err(bad(format!("invalid board id {board:?}")));
+    }
+    let mut role = Role::Viewer;
+    for pair in uri.query().unwrap_or("").split('&') {
+        if let Some(token) = pair.strip_prefix("token=") {
+            role = verify_token(secret, board, token).ok_or_else(|| {
+                (
+                    StatusCode::FORBIDDEN,
+                    format!("the share token is not valid for {board}"),
+                )
+            })?;
+        }
+    }
+    Ok((board.to_string(), role))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::Edit;
+    use crate::model::Shape;
+    use tokio_tungstenite::tungstenite::Error as WsError;
+    use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+    type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+    fn storage(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("canvas-relay-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    const SECRET: &str = "correct horse battery staple";
+
+    async fn start(storage: &PathBuf) -> String {
+        start_with(storage, Some(SECRET)).await
+    }
+
+    async fn start_with(storage: &PathBuf, secret: Option<&str>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(Arc::new(Relay::new(storage, secret)).serve(listener));
+        format!("ws://{addr}")
+    }
+
+    /// Path of a share link for `role` on `board`
+    fn link(board: &str, role: Role) -> String {
+        format!("/rooms/{board}?token={}", share_token(SECRET, board, role))
+    }
+
+    async fn refused(base: &str, path: &str) -> StatusCode {
+        let result = tokio_tungstenite::connect_async(format!("{base}{path}")).await;
+        let Err(WsError::Http(response)) = result else {
+            panic!("{path} was accepted");
+        };
+        response.status()
+    }
+
+    async fn join(base: &str, path: &str) -> Socket {
+        tokio_tungstenite::connect_async(format!("{base}{path}"))
+            .await
+            .unwrap()
+            .0
+    }
+
+    async fn next(socket: &mut Socket) -> Message {
+        tokio::time::timeout(Duration::from_secs(5), socket.next())
+            .await
+            .expect("no message within 5 s")
+            .unwrap()
+            .unwrap()
+    }
+
+    /// Applies the initial state message to a fresh replica
+    async fn replica(socket: &mut Socket, peer: PeerId) -> CrdtDoc {
+        let mut doc = CrdtDoc::new(peer);
+        doc.apply_update(&next(socket).await.into_data()).unwrap();
+        doc
+    }
+
+    fn rect(id: &str) -> Edit {
+        let shape: Shape = serde_json::from_value(serde_json::json!({ "id": id,
+            "type": "rectangle", "x": 0, "y": 0, "w": 10, "h": 10,
+            "stroke": "#000", "fill": null, "strokeWidth": 2 }))
+        .unwrap();
+        Edit::Insert { shape }
+    }
+
+    #[tokio::test]
+    async fn relays_updates_and_enforces_roles() {
+        let dir = storage("roles");
+        let base = start(&dir).await;
+        let mut editor = join(&base, &link("board-1", Role::Editor)).await;
+        let mut viewer = join(&base, &link("board-1", Role::Viewer)).await;
+        let mut elsewhere = join(&base, &link("board-2", Role::Editor)).await;
+        let mut ours = replica(&mut editor, 1).await;
+        let mut theirs = replica(&mut viewer, 2).await;
+        replica(&mut elsewhere, 3).await;
+
+        let update = ours.edit(rect("a")).unwrap();
+        editor.send(Message::Binary(update.into())).await.unwrap();
+        theirs
+            .apply_update(&next(&mut viewer).await.into_data())
+            .unwrap();
+        assert_eq!(theirs.doc(), ours.doc());
+
+        // viewers may share presence but not edit
+        let edit = theirs.edit(rect("b")).unwrap();
+        viewer.send(Message::Binary(edit.into())).await.unwrap();
+        let Message::Text(error) = next(&mut viewer).await else {
+            panic!("expected an error");
+        };
+        assert!(error.contains("viewers cannot edit"));
+        viewer.send(Message::Text("cursor".into())).await.unwrap();
+        assert_eq!(next(&mut editor).await, Message::Text("cursor".into()));
+
+        // board-2 saw none of it
+        elsewhere.send(Message::Text("ping".into())).await.unwrap();
+        assert!(
+            tokio::time::timeout(Duration::from_millis(200), elsewhere.next())
+                .await
+                .is_err()
+        );
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn persists_rooms_across_restarts() {
+        let dir = storage("persist");
+        let base = start(&dir).await;
+        let mut editor = join(&base, &link("saved", Role::Editor)).await;
+        let mut doc = replica(&mut editor, 1).await;
+        for id in ["a", "b"] {
+            let update = doc.edit(rect(id)).unwrap();
+            editor.send(Message::Binary(update.into())).await.unwrap();
+        }
+        editor.close(None).await.unwrap();
+        let snapshot = dir.join("saved.wbupd");
+        for _ in 0..100 {
+            if snapshot.exists() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        let base = start(&dir).await;
+        let mut late = join(&base, "/rooms/saved").await;
+        assert_eq!(replica(&mut late, 2).await.doc(), doc.doc());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn viewer_links_cannot_edit() {
+        let dir = storage("viewer-links");
+        let base = start(&dir).await;
+
+        // the role comes from the signature, not from what the client asks
+        let viewer = share_token(SECRET, "board", Role::Viewer);
+        let relabelled = viewer.replacen("viewer", "editor", 1);
+        let other_board = link("other", Role::Editor).replace("other", "board");
+        let foreign = share_token("another relay's secret", "board", Role::Editor);
+        for token in [&relabelled, "editor.00", "editor", &foreign] {
+            let path = format!("/rooms/board?token={token}");
+            assert_eq!(refused(&base, &path).await, StatusCode::FORBIDDEN);
+        }
+        assert_eq!(refused(&base, &other_board).await, StatusCode::FORBIDDEN);
+
+        for path in [
+            link("board", Role::Viewer),
+            "/rooms/board?role=editor".into(),
+        ] {
+            let mut socket = join(&base, &path).await;
+            let mut doc = replica(&mut socket, 2).await;
+            let edit = doc.edit(rect("b")).unwrap();
+            socket.send(Message::Binary(edit.into())).await.unwrap();
+            let Message::Text(error) = next(&mut socket).await else {
+                panic!("expected an error");
+            };
+            assert!(error.contains("viewers cannot edit"), "{path}");
+        }
+
+        // a relay without a secret lets everyone in, but makes no editors
+        let base = start_with(&dir, None).await;
+        for path in [
+            link("board", Role::Editor),
+            "/rooms/board?token=editor.00".into(),
+            "/rooms/board".into(),
+        ] {
+            let mut socket = join(&base, &path).await;
+            let mut doc = replica(&mut socket, 3).await;
+            let edit = doc.edit(rect("c")).unwrap();
+            socket.send(Message::Binary(edit.into())).await.unwrap();
+            let Message::Text(error) = next(&mut socket).await else {
+                panic!("expected an error");
+            };
+            assert!(error.contains("viewers cannot edit"), "{path}");
+        }
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    fn room(doc: CrdtDoc, edits: u64) -> Room {
+        Room {
+            doc,
+            members: HashMap::new(),
+            edits,
+            saved: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn saves_every_room_it_can() {
+        let dir = storage("save-errors");
+        let relay = Relay::new(&dir, None);
+        let mut doc = CrdtDoc::new(1);
+        doc.edit(rect("a")).unwrap();
+        for board in ["broken", "fine", "idle"] {
+            let edits = if board == "idle" { 0 } else { 1 };
+            relay
+                .rooms()
+                .insert(board.to_string(), room(doc.clone(), edits));
+        }
+        // a directory in the way of the partial file fails that save only
+        std::fs::create_dir_all(dir.join("broken.wbupd.partial")).unwrap();
+
+        let errors = relay.save_rooms(false).await;
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].to_string().contains("save broken"));
+        assert!(dir.join("fine.wbupd").exists() && !dir.join("idle.wbupd").exists());
+        {
+            let rooms = relay.rooms();
+            assert!(rooms["broken"].dirty() && !rooms["fine"].dirty());
+        }
+        assert!(relay.save_all().await.is_err());
+        assert!(dir.join("idle.wbupd").exists());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn members_that_fall_behind_are_dropped() {
+        let mut room = room(CrdtDoc::new(RELAY_PEER), 0);
+        let (slow, _queued) = mpsc::channel(1);
+        let (fast, mut delivered) = mpsc::channel(MEMBER_QUEUE);
+        room.members.insert(1, slow);
+        room.members.insert(2, fast);
+        for _ in 0..2 {
+            broadcast(&mut room, 3, Message::Text("cursor".into()));
+        }
+        assert_eq!(room.members.keys().collect::<Vec<_>>(), [&2]);
+        assert_eq!(
+            delivered.try_recv().unwrap(),
+            Message::Text("cursor".into())
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_targets() {
+        let base = start(&storage("reject")).await;
+        for path in ["/boards/x", "/rooms/../etc", "/rooms/"] {
+            assert_eq!(refused(&base, path).await, StatusCode::BAD_REQUEST);
+        }
+    }
+}
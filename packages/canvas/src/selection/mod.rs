@@ -0,0 +1,261 @@
+//! Marquee and lasso selection. Both compare the selection polygon with what
+//! each shape paints, as [`geometry::distance`] measures it, so dragging
+//! across the middle of an unfilled rectangle does not pick it up and a thin
+//! diagonal line is only caught where the marquee actually crosses it.
+
+use crate::geometry::{self, polygon, text::TextMetrics, OrientedRect};
+use crate::ink;
+use crate::model::color::Rgba;
+use crate::model::{Point, Rect, Shape, ShapeId, ShapeKind, WhiteboardDoc};
+use crate::spatial::SpatialIndex;
+
+/// Largest gap between the corners of an ellipse outline, in world units
+const ELLIPSE_CHORD: f64 = 4.0;
+
+/// Which shapes a marquee or lasso picks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SelectionMode {
+    /// Shapes painted entirely inside the area
+    Contained,
+    /// Shapes with any painted part inside the area or touching its edge
+    #[default]
+    Intersecting,
+}
+
+/// Shapes picked by the rectangle `rect`, bottom first
+pub fn marquee(
+    index: &SpatialIndex,
+    doc: &WhiteboardDoc,
+    rect: Rect,
+    mode: SelectionMode,
+    metrics: &dyn TextMetrics,
+) -> Vec<ShapeId> {
+    lasso(index, doc, &polygon::from_rect(rect), mode, metrics)
+}
+
+/// Shapes picked by the freeform polygon `points`, closed from the last point
+/// back to the first, bottom first. Fewer than three points pick nothing.
+pub fn lasso(
+    index: &SpatialIndex,
+    doc: &WhiteboardDoc,
+    points: &[Point],
+    mode: SelectionMode,
+    metrics: &dyn TextMetrics,
+) -> Vec<ShapeId> {
+    let Some(area) = polygon::bounds(points).filter(|_| points.len() >= 3) else {
+        return Vec::new();
+    };
+    index
+        .query_rect(area)
+        .into_iter()
+        .filter(|id| {
+            let Some(shape) = doc.get(id) else {
+                return false;
+            };
+            let parts = parts(shape, metrics);
+            match mode {
+                SelectionMode::Contained => {
+                    !parts.is_empty() && parts.iter().all(|p| p.inside(points))
+                }
+                SelectionMode::Intersecting => parts.iter().any(|p| p.intersects(points)),
+            }
+        })
+        .collect()
+}
+
+/// A piece of what a shape paints
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    /// Filled polygon
+    Polygon(Vec<Point>),
+    /// Everything within the radius of a segment, a round-capped stroke
+    Capsule(Point, Point, f64),
+    /// The outer polygon minus the inside of the convex inner one, the stroke
+    /// of an unfilled rectangle
+    Band {
+        outer: Vec<Point>,
+        inner: Vec<Point>,
+    },
+}
+
+impl Part {
+    fn intersects(&self, area: &[Point]) -> bool {
+        match self {
+            Part::Polygon(corners) => polygon::polygons_intersect(corners, area),
+            Part::Capsule(a, b, radius) => {
+                polygon::contains(area, *a)
+                    || polygon::edges(area)
+                        .any(|(c, d)| polygon::segment_distance(*a, *b, c, d) <= *radius)
+            }
+            // the band is hit unless the area hides in the hole
+            Part::Band { outer, inner } => {
+                polygon::polygons_intersect(outer, area) && !polygon::polygon_inside(area, inner)
+            }
+        }
+    }
+
+    fn inside(&self, area: &[Point]) -> bool {
+        match self {
+            Part::Polygon(corners) | Part::Band { outer: corners, .. } => {
+                polygon::polygon_inside(corners, area)
+            }
+            Part::Capsule(a, b, radius) => {
+                polygon::polygon_inside(&[*a, *b], area)
+                    && polygon::edges(area)
+                        .all(|(c, d)| polygon::segment_distance(*a, *b, c, d) >= *radius)
+            }
+        }
+    }
+}
+
+/// What `shape` paints, with the same fill and stroke rules as
+/// [`geometry::distance`]
+fn parts(shape: &Shape, metrics: &dyn TextMetrics) -> Vec<Part> {
+    let half = shape.stroke_width.max(0.0) / 2.0;
+    let filled = shape
+        .fill
+        .as_deref()
+        .and_then(Rgba::parse)
+        .is_some_and(|c| c.is_visible());
+    match &shape.kind {
+        ShapeKind::Rectangle { x, y, w, h } => {
+            let r = Rect::from_points(Point::new(*x, *y), Point::new(x + w, y + h));
+            let outer = polygon::from_rect(r.expand(half));
+            let hole = r.expand(-half);
+            if filled || hole.w <= 0.0 || hole.h <= 0.0 {
+                vec![Part::Polygon(outer)]
+            } else {
+                vec![Part::Band {
+                    outer,
+                    inner: polygon::from_rect(hole),
+                }]
+            }
+        }
+        ShapeKind::Ellipse { x, y, w, h } => {
+            let e = geometry::Ellipse::from_rect(Rect::new(*x, *y, *w, *h));
+            let perimeter = std::f64::consts::PI * (e.rx + e.ry);
+            let segments = ((perimeter / ELLIPSE_CHORD).ceil() as usize).clamp(16, 1024);
+            let outline: Vec<Point> = (0..segments)
+                .map(|i| {
+                    let t = i as f64 / segments as f64 * std::f64::consts::TAU;
+                    Point::new(e.center.x + e.rx * t.cos(), e.center.y + e.ry * t.sin())
+                })
+                .collect();
+            let mut parts: Vec<Part> = polygon::edges(&outline)
+                .map(|(a, b)| Part::Capsule(a, b, half))
+                .collect();
+            if filled {
+                parts.push(Part::Polygon(outline));
+            }
+            parts
+        }
+        ShapeKind::Line { a, b } => line_parts(*a, *b, half),
+        ShapeKind::Arrow { a, b } => {
+            let mut parts = line_parts(*a, *b, half);
+            parts.push(Part::Polygon(geometry::arrow_head(*a, *b).to_vec()));
+            parts
+        }
+        ShapeKind::Pencil { points, pressure } => {
+            // a tapering segment fits in a capsule as wide as its wider end
+            let halves = ink::half_widths(shape.stroke_width, pressure, points.len());
+            match points.as_slice() {
+                [only] => vec![Part::Capsule(*only, *only, halves[0])],
+                _ => points
+                    .windows(2)
+                    .zip(halves.windows(2))
+                    .map(|(s, h)| Part::Capsule(s[0], s[1], h[0].max(h[1])))
+                    .collect(),
+            }
+        }
+        ShapeKind::Text { .. } => geometry::text_box(shape, metrics)
+            .map(|r| vec![Part::Polygon(polygon::from_rect(r))])
+            .unwrap_or_default(),
+    }
+}
+
+/// The butt-capped band of a straight stroke; zero-length ones paint nothing
+fn line_parts(a: Point, b: Point, half: f64) -> Vec<Part> {
+    if a == b {
+        return Vec::new();
+    }
+    vec![Part::Polygon(
+        OrientedRect::along_segment(a, b, half * 2.0)
+            .corners()
+            .to_vec(),
+    )]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::text::SansMetrics;
+
+    const DOC_JSON: &str = r##"{
+        "shapes": {
+            "box": { "id": "box", "type": "rectangle", "x": 0, "y": 0, "w": 100, "h": 100,
+                     "stroke": "#000", "fill": null, "strokeWidth": 2 },
+            "solid": { "id": "solid", "type": "ellipse", "x": 200, "y": 0, "w": 100, "h": 50,
+                       "stroke": "#000", "fill": "#f00", "strokeWidth": 2 },
+            "slash": { "id": "slash", "type": "line", "a": { "x": 0, "y": 200 }, "b": { "x": 100, "y": 300 },
+                       "stroke": "#000", "fill": null, "strokeWidth": 2 },
+            "label": { "id": "label", "type": "text", "x": 20, "y": 60, "text": "Hi", "fontSize": 20,
+                       "stroke": "#000", "fill": null, "strokeWidth": 1 }
+        },
+        "order": ["solid", "label", "box", "slash"]
+    }"##;
+
+    fn select(rect: Rect, mode: SelectionMode) -> Vec<ShapeId> {
+        let doc = WhiteboardDoc::from_json(DOC_JSON).unwrap();
+        let index = SpatialIndex::from_doc(&doc, &SansMetrics);
+        marquee(&index, &doc, rect, mode, &SansMetrics)
+    }
+
+    #[test]
+    fn marquee_modes() {
+        let everything = Rect::new(-10.0, -10.0, 400.0, 400.0);
+        assert_eq!(
+            select(everything, SelectionMode::Contained),
+            ["solid", "label", "box", "slash"]
+        );
+        // inside the unfilled box: only the text is painted there
+        let middle = Rect::new(10.0, 10.0, 80.0, 80.0);
+        assert_eq!(select(middle, SelectionMode::Intersecting), ["label"]);
+        assert_eq!(select(middle, SelectionMode::Contained), ["label"]);
+        // dragged from the right, across the box's edge and into the ellipse
+        let across = Rect::new(90.0, 20.0, 150.0, 10.0);
+        assert_eq!(
+            select(across, SelectionMode::Intersecting),
+            ["solid", "box"]
+        );
+        assert!(select(across, SelectionMode::Contained).is_empty());
+        // inside the line's bounds but off the line itself
+        let beside = Rect::new(60.0, 200.0, 40.0, 30.0);
+        assert!(select(beside, SelectionMode::Intersecting).is_empty());
+    }
+
+    #[test]
+    fn lasso_follows_concave_outlines() {
+        let doc = WhiteboardDoc::from_json(DOC_JSON).unwrap();
+        let index = SpatialIndex::from_doc(&doc, &SansMetrics);
+        // a U around the ellipse and the line, with the box in its notch
+        let u = [
+            Point::new(-50.0, 350.0),
+            Point::new(-50.0, 150.0),
+            Point::new(150.0, 150.0),
+            Point::new(150.0, -50.0),
+            Point::new(350.0, -50.0),
+            Point::new(350.0, 350.0),
+        ];
+        let pick = |mode| lasso(&index, &doc, &u, mode, &SansMetrics);
+        assert_eq!(pick(SelectionMode::Contained), ["solid", "slash"]);
+        assert_eq!(pick(SelectionMode::Intersecting), ["solid", "slash"]);
+        assert!(lasso(
+            &index,
+            &doc,
+            &u[..2],
+            SelectionMode::Intersecting,
+            &SansMetrics
+        )
+        .is_empty());
+    }
+}
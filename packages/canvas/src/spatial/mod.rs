@@ -0,0 +1,517 @@
+//! Spatial index over shape bounds for hit testing and viewport culling.
+//!
+//! A loose quadtree: every cell accepts shapes whose centre lies inside it and
+//! whose bounds fit in the cell grown by half its size on each side, so a
+//! shape lives in exactly one cell picked from its centre and size alone.
+//! Moving a shape is then a removal from one item list and a push onto
+//! another, and the root simply doubles towards shapes outside of it, which
+//! suits a board without edges.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::geometry::{self, text::TextMetrics};
+use crate::model::{Point, Rect, Shape, ShapeId, WhiteboardDoc};
+
+/// Side of the smallest cells in world units
+const MIN_CELL: f64 = 64.0;
+
+/// Hit tolerance in world units when none is given, as in `hitTestShape`
+pub const DEFAULT_TOLERANCE: f64 = 6.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Cell {
+    x: f64,
+    y: f64,
+    size: f64,
+}
+
+impl Cell {
+    /// The cell grown by half its size on every side
+    fn loose(&self) -> Rect {
+        Rect::new(self.x, self.y, self.size, self.size).expand(self.size / 2.0)
+    }
+
+    fn contains(&self, p: Point) -> bool {
+        p.x >= self.x && p.x < self.x + self.size && p.y >= self.y && p.y < self.y + self.size
+    }
+
+    /// Index of the quadrant holding `p`: bit 0 for the right half, bit 1
+    /// for the bottom half
+    fn quadrant_of(&self, p: Point) -> usize {
+        let half = self.size / 2.0;
+        usize::from(p.x >= self.x + half) | usize::from(p.y >= self.y + half) << 1
+    }
+
+    fn quadrant(&self, q: usize) -> Cell {
+        let half = self.size / 2.0;
+        Cell {
+            x: self.x + half * (q & 1) as f64,
+            y: self.y + half * (q >> 1) as f64,
+            size: half,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Node {
+    parent: Option<usize>,
+    children: [Option<usize>; 4],
+    items: Vec<ShapeId>,
+}
+
+impl Node {
+    fn is_empty(&self) -> bool {
+        self.items.is_empty() && self.children.iter().all(Option::is_none)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    bounds: Rect,
+    node: usize,
+    /// Position in the z-order, higher is on top
+    z: usize,
+}
+
+/// Shapes' [`geometry::painted_bounds`] in a loose quadtree, kept up to date one
+/// shape at a time alongside the document. Results come back in z-order.
+#[derive(Debug, Default)]
+pub struct SpatialIndex {
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    root: Option<(usize, Cell)>,
+    entries: HashMap<ShapeId, Entry>,
+    next_z: usize,
+}
+
+impl SpatialIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Indexes the shapes `doc` draws, in its z-order, with text measured
+    /// by `metrics`
+    pub fn from_doc(doc: &WhiteboardDoc, metrics: &dyn TextMetrics) -> Self {
+        let mut index = Self::new();
+        for shape in doc.ordered_shapes() {
+            index.insert(shape, metrics);
+        }
+        index
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Bounds the shape is indexed under
+    pub fn bounds(&self, id: &str) -> Option<Rect> {
+        self.entries.get(id).map(|e| e.bounds)
+    }
+
+    /// Adds a shape on top, or moves an indexed one to its new bounds while
+    /// keeping its place in the z-order, mirroring [`WhiteboardDoc::add_shape`]
+    pub fn insert(&mut self, shape: &Shape, metrics: &dyn TextMetrics) {
+        let bounds = geometry::painted_bounds(shape, metrics);
+        // NaN or infinite coordinates would grow the root forever
+        let bounds = if [bounds.x, bounds.y, bounds.w, bounds.h]
+            .iter()
+            .all(|v| v.is_finite())
+        {
+            bounds
+        } else {
+            Rect::default()
+        };
+        let z = match self.entries.get(&shape.id) {
+            Some(entry) => {
+                let (node, z) = (entry.node, entry.z);
+                self.unlink(&shape.id, node);
+                z
+            }
+            None => {
+                self.next_z += 1;
+                self.next_z - 1
+            }
+        };
+        let node = self.place(shape.id.clone(), bounds);
+        self.entries
+            .insert(shape.id.clone(), Entry { bounds, node, z });
+    }
+
+    pub fn remove(&mut self, id: &str) {
+        if let Some(entry) = self.entries.remove(id) {
+            self.unlink(id, entry.node);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Takes the document's z-order after a reorder, bottom first
+    pub fn set_order(&mut self, order: &[ShapeId]) {
+        for (z, id) in order.iter().enumerate() {
+            if let Some(entry) = self.entries.get_mut(id) {
+                entry.z = z;
+            }
+        }
+        self.next_z = self.next_z.max(order.len());
+    }
+
+    /// Shapes whose bounds overlap or touch `rect`, bottom first
+    pub fn query_rect(&self, rect: Rect) -> Vec<ShapeId> {
+        let mut found: Vec<(usize, &ShapeId)> = Vec::new();
+        let mut stack: Vec<(usize, Cell)> = self.root.into_iter().collect();
+        while let Some((node, cell)) = stack.pop() {
+            if !cell.loose().intersects(&rect) {
+                continue;
+            }
+            let node = &self.nodes[node];
+            for id in &node.items {
+                let entry = &self.entries[id];
+                if entry.bounds.intersects(&rect) {
+                    found.push((entry.z, id));
+                }
+            }
+            for (q, child) in node.children.iter().enumerate() {
+                if let Some(child) = child {
+                    stack.push((*child, cell.quadrant(q)));
+                }
+            }
+        }
+        found.sort_unstable_by_key(|(z, _)| *z);
+        found.into_iter().map(|(_, id)| id.clone()).collect()
+    }
+
+    /// Topmost shape of `doc` within `tolerance` world units of what it
+    /// paints at `p`, the indexed and exact version of `hitTestTop` in
+    /// geometry.ts. `metrics` must be what the index was built with.
+    pub fn hit_test(
+        &self,
+        doc: &WhiteboardDoc,
+        p: Point,
+        tolerance: f64,
+        metrics: &dyn TextMetrics,
+    ) -> Option<ShapeId> {
+        let tolerance = tolerance.max(0.0);
+        let probe = Rect::new(p.x, p.y, 0.0, 0.0).expand(tolerance);
+        self.query_rect(probe).into_iter().rev().find(|id| {
+            doc.get(id)
+                .is_some_and(|s| geometry::hit_test(s, p, tolerance, metrics))
+        })
+    }
+
+    /// Shape whose bounds are closest to `p`, the topmost one on ties such as
+    /// several bounds containing `p`
+    pub fn nearest(&self, p: Point) -> Option<ShapeId> {
+        let (root, cell) = self.root?;
+        let mut best: Option<(f64, usize, &ShapeId)> = None;
+        let mut pending = BinaryHeap::from([Pending {
+            distance: distance_to_rect(&cell.loose(), p),
+            node: root,
+            cell,
+        }]);
+        while let Some(next) = pending.pop() {
+            if best.is_some_and(|(d, ..)| next.distance > d) {
+                break;
+            }
+            let node = &self.nodes[next.node];
+            for id in &node.items {
+                let entry = &self.entries[id];
+                let d = distance_to_rect(&entry.bounds, p);
+                let closer = match best {
+                    None => true,
+                    Some((bd, bz, _)) => d < bd || d == bd && entry.z > bz,
+                };
+                if closer {
+                    best = Some((d, entry.z, id));
+                }
+            }
+            for (q, child) in node.children.iter().enumerate() {
+                if let Some(child) = child {
+                    let cell = next.cell.quadrant(q);
+                    pending.push(Pending {
+                        distance: distance_to_rect(&cell.loose(), p),
+                        node: *child,
+                        cell,
+                    });
+                }
+            }
+        }
+        best.map(|(.., id)| id.clone())
+    }
+
+    /// Files `id` under the smallest cell that holds `bounds`, growing the
+    /// tree as needed, and returns that cell's node
+    fn place(&mut self, id: ShapeId, bounds: Rect) -> usize {
+        let centre = Point::new(bounds.x + bounds.w / 2.0, bounds.y + bounds.h / 2.0);
+        let extent = bounds.w.max(bounds.h).max(MIN_CELL);
+
+        let (mut node, mut cell) = match self.root {
+            Some(root) => root,
+            None => {
+                let mut size = MIN_CELL;
+                while size < extent {
+                    size *= 2.0;
+                }
+                let cell = Cell {
+                    x: (centre.x / size).floor() * size,
+                    y: (centre.y / size).floor() * size,
+                    size,
+                };
+                (self.alloc(None), cell)
+            }
+        };
+        while !cell.contains(centre) || cell.size < extent {
+            // double towards the shape; the old root becomes a quadrant
+            let left = centre.x < cell.x;
+            let up = centre.y < cell.y;
+            let parent = self.alloc(None);
+            self.nodes[parent].children[usize::from(left) | usize::from(up) << 1] = Some(node);
+            self.nodes[node].parent = Some(parent);
+            node = parent;
+            cell = Cell {
+                x: if left { cell.x - cell.size } else { cell.x },
+                y: if up { cell.y - cell.size } else { cell.y },
+                size: cell.size * 2.0,
+            };
+        }
+        self.root = Some((node, cell));
+
+        while cell.size / 2.0 >= extent {
+            let q = cell.quadrant_of(centre);
+            node = match self.nodes[node].children[q] {
+                Some(child) => child,
+                None => {
+                    let child = self.alloc(Some(node));
+                    self.nodes[node].children[q] = Some(child);
+                    child
+                }
+            };
+            cell = cell.quadrant(q);
+        }
+        self.nodes[node].items.push(id);
+        node
+    }
+
+    /// Takes `id` off `node` and frees the nodes that are left empty
+    fn unlink(&mut self, id: &str, node: usize) {
+        let items = &mut self.nodes[node].items;
+        if let Some(i) = items.iter().position(|x| x == id) {
+            items.swap_remove(i);
+        }
+        if self.entries.is_empty() {
+            self.nodes.clear();
+            self.free.clear();
+            self.root = None;
+            return;
+        }
+        let mut node = node;
+        while self.nodes[node].is_empty() {
+            let Some(parent) = self.nodes[node].parent else {
+                break;
+            };
+            let slot = self.nodes[parent]
+                .children
+                .iter_mut()
+                .find(|c| **c == Some(node));
+            if let Some(slot) = slot {
+                *slot = None;
+            }
+            self.free.push(node);
+            node = parent;
+        }
+    }
+
+    fn alloc(&mut self, parent: Option<usize>) -> usize {
+        let node = Node {
+            parent,
+            ..Node::default()
+        };
+        match self.free.pop() {
+            Some(i) => {
+                self.nodes[i] = node;
+                i
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+}
+
+/// A node waiting to be searched by [`SpatialIndex::nearest`], closest first
+struct Pending {
+    distance: f64,
+    node: usize,
+    cell: Cell,
+}
+
+impl PartialEq for Pending {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Pending {}
+
+impl PartialOrd for Pending {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Pending {
+    // reversed, so the max-heap pops the smallest distance
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.distance.total_cmp(&self.distance)
+    }
+}
+
+fn distance_to_rect(r: &Rect, p: Point) -> f64 {
+    let dx = (r.x - p.x).max(p.x - r.right()).max(0.0);
+    let dy = (r.y - p.y).max(p.y - r.bottom()).max(0.0);
+    dx.hypot(dy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::text::SansMetrics;
+    use crate::model::ShapeKind;
+
+    fn rect(id: &str, x: f64, y: f64, w: f64, h: f64) -> Shape {
+        Shape {
+            id: id.to_string(),
+            stroke: "#000000".to_string(),
+            fill: Some("#ffffff".to_string()),
+            stroke_width: 0.0,
+            kind: ShapeKind::Rectangle { x, y, w, h },
+        }
+    }
+
+    /// Small deterministic generator so the comparison covers many layouts
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self, range: f64) -> f64 {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (self.0 >> 11) as f64 / (1u64 << 53) as f64 * range
+        }
+    }
+
+    fn brute_force(doc: &WhiteboardDoc, r: Rect) -> Vec<ShapeId> {
+        doc.ordered_shapes()
+            .filter(|s| geometry::painted_bounds(s, &SansMetrics).intersects(&r))
+            .map(|s| s.id.clone())
+            .collect()
+    }
+
+    #[test]
+    fn queries_match_a_linear_scan_through_edits() {
+        let mut rng = Lcg(7);
+        let mut doc = WhiteboardDoc::new();
+        let mut index = SpatialIndex::new();
+        for i in 0..400 {
+            let size = if i % 50 == 0 { 5000.0 } else { 80.0 };
+            let shape = rect(
+                &format!("s{}", i % 300),
+                rng.next(20000.0) - 10000.0,
+                rng.next(20000.0) - 10000.0,
+                rng.next(size),
+                rng.next(size),
+            );
+            index.insert(&shape, &SansMetrics);
+            doc.add_shape(shape);
+            if i % 7 == 0 {
+                let id = format!("s{}", rng.next(300.0) as usize);
+                index.remove(&id);
+                doc.remove_shape(&id);
+            }
+        }
+        let mut order = doc.order.clone();
+        order.reverse();
+        doc.reorder(&order);
+        index.set_order(&doc.order);
+        assert_eq!(index.len(), doc.len());
+
+        for _ in 0..200 {
+            let r = Rect::new(
+                rng.next(24000.0) - 12000.0,
+                rng.next(24000.0) - 12000.0,
+                rng.next(3000.0),
+                rng.next(3000.0),
+            );
+            assert_eq!(index.query_rect(r), brute_force(&doc, r), "{r:?}");
+        }
+
+        for id in doc.order.clone() {
+            index.remove(&id);
+        }
+        assert!(index.is_empty());
+        assert!(index.nodes.is_empty() && index.root.is_none());
+    }
+
+    #[test]
+    fn hit_test_returns_the_topmost_shape() {
+        let mut doc = WhiteboardDoc::new();
+        doc.add_shape(rect("below", 0.0, 0.0, 100.0, 100.0));
+        doc.add_shape(rect("above", 50.0, 50.0, 100.0, 100.0));
+        doc.add_shape(Shape {
+            kind: ShapeKind::Line {
+                a: Point::new(-200.0, 0.0),
+                b: Point::new(-100.0, 0.0),
+            },
+            ..rect("line", 0.0, 0.0, 0.0, 0.0)
+        });
+        let mut index = SpatialIndex::from_doc(&doc, &SansMetrics);
+
+        let at = |index: &SpatialIndex, doc: &WhiteboardDoc, x, y| {
+            index.hit_test(doc, Point::new(x, y), DEFAULT_TOLERANCE, &SansMetrics)
+        };
+        assert_eq!(at(&index, &doc, 75.0, 75.0).as_deref(), Some("above"));
+        assert_eq!(at(&index, &doc, 10.0, 10.0).as_deref(), Some("below"));
+        assert_eq!(at(&index, &doc, -150.0, 5.0).as_deref(), Some("line"));
+        assert_eq!(at(&index, &doc, -150.0, 7.0), None);
+        assert_eq!(at(&index, &doc, 154.0, 154.0).as_deref(), Some("above"));
+        assert_eq!(at(&index, &doc, 155.0, 155.0), None);
+
+        doc.reorder(&["above".to_string(), "below".to_string()]);
+        index.set_order(&doc.order);
+        assert_eq!(at(&index, &doc, 75.0, 75.0).as_deref(), Some("below"));
+    }
+
+    #[test]
+    fn nearest_prefers_closest_then_topmost() {
+        let mut doc = WhiteboardDoc::new();
+        doc.add_shape(rect("far", 1000.0, 1000.0, 10.0, 10.0));
+        doc.add_shape(rect("near", 100.0, 0.0, 10.0, 10.0));
+        doc.add_shape(rect("huge", -5000.0, -5000.0, 4000.0, 4000.0));
+        let mut index = SpatialIndex::from_doc(&doc, &SansMetrics);
+
+        assert_eq!(index.nearest(Point::new(0.0, 0.0)).as_deref(), Some("near"));
+        assert_eq!(
+            index.nearest(Point::new(990.0, 990.0)).as_deref(),
+            Some("far")
+        );
+        index.insert(&rect("cover", 95.0, -5.0, 20.0, 20.0), &SansMetrics);
+        assert_eq!(
+            index.nearest(Point::new(105.0, 5.0)).as_deref(),
+            Some("cover")
+        );
+        index.insert(&rect("near", 2000.0, 2000.0, 10.0, 10.0), &SansMetrics);
+        assert_eq!(
+            index.nearest(Point::new(0.0, 0.0)).as_deref(),
+            Some("cover")
+        );
+        assert_eq!(SpatialIndex::new().nearest(Point::new(0.0, 0.0)), None);
+    }
+}
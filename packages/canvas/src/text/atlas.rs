@@ -0,0 +1,304 @@
+//! Glyph atlas: the distance field of every glyph drawn so far, packed on
+//! shelves into one single-channel image that the renderer mirrors in a
+//! texture. Glyphs are added on first use; once the atlas is at its largest
+//! and full it starts over empty, so text set with the glyphs it had must be
+//! set again, see [`GlyphAtlas::generation`].
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::text::sdf::{GlyphField, GLYPH_EM_PX};
+
+/// Width of the atlas in pixels
+pub const ATLAS_WIDTH: u32 = 1024;
+/// Height the atlas starts at; it doubles whenever it runs out of room
+const INITIAL_HEIGHT: u32 = 256;
+/// Tallest the atlas grows, the texture limit of WebGL2-class devices
+pub const MAX_ATLAS_HEIGHT: u32 = 2048;
+/// Empty pixels between fields, so filtering never reads a neighbour
+pub(crate) const GUTTER: u32 = 1;
+
+/// Where a glyph's field sits in the atlas and where it goes on screen
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AtlasGlyph {
+    /// Left, top, right and bottom edge in atlas pixels
+    pub uv: [f32; 4],
+    /// The same edges relative to the glyph origin, in ems with y down
+    pub plane: [f32; 4],
+}
+
+/// Glyphs are keyed by the index of their font and their glyph id
+type GlyphKey = (usize, u16);
+
+#[derive(Debug, Clone)]
+pub struct GlyphAtlas {
+    height: u32,
+    pixels: Vec<u8>,
+    shelves: Vec<Shelf>,
+    /// `None` for glyphs that draw nothing or did not fit
+    glyphs: HashMap<GlyphKey, Option<AtlasGlyph>>,
+    /// Rows written since the last [`take_dirty`](Self::take_dirty)
+    dirty: Option<Range<u32>>,
+    generation: u64,
+}
+
+#[derive(Debug, Clone)]
+struct Shelf {
+    y: u32,
+    height: u32,
+    /// Left edge of the free space
+    x: u32,
+}
+
+impl Default for GlyphAtlas {
+    fn default() -> Self {
+        Self {
+            height: INITIAL_HEIGHT,
+            pixels: vec![0; (ATLAS_WIDTH * INITIAL_HEIGHT) as usize],
+            shelves: Vec::new(),
+            glyphs: HashMap::new(),
+            dirty: None,
+            generation: 0,
+        }
+    }
+}
+
+impl GlyphAtlas {
+    /// Width and height in pixels
+    pub fn size(&self) -> (u32, u32) {
+        (ATLAS_WIDTH, self.height)
+    }
+
+    /// Rows top first, one byte per pixel
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Number of glyphs placed so far
+    pub fn len(&self) -> usize {
+        self.glyphs.values().flatten().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Bumped whenever the atlas starts over, which drops every glyph placed
+    /// before
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Rows changed since the last call, for uploading just those
+    pub fn take_dirty(&mut self) -> Option<Range<u32>> {
+        self.dirty.take()
+    }
+
+    /// Bilinear sample at (`x`, `y`) in atlas pixels, like a linear sampler
+    /// clamped to the edge, between 0 and 1
+    pub fn sample(&self, x: f32, y: f32) -> f32 {
+        let (x, y) = (x - 0.5, y - 0.5);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let texel = |dx: f32, dy: f32| {
+            let col = (x0 + dx).clamp(0.0, (ATLAS_WIDTH - 1) as f32) as usize;
+            let row = (y0 + dy).clamp(0.0, (self.height - 1) as f32) as usize;
+            self.pixels[row * ATLAS_WIDTH as usize + col] as f32 / 255.0
+        };
+        let top = texel(0.0, 0.0) * (1.0 - fx) + texel(1.0, 0.0) * fx;
+        let bottom = texel(0.0, 1.0) * (1.0 - fx) + texel(1.0, 1.0) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+
+    /// The glyph if it was added before; `Some(None)` when it draws nothing
+    pub(crate) fn get(&self, key: GlyphKey) -> Option<Option<AtlasGlyph>> {
+        self.glyphs.get(&key).copied()
+    }
+
+    /// Packs `field` for `key`, starting over when the atlas is full. A glyph
+    /// that does not fit even in an empty atlas is remembered as not drawn.
+    pub(crate) fn insert(
+        &mut self,
+        key: GlyphKey,
+        field: Option<GlyphField>,
+    ) -> Option<AtlasGlyph> {
+        let placed = field.and_then(|field| {
+            let mut at = self.allocate(field.width, field.height);
+            let fits =
+                field.width + GUTTER <= ATLAS_WIDTH && field.height + GUTTER <= MAX_ATLAS_HEIGHT;
+            if at.is_none() && fits && !self.shelves.is_empty() {
+                self.restart();
+                at = self.allocate(field.width, field.height);
+            }
+            let Some((x, y)) = at else {
+                tracing::warn!("glyph {} does not fit in the atlas, it is not drawn", key.1);
+                return None;
+            };
+            for row in 0..field.height {
+                let src = (row * field.width) as usize;
+                let dst = ((y + row) * ATLAS_WIDTH + x) as usize;
+                self.pixels[dst..dst + field.width as usize]
+                    .copy_from_slice(&field.data[src..src + field.width as usize]);
+            }
+            let rows = y..y + field.height;
+            self.dirty = Some(match self.dirty.take() {
+                Some(d) => d.start.min(rows.start)..d.end.max(rows.end),
+                None => rows,
+            });
+            let (w, h) = (field.width as f64, field.height as f64);
+            Some(AtlasGlyph {
+                uv: [x, y, x + field.width, y + field.height].map(|v| v as f32),
+                plane: [
+                    field.left,
+                    field.top,
+                    field.left + w / GLYPH_EM_PX,
+                    field.top + h / GLYPH_EM_PX,
+                ]
+                .map(|v| v as f32),
+            })
+        });
+        self.glyphs.insert(key, placed);
+        placed
+    }
+
+    /// Drops every glyph, keeping the size reached so far
+    fn restart(&mut self) {
+        tracing::debug!("glyph atlas is full, starting over");
+        self.pixels.fill(0);
+        self.shelves.clear();
+        self.glyphs.clear();
+        self.dirty = Some(0..self.height);
+        self.generation += 1;
+    }
+
+    /// Top left corner of a free `width × height` area, growing the atlas
+    /// when no shelf has room
+    fn allocate(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        let (w, h) = (width + GUTTER, height + GUTTER);
+        if w > ATLAS_WIDTH {
+            return None;
+        }
+        // the lowest shelf that fits wastes the least space
+        let best = self
+            .shelves
+            .iter_mut()
+            .filter(|s| s.height >= h && s.x + w <= ATLAS_WIDTH)
+            .min_by_key(|s| s.height);
+        if let Some(shelf) = best {
+            let x = shelf.x;
+            shelf.x += w;
+            return Some((x, shelf.y));
+        }
+        let y = self.shelves.last().map_or(0, |s| s.y + s.height);
+        while y + h > self.height {
+            if self.height >= MAX_ATLAS_HEIGHT {
+                return None;
+            }
+            self.height *= 2;
+            self.pixels.resize((ATLAS_WIDTH * self.height) as usize, 0);
+        }
+        self.shelves.push(Shelf { y, height: h, x: w });
+        Some((0, y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text::font::Font;
+    use crate::text::sdf::glyph_field;
+    use crate::text::test_font::*;
+
+    fn field(glyph: u16) -> Option<GlyphField> {
+        let font = Font::from_bytes(test_font(true, true)).unwrap();
+        glyph_field(&font.face(), glyph)
+    }
+
+    #[test]
+    fn fields_are_inside_positive_and_placed_by_the_outline() {
+        assert_eq!(field(SPACE), None);
+        let mut atlas = GlyphAtlas::default();
+        // the box of `!` spans 0.05..0.45 em across and 0..0.7 em up
+        let glyph = atlas.insert((0, LATIN), field(LATIN)).unwrap();
+        let [l, t, r, b] = glyph.plane;
+        assert!(l < 0.05 && l > 0.05 - 6.0 / 48.0);
+        assert!(t < -0.7 && b > 0.0 && r > 0.45);
+
+        let at = |x: f32, y: f32| {
+            let [u0, v0, u1, v1] = glyph.uv;
+            atlas.sample(
+                u0 + (x - l) / (r - l) * (u1 - u0),
+                v0 + (y - t) / (b - t) * (v1 - v0),
+            )
+        };
+        assert!(at(0.25, -0.35) > 0.95);
+        assert!((at(0.05, -0.35) - 0.5).abs() < 0.05);
+        // 0.05 em outside is 2.4 of the 4 pixels the field ramps over
+        assert!((at(0.0, -0.35) - 0.2).abs() < 0.05);
+    }
+
+    #[test]
+    fn packs_glyphs_apart_and_tracks_dirty_rows() {
+        let mut atlas = GlyphAtlas::default();
+        let glyphs: Vec<AtlasGlyph> = [NOTDEF, LATIN, THAI_CONSONANT, MAI_EK]
+            .into_iter()
+            .map(|g| atlas.insert((0, g), field(g)).unwrap())
+            .collect();
+        for (i, a) in glyphs.iter().enumerate() {
+            for b in &glyphs[i + 1..] {
+                let apart = a.uv[2] <= b.uv[0]
+                    || b.uv[2] <= a.uv[0]
+                    || a.uv[3] <= b.uv[1]
+                    || b.uv[3] <= a.uv[1];
+                assert!(apart, "{a:?} overlaps {b:?}");
+            }
+        }
+        let bottom = glyphs.iter().map(|g| g.uv[3] as u32).max().unwrap();
+        assert_eq!(atlas.take_dirty(), Some(0..bottom));
+        assert_eq!(atlas.take_dirty(), None);
+        assert_eq!(atlas.get((0, LATIN)), Some(Some(glyphs[1])));
+        assert_eq!(atlas.insert((0, SPACE), None), None);
+        assert_eq!((atlas.len(), atlas.get((0, SPACE))), (4, Some(None)));
+    }
+
+    #[test]
+    fn grows_until_the_limit_then_starts_over() {
+        let tall = GlyphField {
+            width: ATLAS_WIDTH - GUTTER,
+            height: 200,
+            data: vec![255; ((ATLAS_WIDTH - GUTTER) * 200) as usize],
+            left: 0.0,
+            top: 0.0,
+        };
+        let mut atlas = GlyphAtlas::default();
+        for id in 0..10 {
+            assert!(atlas.insert((0, id), Some(tall.clone())).is_some());
+        }
+        assert_eq!(atlas.size(), (ATLAS_WIDTH, MAX_ATLAS_HEIGHT));
+        assert_eq!(
+            atlas.pixels().len(),
+            (ATLAS_WIDTH * MAX_ATLAS_HEIGHT) as usize
+        );
+        assert_eq!(atlas.generation(), 0);
+        atlas.take_dirty();
+
+        // ten shelves of 201 rows fill 2010 of the 2048, so the next one
+        // starts the atlas over at the top
+        let placed = atlas.insert((0, 10), Some(tall.clone())).unwrap();
+        assert_eq!(placed.uv[1], 0.0);
+        assert_eq!((atlas.len(), atlas.generation()), (1, 1));
+        assert_eq!(atlas.get((0, 0)), None);
+        assert_eq!(atlas.take_dirty(), Some(0..MAX_ATLAS_HEIGHT));
+        assert_eq!(atlas.size(), (ATLAS_WIDTH, MAX_ATLAS_HEIGHT));
+
+        let huge = GlyphField {
+            height: MAX_ATLAS_HEIGHT,
+            data: vec![255; ((ATLAS_WIDTH - GUTTER) * MAX_ATLAS_HEIGHT) as usize],
+            ..tall
+        };
+        assert_eq!(atlas.insert((0, 11), Some(huge)), None);
+        assert_eq!(atlas.get((0, 11)), Some(None));
+        // and keeps what it has
+        assert_eq!((atlas.len(), atlas.generation()), (1, 1));
+    }
+}
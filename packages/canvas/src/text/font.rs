@@ -0,0 +1,110 @@
+//! Fonts loaded from TrueType or OpenType bytes
+
+use std::fmt;
+use std::sync::Arc;
+
+use rustybuzz::ttf_parser::GlyphId;
+use rustybuzz::Face;
+
+use crate::error::CanvasError;
+
+/// One face of a font file. The bytes are kept and parsed again whenever
+/// the face is needed, which only reads the table directory.
+#[derive(Clone)]
+pub struct Font {
+    data: Arc<[u8]>,
+    index: u32,
+    /// `hhea` metrics in ems
+    ascent: f64,
+    descent: f64,
+    line_gap: f64,
+}
+
+impl fmt::Debug for Font {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Font")
+            .field("bytes", &self.data.len())
+            .field("index", &self.index)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Font {
+    /// The first face of a `.ttf` or `.otf` file, or of a collection
+    pub fn from_bytes(data: impl Into<Arc<[u8]>>) -> Result<Self, CanvasError> {
+        Self::from_collection(data, 0)
+    }
+
+    /// Face `index` of a `.ttc` collection
+    pub fn from_collection(data: impl Into<Arc<[u8]>>, index: u32) -> Result<Self, CanvasError> {
+        let data = data.into();
+        let face = rustybuzz::ttf_parser::Face::parse(&data, index)
+            .map_err(|e| CanvasError::InvalidFont(e.to_string()))?;
+        let em = face.units_per_em() as f64;
+        let (ascent, descent, line_gap) = (
+            face.ascender() as f64 / em,
+            -face.descender() as f64 / em,
+            face.line_gap() as f64 / em,
+        );
+        Ok(Self {
+            data,
+            index,
+            ascent,
+            descent,
+            line_gap,
+        })
+    }
+
+    pub(crate) fn face(&self) -> Face<'_> {
+        Face::from_slice(&self.data, self.index).expect("parsed when the font was loaded")
+    }
+
+    /// The whole font file
+    pub(crate) fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Index of the face in the file
+    pub(crate) fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// Height above the baseline, in ems
+    pub fn ascent(&self) -> f64 {
+        self.ascent
+    }
+
+    /// Depth below the baseline, in ems
+    pub fn descent(&self) -> f64 {
+        self.descent
+    }
+
+    pub fn line_gap(&self) -> f64 {
+        self.line_gap
+    }
+
+    /// Whether the font has a glyph for `c`
+    pub fn covers(&self, c: char) -> bool {
+        self.face()
+            .glyph_index(c)
+            .is_some_and(|id| id != GlyphId(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text::test_font::test_font;
+
+    #[test]
+    fn reads_metrics_and_coverage() {
+        let font = Font::from_bytes(test_font(false, true)).unwrap();
+        assert_eq!((font.ascent(), font.descent()), (0.8, 0.2));
+        assert!(font.covers('ก') && font.covers(' '));
+        assert!(!font.covers('a'));
+        assert!(matches!(
+            Font::from_bytes(vec![0u8; 16]),
+            Err(CanvasError::InvalidFont(_))
+        ));
+    }
+}
@@ -0,0 +1,557 @@
+//! Shaping and line breaking. Each paragraph is split into runs by the first
+//! font that covers each character, the runs are shaped with rustybuzz, and
+//! lines break greedily at the opportunities of UAX #14.
+//!
+//! Thai is written without spaces between words and there is no dictionary
+//! here, so a line may also end before the leading vowels เ แ โ ใ ไ, which
+//! always start a syllable, and as a last resort between any two clusters.
+//! Right-to-left runs are shaped and drawn right to left, but runs are not
+//! reordered against each other.
+
+use std::ops::Range;
+
+use rustybuzz::{Direction, Face, UnicodeBuffer};
+use serde::{Deserialize, Serialize};
+use unicode_linebreak::{linebreaks, BreakOpportunity};
+
+use crate::geometry::text::{is_combining, TextMetrics};
+use crate::model::{Rect, ShapeKind};
+use crate::text::font::Font;
+
+/// Distance between baselines in ems, what browsers use for `normal`
+/// with most sans-serif faces
+pub const DEFAULT_LINE_HEIGHT: f64 = 1.2;
+
+/// Where lines sit relative to the anchor, like `CanvasTextAlign`
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TextAlign {
+    /// Lines start at the anchor
+    #[default]
+    Left,
+    Center,
+    /// Lines end at the anchor
+    Right,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextStyle {
+    pub font_size: f64,
+    /// Distance between baselines as a multiple of `font_size`
+    pub line_height: f64,
+    pub align: TextAlign,
+    /// Longest a line gets before it wraps, in the units of `font_size`;
+    /// `None` only breaks at newlines
+    pub max_width: Option<f64>,
+}
+
+impl TextStyle {
+    pub fn new(font_size: f64) -> Self {
+        Self {
+            font_size,
+            line_height: DEFAULT_LINE_HEIGHT,
+            align: TextAlign::Left,
+            max_width: None,
+        }
+    }
+
+    /// Style of a text shape, `None` for other kinds. Sizes that are not
+    /// finite fall back to the defaults.
+    pub fn of(kind: &ShapeKind) -> Option<Self> {
+        let ShapeKind::Text {
+            font_size,
+            align,
+            max_width,
+            line_height,
+            ..
+        } = kind
+        else {
+            return None;
+        };
+        Some(Self {
+            line_height: line_height
+                .filter(|h| h.is_finite())
+                .unwrap_or(DEFAULT_LINE_HEIGHT),
+            align: align.unwrap_or_default(),
+            max_width: max_width.filter(|w| w.is_finite()),
+            ..Self::new(*font_size)
+        })
+    }
+}
+
+/// A glyph placed with its origin on the baseline. Positions are relative
+/// to the anchor, the first line's baseline where `align` puts it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PositionedGlyph {
+    /// Index of the font in the stack the layout was made with
+    pub font: usize,
+    pub glyph: u16,
+    pub x: f64,
+    pub y: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Line {
+    /// Bytes of the text on this line, newline excluded
+    pub text: Range<usize>,
+    /// Left edge relative to the anchor
+    pub x: f64,
+    pub baseline: f64,
+    /// Advance of the line without its trailing spaces
+    pub width: f64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TextLayout {
+    pub glyphs: Vec<PositionedGlyph>,
+    pub lines: Vec<Line>,
+    /// Line box above and below each baseline, from the first font
+    pub ascent: f64,
+    pub descent: f64,
+}
+
+impl TextLayout {
+    /// Box around every line, relative to the anchor
+    pub fn bounds(&self) -> Rect {
+        let left = self.lines.iter().map(|l| l.x).fold(f64::MAX, f64::min);
+        let right = self
+            .lines
+            .iter()
+            .map(|l| l.x + l.width)
+            .fold(f64::MIN, f64::max);
+        let Some(last) = self.lines.last() else {
+            return Rect::new(0.0, 0.0, 0.0, 0.0);
+        };
+        Rect::new(
+            left,
+            -self.ascent,
+            right - left,
+            last.baseline + self.descent + self.ascent,
+        )
+    }
+}
+
+#[derive(Debug)]
+struct ShapedGlyph {
+    font: usize,
+    glyph: u16,
+    advance: f64,
+    offset: [f64; 2],
+}
+
+/// Glyphs that belong together and cannot be split across lines, such as a
+/// Thai consonant with its vowel and tone marks
+#[derive(Debug)]
+struct Cluster {
+    /// Bytes of the paragraph
+    text: Range<usize>,
+    glyphs: Range<usize>,
+    width: f64,
+    whitespace: bool,
+    /// Index of the run, to keep right-to-left runs together
+    run: usize,
+    rtl: bool,
+}
+
+/// Lays `text` out with `fonts`, falling back along the list for characters
+/// a font does not cover. Without any font the layout is empty.
+pub fn layout(fonts: &[Font], text: &str, style: &TextStyle) -> TextLayout {
+    let Some(first) = fonts.first() else {
+        return TextLayout::default();
+    };
+    let faces: Vec<Face> = fonts.iter().map(Font::face).collect();
+    let size = style.font_size;
+    let mut out = TextLayout {
+        ascent: first.ascent() * size,
+        descent: first.descent() * size,
+        ..TextLayout::default()
+    };
+    let mut start = 0;
+    for paragraph in text.split('\n') {
+        let trimmed = paragraph.strip_suffix('\r').unwrap_or(paragraph);
+        let (glyphs, clusters) = shape_paragraph(&faces, trimmed, size);
+        for range in break_lines(trimmed, &clusters, style.max_width) {
+            place_line(&glyphs, &clusters, range, start, style, &mut out);
+        }
+        start += paragraph.len() + 1;
+    }
+    out
+}
+
+/// Breaks `text` into lines the way [`layout`] does, but measures it with
+/// `metrics` instead of shaping it with a font, for output that leaves
+/// drawing the glyphs to someone else
+pub fn measure<M: TextMetrics + ?Sized>(metrics: &M, text: &str, style: &TextStyle) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for paragraph in text.split('\n') {
+        let trimmed = paragraph.strip_suffix('\r').unwrap_or(paragraph);
+        let mut clusters: Vec<Cluster> = Vec::new();
+        for (i, c) in trimmed.char_indices() {
+            let end = i + c.len_utf8();
+            match clusters.last_mut() {
+                Some(cluster) if is_combining(c as u32) => cluster.text.end = end,
+                _ => clusters.push(Cluster {
+                    text: i..end,
+                    glyphs: 0..0,
+                    width: 0.0,
+                    whitespace: c.is_whitespace(),
+                    run: 0,
+                    rtl: false,
+                }),
+            }
+        }
+        for cluster in &mut clusters {
+            cluster.width = metrics.advance(&trimmed[cluster.text.clone()], style.font_size);
+        }
+        for range in break_lines(trimmed, &clusters, style.max_width) {
+            let index = lines.len();
+            lines.push(line_of(&clusters[range], start, index, style));
+        }
+        start += paragraph.len() + 1;
+    }
+    lines
+}
+
+fn shape_paragraph(faces: &[Face], text: &str, size: f64) -> (Vec<ShapedGlyph>, Vec<Cluster>) {
+    let mut glyphs = Vec::new();
+    let mut clusters: Vec<Cluster> = Vec::new();
+    for (run, (range, font)) in runs(faces, text).into_iter().enumerate() {
+        let face = &faces[font];
+        let mut buffer = UnicodeBuffer::new();
+        buffer.push_str(&text[range.clone()]);
+        buffer.guess_segment_properties();
+        let rtl = buffer.direction() == Direction::RightToLeft;
+        let shaped = rustybuzz::shape(face, &[], buffer);
+        let scale = size / face.units_per_em() as f64;
+        let mut run_glyphs: Vec<(usize, ShapedGlyph)> = shaped
+            .glyph_infos()
+            .iter()
+            .zip(shaped.glyph_positions())
+            .map(|(info, pos)| {
+                let glyph = ShapedGlyph {
+                    font,
+                    glyph: info.glyph_id as u16,
+                    advance: pos.x_advance as f64 * scale,
+                    offset: [pos.x_offset as f64 * scale, -pos.y_offset as f64 * scale],
+                };
+                (range.start + info.cluster as usize, glyph)
+            })
+            .collect();
+        if rtl {
+            // logical order for line breaking, flipped back when placed
+            run_glyphs.reverse();
+        }
+        let first_cluster = clusters.len();
+        for (at, glyph) in run_glyphs {
+            match clusters[first_cluster..].last_mut() {
+                Some(c) if c.text.start == at => {
+                    c.width += glyph.advance;
+                    c.glyphs.end += 1;
+                }
+                _ => clusters.push(Cluster {
+                    text: at..at,
+                    glyphs: glyphs.len()..glyphs.len() + 1,
+                    width: glyph.advance,
+                    whitespace: false,
+                    run,
+                    rtl,
+                }),
+            }
+            glyphs.push(glyph);
+        }
+        // each cluster runs up to the next one in the text
+        let mut ends: Vec<usize> = clusters[first_cluster..]
+            .iter()
+            .map(|c| c.text.start)
+            .skip(1)
+            .collect();
+        ends.push(range.end);
+        for (c, end) in clusters[first_cluster..].iter_mut().zip(ends) {
+            c.text.end = end.max(c.text.start);
+            c.whitespace = text[c.text.clone()].chars().all(char::is_whitespace);
+        }
+    }
+    (glyphs, clusters)
+}
+
+/// Splits `text` where the font changes. Each character goes to the first
+/// font that has it, but marks, joiners and spaces stay with the character
+/// before them when that font covers them too, so they are shaped together.
+fn runs(faces: &[Face], text: &str) -> Vec<(Range<usize>, usize)> {
+    let covers = |font: usize, c: char| faces[font].glyph_index(c).is_some_and(|g| g.0 != 0);
+    let mut runs: Vec<(Range<usize>, usize)> = Vec::new();
+    for (i, c) in text.char_indices() {
+        let end = i + c.len_utf8();
+        let current = runs.last().map(|r| r.1);
+        let sticky = is_combining(c as u32) || c.is_whitespace();
+        let font = match current {
+            Some(f) if sticky && (covers(f, c) || is_combining(c as u32)) => f,
+            _ => (0..faces.len())
+                .find(|&f| covers(f, c))
+                .or(current)
+                .unwrap_or(0),
+        };
+        match runs.last_mut() {
+            Some(run) if run.1 == font => run.0.end = end,
+            _ => runs.push((i..end, font)),
+        }
+    }
+    runs
+}
+
+/// Clusters on each line, breaking greedily before the first cluster that
+/// would overflow `max_width`
+fn break_lines(text: &str, clusters: &[Cluster], max_width: Option<f64>) -> Vec<Range<usize>> {
+    let Some(max_width) = max_width else {
+        return std::iter::once(0..clusters.len()).collect();
+    };
+    let mut allowed = vec![false; text.len() + 1];
+    for (at, opportunity) in linebreaks(text) {
+        if opportunity == BreakOpportunity::Allowed {
+            allowed[at] = true;
+        }
+    }
+    for (at, c) in text.char_indices() {
+        if ('\u{0e40}'..='\u{0e44}').contains(&c) {
+            allowed[at] = true;
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut start, mut width, mut last_break) = (0, 0.0, None);
+    for (k, cluster) in clusters.iter().enumerate() {
+        if k > start && allowed[cluster.text.start] {
+            last_break = Some(k);
+        }
+        if k > start && !cluster.whitespace && width + cluster.width > max_width {
+            let at = last_break.unwrap_or(k);
+            lines.push(start..at);
+            start = at;
+            width = clusters[at..k].iter().map(|c| c.width).sum();
+            last_break = None;
+        }
+        width += cluster.width;
+    }
+    lines.push(start..clusters.len());
+    lines
+}
+
+fn place_line(
+    glyphs: &[ShapedGlyph],
+    clusters: &[Cluster],
+    range: Range<usize>,
+    paragraph_start: usize,
+    style: &TextStyle,
+    out: &mut TextLayout,
+) {
+    let line = &clusters[range.clone()];
+    let placed = line_of(line, paragraph_start, out.lines.len(), style);
+
+    // right-to-left runs go back to visual order
+    let mut order = Vec::with_capacity(line.len());
+    let mut k = 0;
+    while k < line.len() {
+        if line[k].rtl {
+            let end = k + line[k..]
+                .iter()
+                .take_while(|c| c.run == line[k].run)
+                .count();
+            order.extend((k..end).rev());
+            k = end;
+        } else {
+            order.push(k);
+            k += 1;
+        }
+    }
+
+    let mut pen = placed.x;
+    for cluster in order.into_iter().map(|k| &line[k]) {
+        let mut indices: Vec<usize> = cluster.glyphs.clone().collect();
+        if cluster.rtl {
+            indices.reverse();
+        }
+        for glyph in indices.into_iter().map(|i| &glyphs[i]) {
+            out.glyphs.push(PositionedGlyph {
+                font: glyph.font,
+                glyph: glyph.glyph,
+                x: pen + glyph.offset[0],
+                y: placed.baseline + glyph.offset[1],
+            });
+            pen += glyph.advance;
+        }
+    }
+    out.lines.push(placed);
+}
+
+/// The `index`th line of a text, holding `clusters`, aligned on the anchor
+fn line_of(clusters: &[Cluster], paragraph_start: usize, index: usize, style: &TextStyle) -> Line {
+    let width: f64 = clusters
+        .iter()
+        .rev()
+        .skip_while(|c| c.whitespace)
+        .map(|c| c.width)
+        .sum();
+    let x = match style.align {
+        TextAlign::Left => 0.0,
+        TextAlign::Center => -width / 2.0,
+        TextAlign::Right => -width,
+    };
+    let text = match (clusters.first(), clusters.last()) {
+        (Some(a), Some(b)) => paragraph_start + a.text.start..paragraph_start + b.text.end,
+        _ => paragraph_start..paragraph_start,
+    };
+    Line {
+        text,
+        x,
+        baseline: index as f64 * style.line_height * style.font_size,
+        width,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text::test_font::*;
+
+    fn fonts(stack: &[(bool, bool)]) -> Vec<Font> {
+        stack
+            .iter()
+            .map(|&(latin, thai)| Font::from_bytes(test_font(latin, thai)).unwrap())
+            .collect()
+    }
+
+    fn wrapped(max_width: f64) -> TextStyle {
+        TextStyle {
+            max_width: Some(max_width),
+            ..TextStyle::new(10.0)
+        }
+    }
+
+    fn ids(layout: &TextLayout) -> Vec<u16> {
+        layout.glyphs.iter().map(|g| g.glyph).collect()
+    }
+
+    #[test]
+    fn thai_marks_are_reordered_and_take_no_room() {
+        let layout = layout(&fonts(&[(false, true)]), "ก่ำ", &TextStyle::new(10.0));
+        // SARA AM splits into NIKHAHIT and SARA AA, the NIKHAHIT before the tone
+        assert_eq!(ids(&layout), [THAI_CONSONANT, NIKHAHIT, MAI_EK, SARA_AA]);
+        assert_eq!(layout.lines.len(), 1);
+        assert!((layout.lines[0].width - 10.0).abs() < 1e-9);
+        assert!((layout.glyphs[3].x - 6.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn falls_back_per_character() {
+        let layout = layout(
+            &fonts(&[(true, false), (false, true)]),
+            "a ก!",
+            &TextStyle::new(10.0),
+        );
+        let used: Vec<(usize, u16)> = layout.glyphs.iter().map(|g| (g.font, g.glyph)).collect();
+        assert_eq!(
+            used,
+            [(0, LATIN), (0, SPACE), (1, THAI_CONSONANT), (0, LATIN)]
+        );
+        let xs: Vec<f64> = layout.glyphs.iter().map(|g| g.x).collect();
+        assert_eq!(xs, [0.0, 5.0, 7.5, 13.5]);
+    }
+
+    #[test]
+    fn wraps_at_spaces_and_before_thai_leading_vowels() {
+        let layout_of =
+            |text: &str, width: f64| layout(&fonts(&[(true, true)]), text, &wrapped(width));
+
+        let words = layout_of("aa aa aa", 25.0);
+        let lines: Vec<(Range<usize>, f64, f64)> = words
+            .lines
+            .iter()
+            .map(|l| (l.text.clone(), l.width, l.baseline))
+            .collect();
+        // the space stays at the end of the first line and is not measured
+        assert_eq!(lines, [(0..6, 22.5, 0.0), (6..8, 10.0, 12.0)]);
+
+        let thai = layout_of("กกเกก", 15.0);
+        let lines: Vec<Range<usize>> = thai.lines.iter().map(|l| l.text.clone()).collect();
+        assert_eq!(lines, [0..6, 6..15]);
+        assert_eq!(thai.glyphs[2].glyph, SARA_E);
+        assert_eq!((thai.glyphs[2].x, thai.glyphs[2].y), (0.0, 12.0));
+
+        // a word longer than the line breaks between clusters
+        let long = layout_of("aaaa", 12.0);
+        assert_eq!(long.lines.len(), 2);
+        assert_eq!(long.lines[1].text, 2..4);
+    }
+
+    /// The advances of the Latin test font: half an em per letter and a
+    /// quarter per space
+    struct TestMetrics;
+
+    impl TextMetrics for TestMetrics {
+        fn advance(&self, text: &str, font_size: f64) -> f64 {
+            let ems: f64 = text
+                .chars()
+                .map(|c| if c == ' ' { 0.25 } else { 0.5 })
+                .sum();
+            ems * font_size
+        }
+
+        fn ascent(&self) -> f64 {
+            0.8
+        }
+
+        fn descent(&self) -> f64 {
+            0.2
+        }
+    }
+
+    #[test]
+    fn measuring_breaks_lines_like_layout() {
+        let fonts = fonts(&[(true, false)]);
+        for (text, width) in [("aa aa aa\naaaa", 25.0), ("aaaa", 12.0), ("a a", 99.0)] {
+            let style = TextStyle {
+                align: TextAlign::Center,
+                ..wrapped(width)
+            };
+            assert_eq!(
+                measure(&TestMetrics, text, &style),
+                layout(&fonts, text, &style).lines,
+                "{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn aligns_lines_to_the_anchor() {
+        let style = TextStyle {
+            align: TextAlign::Center,
+            line_height: 1.5,
+            ..TextStyle::new(10.0)
+        };
+        let layout = layout(&fonts(&[(true, false)]), "aa\r\naaaa", &style);
+        let lines: Vec<(Range<usize>, f64, f64)> = layout
+            .lines
+            .iter()
+            .map(|l| (l.text.clone(), l.x, l.baseline))
+            .collect();
+        assert_eq!(lines, [(0..2, -5.0, 0.0), (4..8, -10.0, 15.0)]);
+        assert_eq!(layout.glyphs[0].x, -5.0);
+        assert_eq!(layout.bounds(), Rect::new(-10.0, -8.0, 20.0, 25.0));
+
+        let right = TextStyle {
+            align: TextAlign::Right,
+            ..TextStyle::new(10.0)
+        };
+        let layout = super::layout(&fonts(&[(true, false)]), "aa ", &right);
+        assert_eq!(layout.lines[0].x, -10.0);
+    }
+
+    #[test]
+    fn nothing_is_laid_out_without_a_font() {
+        assert_eq!(
+            layout(&[], "text", &TextStyle::new(10.0)),
+            TextLayout::default()
+        );
+    }
+}
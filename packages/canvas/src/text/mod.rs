@@ -0,0 +1,129 @@
+//! Text for the GPU renderers. Fonts are loaded from bytes the app supplies,
+//! nothing is bundled; text is shaped with rustybuzz so Thai gets its marks
+//! stacked and reordered, broken into lines, and drawn from a signed
+//! distance field atlas that stays sharp at every zoom. Until a font is
+//! loaded the GPU renderers leave text out.
+
+pub mod atlas;
+pub mod font;
+pub mod layout;
+pub(crate) mod sdf;
+pub(crate) mod subset;
+#[cfg(test)]
+pub(crate) mod test_font;
+
+use self::atlas::{AtlasGlyph, GlyphAtlas};
+use self::font::Font;
+use self::layout::{PositionedGlyph, TextLayout, TextStyle};
+use crate::geometry::text::{SansMetrics, TextMetrics};
+use crate::model::Rect;
+
+/// The loaded fonts, in fallback order, and the glyph atlas they share
+#[derive(Debug, Clone, Default)]
+pub struct Typesetter {
+    fonts: Vec<Font>,
+    atlas: GlyphAtlas,
+}
+
+impl Typesetter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `font` behind those already loaded, so it only draws the
+    /// characters they lack
+    pub fn add_font(&mut self, font: Font) {
+        self.fonts.push(font);
+    }
+
+    pub fn fonts(&self) -> &[Font] {
+        &self.fonts
+    }
+
+    pub fn layout(&self, text: &str, style: &TextStyle) -> TextLayout {
+        layout::layout(&self.fonts, text, style)
+    }
+
+    /// Where `glyph` is in the atlas, computing its field on first use.
+    /// `None` for glyphs that draw nothing.
+    pub fn glyph(&mut self, glyph: &PositionedGlyph) -> Option<AtlasGlyph> {
+        let key = (glyph.font, glyph.glyph);
+        if let Some(entry) = self.atlas.get(key) {
+            return entry;
+        }
+        let field = self
+            .fonts
+            .get(glyph.font)
+            .and_then(|font| sdf::glyph_field(&font.face(), glyph.glyph));
+        self.atlas.insert(key, field)
+    }
+
+    pub fn atlas(&self) -> &GlyphAtlas {
+        &self.atlas
+    }
+
+    pub(crate) fn atlas_mut(&mut self) -> &mut GlyphAtlas {
+        &mut self.atlas
+    }
+}
+
+/// Measures with the loaded fonts, the way they are drawn, or with
+/// [`SansMetrics`] until there is one
+impl TextMetrics for Typesetter {
+    fn advance(&self, text: &str, font_size: f64) -> f64 {
+        if self.fonts.is_empty() {
+            return SansMetrics.advance(text, font_size);
+        }
+        let line = text.replace(['\r', '\n'], " ");
+        self.layout(&line, &TextStyle::new(font_size)).bounds().w
+    }
+
+    fn ascent(&self) -> f64 {
+        self.fonts
+            .first()
+            .map_or_else(|| SansMetrics.ascent(), Font::ascent)
+    }
+
+    fn descent(&self) -> f64 {
+        self.fonts
+            .first()
+            .map_or_else(|| SansMetrics.descent(), Font::descent)
+    }
+
+    fn text_box(&self, x: f64, y: f64, text: &str, style: &TextStyle) -> Rect {
+        if self.fonts.is_empty() {
+            return SansMetrics.text_box(x, y, text, style);
+        }
+        let r = self.layout(text, style).bounds();
+        Rect::new(x + r.x, y + r.y, r.w, r.h)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text::layout::TextAlign;
+    use crate::text::test_font::test_font;
+
+    #[test]
+    fn measures_with_the_loaded_font() {
+        let mut text = Typesetter::new();
+        let style = TextStyle::new(20.0);
+        let before = text.text_box(0.0, 0.0, "!!\n!", &style);
+        assert_eq!(before, SansMetrics.text_box(0.0, 0.0, "!!\n!", &style));
+
+        text.add_font(Font::from_bytes(test_font(true, false)).unwrap());
+        let centred = TextStyle {
+            align: TextAlign::Center,
+            ..style
+        };
+        let r = text.text_box(100.0, 50.0, "!!\n!", &centred);
+        let layout = text.layout("!!\n!", &centred);
+        assert_eq!(layout.lines.len(), 2);
+        assert_eq!(r.w, layout.lines[0].width);
+        assert_eq!((r.x + r.right()) / 2.0, 100.0);
+        assert_eq!(r.y, 50.0 - text.ascent() * 20.0);
+        assert_eq!(r.h, (1.2 + text.ascent() + text.descent()) * 20.0);
+        assert_eq!(text.advance("!!", 20.0), layout.lines[0].width);
+    }
+}
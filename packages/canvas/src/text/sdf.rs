@@ -0,0 +1,173 @@
+//! Signed distance fields of glyph outlines. A field stores, per pixel, the
+//! distance to the nearest point of the outline, so the GPU can cut a sharp
+//! edge out of it at any scale instead of blurring a bitmap.
+
+use rustybuzz::ttf_parser::{GlyphId, OutlineBuilder};
+use rustybuzz::Face;
+
+/// Atlas pixels per em that fields are computed at
+pub const GLYPH_EM_PX: f64 = 48.0;
+/// Distance in atlas pixels the field covers on either side of the outline
+pub const SDF_RANGE: f64 = 4.0;
+/// Pixels of field kept around the outline's bounds
+const PADDING: f64 = SDF_RANGE + 1.0;
+/// Largest distance between a curve and the lines replacing it, in pixels
+const FLATTEN_TOLERANCE: f64 = 0.1;
+const MAX_CURVE_STEPS: usize = 64;
+
+/// Field of one glyph: `width × height` bytes, rows top first, 128 on the
+/// outline and growing towards the inside
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct GlyphField {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) data: Vec<u8>,
+    /// Top left corner relative to the glyph origin, in ems, y down
+    pub(crate) left: f64,
+    pub(crate) top: f64,
+}
+
+/// The field of `glyph`, or `None` when it draws nothing, like a space
+pub(crate) fn glyph_field(face: &Face, glyph: u16) -> Option<GlyphField> {
+    let mut outline = Outline {
+        scale: GLYPH_EM_PX / face.units_per_em() as f64,
+        ..Outline::default()
+    };
+    face.outline_glyph(GlyphId(glyph), &mut outline)?;
+    let edges = outline.edges;
+    if edges.is_empty() {
+        return None;
+    }
+    let (mut min, mut max) = ([f64::MAX; 2], [f64::MIN; 2]);
+    for (a, _) in &edges {
+        for k in 0..2 {
+            min[k] = min[k].min(a[k]);
+            max[k] = max[k].max(a[k]);
+        }
+    }
+    // font units point up, the atlas down
+    let x0 = (min[0] - PADDING).floor();
+    let y_top = (max[1] + PADDING).ceil();
+    let width = ((max[0] + PADDING).ceil() - x0) as u32;
+    let height = (y_top - (min[1] - PADDING).floor()) as u32;
+
+    let mut data = Vec::with_capacity((width * height) as usize);
+    for row in 0..height {
+        let y = y_top - row as f64 - 0.5;
+        for col in 0..width {
+            let p = [x0 + col as f64 + 0.5, y];
+            let mut nearest = f64::MAX;
+            let mut winding = 0;
+            for &(a, b) in &edges {
+                nearest = nearest.min(segment_distance(p, a, b));
+                winding += crossing(p, a, b);
+            }
+            let signed = if winding != 0 { nearest } else { -nearest };
+            let value = (0.5 + signed / (2.0 * SDF_RANGE)).clamp(0.0, 1.0);
+            data.push((value * 255.0).round() as u8);
+        }
+    }
+    Some(GlyphField {
+        width,
+        height,
+        data,
+        left: x0 / GLYPH_EM_PX,
+        top: -y_top / GLYPH_EM_PX,
+    })
+}
+
+/// Collects an outline as line segments, in field pixels with y up
+#[derive(Default)]
+struct Outline {
+    scale: f64,
+    start: [f64; 2],
+    pen: [f64; 2],
+    edges: Vec<([f64; 2], [f64; 2])>,
+}
+
+impl Outline {
+    fn point(&self, x: f32, y: f32) -> [f64; 2] {
+        [x as f64 * self.scale, y as f64 * self.scale]
+    }
+
+    fn line(&mut self, to: [f64; 2]) {
+        if to != self.pen {
+            self.edges.push((self.pen, to));
+        }
+        self.pen = to;
+    }
+
+    /// Flattens the Bézier curve from the pen through `controls` into lines
+    fn curve(&mut self, controls: &[[f64; 2]]) {
+        let p0 = self.pen;
+        let mut points = vec![p0];
+        points.extend_from_slice(controls);
+        // the second differences bound how far the curve strays from a chord
+        let degree = points.len() - 1;
+        let bend = points
+            .windows(3)
+            .map(|w| (w[0][0] - 2.0 * w[1][0] + w[2][0]).hypot(w[0][1] - 2.0 * w[1][1] + w[2][1]))
+            .fold(0.0, f64::max)
+            * (degree * (degree - 1)) as f64
+            / 8.0;
+        let steps = ((bend / FLATTEN_TOLERANCE).sqrt().ceil() as usize).clamp(1, MAX_CURVE_STEPS);
+        for i in 1..=steps {
+            let t = i as f64 / steps as f64;
+            let mut level = points.clone();
+            while level.len() > 1 {
+                level = level
+                    .windows(2)
+                    .map(|w| [0, 1].map(|k| w[0][k] + (w[1][k] - w[0][k]) * t))
+                    .collect();
+            }
+            self.line(level[0]);
+        }
+    }
+}
+
+impl OutlineBuilder for Outline {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.close();
+        self.start = self.point(x, y);
+        self.pen = self.start;
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let to = self.point(x, y);
+        self.line(to);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let controls = [self.point(x1, y1), self.point(x, y)];
+        self.curve(&controls);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let controls = [self.point(x1, y1), self.point(x2, y2), self.point(x, y)];
+        self.curve(&controls);
+    }
+
+    fn close(&mut self) {
+        let start = self.start;
+        self.line(start);
+    }
+}
+
+fn segment_distance(p: [f64; 2], a: [f64; 2], b: [f64; 2]) -> f64 {
+    let (vx, vy) = (b[0] - a[0], b[1] - a[1]);
+    let (wx, wy) = (p[0] - a[0], p[1] - a[1]);
+    let t = ((vx * wx + vy * wy) / (vx * vx + vy * vy)).clamp(0.0, 1.0);
+    (wx - vx * t).hypot(wy - vy * t)
+}
+
+/// Winding contribution of the edge `a`–`b` for a ray from `p` towards +x
+fn crossing(p: [f64; 2], a: [f64; 2], b: [f64; 2]) -> i32 {
+    let side = (b[0] - a[0]) * (p[1] - a[1]) - (p[0] - a[0]) * (b[1] - a[1]);
+    if a[1] <= p[1] && p[1] < b[1] && side > 0.0 {
+        1
+    } else if b[1] <= p[1] && p[1] < a[1] && side < 0.0 {
+        -1
+    } else {
+        0
+    }
+}
@@ -0,0 +1,244 @@
+//! TrueType subsetting for embedding fonts in PDFs. Glyph ids are kept, so a
+//! PDF can address glyphs by their id in the original font: the glyphs that
+//! are not needed are emptied rather than removed, and only the tables a PDF
+//! viewer reads are written.
+
+use std::collections::BTreeSet;
+
+use rustybuzz::ttf_parser::{RawFace, Tag};
+
+use crate::text::font::Font;
+
+/// Tables a viewer needs to draw an embedded TrueType font, in tag order
+const TABLES: [&[u8; 4]; 9] = [
+    b"cvt ", b"fpgm", b"glyf", b"head", b"hhea", b"hmtx", b"loca", b"maxp", b"prep",
+];
+
+/// Offset of `indexToLocFormat` in `head`
+const LOCA_FORMAT: usize = 50;
+/// Offset of `checkSumAdjustment` in `head`
+const CHECKSUM_ADJUSTMENT: usize = 8;
+
+/// Component flags of composite glyphs
+const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+const WE_HAVE_A_SCALE: u16 = 0x0008;
+const MORE_COMPONENTS: u16 = 0x0020;
+const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+
+/// Whether `font` has TrueType outlines, which [`subset`] needs
+pub(crate) fn has_glyf(font: &Font) -> bool {
+    RawFace::parse(font.data(), font.index())
+        .is_ok_and(|raw| raw.table(Tag::from_bytes(b"glyf")).is_some())
+}
+
+/// `font` with only `glyphs`, the components they are built from and
+/// `.notdef` left, as a standalone TrueType file. `None` when the font has
+/// no TrueType outlines or its tables are malformed.
+pub(crate) fn subset(font: &Font, glyphs: &BTreeSet<u16>) -> Option<Vec<u8>> {
+    let raw = RawFace::parse(font.data(), font.index()).ok()?;
+    let table = |tag: &[u8; 4]| raw.table(Tag::from_bytes(tag));
+    let head = table(b"head")?;
+    let glyf = table(b"glyf")?;
+    let count = read16(table(b"maxp")?, 4)? as usize;
+    let long = read16(head, LOCA_FORMAT)? != 0;
+    let loca = table(b"loca")?;
+    let offset = |gid: usize| -> Option<usize> {
+        if long {
+            read32(loca, gid * 4).map(|v| v as usize)
+        } else {
+            read16(loca, gid * 2).map(|v| v as usize * 2)
+        }
+    };
+    let outline = |gid: usize| -> Option<&[u8]> {
+        let (start, end) = (offset(gid)?, offset(gid + 1)?);
+        glyf.get(start..end.max(start))
+    };
+
+    // composite glyphs pull in their components
+    let mut keep = BTreeSet::new();
+    let mut pending: Vec<u16> = glyphs.iter().copied().chain([0]).collect();
+    while let Some(gid) = pending.pop() {
+        if gid as usize >= count || !keep.insert(gid) {
+            continue;
+        }
+        pending.extend(components(outline(gid as usize)?));
+    }
+
+    let mut new_glyf = Vec::new();
+    let mut new_loca = Vec::with_capacity((count + 1) * 4);
+    for gid in 0..count {
+        new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes());
+        if keep.contains(&(gid as u16)) {
+            new_glyf.extend_from_slice(outline(gid)?);
+            pad(&mut new_glyf);
+        }
+    }
+    new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes());
+
+    let mut new_head = head.to_vec();
+    new_head
+        .get_mut(LOCA_FORMAT..LOCA_FORMAT + 2)?
+        .copy_from_slice(&1u16.to_be_bytes());
+    new_head
+        .get_mut(CHECKSUM_ADJUSTMENT..CHECKSUM_ADJUSTMENT + 4)?
+        .fill(0);
+
+    let tables: Vec<(&[u8; 4], Vec<u8>)> = TABLES
+        .iter()
+        .filter_map(|&tag| {
+            let data = match tag {
+                b"glyf" => std::mem::take(&mut new_glyf),
+                b"loca" => std::mem::take(&mut new_loca),
+                b"head" => std::mem::take(&mut new_head),
+                _ => table(tag)?.to_vec(),
+            };
+            Some((tag, data))
+        })
+        .collect();
+    let mut out = write_sfnt(&tables);
+    // the whole file then sums to the magic number
+    let head_at = head_offset(&out)?;
+    let adjustment = 0xb1b0_afbau32.wrapping_sub(checksum(&out));
+    out[head_at + CHECKSUM_ADJUSTMENT..head_at + CHECKSUM_ADJUSTMENT + 4]
+        .copy_from_slice(&adjustment.to_be_bytes());
+    Some(out)
+}
+
+/// Glyph ids a composite glyph refers to; none for simple glyphs
+fn components(outline: &[u8]) -> Vec<u16> {
+    let mut found = Vec::new();
+    if outline.is_empty() || read16(outline, 0).is_none_or(|n| (n as i16) >= 0) {
+        return found;
+    }
+    let mut at = 10;
+    while let (Some(flags), Some(gid)) = (read16(outline, at), read16(outline, at + 2)) {
+        found.push(gid);
+        at += 4 + if flags & ARG_1_AND_2_ARE_WORDS != 0 {
+            4
+        } else {
+            2
+        };
+        at += if flags & WE_HAVE_A_SCALE != 0 {
+            2
+        } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+            4
+        } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+            8
+        } else {
+            0
+        };
+        if flags & MORE_COMPONENTS == 0 {
+            break;
+        }
+    }
+    found
+}
+
+/// An `sfnt` file holding `tables`, which must be sorted by tag
+fn write_sfnt(tables: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+    let n = tables.len() as u16;
+    let power = if n == 0 {
+        0
+    } else {
+        15 - n.leading_zeros() as u16
+    };
+    let search_range = (1u16 << power) * 16;
+    let mut out = Vec::new();
+    for v in [1u16, 0, n, search_range, power, n * 16 - search_range] {
+        out.extend_from_slice(&v.to_be_bytes());
+    }
+    let mut offset = 12 + 16 * tables.len();
+    for (tag, data) in tables {
+        out.extend_from_slice(&tag[..]);
+        let mut padded = data.clone();
+        pad(&mut padded);
+        out.extend_from_slice(&checksum(&padded).to_be_bytes());
+        out.extend_from_slice(&(offset as u32).to_be_bytes());
+        out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        offset += padded.len();
+    }
+    for (_, data) in tables {
+        out.extend_from_slice(data);
+        pad(&mut out);
+    }
+    out
+}
+
+fn head_offset(sfnt: &[u8]) -> Option<usize> {
+    let n = read16(sfnt, 4)? as usize;
+    (0..n)
+        .map(|i| 12 + 16 * i)
+        .find(|&record| sfnt.get(record..record + 4) == Some(b"head"))
+        .and_then(|record| read32(sfnt, record + 8))
+        .map(|v| v as usize)
+}
+
+/// Sum of big-endian words, the last one padded with zeros
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, word| {
+        let mut bytes = [0; 4];
+        bytes[..word.len()].copy_from_slice(word);
+        sum.wrapping_add(u32::from_be_bytes(bytes))
+    })
+}
+
+/// Pads `data` to a multiple of four bytes, as tables and glyphs are aligned
+fn pad(data: &mut Vec<u8>) {
+    data.resize(data.len().next_multiple_of(4), 0);
+}
+
+fn read16(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn read32(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text::test_font::*;
+    use rustybuzz::ttf_parser::{Face, GlyphId};
+
+    #[test]
+    fn keeps_the_glyphs_asked_for_under_their_ids() {
+        let font = Font::from_bytes(test_font(true, true)).unwrap();
+        assert!(has_glyf(&font));
+        let bytes = subset(&font, &BTreeSet::from([THAI_CONSONANT, MAI_EK])).unwrap();
+        assert_eq!(checksum(&bytes), 0xb1b0_afba);
+
+        let face = Face::parse(&bytes, 0).unwrap();
+        let original = font.face();
+        assert_eq!(face.number_of_glyphs(), original.number_of_glyphs());
+        for gid in [NOTDEF, THAI_CONSONANT, MAI_EK] {
+            let bbox = face.glyph_bounding_box(GlyphId(gid));
+            assert!(bbox.is_some(), "glyph {gid} was dropped");
+            assert_eq!(bbox, original.glyph_bounding_box(GlyphId(gid)));
+            assert_eq!(
+                face.glyph_hor_advance(GlyphId(gid)),
+                original.glyph_hor_advance(GlyphId(gid))
+            );
+        }
+        for gid in [LATIN, SARA_AA] {
+            assert_eq!(face.glyph_bounding_box(GlyphId(gid)), None);
+        }
+        assert!(bytes.len() < test_font(true, true).len());
+    }
+
+    #[test]
+    fn follows_composite_glyphs() {
+        // a composite referring to glyphs 3 and 5, with word arguments and a
+        // scale on the second component
+        let mut outline = vec![0xff, 0xff, 0, 0, 0, 0, 0, 0, 0, 0];
+        for v in [MORE_COMPONENTS | ARG_1_AND_2_ARE_WORDS, 3, 0, 0] {
+            outline.extend_from_slice(&v.to_be_bytes());
+        }
+        for v in [WE_HAVE_A_SCALE, 5, 0, 0x4000] {
+            outline.extend_from_slice(&v.to_be_bytes());
+        }
+        assert_eq!(components(&outline), [3, 5]);
+        assert!(components(&outline[..0]).is_empty());
+    }
+}
@@ -0,0 +1,150 @@
+//! A tiny TrueType font built in memory for tests, so they need no font
+//! files. Every glyph is a box; 1000 units per em.
+
+/// Glyph ids of the test font
+pub(crate) const NOTDEF: u16 = 0;
+pub(crate) const SPACE: u16 = 1;
+pub(crate) const LATIN: u16 = 2;
+pub(crate) const THAI_CONSONANT: u16 = 3;
+pub(crate) const NIKHAHIT: u16 = 4;
+pub(crate) const MAI_EK: u16 = 5;
+pub(crate) const SARA_AA: u16 = 6;
+pub(crate) const SARA_E: u16 = 7;
+
+/// Advance and box (x0, y0, x1, y1) of each glyph, in glyph id order. The
+/// marks have advances that the shaper has to zero.
+const GLYPHS: [(u16, Option<[i16; 4]>); 8] = [
+    (500, Some([100, 0, 400, 600])),
+    (250, None),
+    (500, Some([50, 0, 450, 700])),
+    (600, Some([50, 0, 550, 500])),
+    (200, Some([-300, 600, -150, 700])),
+    (200, Some([-250, 750, -150, 900])),
+    (400, Some([50, 0, 350, 500])),
+    (250, Some([50, 0, 200, 500])),
+];
+
+/// Font with a space and, as asked, printable ASCII and a few Thai letters
+pub(crate) fn test_font(latin: bool, thai: bool) -> Vec<u8> {
+    let mut groups: Vec<(u32, u32, u16)> = vec![(0x20, 0x20, SPACE)];
+    if latin {
+        groups.push((0x21, 0x7e, LATIN));
+    }
+    if thai {
+        groups.extend([
+            (0x0e01, 0x0e2e, THAI_CONSONANT),
+            (0x0e32, 0x0e32, SARA_AA),
+            (0x0e40, 0x0e40, SARA_E),
+            (0x0e48, 0x0e48, MAI_EK),
+            (0x0e4d, 0x0e4d, NIKHAHIT),
+        ]);
+    }
+    // format 13 maps whole ranges onto one glyph
+    let mut cmap = Vec::new();
+    put16(&mut cmap, 0);
+    put16(&mut cmap, 1);
+    put16(&mut cmap, 3);
+    put16(&mut cmap, 10);
+    put32(&mut cmap, 12);
+    put16(&mut cmap, 13);
+    put16(&mut cmap, 0);
+    put32(&mut cmap, 16 + 12 * groups.len() as u32);
+    put32(&mut cmap, 0);
+    put32(&mut cmap, groups.len() as u32);
+    for (first, last, glyph) in groups {
+        put32(&mut cmap, first);
+        put32(&mut cmap, last);
+        put32(&mut cmap, glyph as u32);
+    }
+
+    let mut glyf = Vec::new();
+    let mut loca = Vec::new();
+    let mut hmtx = Vec::new();
+    for (advance, bounds) in GLYPHS {
+        put32(&mut loca, glyf.len() as u32);
+        put16(&mut hmtx, advance);
+        put16(&mut hmtx, bounds.map_or(0, |b| b[0]) as u16);
+        let Some([x0, y0, x1, y1]) = bounds else {
+            continue;
+        };
+        put16(&mut glyf, 1);
+        for v in [x0, y0, x1, y1] {
+            put16(&mut glyf, v as u16);
+        }
+        put16(&mut glyf, 3);
+        put16(&mut glyf, 0);
+        glyf.extend([1u8; 4]);
+        // clockwise from the bottom left, as deltas
+        for v in [x0, 0, x1 - x0, 0] {
+            put16(&mut glyf, v as u16);
+        }
+        for v in [y0, y1 - y0, 0, y0 - y1] {
+            put16(&mut glyf, v as u16);
+        }
+    }
+    put32(&mut loca, glyf.len() as u32);
+
+    let mut head = Vec::new();
+    put32(&mut head, 0x0001_0000);
+    put32(&mut head, 0x0001_0000);
+    put32(&mut head, 0);
+    put32(&mut head, 0x5f0f_3cf5);
+    put16(&mut head, 0);
+    put16(&mut head, 1000);
+    head.extend([0u8; 16]);
+    for v in [-300i16, 0, 550, 900] {
+        put16(&mut head, v as u16);
+    }
+    put16(&mut head, 0);
+    put16(&mut head, 8);
+    put16(&mut head, 2);
+    put16(&mut head, 1);
+    put16(&mut head, 0);
+
+    let mut hhea = Vec::new();
+    put32(&mut hhea, 0x0001_0000);
+    for v in [800i16, -200, 0, 600, -300, 0, 550, 1, 0, 0, 0, 0, 0, 0, 0] {
+        put16(&mut hhea, v as u16);
+    }
+    put16(&mut hhea, GLYPHS.len() as u16);
+
+    let mut maxp = Vec::new();
+    put32(&mut maxp, 0x0000_5000);
+    put16(&mut maxp, GLYPHS.len() as u16);
+
+    // tags in the order the table directory is searched
+    let tables: [(&[u8; 4], Vec<u8>); 7] = [
+        (b"cmap", cmap),
+        (b"glyf", glyf),
+        (b"head", head),
+        (b"hhea", hhea),
+        (b"hmtx", hmtx),
+        (b"loca", loca),
+        (b"maxp", maxp),
+    ];
+    let mut font = Vec::new();
+    put32(&mut font, 0x0001_0000);
+    put16(&mut font, tables.len() as u16);
+    font.extend([0u8; 6]);
+    let mut offset = 12 + 16 * tables.len();
+    for (tag, data) in &tables {
+        font.extend_from_slice(*tag);
+        put32(&mut font, 0);
+        put32(&mut font, offset as u32);
+        put32(&mut font, data.len() as u32);
+        offset += data.len().next_multiple_of(4);
+    }
+    for (_, data) in &tables {
+        font.extend_from_slice(data);
+        font.resize(font.len().next_multiple_of(4), 0);
+    }
+    font
+}
+
+fn put16(out: &mut Vec<u8>, v: u16) {
+    out.extend(v.to_be_bytes());
+}
+
+fn put32(out: &mut Vec<u8>, v: u32) {
+    out.extend(v.to_be_bytes());
+}
@@ -0,0 +1,931 @@
+//! Pointer tools, the state machine of `tools.ts`. A [`ToolState`] turns
+//! pointer events in screen pixels into [`ToolAction`]s: undoable edits of
+//! the document plus selection and camera changes for the caller to apply.
+//! Every edit between a pointer down and the following pointer up belongs to
+//! one gesture, which clients record as one undo step.
+
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::error::CanvasError;
+use crate::history::Edit;
+use crate::ink::{self, OneEuroFilter};
+use crate::model::{Camera, Point, Rect, Shape, ShapeId, ShapeKind, WhiteboardDoc};
+use crate::selection::{self, SelectionMode};
+use crate::spatial::{SpatialIndex, DEFAULT_TOLERANCE};
+use crate::text::Typesetter;
+
+/// Bits of `PointerEvent.buttons`
+pub const PRIMARY_BUTTON: u16 = 1;
+pub const MIDDLE_BUTTON: u16 = 4;
+
+/// Zoom steps of the zoom tool, in and with shift held out
+const ZOOM_IN: f64 = 1.1;
+const ZOOM_OUT: f64 = 0.9;
+
+/// Text placed by the text tool, as in `tools.ts`
+const PLACEHOLDER_TEXT: &str = "Text";
+
+/// Simplification tolerance and spline spacing of a finished pencil stroke,
+/// in screen pixels
+const INK_TOLERANCE: f64 = 0.5;
+const INK_SPACING: f64 = 4.0;
+/// Assumed time between pointer events that carry no timestamp, in ms
+const FRAME_MS: f64 = 1000.0 / 60.0;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Tool {
+    #[default]
+    Select,
+    Pan,
+    Pencil,
+    Line,
+    Rectangle,
+    Ellipse,
+    Arrow,
+    Text,
+    Eraser,
+    Zoom,
+}
+
+impl FromStr for Tool {
+    type Err = CanvasError;
+
+    /// Parses a `ToolId` such as `"rectangle"`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_value(Value::String(s.to_string()))
+            .map_err(|_| CanvasError::UnknownTool(s.to_string()))
+    }
+}
+
+/// Style of new shapes, the store's `UiSettings`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ToolSettings {
+    pub stroke: String,
+    /// Fill of rectangles and ellipses
+    pub fill: Option<String>,
+    pub stroke_width: f64,
+    pub font_size: f64,
+}
+
+impl Default for ToolSettings {
+    fn default() -> Self {
+        Self {
+            stroke: "#111827".to_string(),
+            fill: Some("#3b82f6".to_string()),
+            stroke_width: 2.0,
+            font_size: 24.0,
+        }
+    }
+}
+
+/// Keys held during a pointer event
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub meta: bool,
+}
+
+impl Modifiers {
+    pub const SHIFT: u8 = 1;
+    pub const CTRL: u8 = 2;
+    pub const ALT: u8 = 4;
+    pub const META: u8 = 8;
+
+    /// Reads the bit set built from the constants above
+    pub fn from_bits(bits: u8) -> Self {
+        Self {
+            shift: bits & Self::SHIFT != 0,
+            ctrl: bits & Self::CTRL != 0,
+            alt: bits & Self::ALT != 0,
+            meta: bits & Self::META != 0,
+        }
+    }
+}
+
+/// A pointer event in screen pixels relative to the canvas
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Pointer {
+    pub x: f64,
+    pub y: f64,
+    /// `PointerEvent.buttons`
+    pub buttons: u16,
+    pub modifiers: Modifiers,
+    /// `PointerEvent.pressure` from a pen, `None` for mice and touch, which
+    /// only report a fixed pressure
+    pub pressure: Option<f64>,
+    /// `PointerEvent.timeStamp` in ms, if known
+    pub time: Option<f64>,
+}
+
+impl Pointer {
+    fn screen(&self) -> Point {
+        Point::new(self.x, self.y)
+    }
+
+    fn pen_pressure(&self) -> Option<f64> {
+        self.pressure
+            .filter(|p| p.is_finite())
+            .map(|p| p.clamp(0.0, 1.0))
+    }
+}
+
+/// What a tool asks the caller to do
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ToolAction {
+    Edit {
+        edit: Edit,
+    },
+    /// The whole new selection, bottom first
+    Select {
+        ids: Vec<ShapeId>,
+    },
+    /// Screen-space offset for [`Camera::pan_by`]
+    Pan {
+        dx: f64,
+        dy: f64,
+    },
+    /// Arguments of [`Camera::zoom_at`]
+    Zoom {
+        x: f64,
+        y: f64,
+        factor: f64,
+    },
+}
+
+/// The board as the tools see it
+#[derive(Clone, Copy, Debug)]
+pub struct ToolContext<'a> {
+    pub doc: &'a WhiteboardDoc,
+    pub index: &'a SpatialIndex,
+    /// What `index` measured text with
+    pub text: &'a Typesetter,
+    pub camera: Camera,
+}
+
+impl ToolContext<'_> {
+    fn hit(&self, world: Point) -> Option<ShapeId> {
+        self.index
+            .hit_test(self.doc, world, DEFAULT_TOLERANCE, self.text)
+    }
+}
+
+/// The active tool and the gesture in progress, `Session` in `tools.ts`
+#[derive(Debug, Clone)]
+pub struct ToolState {
+    tool: Tool,
+    settings: ToolSettings,
+    selection: Vec<ShapeId>,
+    session: Option<Session>,
+    /// Prefix of new shape ids, unique to this client
+    id_prefix: String,
+    next_id: u64,
+}
+
+#[derive(Debug, Clone)]
+struct Session {
+    start_world: Point,
+    last_screen: Point,
+    gesture: Gesture,
+}
+
+#[derive(Debug, Clone)]
+enum Gesture {
+    /// Dragging the view, with the pan tool or the middle button
+    Pan,
+    /// Drawing the new shape `id`
+    Draw {
+        id: ShapeId,
+        moved: bool,
+    },
+    /// Drawing the pencil stroke `id` from the filtered pointer positions,
+    /// with one pressure per point when drawn with a pen
+    Ink {
+        id: ShapeId,
+        points: Vec<Point>,
+        pressure: Vec<f64>,
+        filter: OneEuroFilter,
+        time: f64,
+    },
+    /// Dragging a marquee from empty space, adding to `base`
+    Marquee {
+        base: Vec<ShapeId>,
+    },
+    Erase,
+    /// Pressed on a shape with the select tool
+    Press,
+}
+
+impl ToolState {
+    /// New shapes get the ids `<id_prefix>-1`, `<id_prefix>-2` and so on, so
+    /// the prefix has to differ between clients editing the same board
+    pub fn new(id_prefix: impl Into<String>) -> Self {
+        Self {
+            tool: Tool::default(),
+            settings: ToolSettings::default(),
+            selection: Vec::new(),
+            session: None,
+            id_prefix: id_prefix.into(),
+            next_id: 0,
+        }
+    }
+
+    pub fn tool(&self) -> Tool {
+        self.tool
+    }
+
+    /// Switches tools, ending any gesture. Like the store's `setTool`, only
+    /// the select tool keeps the selection.
+    pub fn set_tool(&mut self, tool: Tool) {
+        self.tool = tool;
+        self.session = None;
+        if tool != Tool::Select {
+            self.selection.clear();
+        }
+    }
+
+    pub fn settings(&self) -> &ToolSettings {
+        &self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: ToolSettings) {
+        self.settings = settings;
+    }
+
+    pub fn selection(&self) -> &[ShapeId] {
+        &self.selection
+    }
+
+    /// Replaces the selection, for selections made outside the tools
+    pub fn set_selection(&mut self, ids: Vec<ShapeId>) {
+        self.selection = ids;
+    }
+
+    /// Drops the gesture in progress without finishing it, for when the
+    /// document it was editing is replaced
+    pub fn cancel(&mut self) {
+        self.session = None;
+    }
+
+    /// Whether a gesture is in progress
+    pub fn is_active(&self) -> bool {
+        self.session.is_some()
+    }
+
+    pub fn pointer_down(&mut self, ctx: &ToolContext, pointer: Pointer) -> Vec<ToolAction> {
+        // a lost pointer up must not leave a half-drawn shape behind
+        let mut actions = self.pointer_up(ctx, pointer);
+        let screen = pointer.screen();
+        let world = ctx.camera.screen_to_world(screen);
+        let gesture = if pointer.buttons & MIDDLE_BUTTON != 0 {
+            Some(Gesture::Pan)
+        } else if pointer.buttons & PRIMARY_BUTTON == 0 {
+            None
+        } else {
+            self.press(ctx, pointer, world, &mut actions)
+        };
+        self.session = gesture.map(|gesture| Session {
+            start_world: world,
+            last_screen: screen,
+            gesture,
+        });
+        actions
+    }
+
+    /// Follows the pointer. Pencil strokes only collect their points here,
+    /// which [`ToolState::ink_preview`] shows until pointer up patches the
+    /// document once.
+    pub fn pointer_move(&mut self, ctx: &ToolContext, pointer: Pointer) -> Vec<ToolAction> {
+        let mut actions = Vec::new();
+        let Some(mut session) = self.session.take() else {
+            return actions;
+        };
+        let screen = pointer.screen();
+        let world = ctx.camera.screen_to_world(screen);
+        match &mut session.gesture {
+            Gesture::Pan => actions.push(ToolAction::Pan {
+                dx: screen.x - session.last_screen.x,
+                dy: screen.y - session.last_screen.y,
+            }),
+            Gesture::Draw { id, moved } => {
+                *moved |= world != session.start_world;
+                let edits = self.drag_shape(ctx, id, session.start_world, world, pointer);
+                actions.extend(edits.into_iter().map(|edit| ToolAction::Edit { edit }));
+            }
+            Gesture::Ink {
+                id,
+                points,
+                pressure,
+                filter,
+                time,
+            } if ctx.doc.get(id).is_some() => {
+                *time = pointer.time.unwrap_or(*time + FRAME_MS);
+                let smooth = ctx.camera.screen_to_world(filter.filter(screen, *time));
+                if points.last() != Some(&smooth) {
+                    points.push(smooth);
+                    if let Some(&last) = pressure.last() {
+                        pressure.push(pointer.pen_pressure().unwrap_or(last));
+                    }
+                }
+            }
+            Gesture::Ink { .. } => {}
+            Gesture::Marquee { base } => {
+                let rect = Rect::from_points(session.start_world, world);
+                let mut ids = base.clone();
+                for id in selection::marquee(
+                    ctx.index,
+                    ctx.doc,
+                    rect,
+                    SelectionMode::Intersecting,
+                    ctx.text,
+                ) {
+                    if !ids.contains(&id) {
+                        ids.push(id);
+                    }
+                }
+                self.select(ids, &mut actions);
+            }
+            Gesture::Erase => self.erase(ctx, world, &mut actions),
+            Gesture::Press => {}
+        }
+        session.last_screen = screen;
+        self.session = Some(session);
+        actions
+    }
+
+    /// The pencil stroke being drawn, with the points collected so far, for
+    /// clients to draw in place of the one in `doc` while the pointer is down
+    pub fn ink_preview(&self, doc: &WhiteboardDoc) -> Option<Shape> {
+        let Gesture::Ink {
+            id,
+            points,
+            pressure,
+            ..
+        } = &self.session.as_ref()?.gesture
+        else {
+            return None;
+        };
+        let mut shape = doc.get(id)?.clone();
+        shape.kind = ShapeKind::Pencil {
+            points: points.clone(),
+            pressure: pressure.clone(),
+        };
+        Some(shape)
+    }
+
+    /// Ends the gesture. A shape that never grew past its first point is
+    /// dropped again, so a stray click leaves nothing behind, and a pencil
+    /// stroke is simplified and smoothed into what is kept.
+    pub fn pointer_up(&mut self, ctx: &ToolContext, _pointer: Pointer) -> Vec<ToolAction> {
+        let Some(session) = self.session.take() else {
+            return Vec::new();
+        };
+        match session.gesture {
+            Gesture::Ink {
+                id,
+                mut points,
+                mut pressure,
+                ..
+            } => {
+                let Some(shape) = ctx.doc.get(&id) else {
+                    return Vec::new();
+                };
+                // the filter trails the pen, so the stroke ends where the
+                // pointer last was rather than short of it
+                let end = ctx.camera.screen_to_world(session.last_screen);
+                if points.last() != Some(&end) {
+                    points.push(end);
+                    if let Some(&last) = pressure.last() {
+                        pressure.push(last);
+                    }
+                }
+                let zoom = ctx.camera.zoom;
+                let (points, pressure) = ink::finish(
+                    &points,
+                    &pressure,
+                    shape.stroke_width,
+                    INK_TOLERANCE / zoom,
+                    INK_SPACING / zoom,
+                );
+                let mut actions = Vec::new();
+                ink_patches(&id, &points, &pressure, &mut actions);
+                actions
+            }
+            Gesture::Draw {
+                id, moved: false, ..
+            } if ctx.doc.get(&id).is_some() => {
+                vec![ToolAction::Edit {
+                    edit: Edit::Delete { id },
+                }]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Starts what the primary button does with the current tool, returning
+    /// the gesture that follows the pointer, if any
+    fn press(
+        &mut self,
+        ctx: &ToolContext,
+        pointer: Pointer,
+        world: Point,
+        actions: &mut Vec<ToolAction>,
+    ) -> Option<Gesture> {
+        let style = self.settings.clone();
+        let kind = match self.tool {
+            Tool::Pan => return Some(Gesture::Pan),
+            Tool::Zoom => {
+                let factor = if pointer.modifiers.shift {
+                    ZOOM_OUT
+                } else {
+                    ZOOM_IN
+                };
+                actions.push(ToolAction::Zoom {
+                    x: pointer.x,
+                    y: pointer.y,
+                    factor,
+                });
+                return None;
+            }
+            Tool::Select => {
+                let additive = pointer.modifiers.shift;
+                return Some(match ctx.hit(world) {
+                    Some(id) => {
+                        let mut ids = if additive {
+                            self.selection.clone()
+                        } else {
+                            Vec::new()
+                        };
+                        match ids.iter().position(|s| *s == id) {
+                            Some(i) => {
+                                ids.remove(i);
+                            }
+                            None => ids.push(id),
+                        }
+                        self.select(ids, actions);
+                        Gesture::Press
+                    }
+                    None => {
+                        let base = if additive {
+                            self.selection.clone()
+                        } else {
+                            Vec::new()
+                        };
+                        self.select(base.clone(), actions);
+                        Gesture::Marquee { base }
+                    }
+                });
+            }
+            Tool::Eraser => {
+                self.erase(ctx, world, actions);
+                return Some(Gesture::Erase);
+            }
+            Tool::Text => ShapeKind::Text {
+                x: world.x,
+                y: world.y,
+                text: PLACEHOLDER_TEXT.to_string(),
+                font_size: style.font_size,
+                align: None,
+                max_width: None,
+                line_height: None,
+            },
+            Tool::Pencil => ShapeKind::Pencil {
+                points: vec![world],
+                pressure: pointer.pen_pressure().into_iter().collect(),
+            },
+            Tool::Line => ShapeKind::Line { a: world, b: world },
+            Tool::Arrow => ShapeKind::Arrow { a: world, b: world },
+            Tool::Rectangle => ShapeKind::Rectangle {
+                x: world.x,
+                y: world.y,
+                w: 0.0,
+                h: 0.0,
+            },
+            Tool::Ellipse => ShapeKind::Ellipse {
+                x: world.x,
+                y: world.y,
+                w: 0.0,
+                h: 0.0,
+            },
+        };
+        let filled = matches!(
+            kind,
+            ShapeKind::Rectangle { .. } | ShapeKind::Ellipse { .. }
+        );
+        let text = matches!(kind, ShapeKind::Text { .. });
+        let pressure = match &kind {
+            ShapeKind::Pencil { pressure, .. } => Some(pressure.clone()),
+            _ => None,
+        };
+        self.next_id += 1;
+        let shape = Shape {
+            id: format!("{}-{}", self.id_prefix, self.next_id),
+            stroke: style.stroke.clone(),
+            fill: style.fill.clone().filter(|_| filled),
+            stroke_width: if text { 1.0 } else { style.stroke_width },
+            kind,
+        };
+        let id = shape.id.clone();
+        actions.push(ToolAction::Edit {
+            edit: Edit::Insert { shape },
+        });
+        if text {
+            // placed in one click and selected for editing
+            self.select(vec![id], actions);
+            return None;
+        }
+        self.select(Vec::new(), actions);
+        if let Some(pressure) = pressure {
+            let mut filter = OneEuroFilter::default();
+            let time = pointer.time.unwrap_or(0.0);
+            filter.filter(pointer.screen(), time);
+            return Some(Gesture::Ink {
+                id,
+                points: vec![world],
+                pressure,
+                filter,
+                time,
+            });
+        }
+        Some(Gesture::Draw { id, moved: false })
+    }
+
+    /// Edits that follow the pointer to `world` while drawing shape `id`
+    fn drag_shape(
+        &self,
+        ctx: &ToolContext,
+        id: &ShapeId,
+        start: Point,
+        world: Point,
+        pointer: Pointer,
+    ) -> Vec<Edit> {
+        let patch = |key: &str, value: Value| Edit::Patch {
+            id: id.clone(),
+            key: key.to_string(),
+            value,
+        };
+        let Some(shape) = ctx.doc.get(id) else {
+            return Vec::new();
+        };
+        let constrain = pointer.modifiers.shift;
+        match shape.kind {
+            ShapeKind::Line { .. } | ShapeKind::Arrow { .. } => {
+                let end = if constrain {
+                    snap_angle(start, world)
+                } else {
+                    world
+                };
+                vec![patch("b", json!(end))]
+            }
+            ShapeKind::Rectangle { .. } | ShapeKind::Ellipse { .. } => {
+                let end = if constrain {
+                    square(start, world)
+                } else {
+                    world
+                };
+                let r = Rect::from_points(start, end);
+                vec![
+                    patch("x", json!(r.x)),
+                    patch("y", json!(r.y)),
+                    patch("w", json!(r.w)),
+                    patch("h", json!(r.h)),
+                ]
+            }
+            ShapeKind::Text { .. } | ShapeKind::Pencil { .. } => Vec::new(),
+        }
+    }
+
+    /// Deletes the topmost shape under `world`
+    fn erase(&mut self, ctx: &ToolContext, world: Point, actions: &mut Vec<ToolAction>) {
+        let Some(id) = ctx.hit(world) else {
+            return;
+        };
+        if self.selection.contains(&id) {
+            let ids = self
+                .selection
+                .iter()
+                .filter(|s| **s != id)
+                .cloned()
+                .collect();
+            self.select(ids, actions);
+        }
+        actions.push(ToolAction::Edit {
+            edit: Edit::Delete { id },
+        });
+    }
+
+    /// Sets the selection, reporting it only when it changed
+    fn select(&mut self, ids: Vec<ShapeId>, actions: &mut Vec<ToolAction>) {
+        if ids != self.selection {
+            self.selection = ids.clone();
+            actions.push(ToolAction::Select { ids });
+        }
+    }
+}
+
+/// Patches that set the pencil stroke `id` to `points` and `pressure`.
+/// Strokes without pen pressure only get their points.
+fn ink_patches(id: &ShapeId, points: &[Point], pressure: &[f64], actions: &mut Vec<ToolAction>) {
+    let patch = |key: &str, value: Value| ToolAction::Edit {
+        edit: Edit::Patch {
+            id: id.clone(),
+            key: key.to_string(),
+            value,
+        },
+    };
+    if !pressure.is_empty() {
+        actions.push(patch("pressure", json!(pressure)));
+    }
+    actions.push(patch("points", json!(points)));
+}
+
+/// `end` moved onto the nearest multiple of 45° around `start`, keeping its
+/// distance
+fn snap_angle(start: Point, end: Point) -> Point {
+    let (dx, dy) = (end.x - start.x, end.y - start.y);
+    let step = std::f64::consts::FRAC_PI_4;
+    let angle = (dy.atan2(dx) / step).round() * step;
+    let length = dx.hypot(dy);
+    Point::new(
+        start.x + length * angle.cos(),
+        start.y + length * angle.sin(),
+    )
+}
+
+/// `end` moved so the box from `start` is square, its side the longer of the
+/// two
+fn square(start: Point, end: Point) -> Point {
+    let (dx, dy) = (end.x - start.x, end.y - start.y);
+    let side = dx.abs().max(dy.abs());
+    Point::new(start.x + side.copysign(dx), start.y + side.copysign(dy))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::History;
+
+    /// A board driven by the tools the way the clients drive it
+    struct Board {
+        doc: WhiteboardDoc,
+        index: SpatialIndex,
+        text: Typesetter,
+        history: History,
+        camera: Camera,
+        tools: ToolState,
+    }
+
+    impl Board {
+        fn new(tool: Tool) -> Self {
+            let mut tools = ToolState::new("t");
+            tools.set_tool(tool);
+            Self {
+                doc: WhiteboardDoc::default(),
+                index: SpatialIndex::new(),
+                text: Typesetter::new(),
+                history: History::default(),
+                camera: Camera::default(),
+                tools,
+            }
+        }
+
+        fn event(&mut self, kind: &str, x: f64, y: f64, buttons: u16, bits: u8) -> Vec<ToolAction> {
+            let pointer = Pointer {
+                x,
+                y,
+                buttons,
+                modifiers: Modifiers::from_bits(bits),
+                ..Pointer::default()
+            };
+            self.send(kind, pointer)
+        }
+
+        fn send(&mut self, kind: &str, pointer: Pointer) -> Vec<ToolAction> {
+            let ctx = ToolContext {
+                doc: &self.doc,
+                index: &self.index,
+                text: &self.text,
+                camera: self.camera,
+            };
+            let actions = match kind {
+                "down" => {
+                    self.history.begin(None);
+                    self.tools.pointer_down(&ctx, pointer)
+                }
+                "move" => self.tools.pointer_move(&ctx, pointer),
+                _ => self.tools.pointer_up(&ctx, pointer),
+            };
+            for action in &actions {
+                match action {
+                    ToolAction::Edit { edit } => {
+                        let changes = self.history.apply(&mut self.doc, edit.clone()).unwrap();
+                        for id in &changes.removed {
+                            self.index.remove(id);
+                        }
+                        for shape in &changes.upserted {
+                            self.index.insert(shape, &self.text);
+                        }
+                    }
+                    ToolAction::Pan { dx, dy } => self.camera.pan_by(*dx, *dy),
+                    ToolAction::Zoom { x, y, factor } => {
+                        self.camera.zoom_at(Point::new(*x, *y), *factor)
+                    }
+                    ToolAction::Select { .. } => {}
+                }
+            }
+            if kind == "up" {
+                self.history.commit();
+            }
+            actions
+        }
+
+        fn drag(&mut self, from: (f64, f64), to: (f64, f64), bits: u8) {
+            self.event("down", from.0, from.1, PRIMARY_BUTTON, bits);
+            self.event(
+                "move",
+                (from.0 + to.0) / 2.0,
+                (from.1 + to.1) / 2.0,
+                PRIMARY_BUTTON,
+                bits,
+            );
+            self.event("move", to.0, to.1, PRIMARY_BUTTON, bits);
+            self.event("up", to.0, to.1, 0, bits);
+        }
+    }
+
+    #[test]
+    fn draws_shapes_in_world_space_as_one_undo_step() {
+        let mut board = Board::new(Tool::Rectangle);
+        board.camera = Camera {
+            x: 100.0,
+            y: 50.0,
+            zoom: 2.0,
+        };
+        board.drag((300.0, 250.0), (140.0, 90.0), 0);
+        let shape = board.doc.get("t-1").unwrap();
+        assert_eq!(
+            shape.kind,
+            ShapeKind::Rectangle {
+                x: 20.0,
+                y: 20.0,
+                w: 80.0,
+                h: 80.0
+            }
+        );
+        assert_eq!(shape.fill.as_deref(), Some("#3b82f6"));
+        assert_eq!(board.history.len(), 1);
+        board.history.undo(&mut board.doc).unwrap();
+        assert!(board.doc.is_empty());
+
+        // shift keeps lines at 45° steps
+        board.tools.set_tool(Tool::Arrow);
+        board.camera = Camera::default();
+        board.drag((0.0, 0.0), (100.0, 90.0), Modifiers::SHIFT);
+        let ShapeKind::Arrow { b, .. } = board.doc.get("t-2").unwrap().kind else {
+            panic!("not an arrow");
+        };
+        assert!((b.x - b.y).abs() < 1e-9 && (b.x.hypot(b.y) - 100f64.hypot(90.0)).abs() < 1e-9);
+
+        // a click without a drag leaves nothing
+        board.tools.set_tool(Tool::Ellipse);
+        board.event("down", 5.0, 5.0, PRIMARY_BUTTON, 0);
+        board.event("up", 5.0, 5.0, 0, 0);
+        assert!(board.doc.get("t-3").is_none());
+        assert_eq!(board.history.len(), 1);
+    }
+
+    #[test]
+    fn pen_strokes_keep_their_pressure() {
+        let mut board = Board::new(Tool::Pencil);
+        let pen = |x: f64, pressure: f64, time: f64| Pointer {
+            x,
+            y: (x / 10.0).sin() * 20.0,
+            buttons: PRIMARY_BUTTON,
+            pressure: Some(pressure),
+            time: Some(time),
+            ..Pointer::default()
+        };
+        board.send("down", pen(0.0, 0.2, 0.0));
+        for i in 1..=60 {
+            let x = i as f64 * 2.0;
+            let actions = board.send("move", pen(x, 0.2 + i as f64 / 100.0, i as f64 * 8.0));
+            assert!(actions.is_empty());
+        }
+        // moves only collect points, the document gets them on pointer up
+        let Some(ShapeKind::Pencil { points, pressure }) =
+            board.tools.ink_preview(&board.doc).map(|shape| shape.kind)
+        else {
+            panic!("no stroke in progress");
+        };
+        assert_eq!((points.len(), pressure.len()), (61, 61));
+        assert!(matches!(
+            &board.doc.get("t-1").unwrap().kind,
+            ShapeKind::Pencil { points, .. } if points.len() == 1
+        ));
+        board.send("up", Pointer::default());
+        assert_eq!(board.history.len(), 1);
+        let ShapeKind::Pencil { points, pressure } = &board.doc.get("t-1").unwrap().kind else {
+            panic!("not a pencil stroke");
+        };
+        assert_eq!(points.len(), pressure.len());
+        assert_eq!(points[0], Point::new(0.0, 0.0));
+        assert_eq!(
+            *points.last().unwrap(),
+            Point::new(120.0, (12f64).sin() * 20.0)
+        );
+        assert!(pressure[0] < 0.25 && *pressure.last().unwrap() > 0.75);
+        // smoothed, but still following the wave that was drawn
+        let off = points
+            .iter()
+            .map(|p| (p.y - (p.x / 10.0).sin() * 20.0).abs())
+            .fold(0.0, f64::max);
+        assert!(off < 5.0, "{off}");
+    }
+
+    #[test]
+    fn pencil_collects_points_and_text_selects_itself() {
+        let mut board = Board::new(Tool::Pencil);
+        board.event("down", 0.0, 0.0, PRIMARY_BUTTON, 0);
+        for x in [1.0, 1.0, 2.0, 3.0] {
+            board.event("move", x, x, PRIMARY_BUTTON, 0);
+        }
+        board.event("up", 3.0, 3.0, 0, 0);
+        let ShapeKind::Pencil { points, pressure } = &board.doc.get("t-1").unwrap().kind else {
+            panic!("not a pencil stroke");
+        };
+        // a straight run simplifies down to its ends, the last one exactly
+        // where the pointer left off despite the smoothing
+        assert_eq!(points, &[Point::new(0.0, 0.0), Point::new(3.0, 3.0)]);
+        assert!(pressure.is_empty());
+        assert_eq!(board.doc.get("t-1").unwrap().fill, None);
+
+        board.tools.set_tool(Tool::Text);
+        let actions = board.event("down", 40.0, 40.0, PRIMARY_BUTTON, 0);
+        assert!(!board.tools.is_active());
+        assert_eq!(
+            actions.last(),
+            Some(&ToolAction::Select {
+                ids: vec!["t-2".to_string()]
+            })
+        );
+        board.event("up", 40.0, 40.0, 0, 0);
+        assert_eq!(board.history.len(), 2);
+    }
+
+    #[test]
+    fn selects_by_click_and_marquee_and_erases_by_dragging() {
+        let mut board = Board::new(Tool::Rectangle);
+        board.drag((0.0, 0.0), (20.0, 20.0), 0);
+        board.drag((100.0, 0.0), (120.0, 20.0), 0);
+        board.drag((200.0, 0.0), (220.0, 20.0), 0);
+
+        board.tools.set_tool(Tool::Select);
+        board.event("down", 10.0, 10.0, PRIMARY_BUTTON, 0);
+        board.event("up", 10.0, 10.0, 0, 0);
+        assert_eq!(board.tools.selection(), ["t-1"]);
+        board.event("down", 110.0, 10.0, PRIMARY_BUTTON, Modifiers::SHIFT);
+        board.event("up", 110.0, 10.0, 0, Modifiers::SHIFT);
+        assert_eq!(board.tools.selection(), ["t-1", "t-2"]);
+
+        // a marquee from empty space starts over without shift
+        board.drag((90.0, -10.0), (230.0, 30.0), 0);
+        assert_eq!(board.tools.selection(), ["t-2", "t-3"]);
+        board.event("down", 500.0, 500.0, PRIMARY_BUTTON, 0);
+        assert!(board.tools.selection().is_empty());
+        board.event("up", 500.0, 500.0, 0, 0);
+
+        board.tools.set_tool(Tool::Eraser);
+        board.drag((10.0, 10.0), (210.0, 10.0), 0);
+        assert!(board.doc.is_empty());
+        board.history.undo(&mut board.doc).unwrap();
+        assert_eq!(board.doc.len(), 3);
+    }
+
+    #[test]
+    fn pans_with_the_middle_button_and_zooms_at_the_pointer() {
+        let mut board = Board::new(Tool::Pencil);
+        board.event("down", 10.0, 10.0, MIDDLE_BUTTON, 0);
+        board.event("move", 25.0, 5.0, MIDDLE_BUTTON, 0);
+        board.event("up", 25.0, 5.0, 0, 0);
+        assert_eq!((board.camera.x, board.camera.y), (15.0, -5.0));
+        assert!(board.doc.is_empty());
+
+        board.tools.set_tool(Tool::Zoom);
+        let before = board.camera.screen_to_world(Point::new(50.0, 40.0));
+        board.event("down", 50.0, 40.0, PRIMARY_BUTTON, 0);
+        board.event("up", 50.0, 40.0, 0, 0);
+        assert!((board.camera.zoom - ZOOM_IN).abs() < 1e-12);
+        let after = board.camera.screen_to_world(Point::new(50.0, 40.0));
+        assert!((before.x - after.x).abs() < 1e-9 && (before.y - after.y).abs() < 1e-9);
+
+        assert_eq!("arrow".parse::<Tool>().unwrap(), Tool::Arrow);
+        assert!("laser".parse::<Tool>().is_err());
+    }
+}